@@ -0,0 +1,771 @@
+use std::{cmp::Ordering, ops::Range};
+
+use anyhow::{bail, Context};
+
+use crate::{
+  page::{
+    page_buffer::{
+      cell_space, is_interior, overflow_page, page_capacity, PageBuffer, MIN_CELL_SIZE,
+    },
+    page_utils::{Cell, PageType},
+    pager::OVERFLOW_PAGE_POINTER_SIZE,
+  },
+  pager::Pager,
+  read_be_double_at, read_varint_at, write_varint,
+};
+
+use super::cursor::Cursor;
+
+/// Interior pages from the root down to a page, with the child pointer followed
+/// in each. The rightmost pointer counts as the one after the last cell.
+type Path = Vec<(usize, usize)>;
+
+/// Inserts entries into a table or index b-tree, splitting the pages that overflow.
+/// The root keeps its page number: when it splits, its cells move a level down.
+/// Pages are written through the pager, which holds them until it commits.
+#[derive(Debug)]
+pub struct BTree {
+  pager: Pager,
+  root: usize,
+}
+
+impl BTree {
+  pub fn new(pager: Pager, root: usize) -> Self {
+    Self { pager, root }
+  }
+
+  /// Largest rowid of a table b-tree, None when it is empty
+  pub fn max_rowid(&self) -> anyhow::Result<Option<i64>> {
+    let mut page_num = self.root;
+    loop {
+      let page = self.pager.read_page(page_num)?;
+      match page.header.page_type {
+        PageType::TableInterior => {
+          page_num = page
+            .header
+            .rightmost_pointer
+            .context("interior page without rightmost pointer")? as usize;
+        }
+        PageType::TableLeaf => {
+          return Ok(match page.cells.last() {
+            Some(Cell::TableLeaf(cell)) => Some(cell.row_id),
+            _ => None,
+          });
+        }
+        _ => bail!("cannot read rowids of an index b-tree"),
+      }
+    }
+  }
+
+  /// Inserts a row into a table b-tree. Returns false, leaving the tree as is,
+  /// when the rowid is taken.
+  pub fn insert_row(&self, rowid: i64, record: &[u8]) -> anyhow::Result<bool> {
+    let mut path = vec![];
+    let mut rightmost = true;
+    let mut page_num = self.root;
+    loop {
+      let page = self.pager.read_page(page_num)?;
+      match page.header.page_type {
+        PageType::TableInterior => {
+          // interior keys are the largest rowid of their left child
+          let idx = page
+            .cells
+            .partition_point(|c| matches!(c, Cell::TableInterior(c) if c.key < rowid));
+          let child = match page.cells.get(idx) {
+            Some(Cell::TableInterior(cell)) => cell.left_child_page,
+            _ => page
+              .header
+              .rightmost_pointer
+              .context("interior page without rightmost pointer")?,
+          };
+          rightmost &= idx == page.cells.len();
+          path.push((page_num, idx));
+          page_num = child as usize;
+        }
+        PageType::TableLeaf => {
+          let idx = page
+            .cells
+            .partition_point(|c| matches!(c, Cell::TableLeaf(c) if c.row_id < rowid));
+          if matches!(page.cells.get(idx), Some(Cell::TableLeaf(c)) if c.row_id == rowid) {
+            return Ok(false);
+          }
+
+          let mut cell = vec![];
+          write_varint(&mut cell, record.len() as i64);
+          write_varint(&mut cell, rowid);
+          self.push_payload(&mut cell, PageType::TableLeaf, record)?;
+          let append = rightmost && idx == page.cells.len();
+          self.insert_cells(page_num, idx, vec![cell], path, append)?;
+          return Ok(true);
+        }
+        _ => bail!("cannot insert a row into an index b-tree"),
+      }
+    }
+  }
+
+  /// Inserts a record into an index b-tree, or the b-tree of a WITHOUT ROWID table,
+  /// before the first record for which `before` is false
+  pub fn insert_record(
+    &self,
+    record: &[u8],
+    before: impl Fn(&Cursor) -> anyhow::Result<bool>,
+  ) -> anyhow::Result<()> {
+    let mut path = vec![];
+    let mut rightmost = true;
+    let mut page_num = self.root;
+    loop {
+      let page = self.pager.read_page(page_num)?;
+      if matches!(
+        page.header.page_type,
+        PageType::TableLeaf | PageType::TableInterior
+      ) {
+        bail!("cannot insert a record into a table b-tree");
+      }
+
+      // first cell that isn't before the record
+      let (mut low, mut high) = (0, page.cells.len());
+      while low < high {
+        let mid = (low + high) / 2;
+        let payload = match &page.cells[mid] {
+          Cell::IndexInterior(cell) => &cell.payload,
+          Cell::IndexLeaf(cell) => &cell.payload,
+          _ => bail!("table cell in an index b-tree"),
+        };
+        if before(&Cursor::new(payload, None)?.with_encoding(self.pager.text_encoding()))? {
+          low = mid + 1;
+        } else {
+          high = mid;
+        }
+      }
+      rightmost &= low == page.cells.len();
+
+      if page.header.page_type == PageType::IndexLeaf {
+        let mut cell = vec![];
+        write_varint(&mut cell, record.len() as i64);
+        self.push_payload(&mut cell, PageType::IndexLeaf, record)?;
+        return self.insert_cells(page_num, low, vec![cell], path, rightmost);
+      }
+
+      let child = match page.cells.get(low) {
+        Some(Cell::IndexInterior(cell)) => cell.left_child_page,
+        _ => page
+          .header
+          .rightmost_pointer
+          .context("interior page without rightmost pointer")?,
+      };
+      path.push((page_num, low));
+      page_num = child as usize;
+    }
+  }
+
+  /// Appends the part of `payload` stored on the page to `cell`, followed by the
+  /// pointer to the overflow pages holding the rest
+  fn push_payload(
+    &self,
+    cell: &mut Vec<u8>,
+    page_type: PageType,
+    payload: &[u8],
+  ) -> anyhow::Result<()> {
+    let local = self.pager.limits().local_size(page_type, payload.len());
+    cell.extend_from_slice(&payload[..local]);
+    if local < payload.len() {
+      let first_page = self.write_overflow(&payload[local..])?;
+      cell.extend_from_slice(&first_page.to_be_bytes());
+    }
+    cell.resize(cell.len().max(MIN_CELL_SIZE), 0);
+    Ok(())
+  }
+
+  /// Writes `content` to a chain of new overflow pages, returning the first one
+  fn write_overflow(&self, content: &[u8]) -> anyhow::Result<u32> {
+    let chunk_size = self.pager.limits().usable_size - OVERFLOW_PAGE_POINTER_SIZE;
+    let pages = content
+      .chunks(chunk_size)
+      .map(|_| self.pager.allocate_page())
+      .collect::<anyhow::Result<Vec<_>>>()?;
+
+    for (i, chunk) in content.chunks(chunk_size).enumerate() {
+      let next = pages.get(i + 1).copied().unwrap_or_default() as u32;
+      let mut data = vec![0; self.pager.page_size()];
+      data[..OVERFLOW_PAGE_POINTER_SIZE].copy_from_slice(&next.to_be_bytes());
+      data[OVERFLOW_PAGE_POINTER_SIZE..OVERFLOW_PAGE_POINTER_SIZE + chunk.len()]
+        .copy_from_slice(chunk);
+      self.pager.write_page(pages[i], data)?;
+    }
+    Ok(pages[0] as u32)
+  }
+
+  /// Inserts `cells` into page `page_num` before its cell `idx`, splitting the page
+  /// when they don't fit. `append` tells the cells go at the very end of the tree.
+  fn insert_cells(
+    &self,
+    page_num: usize,
+    idx: usize,
+    cells: Vec<Vec<u8>>,
+    path: Path,
+    append: bool,
+  ) -> anyhow::Result<()> {
+    let limits = self.pager.limits();
+    let mut page = self.page_buffer(page_num)?;
+
+    let needed = cells.iter().map(|c| cell_space(c)).sum::<usize>();
+    if needed <= page.free_space()? {
+      for (i, cell) in cells.iter().enumerate() {
+        page.insert_cell(idx + i, cell, limits)?;
+      }
+      return self.pager.write_page(page_num, page.data);
+    }
+
+    let header = page.header()?;
+    let mut all_cells = page.cells(limits)?;
+    all_cells.splice(idx..idx, cells);
+    self.split(
+      page_num,
+      header.page_type,
+      all_cells,
+      header.rightmost_pointer,
+      path,
+      append,
+    )
+  }
+
+  /// Spreads `cells` over page `page_num` and as many new pages as they need,
+  /// then adds a divider for every new page to the parent. A root that splits
+  /// becomes an interior page over the new pages.
+  fn split(
+    &self,
+    page_num: usize,
+    page_type: PageType,
+    cells: Vec<Vec<u8>>,
+    rightmost_pointer: Option<u32>,
+    mut path: Path,
+    append: bool,
+  ) -> anyhow::Result<()> {
+    let usable_size = self.pager.limits().usable_size;
+    let mut page = self.page_buffer(page_num)?;
+    let sizes = cells.iter().map(|c| cell_space(c)).collect::<Vec<_>>();
+    if sizes.iter().sum::<usize>() <= page.capacity(page_type, usable_size) {
+      page.rebuild(page_type, &cells, rightmost_pointer, usable_size);
+      return self.pager.write_page(page_num, page.data);
+    }
+
+    // leaf cells of a table stay in their run, the divider is the largest rowid of
+    // the run before it. Otherwise the cell between two runs moves up as their divider.
+    let moves_divider = page_type != PageType::TableLeaf;
+    let runs = partition(
+      &sizes,
+      page_capacity(page_type, usable_size),
+      moves_divider,
+      append,
+    )?;
+
+    // the last run stays on the page, unless it is the root
+    let parent = path.pop();
+    let mut pages = vec![];
+    for j in 0..runs.len() {
+      pages.push(match parent {
+        Some(_) if j == runs.len() - 1 => page_num,
+        _ => self.pager.allocate_page()?,
+      });
+    }
+    let dividers = self.write_runs(page_type, &cells, &runs, &pages, rightmost_pointer)?;
+
+    match parent {
+      Some((parent, idx)) => self.insert_cells(parent, idx, dividers, path, false),
+      None => {
+        let interior = match page_type {
+          PageType::TableLeaf | PageType::TableInterior => PageType::TableInterior,
+          PageType::IndexLeaf | PageType::IndexInterior => PageType::IndexInterior,
+        };
+        let last_page = *pages.last().context("split without pages")? as u32;
+        self.split(page_num, interior, dividers, Some(last_page), path, false)
+      }
+    }
+  }
+
+  /// Writes every run of `cells` to its page, returning the dividers of all the
+  /// pages but the last, which takes `rightmost_pointer`
+  fn write_runs(
+    &self,
+    page_type: PageType,
+    cells: &[Vec<u8>],
+    runs: &[Range<usize>],
+    pages: &[usize],
+    rightmost_pointer: Option<u32>,
+  ) -> anyhow::Result<Vec<Vec<u8>>> {
+    let usable_size = self.pager.limits().usable_size;
+    let mut dividers = vec![];
+    for (j, run) in runs.iter().enumerate() {
+      let last = j == runs.len() - 1;
+      let run_rightmost = match is_interior(page_type) {
+        true if last => rightmost_pointer,
+        // the child left of the divider
+        true => Some(read_be_double_at(&cells[run.end], 0)),
+        false => None,
+      };
+      let mut run_page = PageBuffer::new(pages[j], vec![0; self.pager.page_size()]);
+      run_page.rebuild(page_type, &cells[run.clone()], run_rightmost, usable_size);
+      self.pager.write_page(pages[j], run_page.data)?;
+
+      if last {
+        break;
+      }
+      let mut divider = (pages[j] as u32).to_be_bytes().to_vec();
+      match page_type {
+        PageType::TableLeaf => {
+          let cell = &cells[run.end - 1];
+          let (size_len, _) = read_varint_at(cell, 0);
+          write_varint(&mut divider, read_varint_at(cell, size_len as usize).1);
+        }
+        PageType::IndexLeaf => divider.extend_from_slice(&cells[run.end]),
+        PageType::TableInterior | PageType::IndexInterior => {
+          divider.extend_from_slice(&cells[run.end][4..])
+        }
+      }
+      dividers.push(divider);
+    }
+    Ok(dividers)
+  }
+
+  /// Deletes a row from a table b-tree, returns false when there is no such rowid
+  pub fn delete_row(&self, rowid: i64) -> anyhow::Result<bool> {
+    let Some((page_num, idx, path)) = self.find_row(rowid)? else {
+      return Ok(false);
+    };
+    self.remove_cell(page_num, idx, path)?;
+    Ok(true)
+  }
+
+  /// Replaces the record of a row, returns false when there is no such rowid.
+  /// A record of the same size is written over the old one, others take its place
+  /// on the page, which splits when it runs out of room.
+  pub fn update_row(&self, rowid: i64, record: &[u8]) -> anyhow::Result<bool> {
+    let Some((page_num, idx, path)) = self.find_row(rowid)? else {
+      return Ok(false);
+    };
+    let limits = self.pager.limits();
+    let mut page = self.page_buffer(page_num)?;
+    let old_cell = page.cell(idx, limits)?;
+    let old_size = old_cell.len();
+    if let Some(first) = overflow_page(PageType::TableLeaf, old_cell, limits) {
+      self.free_overflow(first)?;
+    }
+
+    let mut cell = vec![];
+    write_varint(&mut cell, record.len() as i64);
+    write_varint(&mut cell, rowid);
+    self.push_payload(&mut cell, PageType::TableLeaf, record)?;
+    if cell.len() == old_size {
+      let start = page.cell_offset(idx)?;
+      page.data[start..start + old_size].copy_from_slice(&cell);
+      self.pager.write_page(page_num, page.data)?;
+      return Ok(true);
+    }
+
+    page.remove_cell(idx, limits)?;
+    self.pager.write_page(page_num, page.data)?;
+    self.insert_cells(page_num, idx, vec![cell], path, false)?;
+    Ok(true)
+  }
+
+  /// Leaf page and cell of a row, with the path to the page
+  fn find_row(&self, rowid: i64) -> anyhow::Result<Option<(usize, usize, Path)>> {
+    let mut path = vec![];
+    let mut page_num = self.root;
+    loop {
+      let page = self.pager.read_page(page_num)?;
+      match page.header.page_type {
+        PageType::TableInterior => {
+          let idx = page
+            .cells
+            .partition_point(|c| matches!(c, Cell::TableInterior(c) if c.key < rowid));
+          let child = match page.cells.get(idx) {
+            Some(Cell::TableInterior(cell)) => cell.left_child_page,
+            _ => page
+              .header
+              .rightmost_pointer
+              .context("interior page without rightmost pointer")?,
+          };
+          path.push((page_num, idx));
+          page_num = child as usize;
+        }
+        PageType::TableLeaf => {
+          let idx = page
+            .cells
+            .partition_point(|c| matches!(c, Cell::TableLeaf(c) if c.row_id < rowid));
+          return Ok(
+            matches!(page.cells.get(idx), Some(Cell::TableLeaf(c)) if c.row_id == rowid)
+              .then_some((page_num, idx, path)),
+          );
+        }
+        _ => bail!("cannot look up a row in an index b-tree"),
+      }
+    }
+  }
+
+  /// Deletes `record` from an index b-tree, or the b-tree of a WITHOUT ROWID table.
+  /// `compare` orders two records. Returns false when the record isn't in the tree.
+  pub fn delete_record(
+    &self,
+    record: &[u8],
+    compare: impl Fn(&Cursor, &Cursor) -> anyhow::Result<Ordering>,
+  ) -> anyhow::Result<bool> {
+    let target = Cursor::new(record, None)?.with_encoding(self.pager.text_encoding());
+    let (page_num, idx, path) = match self.find_record(&target, &compare)? {
+      Some(found) => found,
+      None => return Ok(false),
+    };
+    let page = self.pager.read_page(page_num)?;
+    if page.header.page_type == PageType::IndexLeaf {
+      self.remove_cell(page_num, idx, path)?;
+      return Ok(true);
+    }
+
+    // the largest record of the left subtree takes the place of the interior cell
+    let limits = self.pager.limits();
+    let mut interior = self.page_buffer(page_num)?;
+    let cell = interior.cell(idx, limits)?.to_vec();
+    let left_child = read_be_double_at(&cell, 0);
+    let mut leaf_path = path.clone();
+    leaf_path.push((page_num, idx));
+    let (leaf_num, _) = self.rightmost_leaf(left_child as usize, leaf_path)?;
+
+    let leaf = self.pager.read_page(leaf_num)?;
+    let moved_payload = match leaf.cells.last() {
+      Some(Cell::IndexLeaf(cell)) => cell.payload.clone(),
+      _ => bail!("index page {leaf_num} has no cells to move up"),
+    };
+    let mut leaf = self.page_buffer(leaf_num)?;
+    let last = leaf.cell_count() - 1;
+    let mut replacement = left_child.to_be_bytes().to_vec();
+    replacement.extend_from_slice(leaf.cell(last, limits)?);
+    leaf.remove_cell(last, limits)?;
+    self.pager.write_page(leaf_num, leaf.data)?;
+
+    if let Some(first) = overflow_page(PageType::IndexInterior, &cell, limits) {
+      self.free_overflow(first)?;
+    }
+    interior.remove_cell(idx, limits)?;
+    self.pager.write_page(page_num, interior.data)?;
+    self.insert_cells(page_num, idx, vec![replacement], path, false)?;
+
+    // the interior page may have split, the leaf is found again through the moved record
+    let moved = Cursor::new(&moved_payload, None)?.with_encoding(self.pager.text_encoding());
+    let (page_num, idx, path) = self
+      .find_record(&moved, &compare)?
+      .context("moved index record is missing")?;
+    let page = self.pager.read_page(page_num)?;
+    let Some(Cell::IndexInterior(cell)) = page.cells.get(idx) else {
+      bail!("moved index record is not on an interior page");
+    };
+    let mut leaf_path = path;
+    leaf_path.push((page_num, idx));
+    let (leaf_num, leaf_path) = self.rightmost_leaf(cell.left_child_page as usize, leaf_path)?;
+    self.rebalance(leaf_num, leaf_path)?;
+    Ok(true)
+  }
+
+  /// Page and cell holding a record equal to `target`, with the path to the page
+  fn find_record(
+    &self,
+    target: &Cursor,
+    compare: &impl Fn(&Cursor, &Cursor) -> anyhow::Result<Ordering>,
+  ) -> anyhow::Result<Option<(usize, usize, Path)>> {
+    let mut path = vec![];
+    let mut page_num = self.root;
+    loop {
+      let page = self.pager.read_page(page_num)?;
+      let payload = |i: usize| match &page.cells[i] {
+        Cell::IndexInterior(cell) => Ok(&cell.payload),
+        Cell::IndexLeaf(cell) => Ok(&cell.payload),
+        _ => bail!("table cell in an index b-tree"),
+      };
+
+      // first cell that isn't before the target
+      let (mut low, mut high) = (0, page.cells.len());
+      while low < high {
+        let mid = (low + high) / 2;
+        if compare(
+          &Cursor::new(payload(mid)?, None)?.with_encoding(self.pager.text_encoding()),
+          target,
+        )?
+        .is_lt()
+        {
+          low = mid + 1;
+        } else {
+          high = mid;
+        }
+      }
+      if low < page.cells.len()
+        && compare(
+          &Cursor::new(payload(low)?, None)?.with_encoding(self.pager.text_encoding()),
+          target,
+        )?
+        .is_eq()
+      {
+        return Ok(Some((page_num, low, path)));
+      }
+
+      match page.header.page_type {
+        PageType::IndexInterior => {
+          let child = match page.cells.get(low) {
+            Some(Cell::IndexInterior(cell)) => cell.left_child_page,
+            _ => page
+              .header
+              .rightmost_pointer
+              .context("interior page without rightmost pointer")?,
+          };
+          path.push((page_num, low));
+          page_num = child as usize;
+        }
+        PageType::IndexLeaf => return Ok(None),
+        _ => bail!("cannot look up a record in a table b-tree"),
+      }
+    }
+  }
+
+  /// Leaf reached from `page_num` by following rightmost pointers
+  fn rightmost_leaf(&self, mut page_num: usize, mut path: Path) -> anyhow::Result<(usize, Path)> {
+    loop {
+      let page = self.pager.read_page(page_num)?;
+      if !is_interior(page.header.page_type) {
+        return Ok((page_num, path));
+      }
+      path.push((page_num, page.cells.len()));
+      page_num = page
+        .header
+        .rightmost_pointer
+        .context("interior page without rightmost pointer")? as usize;
+    }
+  }
+
+  /// Removes cell `idx` of a page, freeing its overflow pages, then rebalances the page
+  fn remove_cell(&self, page_num: usize, idx: usize, path: Path) -> anyhow::Result<()> {
+    let limits = self.pager.limits();
+    let mut page = self.page_buffer(page_num)?;
+    let page_type = page.header()?.page_type;
+    if let Some(first) = overflow_page(page_type, page.cell(idx, limits)?, limits) {
+      self.free_overflow(first)?;
+    }
+    page.remove_cell(idx, limits)?;
+    self.pager.write_page(page_num, page.data)?;
+    self.rebalance(page_num, path)
+  }
+
+  /// Frees every page of the tree, the root and overflow pages included
+  pub fn destroy(&self) -> anyhow::Result<()> {
+    self.free_subtree(self.root)
+  }
+
+  fn free_subtree(&self, page_num: usize) -> anyhow::Result<()> {
+    let limits = self.pager.limits();
+    let page = self.page_buffer(page_num)?;
+    let header = page.header()?;
+    for i in 0..page.cell_count() {
+      let cell = page.cell(i, limits)?;
+      if is_interior(header.page_type) {
+        self.free_subtree(read_be_double_at(cell, 0) as usize)?;
+      }
+      if let Some(first) = overflow_page(header.page_type, cell, limits) {
+        self.free_overflow(first)?;
+      }
+    }
+    if let Some(right) = header.rightmost_pointer {
+      self.free_subtree(right as usize)?;
+    }
+    self.pager.free_page(page_num)
+  }
+
+  fn free_overflow(&self, first_page: u32) -> anyhow::Result<()> {
+    let mut page_num = first_page as usize;
+    while page_num != 0 {
+      let next = read_be_double_at(&self.pager.read_raw_page(page_num)?, 0);
+      self.pager.free_page(page_num)?;
+      page_num = next as usize;
+    }
+    Ok(())
+  }
+
+  /// Merges an underfull page with a sibling when their cells fit on one page,
+  /// otherwise spreads them evenly over both. The merged away page goes on the
+  /// freelist and the parent, which lost a divider, is rebalanced in turn.
+  fn rebalance(&self, page_num: usize, mut path: Path) -> anyhow::Result<()> {
+    let limits = self.pager.limits();
+    let page = self.page_buffer(page_num)?;
+    let Some((parent_num, idx)) = path.pop() else {
+      return self.shrink_root();
+    };
+    if !page.is_underfull(limits.usable_size)? {
+      return Ok(());
+    }
+
+    let parent = self.page_buffer(parent_num)?;
+    let parent_header = parent.header()?;
+    let mut parent_cells = parent.cells(limits)?;
+    let n = parent_cells.len();
+    if n == 0 {
+      return Ok(());
+    }
+    let child = |j: usize| match j < n {
+      true => Ok(read_be_double_at(&parent_cells[j], 0) as usize),
+      false => parent_header
+        .rightmost_pointer
+        .map(|p| p as usize)
+        .context("interior page without rightmost pointer"),
+    };
+    // the page and its left sibling, the leftmost page goes with its right one
+    let left = idx.saturating_sub(1).min(n - 1);
+    let (left_num, right_num) = (child(left)?, child(left + 1)?);
+    let (left_page, right_page) = (self.page_buffer(left_num)?, self.page_buffer(right_num)?);
+    let left_header = left_page.header()?;
+    let page_type = left_header.page_type;
+
+    let mut cells = left_page.cells(limits)?;
+    let divider = &parent_cells[left][4..];
+    match page_type {
+      PageType::TableLeaf => {}
+      PageType::IndexLeaf => cells.push(divider.to_vec()),
+      PageType::TableInterior | PageType::IndexInterior => {
+        let left_rightmost = left_header
+          .rightmost_pointer
+          .context("interior page without rightmost pointer")?;
+        let mut cell = left_rightmost.to_be_bytes().to_vec();
+        cell.extend_from_slice(divider);
+        cells.push(cell);
+      }
+    }
+    cells.extend(right_page.cells(limits)?);
+
+    let capacity = page_capacity(page_type, limits.usable_size);
+    let sizes = cells.iter().map(|c| cell_space(c)).collect::<Vec<_>>();
+    let runs = match sizes.iter().sum::<usize>() <= capacity {
+      true => std::iter::once(0..cells.len()).collect(),
+      false => partition(&sizes, capacity, page_type != PageType::TableLeaf, false)?,
+    };
+    // the parent keeps pointing to the right page for the last run
+    let mut pages = vec![];
+    for j in 0..runs.len() {
+      pages.push(match j {
+        j if j == runs.len() - 1 => right_num,
+        0 => left_num,
+        _ => self.pager.allocate_page()?,
+      });
+    }
+    let rightmost = right_page.header()?.rightmost_pointer;
+    let dividers = self.write_runs(page_type, &cells, &runs, &pages, rightmost)?;
+    if runs.len() == 1 {
+      self.pager.free_page(left_num)?;
+    }
+
+    parent_cells.splice(left..left + 1, dividers);
+    let usable_size = limits.usable_size;
+    let fits = parent_cells.iter().map(|c| cell_space(c)).sum::<usize>()
+      <= parent.capacity(parent_header.page_type, usable_size);
+    if !fits {
+      return self.split(
+        parent_num,
+        parent_header.page_type,
+        parent_cells,
+        parent_header.rightmost_pointer,
+        path,
+        false,
+      );
+    }
+    let mut parent = parent;
+    parent.rebuild(
+      parent_header.page_type,
+      &parent_cells,
+      parent_header.rightmost_pointer,
+      usable_size,
+    );
+    self.pager.write_page(parent_num, parent.data)?;
+    self.rebalance(parent_num, path)
+  }
+
+  /// Replaces an interior root left without cells by its only child, when the
+  /// child's cells fit on the root
+  fn shrink_root(&self) -> anyhow::Result<()> {
+    let limits = self.pager.limits();
+    let mut root = self.page_buffer(self.root)?;
+    let header = root.header()?;
+    if !is_interior(header.page_type) || header.cell_count > 0 {
+      return Ok(());
+    }
+
+    let child_num = header
+      .rightmost_pointer
+      .context("interior page without rightmost pointer")? as usize;
+    let child = self.page_buffer(child_num)?;
+    let child_header = child.header()?;
+    let cells = child.cells(limits)?;
+    let size = cells.iter().map(|c| cell_space(c)).sum::<usize>();
+    if size > root.capacity(child_header.page_type, limits.usable_size) {
+      return Ok(());
+    }
+    root.rebuild(
+      child_header.page_type,
+      &cells,
+      child_header.rightmost_pointer,
+      limits.usable_size,
+    );
+    self.pager.write_page(self.root, root.data)?;
+    self.pager.free_page(child_num)
+  }
+
+  fn page_buffer(&self, page_num: usize) -> anyhow::Result<PageBuffer> {
+    Ok(PageBuffer::new(
+      page_num,
+      self.pager.read_raw_page(page_num)?,
+    ))
+  }
+}
+
+/// Splits cells too large for one page into runs that each fit on a page, given
+/// the size of every cell with its pointer. With `moves_divider` the cell between
+/// two runs belongs to neither. When appending, the cells before the new last one
+/// stay together and the new cell starts a page of its own.
+fn partition(
+  sizes: &[usize],
+  capacity: usize,
+  moves_divider: bool,
+  append: bool,
+) -> anyhow::Result<Vec<Range<usize>>> {
+  let skip = moves_divider as usize;
+  let n = sizes.len();
+  if append && n > 1 + skip && sizes[..n - 1 - skip].iter().sum::<usize>() <= capacity {
+    return Ok(vec![0..n - 1 - skip, n - 1..n]);
+  }
+
+  let mut runs = vec![];
+  split_runs(sizes, 0..n, capacity, skip, &mut runs)?;
+  Ok(runs)
+}
+
+/// Halves `range` by size until every part fits
+fn split_runs(
+  sizes: &[usize],
+  range: Range<usize>,
+  capacity: usize,
+  skip: usize,
+  runs: &mut Vec<Range<usize>>,
+) -> anyhow::Result<()> {
+  let total = sizes[range.clone()].iter().sum::<usize>();
+  if total <= capacity {
+    runs.push(range);
+    return Ok(());
+  }
+  if range.len() < 2 + skip {
+    bail!("cell of {total} bytes does not fit on a page");
+  }
+
+  let (mut left, mut best) = (0, (usize::MAX, range.start + 1));
+  for m in range.start + 1..range.end - skip {
+    left += sizes[m - 1];
+    let right = total - left - skip * sizes[m];
+    if left.max(right) < best.0 {
+      best = (left.max(right), m);
+    }
+  }
+  split_runs(sizes, range.start..best.1, capacity, skip, runs)?;
+  split_runs(sizes, best.1 + skip..range.end, capacity, skip, runs)
+}
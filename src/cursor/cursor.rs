@@ -1,7 +1,9 @@
 use std::borrow::Cow;
 
+use crate::dbheader::TextEncoding;
+
 use super::{
-  record::{RecordFieldType, RecordHeader},
+  record::{parse_record_header, RecordFieldType, RecordHeader},
   value::{OwnedValue, Value},
 };
 
@@ -9,9 +11,46 @@ use super::{
 pub struct Cursor {
   pub header: RecordHeader,
   pub payload: Vec<u8>,
+  /// rowid of the table b-tree cell the record was read from, index records have none
+  pub row_id: Option<i64>,
+  pub encoding: TextEncoding,
+}
+
+/// A column of a table row, read either from the record or from the cell's rowid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+  Record(usize),
+  RowId,
 }
 
 impl Cursor {
+  /// Cursor over the record stored in `payload`
+  pub fn new(payload: &[u8], row_id: Option<i64>) -> anyhow::Result<Self> {
+    Ok(Self {
+      header: parse_record_header(payload)?,
+      payload: payload.to_vec(),
+      row_id,
+      encoding: TextEncoding::Utf8,
+    })
+  }
+
+  /// Reads text values in `encoding` rather than UTF-8
+  pub fn with_encoding(mut self, encoding: TextEncoding) -> Self {
+    self.encoding = encoding;
+    self
+  }
+
+  pub fn get(&self, field: Field) -> Option<Value<'_>> {
+    match field {
+      Field::Record(n) => self.field(n),
+      Field::RowId => self.row_id.map(Value::Int),
+    }
+  }
+
+  pub fn owned_get(&self, field: Field) -> Option<OwnedValue> {
+    self.get(field).map(Into::into)
+  }
+
   pub fn field(&self, n: usize) -> Option<Value<'_>> {
     let record_field = self.header.fields.get(n)?;
 
@@ -28,10 +67,8 @@ impl Cursor {
         record_field.offset,
       ))),
       RecordFieldType::String(length) => {
-        let value =
-          std::str::from_utf8(&self.payload[record_field.offset..record_field.offset + length])
-            .expect("invalid utf8");
-        Some(Value::String(Cow::Borrowed(value)))
+        let value = &self.payload[record_field.offset..record_field.offset + length];
+        Some(Value::String(self.encoding.decode(value)))
       }
       RecordFieldType::Blob(length) => {
         let value = &self.payload[record_field.offset..record_field.offset + length];
@@ -53,7 +90,7 @@ impl Cursor {
 }
 
 fn read_i8_at(input: &[u8], offset: usize) -> i64 {
-  input[offset] as i64
+  input[offset] as i8 as i64
 }
 
 fn read_i16_at(input: &[u8], offset: usize) -> i64 {
@@ -61,7 +98,7 @@ fn read_i16_at(input: &[u8], offset: usize) -> i64 {
 }
 
 fn read_i24_at(input: &[u8], offset: usize) -> i64 {
-  (i32::from_be_bytes(input[offset..offset + 3].try_into().unwrap()) & 0x00FFFFFF) as i64
+  read_signed_at(input, offset, 3)
 }
 
 fn read_i32_at(input: &[u8], offset: usize) -> i64 {
@@ -69,7 +106,15 @@ fn read_i32_at(input: &[u8], offset: usize) -> i64 {
 }
 
 fn read_i48_at(input: &[u8], offset: usize) -> i64 {
-  i64::from_be_bytes(input[offset..offset + 6].try_into().unwrap()) & 0x0000FFFFFFFFFFFF
+  read_signed_at(input, offset, 6)
+}
+
+/// Big-endian two's complement integer of `size` bytes, sign-extended
+fn read_signed_at(input: &[u8], offset: usize, size: usize) -> i64 {
+  let mut bytes = [0; 8];
+  bytes[8 - size..].copy_from_slice(&input[offset..offset + size]);
+  let shift = 64 - 8 * size as u32;
+  (i64::from_be_bytes(bytes) << shift) >> shift
 }
 
 fn read_i64_at(input: &[u8], offset: usize) -> i64 {
@@ -85,8 +130,10 @@ fn test_read_functions() {
   let data = vec![0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
 
   // Test various read functions
-  assert_eq!(read_i8_at(&data, 0), 255);
+  assert_eq!(read_i8_at(&data, 0), -1);
   assert_eq!(read_i16_at(&data, 0), -1);
+  assert_eq!(read_i24_at(&data, 0), -1);
+  assert_eq!(read_i48_at(&data, 0), -1);
   assert_eq!(read_i32_at(&data, 0), -1);
   assert_eq!(read_i64_at(&data, 0), -1);
 
@@ -95,4 +142,6 @@ fn test_read_functions() {
   assert_eq!(read_i8_at(&data, 0), 0);
   assert_eq!(read_i16_at(&data, 5), 32);
   assert_eq!(read_i32_at(&data, 0), 42);
+  assert_eq!(read_i24_at(&data, 1), 42);
+  assert_eq!(read_i48_at(&data, 2), 0x2A_0000_2000);
 }
@@ -1,3 +1,5 @@
+pub mod btree;
+#[allow(clippy::module_inception)]
 pub mod cursor;
 pub mod record;
 pub mod scanner;
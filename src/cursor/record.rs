@@ -1,3 +1,7 @@
+use crate::dbheader::TextEncoding;
+
+use super::value::OwnedValue;
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum RecordFieldType {
   Null,
@@ -67,3 +71,56 @@ pub fn parse_record_header(mut cell_buff: &[u8]) -> anyhow::Result<RecordHeader>
   }
   Ok(RecordHeader { fields })
 }
+
+/// Encodes values as a record: the header of serial types, then the values in
+/// their smallest encoding, text in the database `encoding`
+pub fn serialize_record(values: &[OwnedValue], encoding: TextEncoding) -> Vec<u8> {
+  let mut serial_types = vec![];
+  let mut body = vec![];
+
+  for value in values {
+    let serial_type = match value {
+      OwnedValue::Null => 0,
+      OwnedValue::Int(0) | OwnedValue::Bool(false) => 8,
+      OwnedValue::Int(1) | OwnedValue::Bool(true) => 9,
+      OwnedValue::Int(i) => {
+        let (serial_type, size) = match *i {
+          -0x80..=0x7f => (1, 1),
+          -0x8000..=0x7fff => (2, 2),
+          -0x80_0000..=0x7f_ffff => (3, 3),
+          -0x8000_0000..=0x7fff_ffff => (4, 4),
+          -0x8000_0000_0000..=0x7fff_ffff_ffff => (5, 6),
+          _ => (6, 8),
+        };
+        body.extend_from_slice(&i.to_be_bytes()[8 - size..]);
+        serial_type
+      }
+      OwnedValue::Float(f) => {
+        body.extend_from_slice(&f.to_be_bytes());
+        7
+      }
+      OwnedValue::String(s) => {
+        let text = encoding.encode(s);
+        body.extend_from_slice(&text);
+        text.len() as i64 * 2 + 13
+      }
+      OwnedValue::Blob(b) => {
+        body.extend_from_slice(b);
+        b.len() as i64 * 2 + 12
+      }
+    };
+    crate::write_varint(&mut serial_types, serial_type);
+  }
+
+  // the header size counts the varint holding it
+  let mut header_size = serial_types.len() + 1;
+  while crate::varint_size(header_size as i64) + serial_types.len() != header_size {
+    header_size = crate::varint_size(header_size as i64) + serial_types.len();
+  }
+
+  let mut record = Vec::with_capacity(header_size + body.len());
+  crate::write_varint(&mut record, header_size as i64);
+  record.extend(serial_types);
+  record.extend(body);
+  record
+}
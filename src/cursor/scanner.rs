@@ -1,9 +1,14 @@
+use anyhow::Context;
+
 use crate::{
-  page::{page_utils::Cell, positioned_page::PositionedPage},
+  page::{
+    page_utils::{Cell, PageType},
+    positioned_page::PositionedPage,
+  },
   pager::Pager,
 };
 
-use super::{cursor::Cursor, record::parse_record_header};
+use super::cursor::Cursor;
 
 #[derive(Debug)]
 enum ScannerElem {
@@ -11,11 +16,17 @@ enum ScannerElem {
   Cursor(Cursor),
 }
 
+/// Walks a table or index b-tree in key order. Table trees yield their rows,
+/// index trees yield index records made of the indexed columns followed by the rowid.
 #[derive(Debug)]
 pub struct Scanner {
   pager: Pager,
   inital_page: usize,
   page_stack: Vec<PositionedPage>,
+  rowid_start: Option<i64>,
+  /// last rowid to hand out, the scan ends after it
+  rowid_end: Option<i64>,
+  exhausted: bool,
 }
 
 impl Scanner {
@@ -24,20 +35,165 @@ impl Scanner {
       pager,
       inital_page: page,
       page_stack: vec![],
+      rowid_start: None,
+      rowid_end: None,
+      exhausted: false,
+    }
+  }
+
+  /// Positions a table scanner on the first row whose rowid is >= `rowid`,
+  /// binary searching interior keys on the way down instead of walking every page
+  pub fn seek(&mut self, rowid: i64) -> anyhow::Result<()> {
+    self.page_stack.clear();
+    self.rowid_start = Some(rowid);
+    self.exhausted = false;
+
+    let mut page_num = self.inital_page;
+    loop {
+      let page = self.pager.read_page(page_num)?;
+      let mut positioned = PositionedPage::new(page.clone());
+
+      match page.header.page_type {
+        PageType::TableInterior => {
+          // interior keys are the largest rowid of their left child
+          let idx = page
+            .cells
+            .partition_point(|c| matches!(c, Cell::TableInterior(c) if c.key < rowid));
+          let child = match page.cells.get(idx) {
+            Some(Cell::TableInterior(cell)) => cell.left_child_page,
+            _ => page
+              .header
+              .rightmost_pointer
+              .context("interior page without rightmost pointer")?,
+          };
+
+          // resume after the chosen child once it is exhausted
+          positioned.cell = idx + 1;
+          self.page_stack.push(positioned);
+          page_num = child as usize;
+        }
+        PageType::TableLeaf => {
+          positioned.cell = page
+            .cells
+            .partition_point(|c| matches!(c, Cell::TableLeaf(c) if c.row_id < rowid));
+          self.page_stack.push(positioned);
+          return Ok(());
+        }
+        _ => anyhow::bail!("cannot seek a rowid in an index b-tree"),
+      }
+    }
+  }
+
+  /// Positions an index scanner on the first record for which `before` is false.
+  /// `before` must hold for a prefix of the index, in key order.
+  pub fn seek_index(
+    &mut self,
+    before: impl Fn(&Cursor) -> anyhow::Result<bool>,
+  ) -> anyhow::Result<()> {
+    self.page_stack.clear();
+    self.rowid_start = None;
+    self.exhausted = false;
+
+    let mut page_num = self.inital_page;
+    loop {
+      let page = self.pager.read_page(page_num)?;
+      let mut positioned = PositionedPage::new(page.clone());
+
+      // first cell that isn't before the key
+      let (mut low, mut high) = (0, page.cells.len());
+      while low < high {
+        let mid = (low + high) / 2;
+        let payload = match &page.cells[mid] {
+          Cell::IndexInterior(cell) => &cell.payload,
+          Cell::IndexLeaf(cell) => &cell.payload,
+          _ => anyhow::bail!("cannot seek a key in a table b-tree"),
+        };
+        if before(&Cursor::new(payload, None)?.with_encoding(self.pager.text_encoding()))? {
+          low = mid + 1;
+        } else {
+          high = mid;
+        }
+      }
+
+      match page.header.page_type {
+        PageType::IndexInterior => {
+          // the left child of that cell may still hold records that aren't before
+          // the key, the cell itself comes after them
+          let child = match page.cells.get(low) {
+            Some(Cell::IndexInterior(cell)) => {
+              positioned.cell = low;
+              positioned.left_child_visited = true;
+              cell.left_child_page
+            }
+            _ => {
+              positioned.cell = low + 1;
+              page
+                .header
+                .rightmost_pointer
+                .context("interior page without rightmost pointer")?
+            }
+          };
+          self.page_stack.push(positioned);
+          page_num = child as usize;
+        }
+        _ => {
+          positioned.cell = low;
+          self.page_stack.push(positioned);
+          return Ok(());
+        }
+      }
+    }
+  }
+
+  /// Ends the scan after the last row whose rowid is <= `rowid`
+  pub fn stop_after(&mut self, rowid: i64) {
+    self.rowid_end = Some(rowid);
+  }
+
+  /// Restarts the scan from its first row, or from the row `seek` positioned it on
+  pub fn rewind(&mut self) -> anyhow::Result<()> {
+    if let Some(rowid) = self.rowid_start {
+      return self.seek(rowid);
+    }
+    self.page_stack.clear();
+    self.exhausted = false;
+    Ok(())
+  }
+
+  /// Rough number of entries in the tree: the fan-out of the pages on its leftmost
+  /// path multiplied together, read without walking the whole tree
+  pub fn estimated_entries(&self) -> anyhow::Result<u64> {
+    let mut estimate = 1u64;
+    let mut page_num = self.inital_page;
+    loop {
+      let page = self.pager.read_page(page_num)?;
+      let child = match page.cells.first() {
+        Some(Cell::TableInterior(cell)) => cell.left_child_page,
+        Some(Cell::IndexInterior(cell)) => cell.left_child_page,
+        _ => return Ok(estimate.saturating_mul(page.cells.len() as u64)),
+      };
+      estimate = estimate.saturating_mul(page.cells.len() as u64 + 1);
+      page_num = child as usize;
     }
   }
 
+  /// Rowid range the scanner was restricted to by `seek` and `stop_after`
+  pub fn rowid_bounds(&self) -> (Option<i64>, Option<i64>) {
+    (self.rowid_start, self.rowid_end)
+  }
+
   pub fn next_record(&mut self) -> anyhow::Result<Option<Cursor>> {
     loop {
+      if self.exhausted {
+        return Ok(None);
+      }
+
       match self.next_elem() {
         Ok(Some(ScannerElem::Cursor(cursor))) => return Ok(Some(cursor)),
         Ok(Some(ScannerElem::Page(page_num))) => {
           // for next page
           let new_page = self.pager.read_page(page_num as usize)?.clone();
-          self.page_stack.push(PositionedPage {
-            page: new_page,
-            cell: 0,
-          });
+          self.page_stack.push(PositionedPage::new(new_page));
         }
         Ok(None) if self.page_stack.len() > 1 => {
           self.page_stack.pop();
@@ -49,30 +205,41 @@ impl Scanner {
   }
 
   fn next_elem(&mut self) -> anyhow::Result<Option<ScannerElem>> {
+    let rowid_end = self.rowid_end;
     let Some(page) = self.current_page()? else {
       return Ok(None);
     };
 
-    // for overflow maybe
+    // rightmost child of an interior page
     if let Some(page) = page.next_page() {
       return Ok(Some(ScannerElem::Page(page)));
     }
 
+    if let Some(page) = page.next_left_child() {
+      return Ok(Some(ScannerElem::Page(page)));
+    }
+
     let Some(cell) = page.next_cell() else {
       return Ok(None);
     };
 
-    match cell {
-      Cell::TableLeaf(cell) => {
-        let header = parse_record_header(&cell.payload)?;
-        Ok(Some(ScannerElem::Cursor(Cursor {
-          header,
-          payload: cell.payload.clone(),
-        })))
-      }
-      Cell::TableInterior(cell) => Ok(Some(ScannerElem::Page(cell.left_child_page))),
-      c => unimplemented!("Scannine {c:?} not implemented"),
-    }
+    let elem = match cell {
+      Cell::TableLeaf(cell) if rowid_end.is_some_and(|end| cell.row_id > end) => None,
+      Cell::TableLeaf(cell) => Some(ScannerElem::Cursor(
+        Cursor::new(&cell.payload, Some(cell.row_id))?.with_encoding(self.pager.text_encoding()),
+      )),
+      Cell::TableInterior(cell) => Some(ScannerElem::Page(cell.left_child_page)),
+      Cell::IndexLeaf(cell) => Some(ScannerElem::Cursor(
+        Cursor::new(&cell.payload, None)?.with_encoding(self.pager.text_encoding()),
+      )),
+      Cell::IndexInterior(cell) => Some(ScannerElem::Cursor(
+        Cursor::new(&cell.payload, None)?.with_encoding(self.pager.text_encoding()),
+      )),
+    };
+
+    // only a row past the end of the rowid range yields nothing here
+    self.exhausted = elem.is_none();
+    Ok(elem)
   }
 
   fn current_page(&mut self) -> anyhow::Result<Option<&mut PositionedPage>> {
@@ -82,7 +249,7 @@ impl Scanner {
         Err(e) => return Err(e),
       };
 
-      self.page_stack.push(PositionedPage { page, cell: 0 });
+      self.page_stack.push(PositionedPage::new(page));
     }
     Ok(self.page_stack.last_mut())
   }
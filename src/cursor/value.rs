@@ -30,17 +30,19 @@ impl<'p> Value<'p> {
   }
 }
 
-impl<'p> From<&Expr> for Value<'p> {
-  fn from(value: &Expr) -> Self {
+/// Literals only, other expressions need a row to evaluate against
+impl<'p> TryFrom<&Expr> for Value<'p> {
+  type Error = anyhow::Error;
+
+  fn try_from(value: &Expr) -> anyhow::Result<Self> {
     match value {
-      Expr::Column(_) => todo!(),
-      Expr::Alias(_) => todo!(),
-      Expr::Null => Value::Null,
-      Expr::Int(i) => Value::Int(*i),
-      Expr::Bool(v) => Value::Bool(*v),
-      Expr::Real(i) => Value::Float(*i),
-      Expr::Text(i) => Value::String(Cow::Owned(i.clone())),
-      Expr::Comparison(_expr, _ops, _expr1) => todo!(),
+      Expr::Null => Ok(Value::Null),
+      Expr::Int(i) => Ok(Value::Int(*i)),
+      Expr::Bool(v) => Ok(Value::Bool(*v)),
+      Expr::Real(i) => Ok(Value::Float(*i)),
+      Expr::Text(i) => Ok(Value::String(Cow::Owned(i.clone()))),
+      Expr::Blob(b) => Ok(Value::Blob(Cow::Owned(b.clone()))),
+      expr => anyhow::bail!("expected a literal, got {expr:?}"),
     }
   }
 }
@@ -51,13 +53,36 @@ impl<'p> From<bool> for Value<'p> {
   }
 }
 
-impl<'p> From<Value<'p>> for bool {
-  fn from(value: Value<'p>) -> Self {
-    match value {
-      Value::Int(v) => v == 1,
-      _ => false,
+/// Formats a real the way sqlite prints it: 15 significant digits, and always
+/// with a decimal point or an exponent so it reads back as a real
+pub fn format_real(value: f64) -> String {
+  if !value.is_finite() {
+    return match value {
+      v if v.is_nan() => "NaN".to_string(),
+      v if v > 0.0 => "Inf".to_string(),
+      _ => "-Inf".to_string(),
+    };
+  }
+
+  let scientific = format!("{value:.14e}");
+  let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+  let exponent = exponent.parse::<i32>().unwrap_or_default();
+
+  let trim = |digits: &str| -> String {
+    match digits.split_once('.') {
+      Some((int, frac)) => {
+        let frac = frac.trim_end_matches('0');
+        format!("{int}.{}", if frac.is_empty() { "0" } else { frac })
+      }
+      None => format!("{digits}.0"),
     }
+  };
+
+  if !(-4..15).contains(&exponent) {
+    let sign = if exponent < 0 { '-' } else { '+' };
+    return format!("{}e{sign}{:02}", trim(mantissa), exponent.abs());
   }
+  trim(&format!("{value:.*}", (14 - exponent).max(0) as usize))
 }
 
 #[derive(Debug, Clone, PartialEq)] // Added for testing
@@ -100,7 +125,31 @@ impl std::fmt::Display for OwnedValue {
       OwnedValue::String(s) => s.fmt(f),
       OwnedValue::Bool(b) => b.fmt(f),
       OwnedValue::Int(i) => i.fmt(f),
-      OwnedValue::Float(x) => x.fmt(f),
+      OwnedValue::Float(x) => f.write_str(&format_real(*x)),
+    }
+  }
+}
+
+impl OwnedValue {
+  pub fn into_value(self) -> Value<'static> {
+    match self {
+      OwnedValue::Null => Value::Null,
+      OwnedValue::String(s) => Value::String(Cow::Owned(Rc::unwrap_or_clone(s))),
+      OwnedValue::Blob(b) => Value::Blob(Cow::Owned(Rc::unwrap_or_clone(b))),
+      OwnedValue::Int(i) => Value::Int(i),
+      OwnedValue::Bool(b) => Value::Bool(b),
+      OwnedValue::Float(f) => Value::Float(f),
+    }
+  }
+
+  pub fn as_value(&self) -> Value<'_> {
+    match self {
+      OwnedValue::Null => Value::Null,
+      OwnedValue::String(s) => Value::String(Cow::Borrowed(s.as_str())),
+      OwnedValue::Blob(b) => Value::Blob(Cow::Borrowed(b.as_slice())),
+      OwnedValue::Int(i) => Value::Int(*i),
+      OwnedValue::Bool(b) => Value::Bool(*b),
+      OwnedValue::Float(f) => Value::Float(*f),
     }
   }
 }
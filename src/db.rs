@@ -1,30 +1,127 @@
-use std::{io::Read, path::Path};
+use std::{
+  io::{Read, Seek, Write},
+  path::Path,
+};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 
 use crate::{
-  cursor::{cursor::Cursor, scanner::Scanner},
-  dbheader::{self, DbHeader},
+  cursor::{
+    cursor::{Cursor, Field},
+    scanner::Scanner,
+  },
+  dbheader::{self, DbHeader, TextEncoding},
+  page::{
+    journal,
+    lock::{self, LockLevel},
+    page_buffer::PageBuffer,
+    page_utils::PageType,
+  },
   pager::Pager,
   sql::{self, ast},
 };
 
 pub struct Db {
   pub header: DbHeader,
+  /// rows of sqlite_schema, in storage order
+  pub schema: Vec<SchemaEntry>,
   pub tables_metadata: Vec<TableMetadata>,
+  pub indexes_metadata: Vec<IndexMetadata>,
   pager: Pager,
 }
 
+/// Settings of a database made by `Db::create`, fixed for its lifetime
+#[derive(Debug, Clone, Copy)]
+pub struct DbOptions {
+  pub page_size: u32,
+  pub text_encoding: TextEncoding,
+  /// bytes left unused at the end of every page
+  pub reserved_bytes: u8,
+}
+
+impl Default for DbOptions {
+  fn default() -> Self {
+    Self {
+      page_size: 4096,
+      text_encoding: TextEncoding::Utf8,
+      reserved_bytes: 0,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaKind {
+  Table,
+  Index,
+  View,
+  Trigger,
+}
+
+impl SchemaKind {
+  /// Type of the object as stored in sqlite_schema
+  pub fn as_str(self) -> &'static str {
+    match self {
+      SchemaKind::Table => "table",
+      SchemaKind::Index => "index",
+      SchemaKind::View => "view",
+      SchemaKind::Trigger => "trigger",
+    }
+  }
+}
+
+/// A row of the sqlite_schema table
+#[derive(Debug, Clone)]
+pub struct SchemaEntry {
+  /// rowid of the row in sqlite_schema
+  pub rowid: i64,
+  pub kind: SchemaKind,
+  pub name: String,
+  /// table the object belongs to, a table's own name for tables
+  pub table_name: String,
+  /// root b-tree page, 0 for views and triggers
+  pub root_page: usize,
+  /// create statement, missing for indexes created by UNIQUE and PRIMARY KEY constraints
+  pub sql: Option<String>,
+}
+
 #[derive(Debug, Clone)]
 pub struct TableMetadata {
   pub name: String,
   pub columns: Vec<ast::ColumnDef>,
+  pub constraints: Vec<ast::TableConstraint>,
+  /// WITHOUT ROWID tables are stored in an index b-tree keyed by their primary key
+  pub without_rowid: bool,
   pub first_page: usize,
 }
 
+#[derive(Debug, Clone)]
+pub struct IndexMetadata {
+  pub name: String,
+  pub table_name: String,
+  pub columns: Vec<ast::IndexedColumn>,
+  pub unique: bool,
+  pub root_page: usize,
+  /// condition of a partial index
+  pub where_clause: Option<ast::Expr>,
+}
+
 impl Db {
   pub fn from_file(filename: impl AsRef<Path>) -> anyhow::Result<Self> {
-    let mut file = std::fs::File::open(filename.as_ref()).context("open db file")?;
+    // read-only files can still be queried
+    let mut file = std::fs::OpenOptions::new()
+      .read(true)
+      .write(true)
+      .open(filename.as_ref())
+      .or_else(|_| std::fs::File::open(filename.as_ref()))
+      .context("open db file")?;
+
+    // a journal left by an interrupted commit is played back before anything is
+    // read, the shared lock is held until the schema is
+    lock::lock(&file, LockLevel::Shared)?;
+    let journal_path = journal::journal_path(filename.as_ref());
+    if journal::recover(&mut file, &journal_path).context("recover hot journal")? {
+      file.rewind().context("rewind db file")?;
+    }
 
     let mut header_buffer = [0; dbheader::HEADER_SIZE];
     file
@@ -34,62 +131,409 @@ impl Db {
     let header = dbheader::parse_header(&header_buffer).context("parse db header")?;
     // println!("{header:?}");
 
-    let pager = Pager::new(file, header.page_size as usize);
-    let tables_metadata = Self::collect_tables_metadata(pager.clone())?;
-    Ok(Self {
+    let pager = Pager::new(file, &header)
+      .with_journal(journal_path)
+      .with_wal(filename.as_ref().to_owned())
+      .context("read wal")?;
+    let mut db = Self {
       header,
       pager,
-      tables_metadata,
-    })
+      schema: vec![],
+      tables_metadata: vec![],
+      indexes_metadata: vec![],
+    };
+    db.reload_schema()?;
+    db.pager.lock_file(LockLevel::None)?;
+    Ok(db)
+  }
+
+  /// Creates the database file `filename` with an empty schema: page 1 holds the
+  /// header and the empty sqlite_schema table. An existing file must be empty.
+  pub fn create(filename: impl AsRef<Path>, options: &DbOptions) -> anyhow::Result<Self> {
+    let header = dbheader::new_header(
+      options.page_size,
+      options.reserved_bytes,
+      options.text_encoding,
+    )?;
+
+    let mut file = std::fs::OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create(true)
+      .truncate(false)
+      .open(filename.as_ref())
+      .context("create db file")?;
+    if file.metadata().context("read db file metadata")?.len() > 0 {
+      bail!("database already exists: {}", filename.as_ref().display());
+    }
+
+    let usable_size = (options.page_size - options.reserved_bytes as u32) as usize;
+    let mut page = PageBuffer::new(1, vec![0; options.page_size as usize]);
+    page.data[..dbheader::HEADER_SIZE].copy_from_slice(&header);
+    page.rebuild(PageType::TableLeaf, &[], None, usable_size);
+    file.write_all(&page.data).context("write db file")?;
+    file.sync_all().context("sync db file")?;
+    drop(file);
+
+    Self::from_file(filename)
+  }
+
+  /// Reads the header and sqlite_schema again, after statements changed the schema
+  pub fn reload_schema(&mut self) -> anyhow::Result<()> {
+    // pages committed to the write-ahead log replace those of the file, the
+    // header included
+    let page = self.pager.read_raw_page(1)?;
+    self.header =
+      dbheader::parse_header(&page[..dbheader::HEADER_SIZE]).context("parse db header")?;
+    let schema = Self::collect_schema(self.pager.clone())?;
+
+    let tables_metadata = schema
+      .iter()
+      .filter(|e| e.kind == SchemaKind::Table)
+      .map(TableMetadata::from_entry)
+      .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let indexes_metadata = schema
+      .iter()
+      .filter(|e| e.kind == SchemaKind::Index)
+      .map(|e| IndexMetadata::from_entry(e, &tables_metadata))
+      .collect::<anyhow::Result<Vec<_>>>()?;
+
+    self.schema = schema;
+    self.tables_metadata = tables_metadata;
+    self.indexes_metadata = indexes_metadata;
+    Ok(())
+  }
+
+  /// Reloads the schema when the schema cookie changed since it was read, which
+  /// schema changes and the rollback of one both do
+  pub fn refresh_schema(&mut self) -> anyhow::Result<()> {
+    if self.schema_changed()? {
+      self.reload_schema()?;
+    }
+    Ok(())
+  }
+
+  /// Another connection to the same file, with the schema read again
+  pub fn reloaded(&self) -> anyhow::Result<Db> {
+    let mut db = Db {
+      header: self.header,
+      schema: vec![],
+      tables_metadata: vec![],
+      indexes_metadata: vec![],
+      pager: self.pager.clone(),
+    };
+    db.reload_schema()?;
+    Ok(db)
+  }
+
+  /// Whether the schema cookie changed since the schema was read
+  pub fn schema_changed(&self) -> anyhow::Result<bool> {
+    let cookie = self.pager.header_field(dbheader::SCHEMA_COOKIE_OFFSET)?;
+    Ok(cookie != self.header.schema_cookie)
+  }
+
+  pub fn pager(&self) -> Pager {
+    self.pager.clone()
   }
 
   pub fn scanner(&self, page: usize) -> Scanner {
     Scanner::new(self.pager.clone(), page)
   }
 
-  fn collect_tables_metadata(pager: Pager) -> anyhow::Result<Vec<TableMetadata>> {
-    let mut metadata = vec![];
+  pub fn table(&self, name: &str) -> Option<&TableMetadata> {
+    self
+      .tables_metadata
+      .iter()
+      .find(|t| t.name.eq_ignore_ascii_case(name))
+  }
+
+  pub fn index(&self, name: &str) -> Option<&IndexMetadata> {
+    self
+      .indexes_metadata
+      .iter()
+      .find(|i| i.name.eq_ignore_ascii_case(name))
+  }
+
+  /// Indexes built on `table`
+  pub fn table_indexes<'a>(&'a self, table: &'a str) -> impl Iterator<Item = &'a IndexMetadata> {
+    self
+      .indexes_metadata
+      .iter()
+      .filter(move |i| i.table_name.eq_ignore_ascii_case(table))
+  }
+
+  pub fn views(&self) -> impl Iterator<Item = &SchemaEntry> {
+    self.schema.iter().filter(|e| e.kind == SchemaKind::View)
+  }
+
+  pub fn triggers(&self) -> impl Iterator<Item = &SchemaEntry> {
+    self.schema.iter().filter(|e| e.kind == SchemaKind::Trigger)
+  }
+
+  fn collect_schema(pager: Pager) -> anyhow::Result<Vec<SchemaEntry>> {
+    let mut schema = vec![];
     let mut scanner = Scanner::new(pager, 1);
 
     while let Some(record) = scanner.next_record()? {
-      if let Some(m) = TableMetadata::from_cursor(&record)? {
-        metadata.push(m);
-      }
+      schema.push(SchemaEntry::from_cursor(&record)?);
     }
-    Ok(metadata)
+    Ok(schema)
   }
 }
 
-impl TableMetadata {
-  fn from_cursor(cursor: &Cursor) -> anyhow::Result<Option<Self>> {
-    let type_value = cursor
-      .field(0)
-      .context("missing type field")
-      .context("invalid type field")?;
-    if type_value.as_str() != Some("table") {
-      return Ok(None);
-    }
+impl SchemaEntry {
+  fn from_cursor(cursor: &Cursor) -> anyhow::Result<Self> {
+    let kind = match cursor.field(0).context("missing type field")?.as_str() {
+      Some("table") => SchemaKind::Table,
+      Some("index") => SchemaKind::Index,
+      Some("view") => SchemaKind::View,
+      Some("trigger") => SchemaKind::Trigger,
+      kind => anyhow::bail!("invalid schema object type: {kind:?}"),
+    };
 
-    let create_stmt = cursor
+    let text_field = |n: usize, name: &str| -> anyhow::Result<String> {
+      cursor
+        .field(n)
+        .with_context(|| format!("missing {name} field"))?
+        .as_str()
+        .map(str::to_owned)
+        .with_context(|| format!("{name} should be a string"))
+    };
+
+    let root_page = cursor
+      .field(3)
+      .context("missing root page field")?
+      .as_int()
+      .unwrap_or_default() as usize;
+
+    let sql = cursor
       .field(4)
-      .context("missing create statement")
-      .context("invalid create statement")?
+      .context("missing create statement")?
       .as_str()
-      .context("table create statement should be a string")?
-      .to_owned();
+      .map(str::to_owned);
 
-    let create = sql::parser::parse_create_statement(&create_stmt)?;
+    Ok(Self {
+      rowid: cursor.row_id.context("missing schema rowid")?,
+      kind,
+      name: text_field(1, "name")?,
+      table_name: text_field(2, "table name")?,
+      root_page,
+      sql,
+    })
+  }
+}
 
-    let first_page = cursor
-      .field(3)
-      .context("missing table first page")?
-      .as_int()
-      .context("table first page should be an integer")? as usize;
+impl TableMetadata {
+  /// Index of the INTEGER PRIMARY KEY column, which stores the rowid instead of a record value
+  pub fn rowid_alias(&self) -> Option<usize> {
+    if self.without_rowid {
+      return None;
+    }
+
+    let is_integer = |n: &usize| {
+      self.columns[*n]
+        .type_name
+        .as_deref()
+        .is_some_and(|t| t.eq_ignore_ascii_case("integer"))
+    };
+
+    if let Some(n) = self.columns.iter().position(|c| c.is_primary_key()) {
+      // `INTEGER PRIMARY KEY DESC` on the column itself is not an alias, a quirk sqlite keeps
+      let descending = self.columns[n].constraints.iter().any(|c| {
+        matches!(
+          c,
+          ast::ColumnConstraint::PrimaryKey {
+            descending: true,
+            ..
+          }
+        )
+      });
+      return Some(n).filter(|n| !descending && is_integer(n));
+    }
+
+    match self.primary_key_columns().as_slice() {
+      [n] => Some(*n).filter(is_integer),
+      _ => None,
+    }
+  }
+
+  /// Indexes of the primary key columns, in key order
+  pub fn primary_key_columns(&self) -> Vec<usize> {
+    if let Some(n) = self.columns.iter().position(|c| c.is_primary_key()) {
+      return vec![n];
+    }
+
+    self
+      .constraints
+      .iter()
+      .find_map(|c| match c {
+        ast::TableConstraint::PrimaryKey(columns) => Some(
+          columns
+            .iter()
+            .filter_map(|ic| {
+              self
+                .columns
+                .iter()
+                .position(|c| c.name.eq_ignore_ascii_case(&ic.name))
+            })
+            .collect(),
+        ),
+        _ => None,
+      })
+      .unwrap_or_default()
+  }
+
+  /// Resolves a column name, including the `rowid`, `oid` and `_rowid_` aliases
+  /// when the table has no column of that name
+  pub fn field(&self, name: &str) -> Option<Field> {
+    match self
+      .columns
+      .iter()
+      .position(|c| c.name.eq_ignore_ascii_case(name))
+    {
+      Some(n) if Some(n) == self.rowid_alias() => Some(Field::RowId),
+      Some(n) => Some(Field::Record(self.record_position(n))),
+      None if self.without_rowid => None,
+      None
+        if matches!(
+          name.to_ascii_lowercase().as_str(),
+          "rowid" | "oid" | "_rowid_"
+        ) =>
+      {
+        Some(Field::RowId)
+      }
+      None => None,
+    }
+  }
+
+  /// Affinity of every value of the stored record, by record position
+  pub fn record_affinities(&self) -> Vec<ast::Type> {
+    let mut affinities = vec![ast::Type::Blob; self.columns.len()];
+    for (n, column) in self.columns.iter().enumerate() {
+      affinities[self.record_position(n)] = column.col_type.clone();
+    }
+    affinities
+  }
 
-    Ok(Some(TableMetadata {
+  /// Position of column `n` in the stored record. WITHOUT ROWID tables store their
+  /// primary key columns first, then the remaining columns in declaration order.
+  /// Virtual generated columns aren't stored, their positions follow the stored values.
+  pub fn record_position(&self, n: usize) -> usize {
+    let stored = |c: &usize| !self.columns[*c].is_virtual();
+    if !stored(&n) {
+      return self.stored_columns() + (0..n).filter(|c| !stored(c)).count();
+    }
+    if !self.without_rowid {
+      return (0..n).filter(stored).count();
+    }
+
+    let pk = self.primary_key_columns();
+    match pk.iter().position(|&c| c == n) {
+      Some(p) => p,
+      None => pk.len() + (0..n).filter(|c| !pk.contains(c) && stored(c)).count(),
+    }
+  }
+
+  /// Number of values a record of the table holds
+  pub fn stored_columns(&self) -> usize {
+    self.columns.iter().filter(|c| !c.is_virtual()).count()
+  }
+
+  /// Column sets of the PRIMARY KEY and UNIQUE constraints, in the order
+  /// sqlite numbers the `sqlite_autoindex_<table>_<n>` indexes backing them
+  pub fn autoindex_columns(&self) -> Vec<Vec<ast::IndexedColumn>> {
+    let indexed = |name: &str| ast::IndexedColumn {
+      name: name.to_owned(),
+      descending: false,
+      expression: None,
+    };
+    // the rowid and WITHOUT ROWID b-trees already are the primary key index
+    let pk_indexed = self.rowid_alias().is_none() && !self.without_rowid;
+
+    let mut autoindexes = vec![];
+    for column in &self.columns {
+      for constraint in &column.constraints {
+        match constraint {
+          ast::ColumnConstraint::PrimaryKey { .. } if pk_indexed => {
+            autoindexes.push(vec![indexed(&column.name)])
+          }
+          ast::ColumnConstraint::Unique => autoindexes.push(vec![indexed(&column.name)]),
+          _ => {}
+        }
+      }
+    }
+
+    for constraint in &self.constraints {
+      match constraint {
+        ast::TableConstraint::PrimaryKey(columns) if pk_indexed => {
+          autoindexes.push(columns.clone())
+        }
+        ast::TableConstraint::Unique(columns) => autoindexes.push(columns.clone()),
+        _ => {}
+      }
+    }
+    autoindexes
+  }
+
+  fn from_entry(entry: &SchemaEntry) -> anyhow::Result<Self> {
+    let create_stmt = entry
+      .sql
+      .as_deref()
+      .with_context(|| format!("missing create statement of table {}", entry.name))?;
+
+    let create = sql::parser::parse_create_statement(create_stmt)?;
+
+    Ok(TableMetadata {
       name: create.name,
       columns: create.columns,
-      first_page,
-    }))
+      constraints: create.constraints,
+      without_rowid: create.without_rowid,
+      first_page: entry.root_page,
+    })
+  }
+}
+
+impl IndexMetadata {
+  fn from_entry(entry: &SchemaEntry, tables: &[TableMetadata]) -> anyhow::Result<Self> {
+    if let Some(create_stmt) = &entry.sql {
+      let create = sql::parser::parse_create_index_statement(create_stmt)?;
+      return Ok(Self {
+        name: create.name,
+        table_name: create.table,
+        columns: create.columns,
+        unique: create.unique,
+        root_page: entry.root_page,
+        where_clause: create.where_clause,
+      });
+    }
+
+    // indexes backing constraints have no sql, their columns come from the table definition
+    let table = tables
+      .iter()
+      .find(|t| t.name.eq_ignore_ascii_case(&entry.table_name))
+      .with_context(|| format!("missing table of index {}", entry.name))?;
+
+    let n = entry
+      .name
+      .rsplit('_')
+      .next()
+      .and_then(|n| n.parse::<usize>().ok())
+      .with_context(|| format!("invalid automatic index name: {}", entry.name))?;
+
+    let columns = table
+      .autoindex_columns()
+      .into_iter()
+      .nth(n.saturating_sub(1))
+      .with_context(|| format!("no constraint backs index {}", entry.name))?;
+
+    Ok(Self {
+      name: entry.name.clone(),
+      table_name: table.name.clone(),
+      columns,
+      unique: true,
+      root_page: entry.root_page,
+      where_clause: None,
+    })
   }
 }
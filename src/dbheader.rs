@@ -1,3 +1,7 @@
+use std::borrow::Cow;
+
+use anyhow::bail;
+
 use crate::{read_be_byte_at, read_be_double_at, read_be_word_at};
 
 #[derive(Debug, Copy, Clone)]
@@ -5,27 +9,58 @@ pub struct DbHeader {
   pub page_size: u32,
   pub file_format_w: u8,
   pub file_format_r: u8,
+  /// Bytes reserved at the end of each page for extensions
+  pub reserved_bytes: u8,
   pub max_embedded_payload: u8,
   pub min_embedded_payload: u8,
   pub leaf_payload_fraction: u8,
   pub file_change_counter: u32,
   pub db_size: u32,
   pub schema_cookie: u32,
+  pub text_encoding: TextEncoding,
   pub sq_version: u32,
 }
 
+/// Encoding of every text value stored in the database, set when it is created.
+/// Text is compared by code point whatever the encoding, while sqlite orders
+/// UTF-16 text by its bytes, so indexes on non-ASCII UTF-16 text may disagree.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum TextEncoding {
+  #[default]
+  Utf8 = 1,
+  Utf16le = 2,
+  Utf16be = 3,
+}
+
 const HEADER_PREFIX: &[u8] = b"SQLite format 3\0";
 const HEADER_PAGE_SIZE_OFFSET: usize = 16;
-const FILE_FORMAT_W_OFFSET: usize = 18;
-const FILE_FORMAT_R_OFFSET: usize = 19;
+pub const FILE_FORMAT_W_OFFSET: usize = 18;
+pub const FILE_FORMAT_R_OFFSET: usize = 19;
+const RESERVED_BYTES_OFFSET: usize = 20;
 const MAX_EMBEDDED_PAYLOAD_OFFSET: usize = 21;
 const MIN_EMBEDDED_PAYLOAD_OFFSET: usize = 22;
 const LEAF_PAYLOAD_FRACTION_OFFSET: usize = 23;
-const FILE_CHANGE_COUNTER_OFFSET: usize = 24;
-const DB_SIZE_OFFSET: usize = 28;
-const SCHEMA_COOKIE_OFFSET: usize = 32;
+pub const FILE_CHANGE_COUNTER_OFFSET: usize = 24;
+pub const DB_SIZE_OFFSET: usize = 28;
+pub const FREELIST_TRUNK_OFFSET: usize = 32;
+pub const FREELIST_COUNT_OFFSET: usize = 36;
+/// Bumped by every schema change so that readers know to reload the schema
+pub const SCHEMA_COOKIE_OFFSET: usize = 40;
+pub const VERSION_VALID_FOR_OFFSET: usize = 92;
+const SCHEMA_FORMAT_OFFSET: usize = 44;
+const TEXT_ENCODING_OFFSET: usize = 56;
 const SQ_VERSION_OFFSET: usize = 96;
+/// Schema format 4 understands descending indexes and boolean values
+const SCHEMA_FORMAT: u32 = 4;
+/// Version written in the header of created databases
+const SQLITE_VERSION_NUMBER: u32 = 3051002;
+/// File format versions: 1 for a rollback journal, 2 for a write-ahead log
+pub const LEGACY_FILE_FORMAT: u8 = 1;
+pub const WAL_FILE_FORMAT: u8 = 2;
 pub const PAGE_MAX_SIZE: u32 = 65536;
+pub const PAGE_MIN_SIZE: u32 = 512;
+/// Pages need this many usable bytes once the reserved region is taken off
+pub const MIN_USABLE_SIZE: u32 = 480;
 pub const HEADER_SIZE: usize = 100;
 
 /// The header starts with the magic string 'SQLite format 3\0'
@@ -44,24 +79,115 @@ pub fn parse_header(buffer: &[u8]) -> anyhow::Result<DbHeader> {
   };
   let file_format_w = read_be_byte_at(buffer, FILE_FORMAT_W_OFFSET);
   let file_format_r = read_be_byte_at(buffer, FILE_FORMAT_R_OFFSET);
+  let reserved_bytes = read_be_byte_at(buffer, RESERVED_BYTES_OFFSET);
   let max_embedded_payload = read_be_byte_at(buffer, MAX_EMBEDDED_PAYLOAD_OFFSET);
   let min_embedded_payload = read_be_byte_at(buffer, MIN_EMBEDDED_PAYLOAD_OFFSET);
   let leaf_payload_fraction = read_be_byte_at(buffer, LEAF_PAYLOAD_FRACTION_OFFSET);
   let file_change_counter = read_be_double_at(buffer, FILE_CHANGE_COUNTER_OFFSET);
   let db_size = read_be_double_at(buffer, DB_SIZE_OFFSET);
   let schema_cookie = read_be_double_at(buffer, SCHEMA_COOKIE_OFFSET);
+  // a database without any table yet may not have chosen its encoding
+  let text_encoding = match read_be_double_at(buffer, TEXT_ENCODING_OFFSET) {
+    0 | 1 => TextEncoding::Utf8,
+    2 => TextEncoding::Utf16le,
+    3 => TextEncoding::Utf16be,
+    n => bail!("invalid text encoding: {n}"),
+  };
   let sq_version = read_be_double_at(buffer, SQ_VERSION_OFFSET);
 
   Ok(DbHeader {
     page_size,
     file_format_r,
     file_format_w,
+    reserved_bytes,
     max_embedded_payload,
     min_embedded_payload,
     leaf_payload_fraction,
     file_change_counter,
     db_size,
     schema_cookie,
+    text_encoding,
     sq_version,
   })
 }
+
+/// Header of a database holding nothing but an empty sqlite_schema table
+pub fn new_header(
+  page_size: u32,
+  reserved_bytes: u8,
+  text_encoding: TextEncoding,
+) -> anyhow::Result<[u8; HEADER_SIZE]> {
+  if !page_size.is_power_of_two() || !(PAGE_MIN_SIZE..=PAGE_MAX_SIZE).contains(&page_size) {
+    bail!(
+      "page size must be a power of 2 between {PAGE_MIN_SIZE} and {PAGE_MAX_SIZE}: {page_size}"
+    );
+  }
+  if page_size - (reserved_bytes as u32) < MIN_USABLE_SIZE {
+    bail!(
+      "{reserved_bytes} reserved bytes leave less than {MIN_USABLE_SIZE} usable bytes per page"
+    );
+  }
+
+  let mut buffer = [0; HEADER_SIZE];
+  let mut write_u32 = |offset: usize, value: u32| {
+    buffer[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+  };
+  write_u32(FILE_CHANGE_COUNTER_OFFSET, 1);
+  write_u32(DB_SIZE_OFFSET, 1);
+  write_u32(SCHEMA_FORMAT_OFFSET, SCHEMA_FORMAT);
+  write_u32(TEXT_ENCODING_OFFSET, text_encoding as u32);
+  write_u32(VERSION_VALID_FOR_OFFSET, 1);
+  write_u32(SQ_VERSION_OFFSET, SQLITE_VERSION_NUMBER);
+
+  buffer[..HEADER_PREFIX.len()].copy_from_slice(HEADER_PREFIX);
+  // 65536 doesn't fit the 2 bytes and is stored as 1
+  let raw_page_size = if page_size == PAGE_MAX_SIZE {
+    1
+  } else {
+    page_size as u16
+  };
+  buffer[HEADER_PAGE_SIZE_OFFSET..HEADER_PAGE_SIZE_OFFSET + 2]
+    .copy_from_slice(&raw_page_size.to_be_bytes());
+  buffer[FILE_FORMAT_W_OFFSET] = LEGACY_FILE_FORMAT;
+  buffer[FILE_FORMAT_R_OFFSET] = LEGACY_FILE_FORMAT;
+  buffer[RESERVED_BYTES_OFFSET] = reserved_bytes;
+  // the payload fractions are fixed by the file format
+  buffer[MAX_EMBEDDED_PAYLOAD_OFFSET] = 64;
+  buffer[MIN_EMBEDDED_PAYLOAD_OFFSET] = 32;
+  buffer[LEAF_PAYLOAD_FRACTION_OFFSET] = 32;
+  Ok(buffer)
+}
+
+impl DbHeader {
+  /// Page size minus the reserved region at the end of every page
+  pub fn usable_size(&self) -> usize {
+    self.page_size as usize - self.reserved_bytes as usize
+  }
+}
+
+impl TextEncoding {
+  /// Text of a record value stored in this encoding
+  pub fn decode(self, bytes: &[u8]) -> Cow<'_, str> {
+    let units = |from_bytes: fn([u8; 2]) -> u16| {
+      let units = bytes
+        .chunks_exact(2)
+        .map(|c| from_bytes([c[0], c[1]]))
+        .collect::<Vec<_>>();
+      Cow::Owned(String::from_utf16_lossy(&units))
+    };
+    match self {
+      TextEncoding::Utf8 => String::from_utf8_lossy(bytes),
+      TextEncoding::Utf16le => units(u16::from_le_bytes),
+      TextEncoding::Utf16be => units(u16::from_be_bytes),
+    }
+  }
+
+  /// Bytes of `text` as stored in a record
+  pub fn encode(self, text: &str) -> Cow<'_, [u8]> {
+    match self {
+      TextEncoding::Utf8 => Cow::Borrowed(text.as_bytes()),
+      TextEncoding::Utf16le => Cow::Owned(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+      TextEncoding::Utf16be => Cow::Owned(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+    }
+  }
+}
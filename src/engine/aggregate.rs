@@ -0,0 +1,476 @@
+use std::{
+  collections::{HashMap, HashSet},
+  hash::{Hash, Hasher},
+  rc::Rc,
+};
+
+use anyhow::bail;
+
+use crate::{
+  cursor::value::{OwnedValue, Value},
+  sql::ast::Expr,
+};
+
+use super::{
+  eval::{compare_values, numeric_affinity, to_numeric, to_text, Evaluator},
+  operator::Operator,
+  sort::{value_size, SortKey, SortedRow, SortedRows, Sorter},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFunction {
+  CountStar,
+  Count,
+  Sum,
+  Total,
+  Avg,
+  Min,
+  Max,
+  GroupConcat,
+  /// a column outside of any aggregate, takes its value from the last row of the group
+  AnyValue,
+}
+
+impl AggregateFunction {
+  pub fn from_name(name: &str, arity: usize) -> anyhow::Result<Self> {
+    Ok(match (name.to_lowercase().as_str(), arity) {
+      ("count", 0) => Self::CountStar,
+      ("count", 1) => Self::Count,
+      ("sum", 1) => Self::Sum,
+      ("total", 1) => Self::Total,
+      ("avg", 1) => Self::Avg,
+      ("min", 1) => Self::Min,
+      ("max", 1) => Self::Max,
+      ("group_concat", 1 | 2) => Self::GroupConcat,
+      (name, _) => bail!("wrong number of arguments to function {name}()"),
+    })
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::CountStar | Self::Count => "count",
+      Self::Sum => "sum",
+      Self::Total => "total",
+      Self::Avg => "avg",
+      Self::Min => "min",
+      Self::Max => "max",
+      Self::GroupConcat => "group_concat",
+      Self::AnyValue => "any_value",
+    }
+  }
+}
+
+/// An aggregate function applied to expressions over the rows of the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateCall {
+  pub function: AggregateFunction,
+  pub distinct: bool,
+  pub args: Vec<Expr>,
+}
+
+/// Groups the rows of `source` by the `group_by` expressions, outputting one row of
+/// `[group values.., aggregate values..]` per group. Groups are kept in a hash table
+/// until it holds `memory_budget` bytes; rows of groups that don't fit are sorted by
+/// their group values and aggregated as they come out of the sort.
+#[derive(Debug)]
+pub struct Aggregate {
+  pub source: Box<Operator>,
+  pub group_by: Vec<Expr>,
+  pub calls: Vec<AggregateCall>,
+  evaluator: Evaluator,
+  pub memory_budget: usize,
+  /// None until the source is consumed
+  output: Option<Output>,
+  row_buffer: Vec<OwnedValue>,
+}
+
+#[derive(Debug)]
+struct Output {
+  hashed: std::vec::IntoIter<Group>,
+  sorted: Option<SortedRows>,
+  /// first row of the next sorted group
+  pending: Option<SortedRow>,
+  sorted_groups: usize,
+}
+
+#[derive(Debug)]
+struct Group {
+  keys: Vec<OwnedValue>,
+  accumulators: Vec<Accumulator>,
+}
+
+impl Aggregate {
+  pub fn new(
+    source: Operator,
+    group_by: Vec<Expr>,
+    calls: Vec<AggregateCall>,
+    evaluator: Evaluator,
+    memory_budget: usize,
+  ) -> Self {
+    Self {
+      source: Box::new(source),
+      group_by,
+      calls,
+      evaluator,
+      memory_budget,
+      output: None,
+      row_buffer: vec![],
+    }
+  }
+
+  /// Number of groups aggregated through the sort fallback
+  pub fn sorted_groups(&self) -> usize {
+    self.output.as_ref().map_or(0, |o| o.sorted_groups)
+  }
+
+  pub fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    if self.output.is_none() {
+      self.output = Some(self.consume_source()?);
+    }
+    let output = self.output.as_mut().unwrap();
+
+    let group = match output.hashed.next() {
+      Some(group) => group,
+      None => {
+        let Some(first) = output.pending.take() else {
+          return Ok(None);
+        };
+        let sorted = output.sorted.as_mut().unwrap();
+        let mut group = Group::new(first.keys, &self.calls);
+        group.update(&self.calls, &self.evaluator, &first.row)?;
+        while let Some(next) = sorted.next_row()? {
+          if !same_group(&next.keys, &group.keys) {
+            output.pending = Some(next);
+            break;
+          }
+          group.update(&self.calls, &self.evaluator, &next.row)?;
+        }
+        output.sorted_groups += 1;
+        group
+      }
+    };
+
+    self.row_buffer = group.keys;
+    for accumulator in group.accumulators {
+      self.row_buffer.push(accumulator.finish()?);
+    }
+    Ok(Some(&self.row_buffer))
+  }
+
+  fn consume_source(&mut self) -> anyhow::Result<Output> {
+    let mut groups: Vec<Group> = vec![];
+    let mut positions = HashMap::new();
+    let mut used_bytes = 0;
+    let mut overflow: Option<Sorter> = None;
+
+    while let Some(row) = self.source.next_row()? {
+      let keys = self
+        .group_by
+        .iter()
+        .map(|e| Ok(self.evaluator.eval(e, row)?.into()))
+        .collect::<anyhow::Result<Vec<OwnedValue>>>()?;
+
+      let key = GroupKey(keys);
+      let position = match positions.get(&key) {
+        Some(&position) => position,
+        None if used_bytes < self.memory_budget => {
+          used_bytes += key.0.iter().map(value_size).sum::<usize>()
+            + self.calls.len() * std::mem::size_of::<Accumulator>();
+          groups.push(Group::new(key.0.clone(), &self.calls));
+          positions.insert(key, groups.len() - 1);
+          groups.len() - 1
+        }
+        None => {
+          // the hash table is full, new groups go through the sort
+          let sorter = overflow.get_or_insert_with(|| {
+            let keys = (0..self.group_by.len())
+              .map(|i| SortKey {
+                expr: Expr::Alias(i as i64),
+                descending: false,
+                nulls_first: true,
+              })
+              .collect::<Rc<[_]>>();
+            Sorter::new(keys, self.memory_budget)
+          });
+          sorter.push(key.0, row.to_vec())?;
+          continue;
+        }
+      };
+      groups[position].update(&self.calls, &self.evaluator, row)?;
+    }
+
+    // without GROUP BY an empty input still makes one row
+    if self.group_by.is_empty() && groups.is_empty() {
+      groups.push(Group::new(vec![], &self.calls));
+    }
+
+    let mut sorted = overflow.map(Sorter::finish).transpose()?;
+    let pending = match sorted.as_mut() {
+      Some(sorted) => sorted.next_row()?,
+      None => None,
+    };
+    Ok(Output {
+      hashed: groups.into_iter(),
+      sorted,
+      pending,
+      sorted_groups: 0,
+    })
+  }
+}
+
+impl Group {
+  fn new(keys: Vec<OwnedValue>, calls: &[AggregateCall]) -> Self {
+    Self {
+      keys,
+      accumulators: calls.iter().map(Accumulator::new).collect(),
+    }
+  }
+
+  fn update(
+    &mut self,
+    calls: &[AggregateCall],
+    evaluator: &Evaluator,
+    row: &[OwnedValue],
+  ) -> anyhow::Result<()> {
+    // with a single min() or max(), bare columns take their values from the row
+    // holding the minimum or maximum
+    let extremes = calls
+      .iter()
+      .filter(|c| matches!(c.function, AggregateFunction::Min | AggregateFunction::Max))
+      .count();
+    let mut picked = true;
+    let (bare, aggregates): (Vec<_>, Vec<_>) = self
+      .accumulators
+      .iter_mut()
+      .zip(calls)
+      .partition(|(_, c)| c.function == AggregateFunction::AnyValue);
+
+    for (accumulator, call) in aggregates {
+      let changed = accumulator.update(&eval_args(call, evaluator, row)?)?;
+      if extremes == 1
+        && matches!(
+          call.function,
+          AggregateFunction::Min | AggregateFunction::Max
+        )
+      {
+        picked = changed;
+      }
+    }
+    if picked {
+      for (accumulator, call) in bare {
+        accumulator.update(&eval_args(call, evaluator, row)?)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+fn eval_args<'a>(
+  call: &'a AggregateCall,
+  evaluator: &Evaluator,
+  row: &'a [OwnedValue],
+) -> anyhow::Result<Vec<Value<'a>>> {
+  call.args.iter().map(|e| evaluator.eval(e, row)).collect()
+}
+
+#[derive(Debug)]
+struct Accumulator {
+  state: State,
+  /// values already seen by a DISTINCT aggregate
+  seen: Option<HashSet<GroupKey>>,
+}
+
+#[derive(Debug)]
+enum State {
+  Count(i64),
+  Sum {
+    function: AggregateFunction,
+    int: i64,
+    real: f64,
+    is_real: bool,
+    overflow: bool,
+    count: usize,
+  },
+  Min(Option<OwnedValue>),
+  Max(Option<OwnedValue>),
+  GroupConcat(Option<String>),
+  AnyValue(OwnedValue),
+}
+
+impl Accumulator {
+  fn new(call: &AggregateCall) -> Self {
+    let state = match call.function {
+      AggregateFunction::CountStar | AggregateFunction::Count => State::Count(0),
+      function @ (AggregateFunction::Sum | AggregateFunction::Total | AggregateFunction::Avg) => {
+        State::Sum {
+          function,
+          int: 0,
+          real: 0.0,
+          is_real: false,
+          overflow: false,
+          count: 0,
+        }
+      }
+      AggregateFunction::Min => State::Min(None),
+      AggregateFunction::Max => State::Max(None),
+      AggregateFunction::GroupConcat => State::GroupConcat(None),
+      AggregateFunction::AnyValue => State::AnyValue(OwnedValue::Null),
+    };
+    Self {
+      state,
+      seen: call.distinct.then(HashSet::new),
+    }
+  }
+
+  /// Adds a row's argument values, returns whether the state changed
+  fn update(&mut self, args: &[Value]) -> anyhow::Result<bool> {
+    let value = args.first().cloned().unwrap_or(Value::Null);
+    if let State::AnyValue(v) = &mut self.state {
+      *v = value.into();
+      return Ok(true);
+    }
+    // count(*) counts rows, everything else skips NULLs
+    if value == Value::Null && !args.is_empty() {
+      return Ok(false);
+    }
+    if let Some(seen) = &mut self.seen {
+      if !seen.insert(GroupKey(vec![value.clone().into()])) {
+        return Ok(false);
+      }
+    }
+
+    match &mut self.state {
+      State::Count(n) => *n += 1,
+      State::Sum {
+        int,
+        real,
+        is_real,
+        overflow,
+        count,
+        ..
+      } => {
+        *count += 1;
+        match numeric_affinity(value) {
+          Value::Int(i) => {
+            *real += i as f64;
+            match int.checked_add(i) {
+              Some(sum) => *int = sum,
+              None => *overflow = true,
+            }
+          }
+          Value::Bool(b) => {
+            *real += b as i64 as f64;
+            *int += b as i64;
+          }
+          value => {
+            *is_real = true;
+            *real += match to_numeric(&value) {
+              Value::Int(i) => i as f64,
+              Value::Float(f) => f,
+              _ => 0.0,
+            };
+          }
+        }
+      }
+      State::Min(min) => {
+        let lower = min
+          .as_ref()
+          .is_none_or(|m| compare_values(&value, &m.as_value()).is_lt());
+        if lower {
+          *min = Some(value.into());
+        }
+        return Ok(lower);
+      }
+      State::Max(max) => {
+        let higher = max
+          .as_ref()
+          .is_none_or(|m| compare_values(&value, &m.as_value()).is_gt());
+        if higher {
+          *max = Some(value.into());
+        }
+        return Ok(higher);
+      }
+      State::GroupConcat(text) => {
+        let value = to_text(&value).unwrap_or_default();
+        match text {
+          None => *text = Some(value),
+          Some(text) => {
+            let separator = match args.get(1) {
+              Some(separator) => to_text(separator).unwrap_or_default(),
+              None => ",".to_string(),
+            };
+            text.push_str(&separator);
+            text.push_str(&value);
+          }
+        }
+      }
+      State::AnyValue(_) => unreachable!(),
+    }
+    Ok(true)
+  }
+
+  fn finish(self) -> anyhow::Result<OwnedValue> {
+    Ok(match self.state {
+      State::Count(n) => OwnedValue::Int(n),
+      State::Sum {
+        function,
+        int,
+        real,
+        is_real,
+        overflow,
+        count,
+      } => match function {
+        AggregateFunction::Total => OwnedValue::Float(real),
+        _ if count == 0 => OwnedValue::Null,
+        AggregateFunction::Avg => OwnedValue::Float(real / count as f64),
+        _ if is_real => OwnedValue::Float(real),
+        _ if overflow => bail!("integer overflow"),
+        _ => OwnedValue::Int(int),
+      },
+      State::Min(v) | State::Max(v) => v.unwrap_or(OwnedValue::Null),
+      State::GroupConcat(text) => text.map_or(OwnedValue::Null, |t| OwnedValue::String(Rc::new(t))),
+      State::AnyValue(v) => v,
+    })
+  }
+}
+
+/// Group values compared the way sqlite groups them: NULLs are equal to each
+/// other and integers equal to reals of the same value. Also used to find
+/// duplicate rows.
+#[derive(Debug, Clone)]
+pub struct GroupKey(pub Vec<OwnedValue>);
+
+impl PartialEq for GroupKey {
+  fn eq(&self, other: &Self) -> bool {
+    same_group(&self.0, &other.0)
+  }
+}
+
+impl Eq for GroupKey {}
+
+impl Hash for GroupKey {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    for value in &self.0 {
+      match value {
+        OwnedValue::Null => 0u8.hash(state),
+        OwnedValue::Int(i) => (1u8, *i).hash(state),
+        OwnedValue::Bool(b) => (1u8, *b as i64).hash(state),
+        // integral reals hash like the integer they are equal to
+        OwnedValue::Float(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+          (1u8, *f as i64).hash(state)
+        }
+        OwnedValue::Float(f) => (2u8, f.to_bits()).hash(state),
+        OwnedValue::String(s) => (3u8, s.as_str()).hash(state),
+        OwnedValue::Blob(b) => (4u8, b.as_slice()).hash(state),
+      }
+    }
+  }
+}
+
+fn same_group(a: &[OwnedValue], b: &[OwnedValue]) -> bool {
+  a.len() == b.len()
+    && a
+      .iter()
+      .zip(b)
+      .all(|(a, b)| compare_values(&a.as_value(), &b.as_value()).is_eq())
+}
@@ -0,0 +1,515 @@
+use std::{borrow::Cow, cmp::Ordering};
+
+use anyhow::{bail, Context};
+
+use crate::{
+  cursor::{
+    cursor::{Cursor, Field},
+    value::{format_real, OwnedValue, Value},
+  },
+  sql::{
+    ast::{Expr, FunctionCall, Type, UnaryOp},
+    tokenizer::Ops,
+  },
+};
+
+use super::write::storage_value;
+
+/// A row expressions are evaluated against. `Expr::Alias(n)` reads value `n`,
+/// `Expr::RowId` the rowid.
+pub trait Row {
+  fn value(&self, n: usize) -> anyhow::Result<Value<'_>>;
+  fn rowid(&self) -> anyhow::Result<Value<'_>>;
+}
+
+impl Row for Cursor {
+  fn value(&self, n: usize) -> anyhow::Result<Value<'_>> {
+    // records written before an `ALTER TABLE ADD COLUMN` lack the new columns
+    Ok(self.field(n).unwrap_or(Value::Null))
+  }
+
+  fn rowid(&self) -> anyhow::Result<Value<'_>> {
+    self.get(Field::RowId).context("missing rowid")
+  }
+}
+
+/// Reads the values of a table's columns from its records: applies REAL affinity
+/// to the whole numbers sqlite stores as integers, reads the values records
+/// written before an `ALTER TABLE ADD COLUMN` lack as the column defaults, and
+/// computes the virtual generated columns, whose positions follow the stored values
+#[derive(Debug, Clone, Default)]
+pub struct TableValues {
+  /// number of values a record stores
+  stored: usize,
+  /// default of every stored value, NULL when its column has none
+  defaults: Vec<OwnedValue>,
+  /// expression and affinity of every virtual column, its columns resolved to
+  /// record positions
+  generated: Vec<(Expr, Type)>,
+  evaluator: Evaluator,
+}
+
+impl TableValues {
+  pub fn new(stored: usize, generated: Vec<(Expr, Type)>, evaluator: Evaluator) -> Self {
+    Self {
+      stored,
+      defaults: vec![],
+      generated,
+      evaluator,
+    }
+  }
+
+  /// Reads the stored values records lack as `defaults`
+  pub fn with_defaults(mut self, defaults: Vec<OwnedValue>) -> Self {
+    self.defaults = defaults;
+    self
+  }
+
+  pub fn row<'a>(&'a self, record: &'a Cursor) -> TableRow<'a> {
+    TableRow {
+      record,
+      values: self,
+    }
+  }
+
+  /// Value of `field` of a table record
+  pub fn get(&self, record: &Cursor, field: Field) -> anyhow::Result<OwnedValue> {
+    match field {
+      Field::Record(n) if self.generated(n).is_some() => Ok(self.row(record).value(n)?.into()),
+      Field::Record(n) => Ok(self.stored_value(record, n).into()),
+      Field::RowId => record.owned_get(field).context("missing rowid"),
+    }
+  }
+
+  fn generated(&self, n: usize) -> Option<&(Expr, Type)> {
+    self.generated.get(n.checked_sub(self.stored)?)
+  }
+
+  /// Stored value `n` of `record`, as a real when its column has REAL affinity
+  fn stored_value<'a>(&'a self, record: &'a Cursor, n: usize) -> Value<'a> {
+    let value = record.field(n).unwrap_or_else(|| {
+      self
+        .defaults
+        .get(n)
+        .map_or(Value::Null, OwnedValue::as_value)
+    });
+    match value {
+      Value::Int(i) if self.evaluator.affinities.get(n) == Some(&Type::Real) => {
+        Value::Float(i as f64)
+      }
+      value => value,
+    }
+  }
+}
+
+/// A table record along with the values of its virtual columns
+pub struct TableRow<'a> {
+  record: &'a Cursor,
+  values: &'a TableValues,
+}
+
+impl Row for TableRow<'_> {
+  fn value(&self, n: usize) -> anyhow::Result<Value<'_>> {
+    let Some((expr, affinity)) = self.values.generated(n) else {
+      return Ok(self.values.stored_value(self.record, n));
+    };
+    let value = self.values.evaluator.eval(expr, self)?;
+    Ok(storage_value(value.into(), affinity).into_value())
+  }
+
+  fn rowid(&self) -> anyhow::Result<Value<'_>> {
+    self.record.rowid()
+  }
+}
+
+impl Row for [OwnedValue] {
+  fn value(&self, n: usize) -> anyhow::Result<Value<'_>> {
+    self
+      .get(n)
+      .map(OwnedValue::as_value)
+      .with_context(|| format!("missing row value {n}"))
+  }
+
+  fn rowid(&self) -> anyhow::Result<Value<'_>> {
+    bail!("row has no rowid")
+  }
+}
+
+/// Evaluates expressions whose columns were resolved to `Expr::Alias` and `Expr::RowId`,
+/// following sqlite's NULL logic, affinity and numeric coercion rules
+#[derive(Debug, Clone, Default)]
+pub struct Evaluator {
+  /// affinity of every row value, by position
+  affinities: Vec<Type>,
+}
+
+impl Evaluator {
+  pub fn new(affinities: Vec<Type>) -> Self {
+    Self { affinities }
+  }
+
+  /// Number of row values the evaluator knows the affinity of
+  pub fn width(&self) -> usize {
+    self.affinities.len()
+  }
+
+  /// Evaluator for rows made of the values after the first `n`
+  pub fn skip(&self, n: usize) -> Evaluator {
+    Evaluator::new(self.affinities[n..].to_vec())
+  }
+
+  /// Whether `expr` holds for `row`, NULL counts as false
+  pub fn is_true<R: Row + ?Sized>(&self, expr: &Expr, row: &R) -> anyhow::Result<bool> {
+    Ok(truth(&self.eval(expr, row)?) == Some(true))
+  }
+
+  pub fn eval<'a, R: Row + ?Sized>(&self, expr: &'a Expr, row: &'a R) -> anyhow::Result<Value<'a>> {
+    match expr {
+      Expr::Alias(n) => row.value(*n as usize),
+      Expr::RowId => row.rowid(),
+      Expr::Column(name) => bail!("unresolved column: {name}"),
+      Expr::QualifiedColumn(table, name) => bail!("unresolved column: {table}.{name}"),
+      Expr::Null => Ok(Value::Null),
+      Expr::Int(i) => Ok(Value::Int(*i)),
+      Expr::Real(r) => Ok(Value::Float(*r)),
+      Expr::Bool(b) => Ok(Value::Int(*b as i64)),
+      Expr::Text(s) => Ok(Value::String(Cow::Borrowed(s))),
+      Expr::Blob(b) => Ok(Value::Blob(Cow::Borrowed(b))),
+      Expr::Unary(UnaryOp::Not, e) => Ok(from_truth(truth(&self.eval(e, row)?).map(|b| !b))),
+      Expr::Unary(UnaryOp::Neg, e) => Ok(negate(to_numeric(&self.eval(e, row)?))),
+      Expr::Comparison(l, Ops::And, r) => {
+        // false wins over NULL, so the right side can be skipped
+        let l = truth(&self.eval(l, row)?);
+        if l == Some(false) {
+          return Ok(Value::Int(0));
+        }
+        Ok(match (l, truth(&self.eval(r, row)?)) {
+          (_, Some(false)) => Value::Int(0),
+          (Some(true), Some(true)) => Value::Int(1),
+          _ => Value::Null,
+        })
+      }
+      Expr::Comparison(l, Ops::Or, r) => {
+        let l = truth(&self.eval(l, row)?);
+        if l == Some(true) {
+          return Ok(Value::Int(1));
+        }
+        Ok(match (l, truth(&self.eval(r, row)?)) {
+          (_, Some(true)) => Value::Int(1),
+          (Some(false), Some(false)) => Value::Int(0),
+          _ => Value::Null,
+        })
+      }
+      Expr::Comparison(l, op, r) if op.is_comparison() => {
+        let (lv, rv) = self.apply_affinities(l, self.eval(l, row)?, r, self.eval(r, row)?);
+        Ok(compare(*op, &lv, &rv))
+      }
+      Expr::InList {
+        expr,
+        list,
+        negated,
+      } => {
+        // true on a match, otherwise NULL if either side has a NULL
+        let value = self.eval(expr, row)?;
+        let mut found = Some(false);
+        for item in list {
+          let (lv, rv) = self.apply_affinities(expr, value.clone(), item, self.eval(item, row)?);
+          match truth(&compare(Ops::Eq, &lv, &rv)) {
+            Some(true) => {
+              found = Some(true);
+              break;
+            }
+            Some(false) => {}
+            None => found = None,
+          }
+        }
+        Ok(from_truth(found.map(|found| found != *negated)))
+      }
+      Expr::Function(f) if is_aggregate(f) => bail!("misuse of aggregate function {}()", f.name),
+      Expr::Function(f) => bail!("no such function: {}", f.name),
+      Expr::Comparison(l, op, r) => {
+        let (l, r) = (self.eval(l, row)?, self.eval(r, row)?);
+        Ok(match op {
+          Ops::Concat => concat(&l, &r),
+          op => arithmetic(*op, to_numeric(&l), to_numeric(&r)),
+        })
+      }
+    }
+  }
+
+  /// Affinity of an expression: columns have their declared one, anything else none.
+  /// `Type::Blob` is none as well.
+  fn affinity(&self, expr: &Expr) -> Option<Type> {
+    match expr {
+      Expr::Alias(n) => self
+        .affinities
+        .get(*n as usize)
+        .filter(|t| **t != Type::Blob)
+        .cloned(),
+      Expr::RowId => Some(Type::Integer),
+      _ => None,
+    }
+  }
+
+  /// Converts comparison operands as sqlite does before comparing them: numeric affinity
+  /// wins over text and none, text affinity over none
+  fn apply_affinities<'a>(
+    &self,
+    l: &Expr,
+    lv: Value<'a>,
+    r: &Expr,
+    rv: Value<'a>,
+  ) -> (Value<'a>, Value<'a>) {
+    let (la, ra) = (self.affinity(l), self.affinity(r));
+    let is_numeric = |a: &Option<Type>| {
+      matches!(
+        a,
+        Some(Type::Integer | Type::Real | Type::Numeric | Type::Bool)
+      )
+    };
+    let is_text_or_none = |a: &Option<Type>| matches!(a, None | Some(Type::Text | Type::Blob));
+
+    if is_numeric(&la) && is_text_or_none(&ra) {
+      (lv, numeric_affinity(rv))
+    } else if is_numeric(&ra) && is_text_or_none(&la) {
+      (numeric_affinity(lv), rv)
+    } else if la == Some(Type::Text) && ra.is_none() {
+      (lv, text_affinity(rv))
+    } else if ra == Some(Type::Text) && la.is_none() {
+      (text_affinity(lv), rv)
+    } else {
+      (lv, rv)
+    }
+  }
+}
+
+/// Whether `f` is one of the aggregate functions
+pub fn is_aggregate(f: &FunctionCall) -> bool {
+  let arity = f.args.len();
+  match f.name.to_lowercase().as_str() {
+    "count" => arity <= 1,
+    "sum" | "total" | "avg" => arity == 1,
+    // min and max with several arguments are scalar functions
+    "min" | "max" => arity == 1,
+    "group_concat" => arity == 1 || arity == 2,
+    _ => false,
+  }
+}
+
+/// Three-valued truth of a value, None for NULL
+pub fn truth(value: &Value) -> Option<bool> {
+  match to_numeric(value) {
+    Value::Int(i) => Some(i != 0),
+    Value::Float(f) => Some(f != 0.0),
+    _ => None,
+  }
+}
+
+fn from_truth(truth: Option<bool>) -> Value<'static> {
+  match truth {
+    Some(b) => Value::Int(b as i64),
+    None => Value::Null,
+  }
+}
+
+/// Orders values as sqlite does: NULL, then numbers, then text, then blobs
+pub fn compare_values(l: &Value, r: &Value) -> Ordering {
+  fn class(v: &Value) -> u8 {
+    match v {
+      Value::Null => 0,
+      Value::Int(_) | Value::Float(_) | Value::Bool(_) => 1,
+      Value::String(_) => 2,
+      Value::Blob(_) => 3,
+    }
+  }
+
+  match (normalize(l), normalize(r)) {
+    (Value::Int(a), Value::Int(b)) => a.cmp(&b),
+    (Value::Int(a), Value::Float(b)) => (a as f64).total_cmp(&b),
+    (Value::Float(a), Value::Int(b)) => a.total_cmp(&(b as f64)),
+    (Value::Float(a), Value::Float(b)) => a.total_cmp(&b),
+    (Value::String(a), Value::String(b)) => a.cmp(&b),
+    (Value::Blob(a), Value::Blob(b)) => a.cmp(&b),
+    (l, r) => class(&l).cmp(&class(&r)),
+  }
+}
+
+fn compare<'a>(op: Ops, l: &Value, r: &Value) -> Value<'a> {
+  let null = |v: &Value| matches!(v, Value::Null);
+  match op {
+    Ops::Is => Value::Int((null(l) == null(r) && compare_values(l, r).is_eq()) as i64),
+    Ops::IsNot => Value::Int(!(null(l) == null(r) && compare_values(l, r).is_eq()) as i64),
+    _ if null(l) || null(r) => Value::Null,
+    op => {
+      let ordering = compare_values(l, r);
+      let result = match op {
+        Ops::Eq => ordering.is_eq(),
+        Ops::Ne => ordering.is_ne(),
+        Ops::Lt => ordering.is_lt(),
+        Ops::Gt => ordering.is_gt(),
+        Ops::Loe => ordering.is_le(),
+        _ => ordering.is_ge(),
+      };
+      Value::Int(result as i64)
+    }
+  }
+}
+
+fn arithmetic<'a>(op: Ops, l: Value, r: Value) -> Value<'a> {
+  match (l, r) {
+    (Value::Null, _) | (_, Value::Null) => Value::Null,
+    (l, r) if op == Ops::Mod => {
+      let as_int = |v: &Value| match v {
+        Value::Float(f) => *f as i64,
+        Value::Int(i) => *i,
+        _ => 0,
+      };
+      let real = matches!(l, Value::Float(_)) || matches!(r, Value::Float(_));
+      match as_int(&l).checked_rem(as_int(&r)) {
+        None if as_int(&r) == 0 => Value::Null,
+        None => Value::Int(0),
+        Some(m) if real => Value::Float(m as f64),
+        Some(m) => Value::Int(m),
+      }
+    }
+    (Value::Int(a), Value::Int(b)) => {
+      let result = match op {
+        Ops::Add => a.checked_add(b),
+        Ops::Sub => a.checked_sub(b),
+        Ops::Mul => a.checked_mul(b),
+        _ if b == 0 => return Value::Null,
+        _ => a.checked_div(b),
+      };
+      // integer overflow switches to reals
+      match result {
+        Some(i) => Value::Int(i),
+        None => arithmetic(op, Value::Float(a as f64), Value::Float(b as f64)),
+      }
+    }
+    (l, r) => {
+      let as_real = |v: &Value| match v {
+        Value::Float(f) => *f,
+        Value::Int(i) => *i as f64,
+        _ => 0.0,
+      };
+      let (a, b) = (as_real(&l), as_real(&r));
+      match op {
+        Ops::Add => Value::Float(a + b),
+        Ops::Sub => Value::Float(a - b),
+        Ops::Mul => Value::Float(a * b),
+        _ if b == 0.0 => Value::Null,
+        _ => Value::Float(a / b),
+      }
+    }
+  }
+}
+
+fn negate<'a>(value: Value) -> Value<'a> {
+  match value {
+    Value::Int(i) => i
+      .checked_neg()
+      .map_or(Value::Float(-(i as f64)), Value::Int),
+    Value::Float(f) => Value::Float(-f),
+    _ => Value::Null,
+  }
+}
+
+fn concat<'a>(l: &Value, r: &Value) -> Value<'a> {
+  match (to_text(l), to_text(r)) {
+    (Some(l), Some(r)) => Value::String(Cow::Owned(l + &r)),
+    _ => Value::Null,
+  }
+}
+
+fn normalize<'a>(value: &Value<'a>) -> Value<'a> {
+  match value {
+    Value::Bool(b) => Value::Int(*b as i64),
+    v => v.clone(),
+  }
+}
+
+/// Text of a value, None for NULL
+pub fn to_text(value: &Value) -> Option<String> {
+  match value {
+    Value::Null => None,
+    Value::String(s) => Some(s.to_string()),
+    Value::Blob(b) => Some(String::from_utf8_lossy(b).into_owned()),
+    Value::Int(i) => Some(i.to_string()),
+    Value::Bool(b) => Some((*b as i64).to_string()),
+    Value::Float(f) => Some(format_real(*f)),
+  }
+}
+
+/// Numeric value used by arithmetic: text and blobs are read up to the
+/// first character that can't be part of a number, NULL stays NULL
+pub fn to_numeric<'a>(value: &Value) -> Value<'a> {
+  match value {
+    Value::Null => Value::Null,
+    Value::Int(i) => Value::Int(*i),
+    Value::Bool(b) => Value::Int(*b as i64),
+    Value::Float(f) => Value::Float(*f),
+    Value::String(s) => numeric_prefix(s),
+    Value::Blob(b) => numeric_prefix(&String::from_utf8_lossy(b)),
+  }
+}
+
+fn numeric_prefix<'a>(text: &str) -> Value<'a> {
+  let text = text.trim_start();
+  let bytes = text.as_bytes();
+  let digits = |mut i: usize| {
+    while bytes.get(i).is_some_and(u8::is_ascii_digit) {
+      i += 1;
+    }
+    i
+  };
+
+  let mut end = digits(usize::from(matches!(bytes.first(), Some(b'+' | b'-'))));
+  let mut real = false;
+  if bytes.get(end) == Some(&b'.') {
+    end = digits(end + 1);
+    real = true;
+  }
+  if matches!(bytes.get(end), Some(b'e' | b'E')) {
+    let exponent = end + 1 + usize::from(matches!(bytes.get(end + 1), Some(b'+' | b'-')));
+    if bytes.get(exponent).is_some_and(u8::is_ascii_digit) {
+      end = digits(exponent);
+      real = true;
+    }
+  }
+
+  let prefix = &text[..end];
+  if !prefix.bytes().any(|b| b.is_ascii_digit()) {
+    return Value::Int(0);
+  }
+  match prefix.parse::<i64>() {
+    Ok(i) if !real => Value::Int(i),
+    _ => Value::Float(prefix.parse().unwrap_or(0.0)),
+  }
+}
+
+/// Numeric affinity: text that is a well-formed number becomes that number
+pub fn numeric_affinity(value: Value) -> Value {
+  let Value::String(s) = &value else {
+    return value;
+  };
+
+  let trimmed = s.trim();
+  if let Ok(i) = trimmed.parse::<i64>() {
+    return Value::Int(i);
+  }
+  match trimmed.parse::<f64>() {
+    // rust also accepts names like `inf` and `nan`, sqlite only digits
+    Ok(f) if trimmed.bytes().any(|b| b.is_ascii_digit()) => Value::Float(f),
+    _ => value,
+  }
+}
+
+/// Text affinity: numbers are stored as their text
+pub fn text_affinity(value: Value) -> Value {
+  match value {
+    Value::Int(_) | Value::Float(_) | Value::Bool(_) => {
+      Value::String(Cow::Owned(to_text(&value).unwrap_or_default()))
+    }
+    value => value,
+  }
+}
@@ -0,0 +1,518 @@
+use crate::{
+  cursor::{scanner::Scanner, value::OwnedValue},
+  sql::ast::{CompoundOperator, Expr, FunctionCall},
+};
+
+use super::{
+  operator::{IndexScan, Operator},
+  pragma::PragmaAction,
+  schema::SchemaAction,
+  transaction::TransactionAction,
+};
+
+/// Share of the rows a predicate, or one bound of a range, is assumed to keep
+const RANGE_SELECTIVITY: f64 = 0.25;
+/// Share of the rows an equality on an index column is assumed to keep
+const EQUALITY_SELECTIVITY: f64 = 0.1;
+
+/// Column names and rows describing the operator tree, parents before their
+/// children. `EXPLAIN QUERY PLAN` shows how tables are read, joined, sorted and
+/// grouped; `EXPLAIN` shows every operator with its details and estimated rows.
+pub fn explain(
+  operator: &Operator,
+  query_plan: bool,
+) -> anyhow::Result<(Vec<String>, Vec<Vec<OwnedValue>>)> {
+  let mut lines = vec![];
+  describe(operator, 0, query_plan, &mut lines)?;
+
+  let col_names = match query_plan {
+    true => vec!["id", "parent", "detail"],
+    false => vec!["id", "parent", "operator", "rows", "detail"],
+  };
+  let rows = lines
+    .into_iter()
+    .map(|line| {
+      let mut row = vec![
+        OwnedValue::Int(line.id as i64),
+        OwnedValue::Int(line.parent as i64),
+      ];
+      if query_plan {
+        row.push(text(line.summary));
+      } else {
+        row.push(text(line.operator.to_string()));
+        row.push(OwnedValue::Int(line.rows.ceil() as i64));
+        row.push(text(line.detail));
+      }
+      row
+    })
+    .collect();
+  Ok((col_names.into_iter().map(str::to_string).collect(), rows))
+}
+
+/// An operator of the tree, `parent` is 0 for the root
+#[derive(Debug, Default)]
+struct Line {
+  id: usize,
+  parent: usize,
+  operator: &'static str,
+  /// what `EXPLAIN QUERY PLAN` shows
+  summary: String,
+  detail: String,
+  rows: f64,
+}
+
+/// Adds the lines of `operator` and its children, returning its estimated rows.
+/// The query plan leaves out projections and limits, which don't change how rows
+/// are found.
+fn describe(
+  operator: &Operator,
+  parent: usize,
+  query_plan: bool,
+  lines: &mut Vec<Line>,
+) -> anyhow::Result<f64> {
+  let shown = !query_plan || !matches!(operator, Operator::Project(_) | Operator::Limit(_));
+  let n = lines.len();
+  let id = match shown {
+    true => {
+      lines.push(Line {
+        id: n + 1,
+        parent,
+        ..Line::default()
+      });
+      n + 1
+    }
+    false => parent,
+  };
+
+  let mut children = vec![];
+  for child in children_of(operator) {
+    children.push(describe(child, id, query_plan, lines)?);
+  }
+  let input = children.first().copied().unwrap_or_default();
+
+  let (name, summary, detail, rows) = match operator {
+    Operator::SeqScan(scan) => {
+      let (rows, bounds) = rowid_bounds(&scan.scanner, scan.strict_bounds)?;
+      let columns = count(scan.fields.len(), "column");
+      (
+        "SeqScan",
+        scan_summary(&scan.table, &bounds),
+        join(
+          format!("table {}", scan.table),
+          [columns].into_iter().chain(bounds),
+        ),
+        rows,
+      )
+    }
+    Operator::SeqScanWithPredicate(scan) => {
+      let (rows, bounds) = rowid_bounds(&scan.scanner, scan.strict_bounds)?;
+      let predicate = format!("where {}", scan.predicate);
+      (
+        "SeqScanWithPredicate",
+        scan_summary(&scan.table, &bounds),
+        join(
+          format!("table {}", scan.table),
+          bounds.into_iter().chain([predicate]),
+        ),
+        rows * RANGE_SELECTIVITY,
+      )
+    }
+    Operator::IndexScan(scan) => index_scan(scan)?,
+    Operator::Project(project) => (
+      "Project",
+      "PROJECT".to_string(),
+      list(&project.exprs),
+      input,
+    ),
+    Operator::Filter(filter) => (
+      "Filter",
+      "FILTER".to_string(),
+      format!("where {}", filter.predicate),
+      input * RANGE_SELECTIVITY,
+    ),
+    Operator::Sort(sort) => {
+      let keys = sort
+        .keys
+        .iter()
+        .map(|k| {
+          let direction = if k.descending { " DESC" } else { "" };
+          let nulls = match (k.nulls_first, k.descending) {
+            (true, true) => " NULLS FIRST",
+            (false, false) => " NULLS LAST",
+            _ => "",
+          };
+          format!("{}{direction}{nulls}", k.expr)
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+      (
+        "Sort",
+        "SORT FOR ORDER BY".to_string(),
+        format!("by {keys}; memory {} bytes", sort.memory_budget),
+        input,
+      )
+    }
+    Operator::Aggregate(aggregate) => {
+      let calls = aggregate
+        .calls
+        .iter()
+        .map(|c| {
+          Expr::Function(FunctionCall {
+            name: c.function.name().to_string(),
+            distinct: c.distinct,
+            args: c.args.clone(),
+          })
+        })
+        .collect::<Vec<_>>();
+      let memory = format!("memory {} bytes", aggregate.memory_budget);
+      match aggregate.group_by.is_empty() {
+        true => (
+          "Aggregate",
+          "AGGREGATE".to_string(),
+          join(list(&calls), [memory]),
+          1.0,
+        ),
+        false => (
+          "Aggregate",
+          "HASH AGGREGATE FOR GROUP BY".to_string(),
+          join(
+            format!("group by {}", list(&aggregate.group_by)),
+            [list(&calls), memory],
+          ),
+          (input * EQUALITY_SELECTIVITY).max(1.0),
+        ),
+      }
+    }
+    Operator::Distinct(_) => (
+      "Distinct",
+      "HASH DISTINCT".to_string(),
+      String::new(),
+      input,
+    ),
+    Operator::Compound(compound) => {
+      let right = children.get(1).copied().unwrap_or_default();
+      let rows = match compound.operator {
+        CompoundOperator::Intersect => input.min(right),
+        CompoundOperator::Except => input,
+        CompoundOperator::Union | CompoundOperator::UnionAll => input + right,
+      };
+      (
+        "Compound",
+        format!("COMPOUND {}", compound.operator.keyword()),
+        compound.operator.keyword().to_string(),
+        rows,
+      )
+    }
+    Operator::NestedLoopJoin(join_op) => {
+      let right = children.get(1).copied().unwrap_or_default();
+      let matches = match join_op.predicate {
+        Some(_) => input * right * RANGE_SELECTIVITY,
+        None => input * right,
+      };
+      let left = if join_op.outer { "LEFT " } else { "" };
+      (
+        "NestedLoopJoin",
+        format!("{left}NESTED LOOP JOIN"),
+        join_op
+          .predicate
+          .as_ref()
+          .map_or(String::new(), |p| format!("on {p}")),
+        outer_rows(join_op.outer, input, matches),
+      )
+    }
+    Operator::HashJoin(join_op) => {
+      let right = children.get(1).copied().unwrap_or_default();
+      let keys = join_op
+        .left_keys
+        .iter()
+        .zip(&join_op.right_keys)
+        .map(|(l, r)| format!("{l} = right {r}"))
+        .collect::<Vec<_>>()
+        .join(" AND ");
+      let mut matches = input.max(right);
+      if join_op.predicate.is_some() {
+        matches *= RANGE_SELECTIVITY;
+      }
+      let left = if join_op.outer { "LEFT " } else { "" };
+      (
+        "HashJoin",
+        format!("{left}HASH JOIN"),
+        join(
+          format!("on {keys}"),
+          join_op.predicate.iter().map(|p| format!("where {p}")),
+        ),
+        outer_rows(join_op.outer, input, matches),
+      )
+    }
+    Operator::Limit(limit) => {
+      let rows = (input - limit.offset as f64).max(0.0);
+      let (rows, count) = match limit.limit {
+        Some(count) => (rows.min(count as f64), count.to_string()),
+        None => (rows, "none".to_string()),
+      };
+      (
+        "Limit",
+        "LIMIT".to_string(),
+        format!("limit {count}; offset {}", limit.offset),
+        rows,
+      )
+    }
+    Operator::Values(values) => (
+      "Values",
+      "VALUES".to_string(),
+      count(values.rows.len(), "row"),
+      values.rows.len() as f64,
+    ),
+    Operator::Insert(insert) => (
+      "Insert",
+      format!("INSERT INTO {}", insert.writer.table.name),
+      format!(
+        "table {}; {}",
+        insert.writer.table.name,
+        list(&insert.exprs)
+      ),
+      0.0,
+    ),
+    Operator::Delete(delete) => (
+      "Delete",
+      format!("DELETE FROM {}", delete.writer.table.name),
+      format!("table {}", delete.writer.table.name),
+      0.0,
+    ),
+    Operator::Transaction(transaction) => (
+      "Transaction",
+      match &transaction.action {
+        TransactionAction::Begin(_) => "BEGIN".to_string(),
+        TransactionAction::Commit => "COMMIT".to_string(),
+        TransactionAction::Rollback => "ROLLBACK".to_string(),
+        TransactionAction::Savepoint(name) => format!("SAVEPOINT {name}"),
+        TransactionAction::Release(name) => format!("RELEASE {name}"),
+        TransactionAction::RollbackTo(name) => format!("ROLLBACK TO {name}"),
+      },
+      String::new(),
+      0.0,
+    ),
+    Operator::Pragma(pragma) => (
+      "Pragma",
+      match pragma.action {
+        PragmaAction::Checkpoint(mode) => format!("PRAGMA wal_checkpoint({mode:?})"),
+        PragmaAction::AutoCheckpoint(Some(frames)) => {
+          format!("PRAGMA wal_autocheckpoint = {frames}")
+        }
+        PragmaAction::AutoCheckpoint(None) => "PRAGMA wal_autocheckpoint".to_string(),
+        PragmaAction::JournalMode(Some(mode)) => format!("PRAGMA journal_mode = {mode:?}"),
+        PragmaAction::JournalMode(None) => "PRAGMA journal_mode".to_string(),
+      },
+      String::new(),
+      0.0,
+    ),
+    Operator::SchemaChange(change) => (
+      "SchemaChange",
+      match &change.action {
+        SchemaAction::CreateTable { name, .. } => format!("CREATE TABLE {name}"),
+        SchemaAction::DropTable { name, .. } => format!("DROP TABLE {name}"),
+        SchemaAction::AlterTable { name, .. } => format!("ALTER TABLE {name}"),
+      },
+      String::new(),
+      0.0,
+    ),
+    Operator::Update(update) => (
+      "Update",
+      format!("UPDATE {}", update.writer.table.name),
+      format!(
+        "table {}; {}",
+        update.writer.table.name,
+        list(&update.exprs)
+      ),
+      0.0,
+    ),
+  };
+
+  if shown {
+    let line = &mut lines[n];
+    line.operator = name;
+    line.summary = summary;
+    line.detail = detail;
+    line.rows = rows;
+  }
+  Ok(rows)
+}
+
+fn children_of(operator: &Operator) -> Vec<&Operator> {
+  match operator {
+    Operator::SeqScan(_)
+    | Operator::SeqScanWithPredicate(_)
+    | Operator::IndexScan(_)
+    | Operator::Values(_)
+    | Operator::Transaction(_)
+    | Operator::Pragma(_)
+    | Operator::SchemaChange(_) => vec![],
+    Operator::Project(p) => vec![&p.source],
+    Operator::Sort(s) => vec![&s.source],
+    Operator::Limit(l) => vec![&l.source],
+    Operator::Aggregate(a) => vec![&a.source],
+    Operator::Filter(f) => vec![&f.source],
+    Operator::Distinct(d) => vec![&d.source],
+    Operator::Compound(c) => vec![&c.left, &c.right],
+    Operator::NestedLoopJoin(j) => vec![&j.left, &j.right],
+    Operator::HashJoin(j) => vec![&j.left, &j.right],
+    Operator::Insert(i) => vec![&i.source],
+    Operator::Delete(d) => vec![&d.source],
+    Operator::Update(u) => vec![&u.source],
+  }
+}
+
+/// Estimated rows of a table scan and the rowid constraints it seeks with,
+/// `strict` tells whether the query wrote each bound with `>` or `<`
+fn rowid_bounds(scanner: &Scanner, strict: (bool, bool)) -> anyhow::Result<(f64, Vec<String>)> {
+  let rows = scanner.estimated_entries()? as f64;
+  Ok(match scanner.rowid_bounds() {
+    (Some(start), Some(end)) if start == end => (rows.min(1.0), vec!["rowid=?".to_string()]),
+    (start, end) => {
+      let bounds = [
+        start.map(|_| format!("rowid>{}?", or_equal(!strict.0))),
+        end.map(|_| format!("rowid<{}?", or_equal(!strict.1))),
+      ]
+      .into_iter()
+      .flatten()
+      .collect::<Vec<_>>();
+      (rows * RANGE_SELECTIVITY.powi(bounds.len() as i32), bounds)
+    }
+  })
+}
+
+fn scan_summary(table: &str, bounds: &[String]) -> String {
+  match bounds {
+    [] => format!("SCAN {table}"),
+    bounds => format!(
+      "SEARCH {table} USING INTEGER PRIMARY KEY ({})",
+      bounds.join(" AND ")
+    ),
+  }
+}
+
+fn index_scan(scan: &IndexScan) -> anyhow::Result<(&'static str, String, String, f64)> {
+  let (equal, constraints) = index_constraints(scan);
+  let covering = if scan.table_scanner.is_none() {
+    "COVERING "
+  } else {
+    ""
+  };
+  let summary = format!(
+    "SEARCH {} USING {covering}INDEX {} ({})",
+    scan.table,
+    scan.index,
+    constraints.join(" AND ")
+  );
+
+  let entries = scan.scanner.estimated_entries()? as f64;
+  let per_range = match scan.unique && equal == scan.columns.len() {
+    true => entries.min(1.0),
+    false => {
+      entries
+        * EQUALITY_SELECTIVITY.powi(equal as i32)
+        * RANGE_SELECTIVITY.powi((constraints.len() - equal) as i32)
+    }
+  };
+  let mut rows = per_range * scan.ranges.len() as f64;
+  if scan.predicate.is_some() {
+    rows *= RANGE_SELECTIVITY;
+  }
+
+  let lookup = match scan.table_scanner {
+    Some(_) => "rows by rowid",
+    None => "covering",
+  };
+  let detail = join(
+    format!("table {}", scan.table),
+    [
+      format!("index {} ({})", scan.index, constraints.join(" AND ")),
+      count(scan.ranges.len(), "range"),
+      lookup.to_string(),
+    ]
+    .into_iter()
+    .chain(scan.predicate.iter().map(|p| format!("where {p}"))),
+  );
+  Ok(("IndexScan", summary, detail, rows))
+}
+
+/// Number of index columns the ranges of `scan` pin to a value, and the
+/// constraints on the columns, such as `a=?` and `b>?`
+fn index_constraints(scan: &IndexScan) -> (usize, Vec<String>) {
+  let Some(range) = scan.ranges.first() else {
+    return (0, vec![]);
+  };
+  let equal = range
+    .start
+    .iter()
+    .zip(&range.end)
+    .take_while(|(start, end)| start == end)
+    .count();
+  let mut constraints = scan.columns[..equal]
+    .iter()
+    .map(|c| format!("{c}=?"))
+    .collect::<Vec<_>>();
+
+  // a NULL bound only leaves NULLs out of a one-sided range
+  fn bound(values: &[OwnedValue], n: usize) -> Option<&OwnedValue> {
+    values.get(n).filter(|v| **v != OwnedValue::Null)
+  }
+  let start = (bound(&range.start, equal), range.start_inclusive);
+  let end = (bound(&range.end, equal), range.end_inclusive);
+  let (lower, upper) = match scan.descending.get(equal) {
+    Some(true) => (end, start),
+    _ => (start, end),
+  };
+  if let (Some(_), inclusive) = lower {
+    constraints.push(format!("{}>{}?", scan.columns[equal], or_equal(inclusive)));
+  }
+  if let (Some(_), inclusive) = upper {
+    constraints.push(format!("{}<{}?", scan.columns[equal], or_equal(inclusive)));
+  }
+  (equal, constraints)
+}
+
+/// `=` after the `>` or `<` of an inclusive range end
+fn or_equal(inclusive: bool) -> &'static str {
+  match inclusive {
+    true => "=",
+    false => "",
+  }
+}
+
+/// A left join outputs every left row at least once
+fn outer_rows(outer: bool, left: f64, matches: f64) -> f64 {
+  match outer {
+    true => matches.max(left),
+    false => matches,
+  }
+}
+
+fn list(exprs: &[Expr]) -> String {
+  exprs
+    .iter()
+    .map(ToString::to_string)
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+/// Joins the non-empty parts of a detail with `; `
+fn join(first: String, rest: impl IntoIterator<Item = String>) -> String {
+  [first]
+    .into_iter()
+    .chain(rest)
+    .filter(|part| !part.is_empty())
+    .collect::<Vec<_>>()
+    .join("; ")
+}
+
+fn count(n: usize, noun: &str) -> String {
+  match n {
+    1 => format!("1 {noun}"),
+    n => format!("{n} {noun}s"),
+  }
+}
+
+fn text(s: String) -> OwnedValue {
+  OwnedValue::String(s.into())
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+
+use crate::{cursor::value::OwnedValue, sql::ast::Expr};
+
+use super::{aggregate::GroupKey, eval::Evaluator, operator::Operator};
+
+/// Joins every row of `left` with the rows of `right` for which `predicate` holds,
+/// rescanning `right` for each left row. Rows are the left values followed by the
+/// right ones; a left join pads left rows without a match with NULLs.
+#[derive(Debug)]
+pub struct NestedLoopJoin {
+  pub left: Box<Operator>,
+  pub right: Box<Operator>,
+  pub predicate: Option<Expr>,
+  evaluator: Evaluator,
+  pub outer: bool,
+  right_width: usize,
+  /// width of the left row in `row_buffer`, None before the next left row is read
+  left_width: Option<usize>,
+  matched: bool,
+  row_buffer: Vec<OwnedValue>,
+}
+
+/// Equi-join: the rows of `right` are loaded into a hash table keyed by
+/// `right_keys`, then each left row is looked up by its `left_keys`. Rows with a
+/// NULL key never match.
+#[derive(Debug)]
+pub struct HashJoin {
+  pub left: Box<Operator>,
+  pub right: Box<Operator>,
+  /// evaluated over the left rows
+  pub left_keys: Vec<Expr>,
+  /// evaluated over the right rows alone
+  pub right_keys: Vec<Expr>,
+  /// what is left of the join condition, evaluated over the joined rows
+  pub predicate: Option<Expr>,
+  evaluator: Evaluator,
+  right_evaluator: Evaluator,
+  pub outer: bool,
+  left_width: usize,
+  right_width: usize,
+  /// None until `right` is read
+  table: Option<HashMap<GroupKey, Vec<Vec<OwnedValue>>>>,
+  /// key of the current left row and the next of its matches to try
+  probe: Option<(Option<GroupKey>, usize)>,
+  matched: bool,
+  row_buffer: Vec<OwnedValue>,
+}
+
+impl NestedLoopJoin {
+  pub fn new(
+    left: Operator,
+    right: Operator,
+    right_width: usize,
+    predicate: Option<Expr>,
+    evaluator: Evaluator,
+    outer: bool,
+  ) -> Self {
+    Self {
+      left: Box::new(left),
+      right: Box::new(right),
+      predicate,
+      evaluator,
+      outer,
+      right_width,
+      left_width: None,
+      matched: false,
+      row_buffer: vec![],
+    }
+  }
+
+  pub fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    loop {
+      let left_width = match self.left_width {
+        Some(width) => width,
+        None => {
+          let Some(row) = self.left.next_row()? else {
+            return Ok(None);
+          };
+          self.row_buffer.clear();
+          self.row_buffer.extend_from_slice(row);
+          self.left_width = Some(row.len());
+          self.matched = false;
+          self.right.rewind()?;
+          row.len()
+        }
+      };
+
+      self.row_buffer.truncate(left_width);
+      match self.right.next_row()? {
+        Some(right) => {
+          self.row_buffer.extend_from_slice(right);
+          let holds = match &self.predicate {
+            Some(predicate) => self.evaluator.is_true(predicate, &self.row_buffer[..])?,
+            None => true,
+          };
+          if holds {
+            self.matched = true;
+            return Ok(Some(&self.row_buffer));
+          }
+        }
+        None => {
+          self.left_width = None;
+          if self.outer && !self.matched {
+            self
+              .row_buffer
+              .resize(left_width + self.right_width, OwnedValue::Null);
+            return Ok(Some(&self.row_buffer));
+          }
+        }
+      }
+    }
+  }
+}
+
+impl HashJoin {
+  /// `keys` pairs a left key with a right key, right keys read the right rows alone
+  pub fn new(
+    left: Operator,
+    right: Operator,
+    right_width: usize,
+    keys: Vec<(Expr, Expr)>,
+    predicate: Option<Expr>,
+    evaluator: Evaluator,
+    outer: bool,
+  ) -> Self {
+    let (left_keys, right_keys) = keys.into_iter().unzip();
+    let right_evaluator = evaluator.skip(evaluator.width() - right_width);
+    Self {
+      left: Box::new(left),
+      right: Box::new(right),
+      left_keys,
+      right_keys,
+      predicate,
+      evaluator,
+      right_evaluator,
+      outer,
+      left_width: 0,
+      right_width,
+      table: None,
+      probe: None,
+      matched: false,
+      row_buffer: vec![],
+    }
+  }
+
+  pub fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    if self.table.is_none() {
+      self.table = Some(self.build()?);
+    }
+    let table = self.table.as_ref().unwrap();
+
+    loop {
+      let (key, next) = match self.probe.take() {
+        Some(probe) => probe,
+        None => {
+          let Some(row) = self.left.next_row()? else {
+            return Ok(None);
+          };
+          self.row_buffer.clear();
+          self.row_buffer.extend_from_slice(row);
+          self.left_width = row.len();
+          self.matched = false;
+          (key_of(&self.left_keys, &self.evaluator, row)?, 0)
+        }
+      };
+
+      let left_width = self.left_width;
+      let matches = key.as_ref().and_then(|k| table.get(k));
+      match matches.and_then(|rows| rows.get(next)) {
+        Some(right) => {
+          self.row_buffer.truncate(left_width);
+          self.row_buffer.extend_from_slice(right);
+          self.probe = Some((key, next + 1));
+          let holds = match &self.predicate {
+            Some(predicate) => self.evaluator.is_true(predicate, &self.row_buffer[..])?,
+            None => true,
+          };
+          if holds {
+            self.matched = true;
+            return Ok(Some(&self.row_buffer));
+          }
+        }
+        None if self.outer && !self.matched => {
+          self.row_buffer.truncate(left_width);
+          self
+            .row_buffer
+            .resize(left_width + self.right_width, OwnedValue::Null);
+          return Ok(Some(&self.row_buffer));
+        }
+        None => {}
+      }
+    }
+  }
+
+  fn build(&mut self) -> anyhow::Result<HashMap<GroupKey, Vec<Vec<OwnedValue>>>> {
+    let mut table: HashMap<_, Vec<_>> = HashMap::new();
+    while let Some(row) = self.right.next_row()? {
+      if let Some(key) = key_of(&self.right_keys, &self.right_evaluator, row)? {
+        table.entry(key).or_default().push(row.to_vec());
+      }
+    }
+    Ok(table)
+  }
+}
+
+/// Join key of a row, None if any part of it is NULL
+fn key_of(
+  keys: &[Expr],
+  evaluator: &Evaluator,
+  row: &[OwnedValue],
+) -> anyhow::Result<Option<GroupKey>> {
+  let mut values = Vec::with_capacity(keys.len());
+  for key in keys {
+    let value: OwnedValue = evaluator.eval(key, row)?.into();
+    if value == OwnedValue::Null {
+      return Ok(None);
+    }
+    values.push(value);
+  }
+  Ok(Some(GroupKey(values)))
+}
@@ -1,2 +1,12 @@
+pub mod aggregate;
+pub mod eval;
+pub mod explain;
+pub mod join;
 pub mod operator;
 pub mod plan;
+pub mod pragma;
+pub mod query;
+pub mod schema;
+pub mod sort;
+pub mod transaction;
+pub mod write;
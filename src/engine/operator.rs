@@ -1,18 +1,49 @@
-use anyhow::{Context, Ok};
+use std::{cmp::Ordering, collections::HashSet};
+
+use anyhow::{bail, Context, Ok};
 
 use crate::{
   cursor::{
-    cursor::Cursor,
+    cursor::{Cursor, Field},
     scanner::Scanner,
     value::{OwnedValue, Value},
   },
-  sql::ast::{Comparison, Expr},
+  db::IndexMetadata,
+  sql::ast::{CompoundOperator, Expr},
+};
+
+use super::{
+  aggregate::{Aggregate, GroupKey},
+  eval::{compare_values, Evaluator, TableValues},
+  join::{HashJoin, NestedLoopJoin},
+  pragma::Pragma,
+  schema::SchemaChange,
+  sort::Sort,
+  transaction::Transaction,
+  write::{Delete, Insert, Update},
 };
 
 #[derive(Debug)]
 pub enum Operator {
   SeqScan(SeqScan),
   SeqScanWithPredicate(SeqScanWithPredicate),
+  IndexScan(Box<IndexScan>),
+  Project(Project),
+  Sort(Sort),
+  Limit(Limit),
+  Aggregate(Aggregate),
+  Filter(Filter),
+  Distinct(Distinct),
+  Compound(Compound),
+  NestedLoopJoin(NestedLoopJoin),
+  HashJoin(HashJoin),
+  Values(Values),
+  Insert(Insert),
+  Delete(Delete),
+  Update(Update),
+  Transaction(Transaction),
+  Pragma(Pragma),
+  SchemaChange(Box<SchemaChange>),
 }
 
 impl Operator {
@@ -20,6 +51,41 @@ impl Operator {
     match self {
       Operator::SeqScan(s) => s.next_row(),
       Operator::SeqScanWithPredicate(s) => s.next_row(),
+      Operator::IndexScan(s) => s.next_row(),
+      Operator::Project(p) => p.next_row(),
+      Operator::Sort(s) => s.next_row(),
+      Operator::Limit(l) => l.next_row(),
+      Operator::Aggregate(a) => a.next_row(),
+      Operator::Filter(f) => f.next_row(),
+      Operator::Distinct(d) => d.next_row(),
+      Operator::Compound(c) => c.next_row(),
+      Operator::NestedLoopJoin(j) => j.next_row(),
+      Operator::HashJoin(j) => j.next_row(),
+      Operator::Values(v) => v.next_row(),
+      Operator::Insert(i) => i.next_row(),
+      Operator::Delete(d) => d.next_row(),
+      Operator::Update(u) => u.next_row(),
+      Operator::Transaction(t) => t.next_row(),
+      Operator::Pragma(p) => p.next_row(),
+      Operator::SchemaChange(s) => s.next_row(),
+    }
+  }
+
+  /// Restarts a table scan, the inner side of a nested loop join is scanned once per outer row
+  pub fn rewind(&mut self) -> anyhow::Result<()> {
+    match self {
+      Operator::SeqScan(s) => s.scanner.rewind(),
+      Operator::SeqScanWithPredicate(s) => s.scanner.rewind(),
+      Operator::IndexScan(s) => {
+        s.next_range = 0;
+        s.in_range = false;
+        Ok(())
+      }
+      Operator::Values(v) => {
+        v.next = 0;
+        Ok(())
+      }
+      _ => bail!("only table scans can be rewound"),
     }
   }
 }
@@ -27,37 +93,148 @@ impl Operator {
 /// Sequencial scan
 #[derive(Debug)]
 pub struct SeqScan {
-  pub fields: Vec<usize>,
+  /// name the query refers to the table by
+  pub table: String,
+  pub fields: Vec<Field>,
   pub scanner: Scanner,
+  /// whether the rowid bounds of the scanner were written with `>` and `<`
+  pub strict_bounds: (bool, bool),
+  values: TableValues,
   row_buffer: Vec<OwnedValue>,
 }
 
 #[derive(Debug)]
 pub struct SeqScanWithPredicate {
-  fields: Vec<usize>,
-  scanner: Scanner,
+  pub table: String,
+  fields: Vec<Field>,
+  pub scanner: Scanner,
+  pub strict_bounds: (bool, bool),
+  values: TableValues,
   row_buffer: Vec<OwnedValue>,
   pub predicate: Expr,
+  evaluator: Evaluator,
+}
+
+/// Index records a scan visits: those from `start` to `end`, both prefixes of
+/// the indexed values compared in index order
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexRange {
+  pub start: Vec<OwnedValue>,
+  pub start_inclusive: bool,
+  pub end: Vec<OwnedValue>,
+  pub end_inclusive: bool,
+}
+
+/// Seeks each range of an index and reads the rows of the records in it, from the
+/// index record itself when the index covers every field, otherwise from the table
+/// by rowid
+#[derive(Debug)]
+pub struct IndexScan {
+  pub table: String,
+  pub index: String,
+  pub unique: bool,
+  pub columns: Vec<String>,
+  /// whether each indexed column is sorted in descending order
+  pub descending: Vec<bool>,
+  pub ranges: Vec<IndexRange>,
+  pub scanner: Scanner,
+  /// None for a covering scan, `fields` and `predicate` then read the index records
+  pub table_scanner: Option<Scanner>,
+  /// values of the index records, or of the table rows the scan fetches
+  values: TableValues,
+  fields: Vec<Field>,
+  pub predicate: Option<Expr>,
+  evaluator: Evaluator,
+  next_range: usize,
+  in_range: bool,
+  row_buffer: Vec<OwnedValue>,
+}
+
+/// Evaluates result column expressions over the rows of `source`
+#[derive(Debug)]
+pub struct Project {
+  pub source: Box<Operator>,
+  pub exprs: Vec<Expr>,
+  evaluator: Evaluator,
+  row_buffer: Vec<OwnedValue>,
+}
+
+/// Passes on the rows of `source` for which `predicate` holds
+#[derive(Debug)]
+pub struct Filter {
+  pub source: Box<Operator>,
+  pub predicate: Expr,
+  evaluator: Evaluator,
+  row_buffer: Vec<OwnedValue>,
+}
+
+/// Passes on the rows of `source` that weren't seen before
+#[derive(Debug)]
+pub struct Distinct {
+  pub source: Box<Operator>,
+  seen: HashSet<GroupKey>,
+  row_buffer: Vec<OwnedValue>,
+}
+
+/// Combines the rows of two selects. `UNION ALL` outputs both sides, the other
+/// operators output distinct rows of the left side (and the right side for `UNION`)
+#[derive(Debug)]
+pub struct Compound {
+  pub operator: CompoundOperator,
+  pub left: Box<Operator>,
+  pub right: Box<Operator>,
+  /// rows of the right side for `INTERSECT` and `EXCEPT`, None until read
+  right_rows: Option<HashSet<GroupKey>>,
+  left_done: bool,
+  seen: HashSet<GroupKey>,
+  row_buffer: Vec<OwnedValue>,
+}
+
+/// Outputs rows computed up front
+#[derive(Debug)]
+pub struct Values {
+  pub rows: Vec<Vec<OwnedValue>>,
+  next: usize,
+}
+
+/// Skips the first `offset` rows of `source` and stops pulling from it after `limit` rows
+#[derive(Debug)]
+pub struct Limit {
+  pub source: Box<Operator>,
+  /// None for no limit
+  pub limit: Option<usize>,
+  pub offset: usize,
+  skipped: usize,
+  returned: usize,
 }
 
 impl SeqScan {
-  pub fn new(fields: &[usize], scanner: Scanner) -> Self {
+  pub fn new(table: &str, fields: &[Field], scanner: Scanner, values: TableValues) -> Self {
     let row_buffer = vec![OwnedValue::Null; fields.len()];
 
     Self {
+      table: table.to_string(),
       fields: fields.to_vec(),
       scanner,
+      strict_bounds: (false, false),
+      values,
       row_buffer,
     }
   }
 
+  /// Marks the start and end of the scanner's rowid range as written with `>` and `<`
+  pub fn with_strict_bounds(mut self, start: bool, end: bool) -> Self {
+    self.strict_bounds = (start, end);
+    self
+  }
+
   fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
     let Some(record) = self.scanner.next_record()? else {
       return Ok(None);
     };
 
-    for (i, &n) in self.fields.iter().enumerate() {
-      self.row_buffer[i] = record.owned_field(n).context("missing record field")?;
+    for (i, &field) in self.fields.iter().enumerate() {
+      self.row_buffer[i] = self.values.get(&record, field)?;
     }
 
     Ok(Some(&self.row_buffer))
@@ -65,28 +242,48 @@ impl SeqScan {
 }
 
 impl SeqScanWithPredicate {
-  pub fn new(fields: &[usize], scanner: Scanner, predicate: Expr) -> SeqScanWithPredicate {
+  pub fn new(
+    table: &str,
+    fields: &[Field],
+    scanner: Scanner,
+    values: TableValues,
+    predicate: Expr,
+    evaluator: Evaluator,
+  ) -> SeqScanWithPredicate {
     let row_buffer = vec![OwnedValue::Null; fields.len()];
 
     SeqScanWithPredicate {
+      table: table.to_string(),
       fields: fields.to_vec(),
       scanner,
+      strict_bounds: (false, false),
+      values,
       row_buffer,
       predicate,
+      evaluator,
     }
   }
 
+  /// Marks the start and end of the scanner's rowid range as written with `>` and `<`
+  pub fn with_strict_bounds(mut self, start: bool, end: bool) -> Self {
+    self.strict_bounds = (start, end);
+    self
+  }
+
   fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
     loop {
       let Some(record) = self.scanner.next_record()? else {
         return Ok(None);
       };
-      if !apply_where(&record, self.predicate.as_comparison()?)? {
+      if !self
+        .evaluator
+        .is_true(&self.predicate, &self.values.row(&record))?
+      {
         continue;
       }
 
-      for (i, &n) in self.fields.iter().enumerate() {
-        self.row_buffer[i] = record.owned_field(n).context("missing record field")?;
+      for (i, &field) in self.fields.iter().enumerate() {
+        self.row_buffer[i] = self.values.get(&record, field)?;
       }
       break;
     }
@@ -94,18 +291,302 @@ impl SeqScanWithPredicate {
   }
 }
 
-fn apply_where(record: &Cursor, predicate: Comparison) -> anyhow::Result<bool> {
-  match predicate.l {
-    Expr::Alias(_) => {
-      let v = record.field(predicate.l.as_int()?).unwrap();
-      let r = predicate.r;
-      Ok(predicate.op.compare(v, Value::from(&r)))
+impl IndexScan {
+  /// A covering scan of `table` through `index`, see `fetching_rows`
+  pub fn new(
+    table: &str,
+    index: &IndexMetadata,
+    scanner: Scanner,
+    ranges: Vec<IndexRange>,
+    fields: &[Field],
+    predicate: Option<Expr>,
+    evaluator: Evaluator,
+  ) -> Self {
+    Self {
+      table: table.to_string(),
+      index: index.name.clone(),
+      unique: index.unique,
+      columns: index.columns.iter().map(|c| c.name.clone()).collect(),
+      descending: index.columns.iter().map(|c| c.descending).collect(),
+      ranges,
+      scanner,
+      table_scanner: None,
+      values: TableValues::new(evaluator.width(), vec![], evaluator.clone()),
+      fields: fields.to_vec(),
+      predicate,
+      evaluator,
+      next_range: 0,
+      in_range: false,
+      row_buffer: vec![OwnedValue::Null; fields.len()],
+    }
+  }
+
+  /// Reads the rows from the table by the rowid of the index records
+  pub fn fetching_rows(mut self, table_scanner: Scanner, values: TableValues) -> Self {
+    self.table_scanner = Some(table_scanner);
+    self.values = values;
+    self
+  }
+
+  fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    loop {
+      if !self.in_range {
+        let Some(range) = self.ranges.get(self.next_range) else {
+          return Ok(None);
+        };
+        let descending = &self.descending;
+        self.scanner.seek_index(|record| {
+          let ordering = compare_prefix(record, &range.start, descending);
+          Ok(ordering.is_lt() || (ordering.is_eq() && !range.start_inclusive))
+        })?;
+        self.next_range += 1;
+        self.in_range = true;
+      }
+
+      let range = &self.ranges[self.next_range - 1];
+      let record = match self.scanner.next_record()? {
+        Some(record) => record,
+        None => {
+          self.in_range = false;
+          continue;
+        }
+      };
+      let ordering = compare_prefix(&record, &range.end, &self.descending);
+      if ordering.is_gt() || (ordering.is_eq() && !range.end_inclusive) {
+        self.in_range = false;
+        continue;
+      }
+
+      let row = match &mut self.table_scanner {
+        None => record,
+        Some(table) => {
+          let rowid = record
+            .field(self.descending.len())
+            .and_then(|v| v.as_int())
+            .with_context(|| format!("index {} record without rowid", self.index))?;
+          table.seek(rowid)?;
+          table
+            .next_record()?
+            .filter(|row| row.row_id == Some(rowid))
+            .with_context(|| format!("index {} points to missing rowid {rowid}", self.index))?
+        }
+      };
+      if let Some(predicate) = &self.predicate {
+        if !self.evaluator.is_true(predicate, &self.values.row(&row))? {
+          continue;
+        }
+      }
+
+      for (i, &field) in self.fields.iter().enumerate() {
+        self.row_buffer[i] = self.values.get(&row, field)?;
+      }
+      return Ok(Some(&self.row_buffer));
+    }
+  }
+}
+
+/// Compares the leading values of an index record with `key`, in index order
+pub fn compare_prefix(record: &Cursor, key: &[OwnedValue], descending: &[bool]) -> Ordering {
+  for (i, (value, &descending)) in key.iter().zip(descending).enumerate() {
+    let ordering = compare_values(&record.field(i).unwrap_or(Value::Null), &value.as_value());
+    let ordering = if descending {
+      ordering.reverse()
+    } else {
+      ordering
+    };
+    if ordering.is_ne() {
+      return ordering;
+    }
+  }
+  Ordering::Equal
+}
+
+/// Compares the first `descending.len()` values of two index records, in index order
+pub fn compare_records(a: &Cursor, b: &Cursor, descending: &[bool]) -> Ordering {
+  for (i, &descending) in descending.iter().enumerate() {
+    let ordering = compare_values(
+      &a.field(i).unwrap_or(Value::Null),
+      &b.field(i).unwrap_or(Value::Null),
+    );
+    let ordering = if descending {
+      ordering.reverse()
+    } else {
+      ordering
+    };
+    if ordering.is_ne() {
+      return ordering;
+    }
+  }
+  Ordering::Equal
+}
+
+impl Project {
+  pub fn new(source: Operator, exprs: Vec<Expr>, evaluator: Evaluator) -> Self {
+    let row_buffer = vec![OwnedValue::Null; exprs.len()];
+
+    Self {
+      source: Box::new(source),
+      exprs,
+      evaluator,
+      row_buffer,
+    }
+  }
+
+  fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    let Some(row) = self.source.next_row()? else {
+      return Ok(None);
+    };
+
+    for (i, expr) in self.exprs.iter().enumerate() {
+      self.row_buffer[i] = self.evaluator.eval(expr, row)?.into();
+    }
+    Ok(Some(&self.row_buffer))
+  }
+}
+
+impl Filter {
+  pub fn new(source: Operator, predicate: Expr, evaluator: Evaluator) -> Self {
+    Self {
+      source: Box::new(source),
+      predicate,
+      evaluator,
+      row_buffer: vec![],
+    }
+  }
+
+  fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    while let Some(row) = self.source.next_row()? {
+      if self.evaluator.is_true(&self.predicate, row)? {
+        self.row_buffer.clear();
+        self.row_buffer.extend_from_slice(row);
+        return Ok(Some(&self.row_buffer));
+      }
+    }
+    Ok(None)
+  }
+}
+
+impl Distinct {
+  pub fn new(source: Operator) -> Self {
+    Self {
+      source: Box::new(source),
+      seen: HashSet::new(),
+      row_buffer: vec![],
+    }
+  }
+
+  fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    while let Some(row) = self.source.next_row()? {
+      if self.seen.insert(GroupKey(row.to_vec())) {
+        self.row_buffer.clear();
+        self.row_buffer.extend_from_slice(row);
+        return Ok(Some(&self.row_buffer));
+      }
+    }
+    Ok(None)
+  }
+}
+
+impl Compound {
+  pub fn new(operator: CompoundOperator, left: Operator, right: Operator) -> Self {
+    Self {
+      operator,
+      left: Box::new(left),
+      right: Box::new(right),
+      right_rows: None,
+      left_done: false,
+      seen: HashSet::new(),
+      row_buffer: vec![],
+    }
+  }
+
+  fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    let intersect = match self.operator {
+      CompoundOperator::Union | CompoundOperator::UnionAll => return self.next_union_row(),
+      CompoundOperator::Intersect => true,
+      CompoundOperator::Except => false,
+    };
+
+    if self.right_rows.is_none() {
+      let mut rows = HashSet::new();
+      while let Some(row) = self.right.next_row()? {
+        rows.insert(GroupKey(row.to_vec()));
+      }
+      self.right_rows = Some(rows);
+    }
+    let right_rows = self.right_rows.as_ref().unwrap();
+
+    while let Some(row) = self.left.next_row()? {
+      let key = GroupKey(row.to_vec());
+      if right_rows.contains(&key) == intersect && self.seen.insert(key) {
+        self.row_buffer.clear();
+        self.row_buffer.extend_from_slice(row);
+        return Ok(Some(&self.row_buffer));
+      }
+    }
+    Ok(None)
+  }
+
+  fn next_union_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    loop {
+      let row = match self.left_done {
+        false => self.left.next_row()?,
+        true => self.right.next_row()?,
+      };
+      let Some(row) = row else {
+        if self.left_done {
+          return Ok(None);
+        }
+        self.left_done = true;
+        continue;
+      };
+
+      if self.operator == CompoundOperator::Union && !self.seen.insert(GroupKey(row.to_vec())) {
+        continue;
+      }
+      self.row_buffer.clear();
+      self.row_buffer.extend_from_slice(row);
+      return Ok(Some(&self.row_buffer));
+    }
+  }
+}
+
+impl Values {
+  pub fn new(rows: Vec<Vec<OwnedValue>>) -> Self {
+    Self { rows, next: 0 }
+  }
+
+  fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    let row = self.rows.get(self.next);
+    self.next += 1;
+    Ok(row.map(Vec::as_slice))
+  }
+}
+
+impl Limit {
+  pub fn new(source: Operator, limit: Option<usize>, offset: usize) -> Self {
+    Self {
+      source: Box::new(source),
+      limit,
+      offset,
+      skipped: 0,
+      returned: 0,
     }
-    Expr::Comparison(_, _, _) => {
-      let left = apply_where(record, predicate.l.as_comparison()?)?;
-      let right = apply_where(record, predicate.r.as_comparison()?)?;
-      Ok(predicate.op.compare(left.into(), right.into()))
+  }
+
+  fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    if self.limit.is_some_and(|limit| self.returned >= limit) {
+      return Ok(None);
     }
-    _ => anyhow::bail!("Expected a truthy value"),
+
+    while self.skipped < self.offset {
+      if self.source.next_row()?.is_none() {
+        return Ok(None);
+      }
+      self.skipped += 1;
+    }
+
+    self.returned += 1;
+    self.source.next_row()
   }
 }
@@ -1,112 +1,2179 @@
 use anyhow::{bail, Context};
 
 use crate::{
-  db::{Db, TableMetadata},
-  engine::operator::SeqScanWithPredicate,
-  sql::ast::{self, Comparison, Expr, ResultColumn, SelectFrom},
+  cursor::{
+    cursor::Field,
+    value::{OwnedValue, Value},
+  },
+  db::{Db, IndexMetadata, SchemaEntry, SchemaKind, TableMetadata},
+  page::wal::CheckpointMode,
+  pager::JournalMode,
+  sql::{
+    ast::{
+      self, Expr, FunctionCall, JoinConstraint, JoinOperator, ResultColumn, SelectFrom, TableRef,
+      Type,
+    },
+    parser,
+    tokenizer::Ops,
+  },
 };
 
-use super::operator::{Operator, SeqScan};
+use super::{
+  aggregate::{Aggregate, AggregateCall, AggregateFunction},
+  eval::{compare_values, is_aggregate, numeric_affinity, text_affinity, Evaluator, TableValues},
+  explain,
+  join::{HashJoin, NestedLoopJoin},
+  operator::{
+    Compound, Distinct, Filter, IndexRange, IndexScan, Limit, Operator, Project, SeqScan,
+    SeqScanWithPredicate, Values,
+  },
+  pragma::{Pragma, PragmaAction},
+  query::{PreparedQuery, QueryColumn},
+  schema::{RowRewrite, SchemaAction, SchemaChange},
+  sort::{Sort, SortKey, DEFAULT_SORT_MEMORY},
+  transaction::{Transaction, TransactionAction},
+  write::{storage_value, Check, Delete, IndexWriter, Insert, TableWriter, Update},
+};
 
 pub struct Planner<'d> {
   db: &'d Db,
+  sort_memory: usize,
 }
 
 impl<'d> Planner<'d> {
   pub fn new(db: &'d Db) -> Self {
-    Self { db }
+    Self {
+      db,
+      sort_memory: DEFAULT_SORT_MEMORY,
+    }
+  }
+
+  /// Bytes of rows a sort may buffer before spilling sorted runs to temp files
+  pub fn with_sort_memory(mut self, bytes: usize) -> Self {
+    self.sort_memory = bytes;
+    self
   }
 
-  pub fn compile(self, statement: &ast::Statement) -> anyhow::Result<Operator> {
+  pub fn compile(self, statement: &ast::Statement) -> anyhow::Result<PreparedQuery> {
+    // statements are planned against the schema as it is now, which schema
+    // changes since it was read, this connection's or another's, replaced
+    if self.db.schema_changed()? {
+      let db = self.db.reloaded()?;
+      return Planner { db: &db, ..self }.compile(statement);
+    }
+
     match statement {
-      ast::Statement::Select(s) => self.compile_select(s),
+      ast::Statement::Select(s) => {
+        let (mut operator, columns) = self.compile_select(s)?;
+        if let Some(limit) = &s.limit {
+          operator = compile_limit(operator, limit)?;
+        }
+        Ok(PreparedQuery { columns, operator })
+      }
+      ast::Statement::Explain(explain) => {
+        let ast::Statement::Select(s) = explain.statement.as_ref() else {
+          bail!("only SELECT statements can be explained");
+        };
+        let (mut operator, _) = self.compile_select(s)?;
+        if let Some(limit) = &s.limit {
+          operator = compile_limit(operator, limit)?;
+        }
+
+        let (col_names, rows) = explain::explain(&operator, explain.query_plan)?;
+        Ok(PreparedQuery {
+          columns: col_names.into_iter().map(QueryColumn::computed).collect(),
+          operator: Operator::Values(Values::new(rows)),
+        })
+      }
+      ast::Statement::Insert(insert) => Ok(PreparedQuery {
+        columns: vec![],
+        operator: self.compile_insert(insert)?,
+      }),
+      ast::Statement::Delete(delete) => Ok(PreparedQuery {
+        columns: vec![],
+        operator: self.compile_delete(delete)?,
+      }),
+      ast::Statement::Update(update) => Ok(PreparedQuery {
+        columns: vec![],
+        operator: self.compile_update(update)?,
+      }),
+      ast::Statement::Begin(mode) => Ok(self.transaction(TransactionAction::Begin(*mode))),
+      ast::Statement::Commit => Ok(self.transaction(TransactionAction::Commit)),
+      ast::Statement::Rollback => Ok(self.transaction(TransactionAction::Rollback)),
+      ast::Statement::Savepoint(name) => {
+        Ok(self.transaction(TransactionAction::Savepoint(name.clone())))
+      }
+      ast::Statement::Release(name) => {
+        Ok(self.transaction(TransactionAction::Release(name.clone())))
+      }
+      ast::Statement::RollbackTo(name) => {
+        Ok(self.transaction(TransactionAction::RollbackTo(name.clone())))
+      }
+      ast::Statement::Pragma(pragma) => self.compile_pragma(pragma),
+      ast::Statement::CreateTable(create) => self.compile_create_table(create),
+      ast::Statement::DropTable(drop) => self.compile_drop_table(drop),
+      ast::Statement::AlterTable(alter) => self.compile_alter_table(alter),
       stmt => bail!("unsupported statement: {stmt:?}"),
     }
   }
 
-  fn compile_select(self, select: &ast::SelectStatement) -> anyhow::Result<Operator> {
-    let SelectFrom::Table(table_name) = &select.core.from;
+  fn compile_insert(&self, insert: &ast::InsertStatement) -> anyhow::Result<Operator> {
+    let table = self
+      .db
+      .table(&insert.table)
+      .with_context(|| format!("no such table: {}", insert.table))?;
+
+    let (source, width) = match &insert.source {
+      ast::InsertSource::Values(rows) => {
+        let width = rows.first().map_or(0, Vec::len);
+        let mut values = vec![];
+        for row in rows {
+          if row.len() != width {
+            bail!("all VALUES must have the same number of terms");
+          }
+          let row = row
+            .iter()
+            .map(|e| {
+              let e = resolve_columns(e, &mut |_, name| bail!("no such column: {name}"))?;
+              Ok(Evaluator::default().eval(&e, &[] as &[OwnedValue])?.into())
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+          values.push(row);
+        }
+        (Operator::Values(Values::new(values)), width)
+      }
+      ast::InsertSource::Select(select) => {
+        let (mut operator, columns) = self.compile_select(select)?;
+        if let Some(limit) = &select.limit {
+          operator = compile_limit(operator, limit)?;
+        }
+        (operator, columns.len())
+      }
+    };
 
+    // value of every column: the source value named after it, or its default
+    let mut exprs = table
+      .columns
+      .iter()
+      .map(|c| {
+        c.constraints
+          .iter()
+          .find_map(|c| match c {
+            ast::ColumnConstraint::Default(expr) => Some(default_value(expr)),
+            _ => None,
+          })
+          .unwrap_or(Expr::Null)
+      })
+      .collect::<Vec<_>>();
+    let mut rowid = None;
+    if insert.columns.is_empty() {
+      // generated columns take no value
+      let columns = (0..table.columns.len())
+        .filter(|&n| table.columns[n].generated().is_none())
+        .collect::<Vec<_>>();
+      if width != columns.len() {
+        bail!(
+          "table {} has {} columns but {width} values were supplied",
+          table.name,
+          columns.len()
+        );
+      }
+      for (i, n) in columns.into_iter().enumerate() {
+        exprs[n] = Expr::Alias(i as i64);
+      }
+    } else {
+      if width != insert.columns.len() {
+        bail!("{width} values for {} columns", insert.columns.len());
+      }
+      for (i, name) in insert.columns.iter().enumerate() {
+        match table
+          .columns
+          .iter()
+          .position(|c| c.name.eq_ignore_ascii_case(name))
+        {
+          Some(n) if table.columns[n].generated().is_some() => {
+            bail!("cannot INSERT into generated column \"{name}\"")
+          }
+          Some(n) => exprs[n] = Expr::Alias(i as i64),
+          None if table.field(name) == Some(Field::RowId) => rowid = Some(Expr::Alias(i as i64)),
+          None => bail!("table {} has no column named {name}", table.name),
+        }
+      }
+    }
+
+    let writer = self.table_writer(table)?;
+    Ok(Operator::Insert(Insert::new(
+      writer,
+      source,
+      exprs,
+      rowid,
+      Evaluator::default(),
+    )))
+  }
+
+  fn compile_delete(&self, delete: &ast::DeleteStatement) -> anyhow::Result<Operator> {
     let table = self
       .db
-      .tables_metadata
+      .table(&delete.table)
+      .with_context(|| format!("no such table: {}", delete.table))?;
+    let scan = self.compile_write_scan(table, delete.where_clause.as_ref())?;
+    Ok(Operator::Delete(Delete::new(
+      self.table_writer(table)?,
+      scan,
+    )))
+  }
+
+  fn compile_update(&self, update: &ast::UpdateStatement) -> anyhow::Result<Operator> {
+    let table = self
+      .db
+      .table(&update.table)
+      .with_context(|| format!("no such table: {}", update.table))?;
+
+    // scanned rows start with the rowid, then hold every column
+    let offset = if table.without_rowid { 0 } else { 1 };
+    let mut affinities = match table.without_rowid {
+      true => vec![],
+      false => vec![Type::Integer],
+    };
+    affinities.extend(table.columns.iter().map(|c| c.col_type.clone()));
+    let row_column = |expr: &Expr| {
+      resolve_columns(expr, &mut |_, name| match table
+        .columns
+        .iter()
+        .position(|c| c.name.eq_ignore_ascii_case(name))
+      {
+        Some(n) => Ok(Expr::Alias((offset + n) as i64)),
+        None if table.field(name) == Some(Field::RowId) => Ok(Expr::Alias(0)),
+        None => bail!("no such column: {name}"),
+      })
+    };
+
+    let mut exprs = (0..table.columns.len())
+      .map(|n| Expr::Alias((offset + n) as i64))
+      .collect::<Vec<_>>();
+    let mut rowid = None;
+    for (name, expr) in &update.assignments {
+      let expr = row_column(expr)?;
+      match table
+        .columns
+        .iter()
+        .position(|c| c.name.eq_ignore_ascii_case(name))
+      {
+        Some(n) if table.columns[n].generated().is_some() => {
+          bail!("cannot UPDATE generated column \"{name}\"")
+        }
+        Some(n) => exprs[n] = expr,
+        None if table.field(name) == Some(Field::RowId) => match table.rowid_alias() {
+          Some(n) => exprs[n] = expr,
+          None => rowid = Some(expr),
+        },
+        None => bail!("no such column: {name}"),
+      }
+    }
+
+    let scan = self.compile_write_scan(table, update.where_clause.as_ref())?;
+    Ok(Operator::Update(Update::new(
+      self.table_writer(table)?,
+      scan,
+      exprs,
+      rowid,
+      Evaluator::new(affinities),
+    )))
+  }
+
+  /// Scans the rows of `table` a DELETE or UPDATE changes: the rowid then every
+  /// column, as the writer needs them to find the index entries
+  fn compile_write_scan(
+    &self,
+    table: &TableMetadata,
+    where_clause: Option<&Expr>,
+  ) -> anyhow::Result<Operator> {
+    let mut fields = match table.without_rowid {
+      true => vec![],
+      false => vec![Field::RowId],
+    };
+    for column in &table.columns {
+      fields.push(
+        table
+          .field(&column.name)
+          .context("unresolved table column")?,
+      );
+    }
+    let conjuncts = match where_clause {
+      Some(expr) => {
+        let expr = strip_qualifiers(expr)?;
+        and_terms(&expr).into_iter().cloned().collect()
+      }
+      None => vec![],
+    };
+    self.compile_scan(&table.name, table, &fields, conjuncts)
+  }
+
+  fn transaction(&self, action: TransactionAction) -> PreparedQuery {
+    PreparedQuery {
+      columns: vec![],
+      operator: Operator::Transaction(Transaction::new(action, self.db.pager())),
+    }
+  }
+
+  fn compile_pragma(&self, pragma: &ast::PragmaStatement) -> anyhow::Result<PreparedQuery> {
+    let value = pragma.value.as_deref().map(str::to_lowercase);
+    let action = match pragma.name.to_ascii_lowercase().as_str() {
+      "wal_checkpoint" => PragmaAction::Checkpoint(match value.as_deref() {
+        Some("full") => CheckpointMode::Full,
+        Some("restart") => CheckpointMode::Restart,
+        Some("truncate") => CheckpointMode::Truncate,
+        // sqlite takes any other mode as passive
+        _ => CheckpointMode::Passive,
+      }),
+      "wal_autocheckpoint" => PragmaAction::AutoCheckpoint(match value {
+        // a negative threshold turns checkpoints off like 0
+        Some(value) => Some(
+          value
+            .parse::<i64>()
+            .with_context(|| format!("invalid wal_autocheckpoint: {value}"))?
+            .max(0) as usize,
+        ),
+        None => None,
+      }),
+      "journal_mode" => PragmaAction::JournalMode(match value.as_deref() {
+        Some("wal") => Some(JournalMode::Wal),
+        Some("delete") => Some(JournalMode::Delete),
+        Some(mode) => bail!("unsupported journal mode: {mode}"),
+        None => None,
+      }),
+      name => bail!("unsupported pragma: {name}"),
+    };
+    let pragma = Pragma::new(action, self.db.pager());
+    Ok(PreparedQuery {
+      columns: pragma
+        .columns()
+        .into_iter()
+        .map(QueryColumn::computed)
+        .collect(),
+      operator: Operator::Pragma(pragma),
+    })
+  }
+
+  fn compile_create_table(
+    &self,
+    create: &ast::CreateTableStatement,
+  ) -> anyhow::Result<PreparedQuery> {
+    if self.db.index(&create.name).is_some() {
+      bail!("there is already an index named {}", create.name);
+    }
+    if self.db.table(&create.name).is_some() {
+      if create.if_not_exists {
+        return Ok(no_rows());
+      }
+      bail!("table {} already exists", create.name);
+    }
+    check_object_name(&create.name)?;
+    for (n, column) in create.columns.iter().enumerate() {
+      if create.columns[..n]
+        .iter()
+        .any(|c| c.name.eq_ignore_ascii_case(&column.name))
+      {
+        bail!("duplicate column name: {}", column.name);
+      }
+    }
+
+    let table = TableMetadata {
+      name: create.name.clone(),
+      columns: create.columns.clone(),
+      constraints: create.constraints.clone(),
+      without_rowid: create.without_rowid,
+      first_page: 0,
+    };
+    if table.without_rowid && table.primary_key_columns().is_empty() {
+      bail!("PRIMARY KEY missing on table {}", table.name);
+    }
+
+    let action = SchemaAction::CreateTable {
+      name: table.name.clone(),
+      sql: create.sql.clone(),
+      without_rowid: table.without_rowid,
+      autoindexes: table.autoindex_columns().len(),
+    };
+    Ok(self.schema_change(SchemaChange::new(action, self.db.pager())))
+  }
+
+  fn compile_drop_table(&self, drop: &ast::DropTableStatement) -> anyhow::Result<PreparedQuery> {
+    if drop.name.to_ascii_lowercase().starts_with("sqlite_") {
+      bail!("table {} may not be dropped", drop.name);
+    }
+    let Some(table) = self.db.table(&drop.name) else {
+      if drop.if_exists {
+        return Ok(no_rows());
+      }
+      bail!("no such table: {}", drop.name);
+    };
+
+    // views on the table stay, as in sqlite
+    let entries = self
+      .db
+      .schema
+      .iter()
+      .filter(|e| e.kind != SchemaKind::View && e.table_name.eq_ignore_ascii_case(&table.name))
+      .cloned()
+      .collect();
+    let action = SchemaAction::DropTable {
+      name: table.name.clone(),
+      entries,
+    };
+    Ok(self.schema_change(SchemaChange::new(action, self.db.pager())))
+  }
+
+  /// Rewrites the create statements naming the table: its own and those of the
+  /// indexes, views and triggers whose definitions and bodies name a renamed
+  /// table or column. The rows are rewritten without a dropped column, records
+  /// are left lacking an added one, which reads as its default.
+  fn compile_alter_table(&self, alter: &ast::AlterTableStatement) -> anyhow::Result<PreparedQuery> {
+    let table = self
+      .db
+      .table(&alter.table)
+      .with_context(|| format!("no such table: {}", alter.table))?;
+    if table.name.to_ascii_lowercase().starts_with("sqlite_") {
+      bail!("table {} may not be altered", table.name);
+    }
+    let position = |name: &str| {
+      table
+        .columns
+        .iter()
+        .position(|c| c.name.eq_ignore_ascii_case(name))
+    };
+
+    let mut entries = vec![];
+    let mut rewrite = None;
+    match &alter.action {
+      ast::AlterTableAction::RenameTable(new) => {
+        if self.db.table(new).is_some() || self.db.index(new).is_some() {
+          bail!("there is already another table or index with this name: {new}");
+        }
+        check_object_name(new)?;
+
+        for entry in &self.db.schema {
+          let mut renamed = entry.clone();
+          if entry.table_name.eq_ignore_ascii_case(&table.name) {
+            renamed.table_name = new.clone();
+            let autoindex = format!("sqlite_autoindex_{}_", entry.table_name);
+            if entry.kind == SchemaKind::Table {
+              renamed.name = new.clone();
+            } else if let Some(n) = entry.name.strip_prefix(&autoindex) {
+              renamed.name = format!("sqlite_autoindex_{new}_{n}");
+            }
+          }
+          if let Some(sql) = &entry.sql {
+            renamed.sql = Some(parser::rename_table(sql, &table.name, new)?);
+          }
+          if renamed.name != entry.name
+            || renamed.table_name != entry.table_name
+            || renamed.sql != entry.sql
+          {
+            entries.push(renamed);
+          }
+        }
+      }
+      ast::AlterTableAction::RenameColumn { old, new } => {
+        if position(old).is_none() {
+          bail!("no such column: {old}");
+        }
+        if position(new).is_some() {
+          bail!("duplicate column name: {new}");
+        }
+
+        for entry in &self.db.schema {
+          let Some(sql) = &entry.sql else {
+            continue;
+          };
+          let renamed = parser::rename_column(sql, &table.name, old, new)?;
+          if renamed != *sql {
+            entries.push(SchemaEntry {
+              sql: Some(renamed),
+              ..entry.clone()
+            });
+          }
+        }
+      }
+      ast::AlterTableAction::AddColumn { column, sql } => {
+        if position(&column.name).is_some() {
+          bail!("duplicate column name: {}", column.name);
+        }
+        let mut default = OwnedValue::Null;
+        for constraint in &column.constraints {
+          match constraint {
+            ast::ColumnConstraint::PrimaryKey { .. } => bail!("Cannot add a PRIMARY KEY column"),
+            ast::ColumnConstraint::Unique => bail!("Cannot add a UNIQUE column"),
+            ast::ColumnConstraint::Generated { stored: true, .. } => {
+              bail!("cannot add a STORED column")
+            }
+            // current_time and the like are names, which no constant default uses
+            ast::ColumnConstraint::Default(Expr::Column(_)) => {
+              bail!("Cannot add a column with non-constant default")
+            }
+            ast::ColumnConstraint::Default(expr) => {
+              let value = resolve_columns(expr, &mut |_, name| bail!("no such column: {name}"))
+                .and_then(|e| Ok(Evaluator::default().eval(&e, &[] as &[OwnedValue])?.into()))
+                .context("Cannot add a column with non-constant default")?;
+              default = storage_value(value, &column.col_type);
+            }
+            _ => {}
+          }
+        }
+        let not_null = column.constraints.contains(&ast::ColumnConstraint::NotNull);
+        if not_null && default == OwnedValue::Null {
+          bail!("Cannot add a NOT NULL column with default value NULL");
+        }
+
+        // the records are left as they are, lacking the column they read as its default
+        let entry = self.table_entry(table)?;
+        let altered = parser::add_column(entry.sql.as_deref().unwrap_or_default(), sql)?;
+        entries.push(SchemaEntry {
+          sql: Some(altered),
+          ..entry.clone()
+        });
+      }
+      ast::AlterTableAction::DropColumn(name) => {
+        let n = position(name).with_context(|| format!("no such column: {name}"))?;
+        if table.primary_key_columns().contains(&n) {
+          bail!("cannot drop PRIMARY KEY column: \"{name}\"");
+        }
+        let unique = table
+          .autoindex_columns()
+          .iter()
+          .flatten()
+          .any(|c| c.name.eq_ignore_ascii_case(name));
+        if unique {
+          bail!("cannot drop UNIQUE column: \"{name}\"");
+        }
+        if table.columns.len() == 1 {
+          bail!("cannot drop column \"{name}\": no other columns exist");
+        }
+        for index in self.db.table_indexes(&table.name) {
+          let indexed = index.columns.iter().any(|c| match &c.expression {
+            Some(expr) => references_column(expr, name),
+            None => c.name.eq_ignore_ascii_case(name),
+          }) || index
+            .where_clause
+            .as_ref()
+            .is_some_and(|e| references_column(e, name));
+          if indexed {
+            bail!(
+              "error in index {} after drop column: no such column: {name}",
+              index.name
+            );
+          }
+        }
+        for entry in &self.db.schema {
+          let (Some(sql), SchemaKind::View | SchemaKind::Trigger) = (&entry.sql, entry.kind) else {
+            continue;
+          };
+          if let Some(reference) = parser::column_reference(sql, &table.name, name)? {
+            bail!(
+              "error in {} {} after drop column: no such column: {reference}",
+              entry.kind.as_str(),
+              entry.name
+            );
+          }
+        }
+        let foreign_key = table.constraints.iter().any(|c| {
+          matches!(c, ast::TableConstraint::ForeignKey { columns, .. } if columns.iter().any(|c| c.eq_ignore_ascii_case(name)))
+        });
+        if foreign_key {
+          bail!(
+            "error in table {} after drop column: unknown column \"{name}\" in foreign key definition",
+            table.name
+          );
+        }
+
+        let entry = self.table_entry(table)?;
+        let altered = parser::drop_column(entry.sql.as_deref().unwrap_or_default(), n)?;
+        rewrite =
+          Some(self.row_rewrite(table, &altered, n).map_err(|e| {
+            anyhow::anyhow!("error in table {} after drop column: {e}", table.name)
+          })?);
+        entries.push(SchemaEntry {
+          sql: Some(altered),
+          ..entry.clone()
+        });
+      }
+    }
+
+    let action = SchemaAction::AlterTable {
+      name: table.name.clone(),
+      entries,
+    };
+    let mut change = SchemaChange::new(action, self.db.pager());
+    if let Some(rewrite) = rewrite {
+      change = change.with_rewrite(rewrite);
+    }
+    Ok(self.schema_change(change))
+  }
+
+  fn schema_change(&self, change: SchemaChange) -> PreparedQuery {
+    PreparedQuery {
+      columns: vec![],
+      operator: Operator::SchemaChange(Box::new(change)),
+    }
+  }
+
+  /// Row of sqlite_schema defining `table`
+  fn table_entry(&self, table: &TableMetadata) -> anyhow::Result<&SchemaEntry> {
+    self
+      .db
+      .schema
       .iter()
-      .find(|m| &m.name == table_name)
-      .with_context(|| format!("invalid table name: {table_name}"))?;
+      .find(|e| e.kind == SchemaKind::Table && e.table_name.eq_ignore_ascii_case(&table.name))
+      .with_context(|| format!("no such table: {}", table.name))
+  }
 
-    let mut columns = vec![];
+  /// Rewrite of the rows of `table` into the table defined by `sql`, without column `dropped`
+  fn row_rewrite(
+    &self,
+    table: &TableMetadata,
+    sql: &str,
+    dropped: usize,
+  ) -> anyhow::Result<RowRewrite> {
+    let create = parser::parse_create_statement(sql)?;
+    let altered = TableMetadata {
+      columns: create.columns,
+      constraints: create.constraints,
+      ..table.clone()
+    };
+    Ok(RowRewrite {
+      old: self.table_writer(table)?,
+      new: self.table_writer(&altered)?,
+      values: table_values(table)?,
+      dropped,
+    })
+  }
+
+  /// Writer of `table` keeping its indexes up to date and checking its constraints
+  fn table_writer(&self, table: &TableMetadata) -> anyhow::Result<TableWriter> {
+    let table_column = |expr: &Expr| {
+      resolve_columns(expr, &mut |_, name| {
+        let n = table
+          .columns
+          .iter()
+          .position(|c| c.name.eq_ignore_ascii_case(name))
+          .with_context(|| format!("no such column: {name}"))?;
+        Ok(Expr::Alias(n as i64))
+      })
+    };
+    let mut checks = vec![];
+    let column_checks = table.columns.iter().flat_map(|c| &c.constraints);
+    for constraint in column_checks {
+      if let ast::ColumnConstraint::Check(expr) = constraint {
+        checks.push(Check {
+          expr: table_column(expr)?,
+          text: expr.to_string(),
+        });
+      }
+    }
+    for constraint in &table.constraints {
+      if let ast::TableConstraint::Check(expr) = constraint {
+        checks.push(Check {
+          expr: table_column(expr)?,
+          text: expr.to_string(),
+        });
+      }
+    }
+    let indexes = self
+      .db
+      .table_indexes(&table.name)
+      .map(|index| {
+        Ok(IndexWriter {
+          index: index.clone(),
+          condition: index.where_clause.as_ref().map(table_column).transpose()?,
+          expressions: index
+            .columns
+            .iter()
+            .map(|c| c.expression.as_ref().map(table_column).transpose())
+            .collect::<anyhow::Result<_>>()?,
+        })
+      })
+      .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let generated = table
+      .columns
+      .iter()
+      .enumerate()
+      .filter_map(|(n, c)| Some((n, c.generated()?.0)))
+      .map(|(n, expr)| Ok((n, table_column(expr)?)))
+      .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(TableWriter::new(
+      table.clone(),
+      indexes,
+      checks,
+      generated,
+      self.db.pager(),
+    ))
+  }
+
+  /// Compiles the cores of a select and combines them, returning the operator and
+  /// the result columns, described by the first core
+  fn compile_select(
+    &self,
+    select: &ast::SelectStatement,
+  ) -> anyhow::Result<(Operator, Vec<QueryColumn>)> {
+    if select.compound.is_empty() {
+      return self.compile_core(&select.core, &select.order_by);
+    }
+
+    let (mut operator, columns) = self.compile_core(&select.core, &[])?;
+    for compound in &select.compound {
+      let (right, right_columns) = self.compile_core(&compound.core, &[])?;
+      if right_columns.len() != columns.len() {
+        bail!(
+          "SELECTs to the left and right of {} do not have the same number of result columns",
+          compound.operator.keyword()
+        );
+      }
+      operator = Operator::Compound(Compound::new(compound.operator, operator, right));
+    }
+
+    if select.order_by.is_empty() {
+      return Ok((operator, columns));
+    }
+
+    // ORDER BY terms of a compound select name one of its result columns
+    let first_exprs = select
+      .core
+      .result_columns
+      .iter()
+      .map(|c| match c {
+        ResultColumn::Expr(e) => Some(&e.expr),
+        ResultColumn::Star | ResultColumn::TableStar(_) => None,
+      })
+      .collect::<Vec<_>>();
+    let mut sort_keys = vec![];
+    for (i, term) in select.order_by.iter().enumerate() {
+      let column = match &term.expr {
+        Expr::Int(n) => usize::try_from(*n - 1).ok().filter(|n| *n < columns.len()),
+        Expr::Column(name) => columns
+          .iter()
+          .position(|c| c.name.eq_ignore_ascii_case(name)),
+        expr => first_exprs.iter().position(|e| *e == Some(expr)),
+      }
+      .with_context(|| {
+        format!(
+          "{} ORDER BY term does not match any column in the result set",
+          ordinal(i + 1)
+        )
+      })?;
+      sort_keys.push(SortKey {
+        expr: Expr::Alias(column as i64),
+        descending: term.descending,
+        nulls_first: term.nulls_first,
+      });
+    }
+
+    let operator = Operator::Sort(Sort::new(
+      operator,
+      sort_keys,
+      Evaluator::default(),
+      self.sort_memory,
+    ));
+    Ok((operator, columns))
+  }
+
+  fn compile_core(
+    &self,
+    core: &ast::SelectCore,
+    order_by: &[ast::OrderingTerm],
+  ) -> anyhow::Result<(Operator, Vec<QueryColumn>)> {
+    let sources = self.join_sources(&core.from)?;
+
+    let mut exprs = vec![];
     let mut col_names = vec![];
 
-    for res_col in &select.core.result_columns {
+    for res_col in &core.result_columns {
       match res_col {
         ResultColumn::Star => {
-          for (i, col) in table.columns.iter().enumerate() {
-            columns.push(i);
-            col_names.push(col.name.clone());
+          for source in &sources {
+            source.push_columns(&mut exprs, &mut col_names);
           }
         }
-        ResultColumn::Expr(e) => {
-          let Expr::Column(col) = &e.expr else {
-            anyhow::bail!("Expecting a column name")
-          };
-          let (index, _) = table
-            .columns
+        ResultColumn::TableStar(name) => {
+          sources
             .iter()
-            .enumerate()
-            .find(|(_, c)| c.name == *col)
-            .with_context(|| format!("invalid column name: {}", col))?;
-          columns.push(index);
-          col_names.push(if let Some(alias) = &e.alias {
-            alias.clone()
+            .find(|s| s.name.eq_ignore_ascii_case(name))
+            .with_context(|| format!("no such table: {name}"))?
+            .push_columns(&mut exprs, &mut col_names);
+        }
+        ResultColumn::Expr(e) => {
+          exprs.push(e.expr.clone());
+          col_names.push(match (&e.alias, &e.expr) {
+            (Some(alias), _) => alias.clone(),
+            (None, Expr::Column(col)) => declared_name(&sources, None, col),
+            (None, Expr::QualifiedColumn(table, col)) => declared_name(&sources, Some(table), col),
+            (None, _) => e.text.clone(),
+          });
+        }
+      }
+    }
+
+    let columns = exprs
+      .iter()
+      .zip(&col_names)
+      .map(|(expr, name)| query_column(&sources, expr, name))
+      .collect();
+
+    // plain columns are read straight from the records, anything else is computed
+    // over the columns it references. Columns are numbered by first use here and
+    // renumbered table by table once every expression is resolved.
+    let mut slots = vec![];
+
+    let aggregating = !core.group_by.is_empty()
+      || core.having.is_some()
+      || exprs
+        .iter()
+        .chain(order_by.iter().map(|t| &t.expr))
+        .any(contains_aggregate);
+
+    let plain_columns = sources.len() == 1
+      && exprs
+        .iter()
+        .all(|e| matches!(e, Expr::Column(_) | Expr::QualifiedColumn(..)));
+    let mut projection = None;
+    let mut sort_keys = vec![];
+    let mut grouping = None;
+    if plain_columns && order_by.is_empty() && !aggregating {
+      for expr in &exprs {
+        let (table, name) = match expr {
+          Expr::QualifiedColumn(table, name) => (Some(table.as_str()), name),
+          expr => (None, expr.as_str()?),
+        };
+        slots.push(lookup_column(&sources, table, name)?);
+      }
+    } else {
+      let exprs = exprs
+        .iter()
+        .map(|e| {
+          resolve_columns(e, &mut |table, name| {
+            column_slot(&mut slots, &sources, table, name)
+          })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+      for term in order_by {
+        sort_keys.push(SortKey {
+          expr: resolve_term(
+            &term.expr,
+            "ORDER BY",
+            &exprs,
+            &col_names,
+            &mut |table, name| column_slot(&mut slots, &sources, table, name),
+          )?,
+          descending: term.descending,
+          nulls_first: term.nulls_first,
+        });
+      }
+
+      if aggregating {
+        let mut group_by = vec![];
+        for term in &core.group_by {
+          let expr = resolve_term(term, "GROUP BY", &exprs, &col_names, &mut |table, name| {
+            column_slot(&mut slots, &sources, table, name)
+          })?;
+          if contains_aggregate(&expr) {
+            bail!("aggregate functions are not allowed in the GROUP BY clause");
+          }
+          group_by.push(expr);
+        }
+        // table columns take precedence over result column aliases in HAVING
+        let having = match &core.having {
+          Some(having) => Some(resolve_columns(having, &mut |table, name| match (
+            table,
+            col_names.iter().position(|c| c.eq_ignore_ascii_case(name)),
+          ) {
+            (None, Some(n)) if lookup_column(&sources, None, name).is_err() => Ok(exprs[n].clone()),
+            _ => column_slot(&mut slots, &sources, table, name),
+          })?),
+          None => None,
+        };
+        grouping = Some((group_by, having));
+      }
+      projection = Some(exprs);
+    }
+
+    // terms of the where clause and of the join constraints that read a single table
+    // filter its scan, the others are checked by the join that brings in their last
+    // table, or after the joins when that table is the right side of a left join
+    let mut conjuncts = vec![];
+    for (i, source) in sources.iter().enumerate() {
+      if let Some(on) = &source.on {
+        conjuncts.extend(and_terms(on).into_iter().map(|c| (c, Some(i))));
+      }
+    }
+    if let Some(where_clause) = &core.where_clause {
+      conjuncts.extend(and_terms(where_clause).into_iter().map(|c| (c, None)));
+    }
+
+    let mut local = vec![vec![]; sources.len()];
+    let mut join_conditions = vec![vec![]; sources.len()];
+    let mut filters = vec![];
+    for (conjunct, on) in conjuncts {
+      let tables = referenced_tables(conjunct, &sources)?;
+      let last = tables.last().copied().unwrap_or_default();
+      match on {
+        Some(i) if sources[i].outer => {
+          if last > i {
+            bail!("ON clause references tables to its right");
+          }
+          if tables == [i] {
+            local[i].push(strip_qualifiers(conjunct)?);
           } else {
-            col.clone()
+            join_conditions[i].push(resolve_columns(conjunct, &mut |table, name| {
+              column_slot(&mut slots, &sources, table, name)
+            })?);
+          }
+        }
+        _ if tables.len() <= 1 && !sources[last].outer => {
+          local[last].push(strip_qualifiers(conjunct)?)
+        }
+        _ if sources[last].outer => filters.push(resolve_columns(conjunct, &mut |table, name| {
+          column_slot(&mut slots, &sources, table, name)
+        })?),
+        _ => join_conditions[last].push(resolve_columns(conjunct, &mut |table, name| {
+          column_slot(&mut slots, &sources, table, name)
+        })?),
+      }
+    }
+
+    // number the columns of the joined rows table by table
+    let mut table_fields = vec![vec![]; sources.len()];
+    let mut positions = vec![0; slots.len()];
+    let mut affinities = vec![];
+    for (i, source) in sources.iter().enumerate() {
+      let record_affinities = source.table.record_affinities();
+      for (slot, (table, field)) in slots.iter().enumerate() {
+        if *table == i {
+          positions[slot] = affinities.len();
+          table_fields[i].push(*field);
+          affinities.push(match field {
+            Field::Record(idx) => record_affinities[*idx].clone(),
+            Field::RowId => Type::Integer,
           });
         }
       }
     }
-    let formatted = col_names.join("\t| ");
-    println!("{formatted}");
-    println!("-----------------------------------------------------------------------------------------------------------------------");
+    let renumber = |e: &Expr| renumber_aliases(e, &positions);
+    let projection = projection.map(|exprs| exprs.iter().map(renumber).collect::<Vec<_>>());
+    for key in &mut sort_keys {
+      key.expr = renumber(&key.expr);
+    }
+    let grouping = grouping.map(|(group_by, having): (Vec<Expr>, Option<Expr>)| {
+      (
+        group_by.iter().map(renumber).collect::<Vec<_>>(),
+        having.as_ref().map(renumber),
+      )
+    });
 
-    let operator = if select.core.where_clause.is_some() {
-      let predicate = compile_expr(
-        select.core.where_clause.as_ref().unwrap().as_comparison()?,
-        table,
-      )?;
-      Operator::SeqScanWithPredicate(SeqScanWithPredicate::new(
-        &columns,
-        self.db.scanner(table.first_page),
-        predicate,
-      ))
+    let mut scans = vec![];
+    for ((source, fields), local) in sources.iter().zip(&table_fields).zip(local) {
+      scans.push(self.compile_scan(&source.name, source.table, fields, local)?);
+    }
+    let mut scans = scans.into_iter();
+    let mut operator = scans.next().context("no table to select from")?;
+    let mut width = table_fields[0].len();
+    for (i, right) in scans.enumerate().map(|(i, scan)| (i + 1, scan)) {
+      let right_width = table_fields[i].len();
+      let evaluator = Evaluator::new(affinities[..width + right_width].to_vec());
+
+      let mut keys = vec![];
+      let mut residual = vec![];
+      for condition in &join_conditions[i] {
+        let condition = renumber(condition);
+        match equi_join_key(&condition, width, right_width, &affinities) {
+          Some(key) => keys.push(key),
+          None => residual.push(condition),
+        }
+      }
+
+      let predicate = and_all(residual);
+      let outer = sources[i].outer;
+      operator = if keys.is_empty() {
+        Operator::NestedLoopJoin(NestedLoopJoin::new(
+          operator,
+          right,
+          right_width,
+          predicate,
+          evaluator,
+          outer,
+        ))
+      } else {
+        Operator::HashJoin(HashJoin::new(
+          operator,
+          right,
+          right_width,
+          keys,
+          predicate,
+          evaluator,
+          outer,
+        ))
+      };
+      width += right_width;
+    }
+
+    if let Some(filter) = and_all(filters.iter().map(renumber).collect()) {
+      operator = Operator::Filter(Filter::new(
+        operator,
+        filter,
+        Evaluator::new(affinities.clone()),
+      ));
+    }
+
+    let Some(exprs) = projection else {
+      return Ok((distinct(core, operator), columns));
+    };
+
+    let (operator, affinities, exprs) = match grouping {
+      None => (operator, affinities, exprs),
+      Some((group_by, having)) => {
+        // expressions over the scanned rows are rewritten over the aggregated
+        // rows: the group values followed by the aggregate values
+        let mut calls = vec![];
+        let mut lift = |e: &Expr| lift_aggregates(e, &group_by, &mut calls);
+        let exprs = exprs
+          .iter()
+          .map(&mut lift)
+          .collect::<anyhow::Result<Vec<_>>>()?;
+        for key in &mut sort_keys {
+          key.expr = lift(&key.expr)?;
+        }
+        let having = having.as_ref().map(&mut lift).transpose()?;
+
+        let scan_affinity = |e: &Expr| match e {
+          Expr::Alias(n) => affinities[*n as usize].clone(),
+          Expr::RowId => Type::Integer,
+          _ => Type::Blob,
+        };
+        let aggregate_affinities = group_by
+          .iter()
+          .map(scan_affinity)
+          .chain(calls.iter().map(|c| match c.function {
+            AggregateFunction::AnyValue => scan_affinity(&c.args[0]),
+            _ => Type::Blob,
+          }))
+          .collect::<Vec<_>>();
+
+        let operator = Operator::Aggregate(Aggregate::new(
+          operator,
+          group_by,
+          calls,
+          Evaluator::new(affinities),
+          self.sort_memory,
+        ));
+        let operator = match having {
+          Some(having) => Operator::Filter(Filter::new(
+            operator,
+            having,
+            Evaluator::new(aggregate_affinities.clone()),
+          )),
+          None => operator,
+        };
+        (operator, aggregate_affinities, exprs)
+      }
+    };
+
+    let operator = if sort_keys.is_empty() {
+      operator
     } else {
-      Operator::SeqScan(SeqScan::new(&columns, self.db.scanner(table.first_page)))
+      Operator::Sort(Sort::new(
+        operator,
+        sort_keys,
+        Evaluator::new(affinities.clone()),
+        self.sort_memory,
+      ))
     };
 
-    Ok(operator)
+    let operator = Operator::Project(Project::new(operator, exprs, Evaluator::new(affinities)));
+    Ok((distinct(core, operator), columns))
   }
-}
-fn compile_expr(c: Comparison, table: &TableMetadata) -> anyhow::Result<Expr> {
-  match &c.l {
-    Expr::Column(field) | Expr::Text(field) => {
-      let idx = table
+
+  /// Flattens the FROM clause into its tables, left to right
+  fn join_sources(&self, from: &SelectFrom) -> anyhow::Result<Vec<Source<'d>>> {
+    let join = match from {
+      SelectFrom::Table(table) => return Ok(vec![self.source(table)?]),
+      SelectFrom::Join(join) => join,
+    };
+
+    let mut sources = self.join_sources(&join.left)?;
+    let mut right = self.source(&join.right)?;
+    if sources
+      .iter()
+      .any(|s| s.name.eq_ignore_ascii_case(&right.name))
+    {
+      bail!("ambiguous table name: {}", right.name);
+    }
+
+    // USING and NATURAL compare the shared columns, the right table's copies are
+    // left out of `*` and unqualified names
+    let using = match (&join.constraint, join.natural) {
+      (Some(JoinConstraint::Using(columns)), _) => columns.clone(),
+      (_, true) => right
+        .table
         .columns
+        .iter()
+        .map(|c| c.name.clone())
+        .filter(|c| sources.iter().any(|s| s.has_column(c)))
+        .collect(),
+      _ => vec![],
+    };
+    let mut on = vec![];
+    for column in &using {
+      let left = sources
+        .iter()
+        .find(|s| s.has_column(column))
+        .filter(|_| right.has_column(column))
+        .with_context(|| {
+          format!("cannot join using column {column} - column not present in both tables")
+        })?;
+      on.push(Expr::Comparison(
+        Box::new(Expr::QualifiedColumn(left.name.clone(), column.clone())),
+        Ops::Eq,
+        Box::new(Expr::QualifiedColumn(right.name.clone(), column.clone())),
+      ));
+    }
+    if let Some(JoinConstraint::On(expr)) = &join.constraint {
+      on.push(expr.clone());
+    }
+
+    right.on = and_all(on);
+    right.outer = join.operator == JoinOperator::Left;
+    right.merged = using;
+    sources.push(right);
+    Ok(sources)
+  }
+
+  fn source(&self, table_ref: &TableRef) -> anyhow::Result<Source<'d>> {
+    let table = self
+      .db
+      .tables_metadata
+      .iter()
+      .find(|m| m.name.eq_ignore_ascii_case(&table_ref.name))
+      .with_context(|| format!("invalid table name: {}", table_ref.name))?;
+
+    Ok(Source {
+      table,
+      name: table_ref.alias.as_ref().unwrap_or(&table_ref.name).clone(),
+      outer: false,
+      on: None,
+      merged: vec![],
+    })
+  }
+
+  /// Scans `fields` of the rows of `table`, referred to as `name`, for which every
+  /// one of `conjuncts` holds
+  fn compile_scan(
+    &self,
+    name: &str,
+    table: &TableMetadata,
+    fields: &[Field],
+    conjuncts: Vec<Expr>,
+  ) -> anyhow::Result<Operator> {
+    let mut scanner = self.db.scanner(table.first_page);
+    let mut bounds = RowidBounds::default();
+    let predicate = match and_all(conjuncts.clone()) {
+      Some(predicate) => split_rowid_bounds(&predicate, table, &mut bounds),
+      None => None,
+    };
+
+    // a rowid range beats any index
+    if bounds.start.is_none() && bounds.end.is_none() {
+      if let Some(scan) = self.index_scan(name, table, fields, &conjuncts)? {
+        return Ok(scan);
+      }
+    }
+
+    if let Some(start) = bounds.start {
+      scanner.seek(start)?;
+    }
+    if let Some(end) = bounds.end {
+      scanner.stop_after(end);
+    }
+
+    let values = table_values(table)?;
+    let Some(predicate) = predicate else {
+      return Ok(Operator::SeqScan(
+        SeqScan::new(name, fields, scanner, values)
+          .with_strict_bounds(bounds.strict_start, bounds.strict_end),
+      ));
+    };
+    let predicate = record_columns(&predicate, table)?;
+    let evaluator = Evaluator::new(table.record_affinities());
+    Ok(Operator::SeqScanWithPredicate(
+      SeqScanWithPredicate::new(name, fields, scanner, values, predicate, evaluator)
+        .with_strict_bounds(bounds.strict_start, bounds.strict_end),
+    ))
+  }
+
+  /// Scans `fields` of `table` through the index whose leading columns `conjuncts`
+  /// constrain best, None if they constrain none. The index record is read alone
+  /// when it holds every field and every column of the remaining conjuncts.
+  fn index_scan(
+    &self,
+    name: &str,
+    table: &TableMetadata,
+    fields: &[Field],
+    conjuncts: &[Expr],
+  ) -> anyhow::Result<Option<Operator>> {
+    if table.without_rowid {
+      return Ok(None);
+    }
+
+    let mut best: Option<(IndexLookup, Option<Expr>)> = None;
+    for index in self.db.table_indexes(&table.name) {
+      // a partial index lacks the rows its condition excludes, and indexed
+      // expressions aren't matched against the conjuncts
+      if index.where_clause.is_some() || index.columns.iter().any(|c| c.expression.is_some()) {
+        continue;
+      }
+      let Some(mut lookup) = IndexLookup::new(index, table, conjuncts) else {
+        continue;
+      };
+
+      let residual = and_all(
+        conjuncts
+          .iter()
+          .enumerate()
+          .filter(|(i, _)| !lookup.used.contains(i))
+          .map(|(_, c)| c.clone())
+          .collect(),
+      );
+      let covers_residual = match &residual {
+        Some(residual) => index_columns(residual, index, table).is_ok(),
+        None => true,
+      };
+      lookup.covering = fields
+        .iter()
+        .map(|f| index_position(index, table, *f))
+        .collect::<Option<Vec<_>>>()
+        .filter(|_| covers_residual);
+
+      if best.as_ref().is_none_or(|(b, _)| lookup.rank() > b.rank()) {
+        best = Some((lookup, residual));
+      }
+    }
+    let Some((lookup, residual)) = best else {
+      return Ok(None);
+    };
+
+    let index = lookup.index;
+    let scanner = self.db.scanner(index.root_page);
+    let ranges = lookup.ranges();
+    let operator = match &lookup.covering {
+      Some(positions) => {
+        let affinities = index
+          .columns
+          .iter()
+          .map(|c| column_affinity(table, &c.name))
+          .chain([Type::Integer])
+          .collect();
+        let predicate = residual
+          .map(|residual| index_columns(&residual, index, table))
+          .transpose()?;
+        let fields = positions
+          .iter()
+          .map(|n| Field::Record(*n))
+          .collect::<Vec<_>>();
+        IndexScan::new(
+          name,
+          index,
+          scanner,
+          ranges,
+          &fields,
+          predicate,
+          Evaluator::new(affinities),
+        )
+      }
+      None => {
+        let predicate = residual
+          .map(|residual| record_columns(&residual, table))
+          .transpose()?;
+        IndexScan::new(
+          name,
+          index,
+          scanner,
+          ranges,
+          fields,
+          predicate,
+          Evaluator::new(table.record_affinities()),
+        )
+        .fetching_rows(self.db.scanner(table.first_page), table_values(table)?)
+      }
+    };
+    Ok(Some(Operator::IndexScan(Box::new(operator))))
+  }
+}
+
+/// What the conjuncts of a scan pin the leading columns of an index to: equal values,
+/// several for an IN list, then an optional range on the next column
+struct IndexLookup<'d> {
+  index: &'d IndexMetadata,
+  equal: Vec<Vec<OwnedValue>>,
+  /// bounds of the column after the equal ones, and whether they are inclusive
+  lower: Option<(OwnedValue, bool)>,
+  upper: Option<(OwnedValue, bool)>,
+  /// conjuncts the lookup checks
+  used: Vec<usize>,
+  /// index record position of each scanned field, when the index covers the scan
+  covering: Option<Vec<usize>>,
+}
+
+/// A conjunct constraining a single column to constant values
+enum ColumnTerm {
+  /// `column <op> value`
+  Compare(Ops, OwnedValue),
+  In(Vec<OwnedValue>),
+}
+
+impl<'d> IndexLookup<'d> {
+  fn new(index: &'d IndexMetadata, table: &TableMetadata, conjuncts: &[Expr]) -> Option<Self> {
+    let mut lookup = IndexLookup {
+      index,
+      equal: vec![],
+      lower: None,
+      upper: None,
+      used: vec![],
+      covering: None,
+    };
+
+    for column in &index.columns {
+      let affinity = column_affinity(table, &column.name);
+      let terms = conjuncts
         .iter()
         .enumerate()
-        .find(|(_, c)| c.name == *field)
-        .with_context(|| format!("invalid where field: {}", field))?
-        .0;
-      Ok(Expr::Comparison(
-        Box::new(Expr::Alias(idx as i64)),
-        c.op,
-        Box::new(c.r),
-      ))
+        .filter_map(|(i, c)| Some((i, column_term(c, &column.name, &affinity)?)))
+        .collect::<Vec<_>>();
+
+      let equal = terms.iter().find_map(|(i, term)| match term {
+        ColumnTerm::Compare(Ops::Eq, value) => Some((*i, vec![value.clone()])),
+        _ => None,
+      });
+      // a single IN list keeps the number of ranges down
+      let in_list = || {
+        terms.iter().find_map(|(i, term)| match term {
+          ColumnTerm::In(values) => Some((*i, values.clone())),
+          _ => None,
+        })
+      };
+      let has_in_list = lookup.equal.iter().any(|values| values.len() != 1);
+      if let Some((i, mut values)) = equal.or_else(|| in_list().filter(|_| !has_in_list)) {
+        // visit the values in index order
+        values.sort_by(|a, b| {
+          let ordering = compare_values(&a.as_value(), &b.as_value());
+          match column.descending {
+            true => ordering.reverse(),
+            false => ordering,
+          }
+        });
+        lookup.equal.push(values);
+        lookup.used.push(i);
+        continue;
+      }
+
+      for (i, term) in &terms {
+        match term {
+          ColumnTerm::Compare(op @ (Ops::Gt | Ops::Goe), value) if lookup.lower.is_none() => {
+            lookup.lower = Some((value.clone(), *op == Ops::Goe));
+          }
+          ColumnTerm::Compare(op @ (Ops::Lt | Ops::Loe), value) if lookup.upper.is_none() => {
+            lookup.upper = Some((value.clone(), *op == Ops::Loe));
+          }
+          _ => continue,
+        }
+        lookup.used.push(*i);
+      }
+      break;
+    }
+
+    (!lookup.used.is_empty()).then_some(lookup)
+  }
+
+  /// Lookups pinning more columns come first, then covering ones
+  fn rank(&self) -> (usize, bool, bool) {
+    (
+      self.equal.len(),
+      self.lower.is_some() || self.upper.is_some(),
+      self.covering.is_some(),
+    )
+  }
+
+  /// One range per combination of the equal values
+  fn ranges(&self) -> Vec<IndexRange> {
+    let mut prefixes = vec![vec![]];
+    for values in &self.equal {
+      prefixes = prefixes
+        .iter()
+        .flat_map(|prefix| {
+          values
+            .iter()
+            .map(|v| [prefix.clone(), vec![v.clone()]].concat())
+        })
+        .collect();
+    }
+
+    // NULLs sort first and are in no range
+    let (start, end) = match (&self.lower, &self.upper) {
+      (None, None) => (None, None),
+      (lower, upper) => {
+        let null = Some((OwnedValue::Null, false));
+        let descending = self.index.columns[self.equal.len()].descending;
+        match descending {
+          false => (lower.clone().or(null), upper.clone()),
+          true => (upper.clone(), lower.clone().or(null)),
+        }
+      }
+    };
+    let bound = |prefix: &Vec<OwnedValue>, bound: &Option<(OwnedValue, bool)>| match bound {
+      Some((value, inclusive)) => ([prefix.clone(), vec![value.clone()]].concat(), *inclusive),
+      None => (prefix.clone(), true),
+    };
+    prefixes
+      .iter()
+      .map(|prefix| {
+        let (start, start_inclusive) = bound(prefix, &start);
+        let (end, end_inclusive) = bound(prefix, &end);
+        IndexRange {
+          start,
+          start_inclusive,
+          end,
+          end_inclusive,
+        }
+      })
+      .collect()
+  }
+}
+
+/// Reads `column <op> constant`, `constant <op> column` or `column IN (constants)`
+/// with the constants converted to the column's affinity. Comparisons with NULL and
+/// NULLs of an IN list match nothing, they are left out.
+fn column_term(term: &Expr, column: &str, affinity: &Type) -> Option<ColumnTerm> {
+  let is_column = |e: &Expr| matches!(e, Expr::Column(name) if name.eq_ignore_ascii_case(column));
+  let value = |e: &Expr| {
+    let value = with_affinity(constant_value(e)?, affinity);
+    (value != OwnedValue::Null).then_some(value)
+  };
+
+  match term {
+    Expr::Comparison(l, op, r) if is_column(l) => Some(ColumnTerm::Compare(*op, value(r)?)),
+    Expr::Comparison(l, op, r) if is_column(r) => {
+      let op = match op {
+        Ops::Lt => Ops::Gt,
+        Ops::Gt => Ops::Lt,
+        Ops::Loe => Ops::Goe,
+        Ops::Goe => Ops::Loe,
+        op => *op,
+      };
+      Some(ColumnTerm::Compare(op, value(l)?))
+    }
+    Expr::InList {
+      expr,
+      list,
+      negated: false,
+    } if is_column(expr) => {
+      let mut values = vec![];
+      for item in list {
+        let item = with_affinity(constant_value(item)?, affinity);
+        let seen = values
+          .iter()
+          .any(|v: &OwnedValue| compare_values(&v.as_value(), &item.as_value()).is_eq());
+        if item != OwnedValue::Null && !seen {
+          values.push(item);
+        }
+      }
+      Some(ColumnTerm::In(values))
+    }
+    _ => None,
+  }
+}
+
+/// Expression of a column default, `CURRENT_TIMESTAMP` and the like are read from
+/// the clock as UTC text
+/// A statement that outputs no rows and changes nothing
+fn no_rows() -> PreparedQuery {
+  PreparedQuery {
+    columns: vec![],
+    operator: Operator::Values(Values::new(vec![])),
+  }
+}
+
+/// Names starting with `sqlite_` are kept for the objects sqlite creates
+fn check_object_name(name: &str) -> anyhow::Result<()> {
+  if name.to_ascii_lowercase().starts_with("sqlite_") {
+    bail!("object name reserved for internal use: {name}");
+  }
+  Ok(())
+}
+
+/// Whether `expr` reads the column `name`
+fn references_column(expr: &Expr, name: &str) -> bool {
+  let mut found = false;
+  let _ = resolve_columns(expr, &mut |_, column| {
+    found |= column.eq_ignore_ascii_case(name);
+    Ok(Expr::Null)
+  });
+  found
+}
+
+fn default_value(expr: &Expr) -> Expr {
+  let Expr::Column(name) = expr else {
+    return expr.clone();
+  };
+  let now = std::time::SystemTime::now()
+    .duration_since(std::time::UNIX_EPOCH)
+    .map_or(0, |d| d.as_secs() as i64);
+  let (days, seconds) = (now.div_euclid(86_400), now.rem_euclid(86_400));
+
+  // civil date from days since 1970-01-01, after Howard Hinnant's algorithm
+  let z = days + 719_468;
+  let era = z.div_euclid(146_097);
+  let doe = z - era * 146_097;
+  let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + (month <= 2) as i64;
+
+  let date = format!("{year:04}-{month:02}-{day:02}");
+  let time = format!(
+    "{:02}:{:02}:{:02}",
+    seconds / 3600,
+    seconds / 60 % 60,
+    seconds % 60
+  );
+  match name.to_ascii_lowercase().as_str() {
+    "current_date" => Expr::Text(date),
+    "current_time" => Expr::Text(time),
+    "current_timestamp" => Expr::Text(format!("{date} {time}")),
+    _ => expr.clone(),
+  }
+}
+
+/// Value of an expression that reads no column
+fn constant_value(expr: &Expr) -> Option<OwnedValue> {
+  let mut reads_columns = false;
+  resolve_columns(expr, &mut |_, _| {
+    reads_columns = true;
+    Ok(Expr::Null)
+  })
+  .ok()?;
+  if reads_columns {
+    return None;
+  }
+  let row: &[OwnedValue] = &[];
+  Evaluator::default().eval(expr, row).ok().map(Into::into)
+}
+
+/// Position of a field of `table` in the records of `index`, which end with the rowid
+fn index_position(index: &IndexMetadata, table: &TableMetadata, field: Field) -> Option<usize> {
+  match field {
+    Field::RowId => Some(index.columns.len()),
+    field => index
+      .columns
+      .iter()
+      .position(|c| table.field(&c.name) == Some(field)),
+  }
+}
+
+/// Resolves the columns of `expr` to their position in the records of `index`
+fn index_columns(
+  expr: &Expr,
+  index: &IndexMetadata,
+  table: &TableMetadata,
+) -> anyhow::Result<Expr> {
+  resolve_columns(expr, &mut |_, name| {
+    let field = table
+      .field(name)
+      .with_context(|| format!("invalid where field: {}", name))?;
+    let n = index_position(index, table, field)
+      .with_context(|| format!("{name} is not in index {}", index.name))?;
+    Ok(Expr::Alias(n as i64))
+  })
+}
+
+/// Declared affinity of a column of `table`
+fn column_affinity(table: &TableMetadata, name: &str) -> Type {
+  table
+    .columns
+    .iter()
+    .find(|c| c.name.eq_ignore_ascii_case(name))
+    .map_or(Type::Blob, |c| c.col_type.clone())
+}
+
+/// Converts a constant compared with a column of that affinity, as the evaluator does
+fn with_affinity(value: OwnedValue, affinity: &Type) -> OwnedValue {
+  match affinity {
+    Type::Integer | Type::Real | Type::Numeric | Type::Bool => {
+      numeric_affinity(value.as_value()).into()
     }
-    Expr::Comparison(_, _, _) => {
-      let left = compile_expr(c.l.as_comparison()?, table)?;
-      let right = compile_expr(c.r.as_comparison()?, table)?;
-      Ok(Expr::Comparison(Box::new(left), c.op, Box::new(right)))
+    Type::Text => text_affinity(value.as_value()).into(),
+    Type::Blob => value,
+  }
+}
+
+/// A table of the FROM clause
+struct Source<'d> {
+  table: &'d TableMetadata,
+  /// alias or table name
+  name: String,
+  /// right side of a left join
+  outer: bool,
+  /// join constraint with the tables before it
+  on: Option<Expr>,
+  /// columns joined by USING or NATURAL to a table before it
+  merged: Vec<String>,
+}
+
+impl Source<'_> {
+  fn has_column(&self, name: &str) -> bool {
+    self
+      .table
+      .columns
+      .iter()
+      .any(|c| c.name.eq_ignore_ascii_case(name))
+      && !self.merged.iter().any(|m| m.eq_ignore_ascii_case(name))
+  }
+
+  /// Result columns of `*`
+  fn push_columns(&self, exprs: &mut Vec<Expr>, col_names: &mut Vec<String>) {
+    for column in &self.table.columns {
+      if self
+        .merged
+        .iter()
+        .any(|m| m.eq_ignore_ascii_case(&column.name))
+      {
+        continue;
+      }
+      exprs.push(Expr::QualifiedColumn(
+        self.name.clone(),
+        column.name.clone(),
+      ));
+      col_names.push(column.name.clone());
     }
-    Expr::Alias(_) => unimplemented!(),
-    expr => anyhow::bail!("Expected a column or an alias. Got a value {expr:?}"),
   }
 }
+
+/// `expr` over a record of `table`, its columns resolved to record positions
+fn record_columns(expr: &Expr, table: &TableMetadata) -> anyhow::Result<Expr> {
+  resolve_columns(expr, &mut |_, name| match table
+    .field(name)
+    .with_context(|| format!("invalid where field: {}", name))?
+  {
+    Field::Record(idx) => Ok(Expr::Alias(idx as i64)),
+    Field::RowId => Ok(Expr::RowId),
+  })
+}
+
+/// Computes the virtual generated columns of the records of `table`
+fn table_values(table: &TableMetadata) -> anyhow::Result<TableValues> {
+  let generated = table
+    .columns
+    .iter()
+    .filter(|c| c.is_virtual())
+    .filter_map(|c| Some((c.generated()?.0, c.col_type.clone())))
+    .map(|(expr, affinity)| Ok((record_columns(expr, table)?, affinity)))
+    .collect::<anyhow::Result<Vec<_>>>()?;
+  let defaults = table
+    .columns
+    .iter()
+    .filter(|c| !c.is_virtual())
+    .map(|c| {
+      let default = c.constraints.iter().find_map(|c| match c {
+        ast::ColumnConstraint::Default(expr) => constant_value(&default_value(expr)),
+        _ => None,
+      });
+      storage_value(default.unwrap_or(OwnedValue::Null), &c.col_type)
+    })
+    .collect();
+  Ok(
+    TableValues::new(
+      table.stored_columns(),
+      generated,
+      Evaluator::new(table.record_affinities()),
+    )
+    .with_defaults(defaults),
+  )
+}
+
+/// Alias of a column in the joined rows before they are renumbered table by table
+fn column_slot(
+  slots: &mut Vec<(usize, Field)>,
+  sources: &[Source],
+  table: Option<&str>,
+  name: &str,
+) -> anyhow::Result<Expr> {
+  let slot = lookup_column(sources, table, name)?;
+  let idx = slots.iter().position(|s| *s == slot).unwrap_or_else(|| {
+    slots.push(slot);
+    slots.len() - 1
+  });
+  Ok(Expr::Alias(idx as i64))
+}
+
+/// Table index and field of a column, unqualified names must match a single table
+fn lookup_column(
+  sources: &[Source],
+  table: Option<&str>,
+  name: &str,
+) -> anyhow::Result<(usize, Field)> {
+  if let Some(table) = table {
+    return sources
+      .iter()
+      .position(|s| s.name.eq_ignore_ascii_case(table))
+      .and_then(|i| Some((i, sources[i].table.field(name)?)))
+      .with_context(|| format!("no such column: {table}.{name}"));
+  }
+
+  let mut found = sources
+    .iter()
+    .enumerate()
+    .filter(|(_, s)| !s.merged.iter().any(|m| m.eq_ignore_ascii_case(name)))
+    .filter_map(|(i, s)| Some((i, s.table.field(name)?)));
+  let column = found
+    .next()
+    .with_context(|| format!("invalid column name: {}", name))?;
+  if found.next().is_some() {
+    bail!("ambiguous column name: {name}");
+  }
+  Ok(column)
+}
+
+/// Name of a column as its table spells it, which sqlite names result columns
+/// reading it after. The rowid is named after the column aliasing it, if any,
+/// or as the query spells it.
+fn declared_name(sources: &[Source], table: Option<&str>, name: &str) -> String {
+  match lookup_column(sources, table, name) {
+    Ok((i, field)) => column_def(sources[i].table, field, name)
+      .map_or_else(|| name.to_string(), |def| def.name.clone()),
+    Err(_) => name.to_string(),
+  }
+}
+
+/// Definition of the column `field` of `table` named `name` reads: the column
+/// aliasing the rowid for the rowid, none when no column does
+fn column_def<'t>(
+  table: &'t TableMetadata,
+  field: Field,
+  name: &str,
+) -> Option<&'t ast::ColumnDef> {
+  match field {
+    Field::RowId => table.rowid_alias().map(|n| &table.columns[n]),
+    Field::Record(_) => table
+      .columns
+      .iter()
+      .find(|c| c.name.eq_ignore_ascii_case(name)),
+  }
+}
+
+/// Describes a result column, with its origin when it reads a table column as is
+fn query_column(sources: &[Source], expr: &Expr, name: &str) -> QueryColumn {
+  let (table, column) = match expr {
+    Expr::Column(column) => (None, column),
+    Expr::QualifiedColumn(table, column) => (Some(table.as_str()), column),
+    _ => return QueryColumn::computed(name),
+  };
+  let Ok((i, field)) = lookup_column(sources, table, column) else {
+    return QueryColumn::computed(name);
+  };
+
+  let table = sources[i].table;
+  let (column, declared_type) = match column_def(table, field, column) {
+    Some(def) => (def.name.clone(), def.type_name.clone()),
+    None => ("rowid".to_string(), None),
+  };
+  QueryColumn {
+    name: name.to_string(),
+    declared_type,
+    table: Some(table.name.clone()),
+    column: Some(column),
+  }
+}
+
+/// Sorted indexes of the tables `expr` reads
+fn referenced_tables(expr: &Expr, sources: &[Source]) -> anyhow::Result<Vec<usize>> {
+  let mut tables = vec![];
+  resolve_columns(expr, &mut |table, name| {
+    tables.push(lookup_column(sources, table, name)?.0);
+    Ok(Expr::Null)
+  })?;
+  tables.sort();
+  tables.dedup();
+  Ok(tables)
+}
+
+/// Drops the table of qualified columns, for terms that read a single table
+fn strip_qualifiers(expr: &Expr) -> anyhow::Result<Expr> {
+  resolve_columns(expr, &mut |_, name| Ok(Expr::Column(name.to_string())))
+}
+
+/// Terms of the top-level AND chain of `expr`
+fn and_terms(expr: &Expr) -> Vec<&Expr> {
+  match expr {
+    Expr::Comparison(l, Ops::And, r) => [and_terms(l), and_terms(r)].concat(),
+    expr => vec![expr],
+  }
+}
+
+fn and_all(terms: Vec<Expr>) -> Option<Expr> {
+  terms
+    .into_iter()
+    .reduce(|l, r| Expr::Comparison(Box::new(l), Ops::And, Box::new(r)))
+}
+
+/// Splits `left = right` into the keys of a hash join when each side reads one side
+/// of the join, and comparing them needs no affinity conversion
+fn equi_join_key(
+  condition: &Expr,
+  left_width: usize,
+  right_width: usize,
+  affinities: &[Type],
+) -> Option<(Expr, Expr)> {
+  let Expr::Comparison(l, Ops::Eq, r) = condition else {
+    return None;
+  };
+
+  // Some(true) for expressions over the right table only
+  let side = |expr: &Expr| {
+    let mut aliases = vec![];
+    map_aliases(expr, &mut |n| {
+      aliases.push(n as usize);
+      n
+    });
+    if aliases.is_empty() {
+      None
+    } else if aliases.iter().all(|&n| n < left_width) {
+      Some(false)
+    } else if aliases
+      .iter()
+      .all(|&n| (left_width..left_width + right_width).contains(&n))
+    {
+      Some(true)
+    } else {
+      None
+    }
+  };
+  let (l, r) = match (side(l)?, side(r)?) {
+    (false, true) => (l, r),
+    (true, false) => (r, l),
+    _ => return None,
+  };
+
+  let class = |expr: &Expr| match expr {
+    Expr::Alias(n) => match affinities[*n as usize] {
+      Type::Integer | Type::Real | Type::Numeric | Type::Bool => 0,
+      Type::Text => 1,
+      Type::Blob => 2,
+    },
+    _ => 2,
+  };
+  if class(l) != class(r) {
+    return None;
+  }
+  let right_key = map_aliases(r, &mut |n| n - left_width as i64);
+  Some((l.as_ref().clone(), right_key))
+}
+
+fn renumber_aliases(expr: &Expr, positions: &[usize]) -> Expr {
+  map_aliases(expr, &mut |n| positions[n as usize] as i64)
+}
+
+/// Replaces every `Expr::Alias(n)` of `expr` by `Expr::Alias(f(n))`
+fn map_aliases(expr: &Expr, f: &mut impl FnMut(i64) -> i64) -> Expr {
+  match expr {
+    Expr::Alias(n) => Expr::Alias(f(*n)),
+    Expr::Comparison(l, op, r) => Expr::Comparison(
+      Box::new(map_aliases(l, f)),
+      *op,
+      Box::new(map_aliases(r, f)),
+    ),
+    Expr::Unary(op, e) => Expr::Unary(*op, Box::new(map_aliases(e, f))),
+    Expr::Function(call) => Expr::Function(FunctionCall {
+      args: call.args.iter().map(|a| map_aliases(a, f)).collect(),
+      ..call.clone()
+    }),
+    Expr::InList {
+      expr,
+      list,
+      negated,
+    } => Expr::InList {
+      expr: Box::new(map_aliases(expr, f)),
+      list: list.iter().map(|e| map_aliases(e, f)).collect(),
+      negated: *negated,
+    },
+    expr => expr.clone(),
+  }
+}
+
+fn distinct(core: &ast::SelectCore, operator: Operator) -> Operator {
+  match core.distinct {
+    true => Operator::Distinct(Distinct::new(operator)),
+    false => operator,
+  }
+}
+
+fn ordinal(n: usize) -> String {
+  let suffix = match (n % 10, n % 100) {
+    (_, 11..=13) => "th",
+    (1, _) => "st",
+    (2, _) => "nd",
+    (3, _) => "rd",
+    _ => "th",
+  };
+  format!("{n}{suffix}")
+}
+
+fn compile_limit(operator: Operator, limit: &ast::Limit) -> anyhow::Result<Operator> {
+  // a negative limit is no limit, a negative offset no offset
+  let count = constant_integer(&limit.limit, "LIMIT")?;
+  let offset = match &limit.offset {
+    Some(offset) => constant_integer(offset, "OFFSET")?,
+    None => 0,
+  };
+
+  Ok(Operator::Limit(Limit::new(
+    operator,
+    usize::try_from(count).ok(),
+    usize::try_from(offset).unwrap_or_default(),
+  )))
+}
+
+/// Evaluates an expression that can't reference columns to an integer
+fn constant_integer(expr: &Expr, clause: &str) -> anyhow::Result<i64> {
+  let row: &[OwnedValue] = &[];
+  let value = Evaluator::default()
+    .eval(expr, row)
+    .with_context(|| format!("{clause} should be a constant expression"))?;
+  match numeric_affinity(value) {
+    Value::Int(i) => Ok(i),
+    value => bail!("datatype mismatch: {clause} should be an integer, got {value:?}"),
+  }
+}
+
+/// Inclusive rowid range the scanner can seek to
+#[derive(Debug, Default, Clone, Copy)]
+struct RowidBounds {
+  start: Option<i64>,
+  end: Option<i64>,
+  /// whether the query wrote the bounds as `rowid > x` and `rowid < x`
+  strict_start: bool,
+  strict_end: bool,
+}
+
+impl RowidBounds {
+  /// Narrows the range by `rowid <op> value`, returns false if the
+  /// comparison can't be expressed as a range
+  fn restrict(&mut self, op: Ops, value: i64) -> bool {
+    let (start, end) = match op {
+      Ops::Eq => (Some(value), Some(value)),
+      Ops::Goe => (Some(value), None),
+      Ops::Loe => (None, Some(value)),
+      Ops::Gt => match value.checked_add(1) {
+        Some(start) => (Some(start), None),
+        None => return false,
+      },
+      Ops::Lt => match value.checked_sub(1) {
+        Some(end) => (None, Some(end)),
+        None => return false,
+      },
+      _ => return false,
+    };
+
+    if start > self.start {
+      self.start = start;
+      self.strict_start = op == Ops::Gt;
+    }
+    if end.is_some() && self.end.is_none_or(|e| end < Some(e)) {
+      self.end = end;
+      self.strict_end = op == Ops::Lt;
+    }
+    true
+  }
+}
+
+/// Pulls `rowid <op> <int>` terms out of the top-level AND chain of a where clause
+/// into `bounds`, returning what is left of the predicate
+fn split_rowid_bounds(
+  expr: &Expr,
+  table: &TableMetadata,
+  bounds: &mut RowidBounds,
+) -> Option<Expr> {
+  let Expr::Comparison(l, op, r) = expr else {
+    return Some(expr.clone());
+  };
+
+  if *op == Ops::And {
+    let l = split_rowid_bounds(l, table, bounds);
+    let r = split_rowid_bounds(r, table, bounds);
+    return match (l, r) {
+      (Some(l), Some(r)) => Some(Expr::Comparison(Box::new(l), Ops::And, Box::new(r))),
+      (l, r) => l.or(r),
+    };
+  }
+
+  if let (Expr::Column(name), Expr::Int(value)) = (l.as_ref(), r.as_ref()) {
+    if table.field(name) == Some(Field::RowId) && bounds.restrict(*op, *value) {
+      return None;
+    }
+  }
+  Some(expr.clone())
+}
+
+/// Resolves an ORDER BY or GROUP BY term: a number picks that result column, a result
+/// column name or alias its expression, anything else is resolved against the table
+fn resolve_term(
+  term: &Expr,
+  clause: &str,
+  exprs: &[Expr],
+  col_names: &[String],
+  resolve: &mut impl FnMut(Option<&str>, &str) -> anyhow::Result<Expr>,
+) -> anyhow::Result<Expr> {
+  match term {
+    Expr::Int(n) => usize::try_from(*n - 1)
+      .ok()
+      .and_then(|n| exprs.get(n))
+      .cloned()
+      .with_context(|| {
+        format!(
+          "{clause} term out of range - should be between 1 and {}",
+          exprs.len()
+        )
+      }),
+    // result column names and aliases take precedence over table columns
+    Expr::Column(name) if col_names.iter().any(|c| c.eq_ignore_ascii_case(name)) => {
+      let n = col_names
+        .iter()
+        .position(|c| c.eq_ignore_ascii_case(name))
+        .unwrap();
+      Ok(exprs[n].clone())
+    }
+    expr => resolve_columns(expr, resolve),
+  }
+}
+
+fn contains_aggregate(expr: &Expr) -> bool {
+  match expr {
+    Expr::Function(f) => is_aggregate(f) || f.args.iter().any(contains_aggregate),
+    Expr::Comparison(l, _, r) => contains_aggregate(l) || contains_aggregate(r),
+    Expr::Unary(_, e) => contains_aggregate(e),
+    Expr::InList { expr, list, .. } => {
+      contains_aggregate(expr) || list.iter().any(contains_aggregate)
+    }
+    _ => false,
+  }
+}
+
+/// Rewrites an expression over the scanned rows into one over the rows of an
+/// `Aggregate`: group expressions become their group value, aggregate calls and
+/// bare columns are added to `calls` and become the value of that call
+fn lift_aggregates(
+  expr: &Expr,
+  group_by: &[Expr],
+  calls: &mut Vec<AggregateCall>,
+) -> anyhow::Result<Expr> {
+  if let Some(n) = group_by.iter().position(|g| g == expr) {
+    return Ok(Expr::Alias(n as i64));
+  }
+
+  let call = match expr {
+    Expr::Function(f) if is_aggregate(f) => {
+      if f.args.iter().any(contains_aggregate) {
+        bail!("misuse of aggregate function {}()", f.name);
+      }
+      AggregateCall {
+        function: AggregateFunction::from_name(&f.name, f.args.len())?,
+        distinct: f.distinct,
+        args: f.args.clone(),
+      }
+    }
+    Expr::Alias(_) | Expr::RowId => AggregateCall {
+      function: AggregateFunction::AnyValue,
+      distinct: false,
+      args: vec![expr.clone()],
+    },
+    Expr::Comparison(l, op, r) => {
+      return Ok(Expr::Comparison(
+        Box::new(lift_aggregates(l, group_by, calls)?),
+        *op,
+        Box::new(lift_aggregates(r, group_by, calls)?),
+      ))
+    }
+    Expr::Unary(op, e) => {
+      return Ok(Expr::Unary(
+        *op,
+        Box::new(lift_aggregates(e, group_by, calls)?),
+      ))
+    }
+    Expr::Function(f) => {
+      let args = f
+        .args
+        .iter()
+        .map(|a| lift_aggregates(a, group_by, calls))
+        .collect::<anyhow::Result<_>>()?;
+      return Ok(Expr::Function(FunctionCall { args, ..f.clone() }));
+    }
+    Expr::InList {
+      expr,
+      list,
+      negated,
+    } => {
+      return Ok(Expr::InList {
+        expr: Box::new(lift_aggregates(expr, group_by, calls)?),
+        list: list
+          .iter()
+          .map(|e| lift_aggregates(e, group_by, calls))
+          .collect::<anyhow::Result<_>>()?,
+        negated: *negated,
+      })
+    }
+    expr => return Ok(expr.clone()),
+  };
+
+  let n = calls.iter().position(|c| *c == call).unwrap_or_else(|| {
+    calls.push(call);
+    calls.len() - 1
+  });
+  Ok(Expr::Alias((group_by.len() + n) as i64))
+}
+
+/// Replaces the column names of `expr` by what `resolve` maps them to
+fn resolve_columns(
+  expr: &Expr,
+  resolve: &mut impl FnMut(Option<&str>, &str) -> anyhow::Result<Expr>,
+) -> anyhow::Result<Expr> {
+  Ok(match expr {
+    Expr::Column(name) => resolve(None, name)?,
+    Expr::QualifiedColumn(table, name) => resolve(Some(table), name)?,
+    Expr::Comparison(l, op, r) => Expr::Comparison(
+      Box::new(resolve_columns(l, resolve)?),
+      *op,
+      Box::new(resolve_columns(r, resolve)?),
+    ),
+    Expr::Unary(op, e) => Expr::Unary(*op, Box::new(resolve_columns(e, resolve)?)),
+    Expr::Function(f) => Expr::Function(FunctionCall {
+      args: f
+        .args
+        .iter()
+        .map(|a| resolve_columns(a, resolve))
+        .collect::<anyhow::Result<_>>()?,
+      ..f.clone()
+    }),
+    Expr::InList {
+      expr,
+      list,
+      negated,
+    } => Expr::InList {
+      expr: Box::new(resolve_columns(expr, resolve)?),
+      list: list
+        .iter()
+        .map(|e| resolve_columns(e, resolve))
+        .collect::<anyhow::Result<_>>()?,
+      negated: *negated,
+    },
+    expr => expr.clone(),
+  })
+}
@@ -0,0 +1,81 @@
+use crate::{
+  cursor::value::OwnedValue,
+  page::wal::CheckpointMode,
+  pager::{JournalMode, Pager},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PragmaAction {
+  /// `wal_checkpoint`, outputs whether it was blocked, the frames in the log
+  /// and the checkpointed ones
+  Checkpoint(CheckpointMode),
+  /// `wal_autocheckpoint`, sets the threshold when given and outputs it
+  AutoCheckpoint(Option<usize>),
+  /// `journal_mode`, switches the mode when given and outputs it
+  JournalMode(Option<JournalMode>),
+}
+
+/// Runs a pragma once its row is asked for. Outputs a single row.
+#[derive(Debug)]
+pub struct Pragma {
+  pub action: PragmaAction,
+  pager: Pager,
+  row: Option<Vec<OwnedValue>>,
+}
+
+impl Pragma {
+  pub fn new(action: PragmaAction, pager: Pager) -> Self {
+    Self {
+      action,
+      pager,
+      row: None,
+    }
+  }
+
+  /// Names of the columns of the output row
+  pub fn columns(&self) -> Vec<&'static str> {
+    match self.action {
+      PragmaAction::Checkpoint(_) => vec!["busy", "log", "checkpointed"],
+      PragmaAction::AutoCheckpoint(_) => vec!["wal_autocheckpoint"],
+      PragmaAction::JournalMode(_) => vec!["journal_mode"],
+    }
+  }
+
+  pub fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    if self.row.is_some() {
+      return Ok(None);
+    }
+
+    let row = match self.action {
+      PragmaAction::Checkpoint(mode) => {
+        // outside of WAL mode there is no log, which sqlite reports as -1
+        let (log, checkpointed) = match self.pager.checkpoint(mode)? {
+          Some((log, checkpointed)) => (log as i64, checkpointed as i64),
+          None => (-1, -1),
+        };
+        vec![
+          OwnedValue::Int(0),
+          OwnedValue::Int(log),
+          OwnedValue::Int(checkpointed),
+        ]
+      }
+      PragmaAction::AutoCheckpoint(frames) => {
+        if let Some(frames) = frames {
+          self.pager.set_wal_autocheckpoint(frames)?;
+        }
+        vec![OwnedValue::Int(self.pager.wal_autocheckpoint()? as i64)]
+      }
+      PragmaAction::JournalMode(mode) => {
+        if let Some(mode) = mode {
+          self.pager.set_journal_mode(mode)?;
+        }
+        let mode = match self.pager.journal_mode()? {
+          JournalMode::Delete => "delete",
+          JournalMode::Wal => "wal",
+        };
+        vec![OwnedValue::String(mode.to_string().into())]
+      }
+    };
+    Ok(Some(self.row.insert(row).as_slice()))
+  }
+}
@@ -0,0 +1,44 @@
+use crate::cursor::value::OwnedValue;
+
+use super::operator::Operator;
+
+/// A column of the rows a query outputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryColumn {
+  pub name: String,
+  /// declared type of the table column it reads
+  pub declared_type: Option<String>,
+  /// table the column is read from, None for computed values
+  pub table: Option<String>,
+  /// name of the table column it reads
+  pub column: Option<String>,
+}
+
+impl QueryColumn {
+  /// A column computed by the query rather than read from a table
+  pub fn computed(name: impl Into<String>) -> Self {
+    Self {
+      name: name.into(),
+      declared_type: None,
+      table: None,
+      column: None,
+    }
+  }
+}
+
+/// A compiled statement: the columns it outputs and the operator tree producing its rows
+#[derive(Debug)]
+pub struct PreparedQuery {
+  pub columns: Vec<QueryColumn>,
+  pub operator: Operator,
+}
+
+impl PreparedQuery {
+  pub fn column_names(&self) -> Vec<&str> {
+    self.columns.iter().map(|c| c.name.as_str()).collect()
+  }
+
+  pub fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    self.operator.next_row()
+  }
+}
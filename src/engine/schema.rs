@@ -0,0 +1,213 @@
+use anyhow::{bail, Context};
+
+use crate::{
+  cursor::{btree::BTree, record::serialize_record, scanner::Scanner, value::OwnedValue},
+  db::{SchemaEntry, SchemaKind},
+  dbheader::SCHEMA_COOKIE_OFFSET,
+  page::{page_buffer::PageBuffer, page_utils::PageType},
+  pager::Pager,
+};
+
+use super::{eval::TableValues, write::TableWriter};
+
+/// Root page of the sqlite_schema table
+const SCHEMA_ROOT_PAGE: usize = 1;
+
+#[derive(Debug, Clone)]
+pub enum SchemaAction {
+  /// `autoindexes` is the number of PRIMARY KEY and UNIQUE constraints backed
+  /// by a `sqlite_autoindex_<table>_<n>` index
+  CreateTable {
+    name: String,
+    sql: String,
+    without_rowid: bool,
+    autoindexes: usize,
+  },
+  /// removes the rows of a table and of the indexes and triggers on it, and
+  /// frees their b-trees
+  DropTable {
+    name: String,
+    entries: Vec<SchemaEntry>,
+  },
+  /// replaces the rows of a table and of the objects whose statements name it
+  AlterTable {
+    name: String,
+    entries: Vec<SchemaEntry>,
+  },
+}
+
+/// Rows of a table rewritten by `ALTER TABLE ... DROP COLUMN`: read with the
+/// values of the table as it was and deleted with its writer, then inserted
+/// without column `dropped` with the writer of the altered table
+#[derive(Debug)]
+pub struct RowRewrite {
+  pub old: TableWriter,
+  pub new: TableWriter,
+  pub values: TableValues,
+  pub dropped: usize,
+}
+
+/// Changes the schema once its row is asked for: writes the rows of
+/// sqlite_schema and the b-trees of the objects, then bumps the schema cookie.
+/// Outputs no rows.
+#[derive(Debug)]
+pub struct SchemaChange {
+  pub action: SchemaAction,
+  pub rewrite: Option<RowRewrite>,
+  pager: Pager,
+  done: bool,
+}
+
+impl SchemaChange {
+  pub fn new(action: SchemaAction, pager: Pager) -> Self {
+    Self {
+      action,
+      rewrite: None,
+      pager,
+      done: false,
+    }
+  }
+
+  /// Rewrites every row of the altered table before its schema row
+  pub fn with_rewrite(mut self, rewrite: RowRewrite) -> Self {
+    self.rewrite = Some(rewrite);
+    self
+  }
+
+  pub fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    if self.done {
+      return Ok(None);
+    }
+    self.done = true;
+
+    self.pager.begin_statement()?;
+    match self.apply() {
+      Ok(()) => self
+        .pager
+        .commit_statement()
+        .context("commit schema change")?,
+      Err(err) => {
+        self.pager.rollback_statement()?;
+        return Err(err);
+      }
+    }
+    Ok(None)
+  }
+
+  fn apply(&self) -> anyhow::Result<()> {
+    let schema = BTree::new(self.pager.clone(), SCHEMA_ROOT_PAGE);
+    match &self.action {
+      SchemaAction::CreateTable {
+        name,
+        sql,
+        without_rowid,
+        autoindexes,
+      } => {
+        let page_type = match without_rowid {
+          true => PageType::IndexLeaf,
+          false => PageType::TableLeaf,
+        };
+        let mut entries = vec![SchemaEntry {
+          rowid: 0,
+          kind: SchemaKind::Table,
+          name: name.clone(),
+          table_name: name.clone(),
+          root_page: self.create_btree(page_type)?,
+          sql: Some(sql.clone()),
+        }];
+        for n in 1..=*autoindexes {
+          entries.push(SchemaEntry {
+            rowid: 0,
+            kind: SchemaKind::Index,
+            name: format!("sqlite_autoindex_{name}_{n}"),
+            table_name: name.clone(),
+            root_page: self.create_btree(PageType::IndexLeaf)?,
+            sql: None,
+          });
+        }
+
+        for entry in entries {
+          let rowid = schema.max_rowid()?.unwrap_or(0) + 1;
+          if !schema.insert_row(rowid, &self.entry_record(&entry))? {
+            bail!("sqlite_schema rowid {rowid} is taken");
+          }
+        }
+      }
+      SchemaAction::DropTable { entries, .. } => {
+        for entry in entries {
+          if entry.root_page != 0 {
+            BTree::new(self.pager.clone(), entry.root_page).destroy()?;
+          }
+          schema.delete_row(entry.rowid)?;
+        }
+      }
+      SchemaAction::AlterTable { entries, .. } => {
+        if let Some(rewrite) = &self.rewrite {
+          rewrite_rows(rewrite)?;
+        }
+        for entry in entries {
+          if !schema.update_row(entry.rowid, &self.entry_record(entry))? {
+            bail!("sqlite_schema has no row {}", entry.rowid);
+          }
+        }
+      }
+    }
+
+    let cookie = self.pager.header_field(SCHEMA_COOKIE_OFFSET)?;
+    self
+      .pager
+      .set_header_field(SCHEMA_COOKIE_OFFSET, cookie.wrapping_add(1))
+  }
+
+  /// Allocates the root page of an empty b-tree
+  fn create_btree(&self, page_type: PageType) -> anyhow::Result<usize> {
+    let num = self.pager.allocate_page()?;
+    let mut page = PageBuffer::new(num, vec![0; self.pager.page_size()]);
+    page.rebuild(page_type, &[], None, self.pager.limits().usable_size);
+    self.pager.write_page(num, page.data)?;
+    Ok(num)
+  }
+
+  fn entry_record(&self, entry: &SchemaEntry) -> Vec<u8> {
+    let text = |s: &str| OwnedValue::String(s.to_string().into());
+    serialize_record(
+      &[
+        text(entry.kind.as_str()),
+        text(&entry.name),
+        text(&entry.table_name),
+        OwnedValue::Int(entry.root_page as i64),
+        entry.sql.as_deref().map_or(OwnedValue::Null, text),
+      ],
+      self.pager.text_encoding(),
+    )
+  }
+}
+
+fn rewrite_rows(rewrite: &RowRewrite) -> anyhow::Result<()> {
+  let table = &rewrite.old.table;
+  let fields = table
+    .columns
+    .iter()
+    .map(|c| table.field(&c.name).context("unresolved column"))
+    .collect::<anyhow::Result<Vec<_>>>()?;
+
+  // the rows are read before any is written back
+  let mut rows = vec![];
+  let mut scanner = Scanner::new(rewrite.old.pager().clone(), table.first_page);
+  while let Some(record) = scanner.next_record()? {
+    let row = fields
+      .iter()
+      .map(|f| rewrite.values.get(&record, *f))
+      .collect::<anyhow::Result<Vec<_>>>()?;
+    rows.push((row, record.row_id));
+  }
+
+  for (mut row, rowid) in rows {
+    rewrite.old.delete(row.clone(), rowid.unwrap_or_default())?;
+    row.remove(rewrite.dropped);
+    rewrite
+      .new
+      .insert(row, rowid.map_or(OwnedValue::Null, OwnedValue::Int))?;
+  }
+  Ok(())
+}
@@ -0,0 +1,480 @@
+use std::{
+  cmp::Ordering,
+  collections::BinaryHeap,
+  fs::File,
+  io::{BufReader, BufWriter, Read, Write},
+  path::PathBuf,
+  rc::Rc,
+  sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+};
+
+use anyhow::{bail, Context};
+
+use crate::{cursor::value::OwnedValue, sql::ast::Expr};
+
+use super::{eval::compare_values, eval::Evaluator, operator::Operator};
+
+/// Default amount of row data a sort keeps in memory before spilling to disk
+pub const DEFAULT_SORT_MEMORY: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct SortKey {
+  pub expr: Expr,
+  pub descending: bool,
+  pub nulls_first: bool,
+}
+
+/// Sorts the rows of `source` with a `Sorter`
+#[derive(Debug)]
+pub struct Sort {
+  pub source: Box<Operator>,
+  pub keys: Rc<[SortKey]>,
+  evaluator: Evaluator,
+  pub memory_budget: usize,
+  /// None until the source is consumed
+  sorted: Option<SortedRows>,
+  row_buffer: Vec<OwnedValue>,
+}
+
+impl Sort {
+  pub fn new(
+    source: Operator,
+    keys: Vec<SortKey>,
+    evaluator: Evaluator,
+    memory_budget: usize,
+  ) -> Self {
+    Self {
+      source: Box::new(source),
+      keys: keys.into(),
+      evaluator,
+      memory_budget,
+      sorted: None,
+      row_buffer: vec![],
+    }
+  }
+
+  /// Number of runs written to disk, 0 when the sort fit in memory
+  pub fn spilled_runs(&self) -> usize {
+    self.sorted.as_ref().map_or(0, SortedRows::spilled_runs)
+  }
+
+  pub fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    if self.sorted.is_none() {
+      let mut sorter = Sorter::new(self.keys.clone(), self.memory_budget);
+      while let Some(row) = self.source.next_row()? {
+        let keys = self
+          .keys
+          .iter()
+          .map(|k| Ok(self.evaluator.eval(&k.expr, row)?.into()))
+          .collect::<anyhow::Result<Vec<_>>>()?;
+        sorter.push(keys, row.to_vec())?;
+      }
+      self.sorted = Some(sorter.finish()?);
+    }
+
+    let Some(sorted) = self.sorted.as_mut().unwrap().next_row()? else {
+      return Ok(None);
+    };
+    self.row_buffer = sorted.row;
+    Ok(Some(&self.row_buffer))
+  }
+}
+
+/// Most runs a merge reads at once, each holds an open file
+pub const MERGE_FAN_IN: usize = 16;
+
+/// Sorts rows by precomputed key values. Rows are buffered until they go over
+/// `memory_budget` bytes, then the buffer is sorted and written to a temp file as
+/// a run; the runs are merged once every row was pushed, in several passes when
+/// there are more than `MERGE_FAN_IN`.
+#[derive(Debug)]
+pub struct Sorter {
+  keys: Rc<[SortKey]>,
+  memory_budget: usize,
+  buffer: Vec<SortedRow>,
+  buffered_bytes: usize,
+  runs: Vec<Run>,
+}
+
+/// Rows coming out of a `Sorter` in order
+#[derive(Debug)]
+pub enum SortedRows {
+  InMemory(std::vec::IntoIter<SortedRow>),
+  Merging(Merger),
+}
+
+#[derive(Debug)]
+pub struct SortedRow {
+  pub keys: Vec<OwnedValue>,
+  pub row: Vec<OwnedValue>,
+}
+
+impl Sorter {
+  /// Only the ordering of `keys` is used, their values are pushed with each row
+  pub fn new(keys: Rc<[SortKey]>, memory_budget: usize) -> Self {
+    Self {
+      keys,
+      memory_budget,
+      buffer: vec![],
+      buffered_bytes: 0,
+      runs: vec![],
+    }
+  }
+
+  pub fn push(&mut self, keys: Vec<OwnedValue>, row: Vec<OwnedValue>) -> anyhow::Result<()> {
+    let sorted = SortedRow { keys, row };
+    self.buffered_bytes += sorted.size();
+    self.buffer.push(sorted);
+
+    if self.buffered_bytes > self.memory_budget {
+      let run = self.spill()?;
+      self.runs.push(run);
+      self.buffered_bytes = 0;
+    }
+    Ok(())
+  }
+
+  pub fn finish(mut self) -> anyhow::Result<SortedRows> {
+    if self.runs.is_empty() {
+      self.sort_buffer();
+      return Ok(SortedRows::InMemory(self.buffer.into_iter()));
+    }
+    if !self.buffer.is_empty() {
+      let run = self.spill()?;
+      self.runs.push(run);
+    }
+
+    let spilled_runs = self.runs.len();
+    let mut runs = self.runs;
+    // merge consecutive groups into longer runs, which keeps the sort stable
+    while runs.len() > MERGE_FAN_IN {
+      let mut merged = vec![];
+      let mut rest = runs.into_iter().peekable();
+      while rest.peek().is_some() {
+        let group = rest.by_ref().take(MERGE_FAN_IN).collect();
+        let mut merger = Merger::new(group, self.keys.clone())?;
+        let mut writer = RunWriter::new()?;
+        while let Some(sorted) = merger.next()? {
+          writer.push(&sorted)?;
+        }
+        merged.push(writer.finish()?);
+      }
+      runs = merged;
+    }
+
+    let mut merger = Merger::new(runs, self.keys)?;
+    merger.spilled_runs = spilled_runs;
+    Ok(SortedRows::Merging(merger))
+  }
+
+  fn sort_buffer(&mut self) {
+    let keys = &self.keys;
+    self
+      .buffer
+      .sort_by(|a, b| compare_keys(keys, &a.keys, &b.keys));
+  }
+
+  /// Sorts the buffer and writes it out as a run, leaving the buffer empty
+  fn spill(&mut self) -> anyhow::Result<Run> {
+    self.sort_buffer();
+
+    let mut writer = RunWriter::new()?;
+    for sorted in self.buffer.drain(..) {
+      writer.push(&sorted)?;
+    }
+    writer.finish()
+  }
+}
+
+impl SortedRows {
+  pub fn next_row(&mut self) -> anyhow::Result<Option<SortedRow>> {
+    match self {
+      SortedRows::InMemory(rows) => Ok(rows.next()),
+      SortedRows::Merging(merger) => merger.next(),
+    }
+  }
+
+  pub fn spilled_runs(&self) -> usize {
+    match self {
+      SortedRows::InMemory(_) => 0,
+      SortedRows::Merging(merger) => merger.spilled_runs,
+    }
+  }
+}
+
+impl SortedRow {
+  /// Approximate memory held by the row
+  fn size(&self) -> usize {
+    self.keys.iter().chain(&self.row).map(value_size).sum()
+  }
+}
+
+/// Approximate memory held by a value
+pub fn value_size(value: &OwnedValue) -> usize {
+  std::mem::size_of::<OwnedValue>()
+    + match value {
+      OwnedValue::String(s) => s.len(),
+      OwnedValue::Blob(b) => b.len(),
+      _ => 0,
+    }
+}
+
+fn compare_keys(keys: &[SortKey], a: &[OwnedValue], b: &[OwnedValue]) -> Ordering {
+  for ((key, a), b) in keys.iter().zip(a).zip(b) {
+    let ordering = match (a, b) {
+      (OwnedValue::Null, OwnedValue::Null) => Ordering::Equal,
+      (OwnedValue::Null, _) if key.nulls_first => Ordering::Less,
+      (OwnedValue::Null, _) => Ordering::Greater,
+      (_, OwnedValue::Null) if key.nulls_first => Ordering::Greater,
+      (_, OwnedValue::Null) => Ordering::Less,
+      (a, b) if key.descending => compare_values(&b.as_value(), &a.as_value()),
+      (a, b) => compare_values(&a.as_value(), &b.as_value()),
+    };
+    if ordering.is_ne() {
+      return ordering;
+    }
+  }
+  Ordering::Equal
+}
+
+/// A sorted run on disk
+#[derive(Debug)]
+struct Run {
+  file: TempFile,
+}
+
+/// Writes rows, in order, to a new run
+struct RunWriter {
+  file: TempFile,
+  writer: BufWriter<File>,
+}
+
+/// A run being merged
+#[derive(Debug)]
+struct RunReader {
+  reader: BufReader<File>,
+  _file: TempFile,
+}
+
+impl Run {
+  fn open(self) -> anyhow::Result<RunReader> {
+    Ok(RunReader {
+      reader: BufReader::new(self.file.open_read()?),
+      _file: self.file,
+    })
+  }
+}
+
+impl RunWriter {
+  fn new() -> anyhow::Result<Self> {
+    let file = TempFile::new()?;
+    let writer = BufWriter::new(file.open_write()?);
+    Ok(Self { file, writer })
+  }
+
+  fn push(&mut self, sorted: &SortedRow) -> anyhow::Result<()> {
+    write_values(&mut self.writer, &sorted.keys)?;
+    write_values(&mut self.writer, &sorted.row)
+  }
+
+  fn finish(mut self) -> anyhow::Result<Run> {
+    self.writer.flush().context("write sort run")?;
+    Ok(Run { file: self.file })
+  }
+}
+
+impl RunReader {
+  fn next(&mut self) -> anyhow::Result<Option<SortedRow>> {
+    let Some(keys) = read_values(&mut self.reader)? else {
+      return Ok(None);
+    };
+    let row = read_values(&mut self.reader)?.context("truncated sort run")?;
+    Ok(Some(SortedRow { keys, row }))
+  }
+}
+
+/// K-way merge of sorted runs
+#[derive(Debug)]
+pub struct Merger {
+  runs: Vec<RunReader>,
+  heap: BinaryHeap<HeapEntry>,
+  /// runs the sorter wrote before merging them down to these
+  spilled_runs: usize,
+}
+
+#[derive(Debug)]
+struct HeapEntry {
+  sorted: SortedRow,
+  run: usize,
+  keys: Rc<[SortKey]>,
+}
+
+impl Ord for HeapEntry {
+  fn cmp(&self, other: &Self) -> Ordering {
+    // BinaryHeap is a max-heap, reverse to pop the smallest row first. Ties go
+    // to the earlier run, which keeps the sort stable.
+    compare_keys(&self.keys, &self.sorted.keys, &other.sorted.keys)
+      .then(self.run.cmp(&other.run))
+      .reverse()
+  }
+}
+
+impl PartialOrd for HeapEntry {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl PartialEq for HeapEntry {
+  fn eq(&self, other: &Self) -> bool {
+    self.cmp(other).is_eq()
+  }
+}
+
+impl Eq for HeapEntry {}
+
+impl Merger {
+  fn new(runs: Vec<Run>, keys: Rc<[SortKey]>) -> anyhow::Result<Self> {
+    let mut runs = runs
+      .into_iter()
+      .map(Run::open)
+      .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut heap = BinaryHeap::with_capacity(runs.len());
+    for (run, reader) in runs.iter_mut().enumerate() {
+      if let Some(sorted) = reader.next()? {
+        heap.push(HeapEntry {
+          sorted,
+          run,
+          keys: keys.clone(),
+        });
+      }
+    }
+    let spilled_runs = runs.len();
+    Ok(Self {
+      runs,
+      heap,
+      spilled_runs,
+    })
+  }
+
+  fn next(&mut self) -> anyhow::Result<Option<SortedRow>> {
+    let Some(entry) = self.heap.pop() else {
+      return Ok(None);
+    };
+
+    if let Some(sorted) = self.runs[entry.run].next()? {
+      self.heap.push(HeapEntry {
+        sorted,
+        run: entry.run,
+        keys: entry.keys.clone(),
+      });
+    }
+    Ok(Some(entry.sorted))
+  }
+}
+
+/// A file in the system temp directory, removed when dropped
+#[derive(Debug)]
+struct TempFile {
+  path: PathBuf,
+}
+
+impl TempFile {
+  fn new() -> anyhow::Result<Self> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, AtomicOrdering::Relaxed);
+    let path = std::env::temp_dir().join(format!("rqlite-sort-{}-{n}", std::process::id()));
+    File::create(&path).with_context(|| format!("create temp file {}", path.display()))?;
+    Ok(Self { path })
+  }
+
+  fn open_write(&self) -> anyhow::Result<File> {
+    File::create(&self.path).context("open sort run for writing")
+  }
+
+  fn open_read(&self) -> anyhow::Result<File> {
+    File::open(&self.path).context("open sort run for reading")
+  }
+}
+
+impl Drop for TempFile {
+  fn drop(&mut self) {
+    let _ = std::fs::remove_file(&self.path);
+  }
+}
+
+const NULL_TAG: u8 = 0;
+const INT_TAG: u8 = 1;
+const FLOAT_TAG: u8 = 2;
+const STRING_TAG: u8 = 3;
+const BLOB_TAG: u8 = 4;
+const BOOL_TAG: u8 = 5;
+
+fn write_values(writer: &mut impl Write, values: &[OwnedValue]) -> anyhow::Result<()> {
+  writer.write_all(&(values.len() as u32).to_be_bytes())?;
+  for value in values {
+    match value {
+      OwnedValue::Null => writer.write_all(&[NULL_TAG])?,
+      OwnedValue::Int(i) => {
+        writer.write_all(&[INT_TAG])?;
+        writer.write_all(&i.to_be_bytes())?;
+      }
+      OwnedValue::Float(f) => {
+        writer.write_all(&[FLOAT_TAG])?;
+        writer.write_all(&f.to_be_bytes())?;
+      }
+      OwnedValue::String(s) => {
+        writer.write_all(&[STRING_TAG])?;
+        writer.write_all(&(s.len() as u32).to_be_bytes())?;
+        writer.write_all(s.as_bytes())?;
+      }
+      OwnedValue::Blob(b) => {
+        writer.write_all(&[BLOB_TAG])?;
+        writer.write_all(&(b.len() as u32).to_be_bytes())?;
+        writer.write_all(b)?;
+      }
+      OwnedValue::Bool(b) => writer.write_all(&[BOOL_TAG, *b as u8])?,
+    }
+  }
+  Ok(())
+}
+
+/// Reads back a row written by `write_values`, None at the end of the run
+fn read_values(reader: &mut impl Read) -> anyhow::Result<Option<Vec<OwnedValue>>> {
+  let mut len = [0; 4];
+  match reader.read_exact(&mut len) {
+    Ok(()) => {}
+    Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+    Err(e) => return Err(e).context("read sort run"),
+  }
+
+  let mut read_bytes = |n: usize| -> anyhow::Result<Vec<u8>> {
+    let mut buffer = vec![0; n];
+    reader.read_exact(&mut buffer).context("read sort run")?;
+    Ok(buffer)
+  };
+
+  let count = u32::from_be_bytes(len) as usize;
+  let mut values = Vec::with_capacity(count);
+  for _ in 0..count {
+    let value = match read_bytes(1)?[0] {
+      NULL_TAG => OwnedValue::Null,
+      INT_TAG => OwnedValue::Int(i64::from_be_bytes(read_bytes(8)?.try_into().unwrap())),
+      FLOAT_TAG => OwnedValue::Float(f64::from_be_bytes(read_bytes(8)?.try_into().unwrap())),
+      STRING_TAG => {
+        let len = u32::from_be_bytes(read_bytes(4)?.try_into().unwrap()) as usize;
+        OwnedValue::String(Rc::new(
+          String::from_utf8(read_bytes(len)?).context("invalid utf8 in sort run")?,
+        ))
+      }
+      BLOB_TAG => {
+        let len = u32::from_be_bytes(read_bytes(4)?.try_into().unwrap()) as usize;
+        OwnedValue::Blob(Rc::new(read_bytes(len)?))
+      }
+      BOOL_TAG => OwnedValue::Bool(read_bytes(1)?[0] != 0),
+      tag => bail!("invalid value tag in sort run: {tag}"),
+    };
+    values.push(value);
+  }
+  Ok(Some(values))
+}
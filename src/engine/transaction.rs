@@ -0,0 +1,48 @@
+use crate::{cursor::value::OwnedValue, pager::Pager, sql::ast::TransactionMode};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionAction {
+  /// the pager serves a single connection, so every mode starts the same transaction
+  Begin(TransactionMode),
+  Commit,
+  Rollback,
+  Savepoint(String),
+  Release(String),
+  RollbackTo(String),
+}
+
+/// Starts or ends a transaction, or takes, releases or rolls back to a savepoint
+/// in it, once its row is asked for. Outputs no rows.
+#[derive(Debug)]
+pub struct Transaction {
+  pub action: TransactionAction,
+  pager: Pager,
+  done: bool,
+}
+
+impl Transaction {
+  pub fn new(action: TransactionAction, pager: Pager) -> Self {
+    Self {
+      action,
+      pager,
+      done: false,
+    }
+  }
+
+  pub fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    if self.done {
+      return Ok(None);
+    }
+    self.done = true;
+
+    match &self.action {
+      TransactionAction::Begin(_) => self.pager.begin()?,
+      TransactionAction::Commit => self.pager.commit()?,
+      TransactionAction::Rollback => self.pager.rollback()?,
+      TransactionAction::Savepoint(name) => self.pager.savepoint(name)?,
+      TransactionAction::Release(name) => self.pager.release(name)?,
+      TransactionAction::RollbackTo(name) => self.pager.rollback_to(name)?,
+    }
+    Ok(None)
+  }
+}
@@ -0,0 +1,685 @@
+use anyhow::{bail, Context};
+
+use crate::{
+  cursor::{btree::BTree, record::serialize_record, scanner::Scanner, value::OwnedValue},
+  db::{IndexMetadata, TableMetadata},
+  pager::Pager,
+  sql::ast::{ColumnConstraint, Expr, TableConstraint, Type},
+};
+
+use super::{
+  eval::{numeric_affinity, text_affinity, Evaluator},
+  operator::{compare_prefix, compare_records, Operator},
+};
+
+/// An index kept up to date with its table
+#[derive(Debug)]
+pub struct IndexWriter {
+  pub index: IndexMetadata,
+  /// condition of a partial index, its columns resolved to `Expr::Alias(column)`
+  pub condition: Option<Expr>,
+  /// expressions of the indexed columns, resolved like the condition, None for
+  /// plain columns
+  pub expressions: Vec<Option<Expr>>,
+}
+
+/// A CHECK constraint, its columns resolved to `Expr::Alias(column)`
+#[derive(Debug)]
+pub struct Check {
+  pub expr: Expr,
+  /// the constraint as written, for the error message
+  pub text: String,
+}
+
+/// Writes rows to a table and its indexes, enforcing the table constraints.
+/// Rows hold the value of every column, in declaration order.
+#[derive(Debug)]
+pub struct TableWriter {
+  pub table: TableMetadata,
+  pub indexes: Vec<IndexWriter>,
+  pub checks: Vec<Check>,
+  /// generated columns and their expressions, resolved like the checks
+  pub generated: Vec<(usize, Expr)>,
+  pager: Pager,
+  evaluator: Evaluator,
+}
+
+impl TableWriter {
+  pub fn new(
+    table: TableMetadata,
+    indexes: Vec<IndexWriter>,
+    checks: Vec<Check>,
+    generated: Vec<(usize, Expr)>,
+    pager: Pager,
+  ) -> Self {
+    let evaluator = Evaluator::new(table.columns.iter().map(|c| c.col_type.clone()).collect());
+    Self {
+      table,
+      indexes,
+      checks,
+      generated,
+      pager,
+      evaluator,
+    }
+  }
+
+  pub fn pager(&self) -> &Pager {
+    &self.pager
+  }
+
+  /// Inserts a row, `rowid` is NULL to pick the next one unless the row sets the
+  /// INTEGER PRIMARY KEY column. Returns the rowid of the row.
+  pub fn insert(&self, mut row: Vec<OwnedValue>, rowid: OwnedValue) -> anyhow::Result<i64> {
+    for (value, column) in row.iter_mut().zip(&self.table.columns) {
+      *value = storage_value(std::mem::replace(value, OwnedValue::Null), &column.col_type);
+    }
+
+    let alias = self.table.rowid_alias();
+    let rowid = match alias.map(|n| &row[n]) {
+      Some(OwnedValue::Null) | None => rowid,
+      Some(value) => value.clone(),
+    };
+    let rowid = match storage_value(rowid, &Type::Integer) {
+      OwnedValue::Int(rowid) => rowid,
+      OwnedValue::Null if self.table.without_rowid => 0,
+      OwnedValue::Null => self.next_rowid()?,
+      _ => bail!("datatype mismatch"),
+    };
+    if let Some(n) = alias {
+      row[n] = OwnedValue::Int(rowid);
+    }
+    self.compute_generated(&mut row)?;
+
+    self.check_constraints(&row)?;
+    for index in &self.indexes {
+      self.check_unique(index, &row, rowid)?;
+    }
+
+    if self.table.without_rowid {
+      self.insert_without_rowid(&row)?;
+    } else {
+      let tree = BTree::new(self.pager.clone(), self.table.first_page);
+      if !tree.insert_row(
+        rowid,
+        &serialize_record(&self.table_record(&row), self.pager.text_encoding()),
+      )? {
+        let column = alias.map_or("rowid", |n| &self.table.columns[n].name);
+        bail!("UNIQUE constraint failed: {}.{column}", self.table.name);
+      }
+    }
+
+    for index in &self.indexes {
+      self.insert_index_entry(index, &row, rowid)?;
+    }
+    Ok(rowid)
+  }
+
+  fn next_rowid(&self) -> anyhow::Result<i64> {
+    let tree = BTree::new(self.pager.clone(), self.table.first_page);
+    match tree.max_rowid()? {
+      None => Ok(1),
+      Some(i64::MAX) => bail!("database or disk is full"),
+      Some(rowid) => Ok(rowid.max(0) + 1),
+    }
+  }
+
+  fn check_constraints(&self, row: &[OwnedValue]) -> anyhow::Result<()> {
+    // primary key columns of WITHOUT ROWID tables can't be NULL either
+    let pk = match self.table.without_rowid {
+      true => self.table.primary_key_columns(),
+      false => vec![],
+    };
+    for (n, column) in self.table.columns.iter().enumerate() {
+      let not_null = column.constraints.contains(&ColumnConstraint::NotNull) || pk.contains(&n);
+      if not_null && row[n] == OwnedValue::Null {
+        bail!(
+          "NOT NULL constraint failed: {}.{}",
+          self.table.name,
+          column.name
+        );
+      }
+    }
+
+    for check in &self.checks {
+      // a NULL result passes, only false fails
+      let value = self.evaluator.eval(&check.expr, row)?;
+      if super::eval::truth(&value) == Some(false) {
+        bail!("CHECK constraint failed: {}", check.text);
+      }
+    }
+    Ok(())
+  }
+
+  /// Fails when a unique index already has an entry with the indexed values of `row`
+  fn check_unique(
+    &self,
+    index: &IndexWriter,
+    row: &[OwnedValue],
+    rowid: i64,
+  ) -> anyhow::Result<()> {
+    if !index.index.unique || !self.is_indexed(index, row)? {
+      return Ok(());
+    }
+    let key = self.index_values(index, row, rowid)?;
+    // NULLs are distinct from each other
+    if key.contains(&OwnedValue::Null) {
+      return Ok(());
+    }
+
+    let descending = index
+      .index
+      .columns
+      .iter()
+      .map(|c| c.descending)
+      .collect::<Vec<_>>();
+    if self.contains_key(index.index.root_page, &key, &descending)? {
+      if index.expressions.iter().any(Option::is_some) {
+        bail!("UNIQUE constraint failed: index '{}'", index.index.name);
+      }
+      let columns = index
+        .index
+        .columns
+        .iter()
+        .map(|c| format!("{}.{}", self.table.name, c.name))
+        .collect::<Vec<_>>();
+      bail!("UNIQUE constraint failed: {}", columns.join(", "));
+    }
+    Ok(())
+  }
+
+  /// Whether an index b-tree has a record starting with `key`
+  fn contains_key(
+    &self,
+    root_page: usize,
+    key: &[OwnedValue],
+    descending: &[bool],
+  ) -> anyhow::Result<bool> {
+    let mut scanner = Scanner::new(self.pager.clone(), root_page);
+    scanner.seek_index(|record| Ok(compare_prefix(record, key, descending).is_lt()))?;
+    Ok(
+      scanner
+        .next_record()?
+        .is_some_and(|record| compare_prefix(&record, key, descending).is_eq()),
+    )
+  }
+
+  fn insert_without_rowid(&self, row: &[OwnedValue]) -> anyhow::Result<()> {
+    let record = self.table_record(row);
+    let pk = self.table.primary_key_columns();
+    let descending = self.primary_key_descending();
+    let key = &record[..pk.len()];
+    if self.contains_key(self.table.first_page, key, &descending)? {
+      let columns = pk
+        .iter()
+        .map(|&n| format!("{}.{}", self.table.name, self.table.columns[n].name))
+        .collect::<Vec<_>>();
+      bail!("UNIQUE constraint failed: {}", columns.join(", "));
+    }
+
+    let tree = BTree::new(self.pager.clone(), self.table.first_page);
+    tree.insert_record(
+      &serialize_record(&record, self.pager.text_encoding()),
+      |r| Ok(compare_prefix(r, key, &descending).is_lt()),
+    )
+  }
+
+  /// Values the record of `row` stores, in record order. The INTEGER PRIMARY KEY
+  /// column is stored as the rowid only, virtual generated columns not at all.
+  fn table_record(&self, row: &[OwnedValue]) -> Vec<OwnedValue> {
+    let alias = self.table.rowid_alias();
+    let mut record = vec![OwnedValue::Null; self.table.stored_columns()];
+    for (n, value) in row.iter().enumerate() {
+      if Some(n) != alias && !self.table.columns[n].is_virtual() {
+        record[self.table.record_position(n)] = value.clone();
+      }
+    }
+    record
+  }
+
+  /// Sets the value of every generated column of `row`
+  fn compute_generated(&self, row: &mut [OwnedValue]) -> anyhow::Result<()> {
+    for (n, expr) in &self.generated {
+      let value = OwnedValue::from(self.evaluator.eval(expr, &*row)?);
+      row[*n] = storage_value(value, &self.table.columns[*n].col_type);
+    }
+    Ok(())
+  }
+
+  /// Sort order of the primary key columns
+  fn primary_key_descending(&self) -> Vec<bool> {
+    let table_pk = self.table.constraints.iter().find_map(|c| match c {
+      TableConstraint::PrimaryKey(columns) => Some(columns),
+      _ => None,
+    });
+    match table_pk {
+      Some(columns) => columns.iter().map(|c| c.descending).collect(),
+      None => self
+        .table
+        .columns
+        .iter()
+        .flat_map(|c| &c.constraints)
+        .filter_map(|c| match c {
+          ColumnConstraint::PrimaryKey { descending, .. } => Some(*descending),
+          _ => None,
+        })
+        .collect(),
+    }
+  }
+
+  fn insert_index_entry(
+    &self,
+    index: &IndexWriter,
+    row: &[OwnedValue],
+    rowid: i64,
+  ) -> anyhow::Result<()> {
+    if !self.is_indexed(index, row)? {
+      return Ok(());
+    }
+
+    let (record, descending) = self.index_record(index, row, rowid)?;
+    let tree = BTree::new(self.pager.clone(), index.index.root_page);
+    tree.insert_record(
+      &serialize_record(&record, self.pager.text_encoding()),
+      |r| Ok(compare_prefix(r, &record, &descending).is_lt()),
+    )
+  }
+
+  /// Deletes a row, `row` holds the value of every column as stored
+  pub fn delete(&self, row: Vec<OwnedValue>, rowid: i64) -> anyhow::Result<()> {
+    for index in &self.indexes {
+      if !self.is_indexed(index, &row)? {
+        continue;
+      }
+      let (record, descending) = self.index_record(index, &row, rowid)?;
+      let tree = BTree::new(self.pager.clone(), index.index.root_page);
+      let deleted = tree.delete_record(
+        &serialize_record(&record, self.pager.text_encoding()),
+        |a, b| Ok(compare_records(a, b, &descending)),
+      )?;
+      if !deleted {
+        bail!("index {} has no entry for rowid {rowid}", index.index.name);
+      }
+    }
+
+    let tree = BTree::new(self.pager.clone(), self.table.first_page);
+    let deleted = match self.table.without_rowid {
+      true => {
+        let descending = self.primary_key_descending();
+        let record = serialize_record(&self.table_record(&row), self.pager.text_encoding());
+        tree.delete_record(&record, |a, b| Ok(compare_records(a, b, &descending)))?
+      }
+      false => tree.delete_row(rowid)?,
+    };
+    if !deleted {
+      bail!("table {} has no row {rowid}", self.table.name);
+    }
+    Ok(())
+  }
+
+  /// Replaces row `old` by `row`, moving it when its rowid changes. `rowid` is the
+  /// new rowid unless the row sets the INTEGER PRIMARY KEY column. Index entries
+  /// are only rewritten for the indexes whose records change.
+  pub fn update(
+    &self,
+    old: Vec<OwnedValue>,
+    old_rowid: i64,
+    mut row: Vec<OwnedValue>,
+    rowid: i64,
+  ) -> anyhow::Result<()> {
+    for (value, column) in row.iter_mut().zip(&self.table.columns) {
+      *value = storage_value(std::mem::replace(value, OwnedValue::Null), &column.col_type);
+    }
+    let alias = self.table.rowid_alias();
+    let rowid = match alias.map(|n| &row[n]) {
+      Some(OwnedValue::Int(rowid)) => *rowid,
+      Some(_) => bail!("datatype mismatch"),
+      None => rowid,
+    };
+    self.compute_generated(&mut row)?;
+    self.check_constraints(&row)?;
+
+    let mut changed = vec![];
+    for index in &self.indexes {
+      let old_entry = match self.is_indexed(index, &old)? {
+        true => Some(self.index_record(index, &old, old_rowid)?),
+        false => None,
+      };
+      let entry = match self.is_indexed(index, &row)? {
+        true => Some(self.index_record(index, &row, rowid)?),
+        false => None,
+      };
+      if old_entry == entry {
+        continue;
+      }
+      if let Some((record, descending)) = old_entry {
+        let tree = BTree::new(self.pager.clone(), index.index.root_page);
+        tree.delete_record(
+          &serialize_record(&record, self.pager.text_encoding()),
+          |a, b| Ok(compare_records(a, b, &descending)),
+        )?;
+      }
+      changed.push(index);
+    }
+    for index in &changed {
+      self.check_unique(index, &row, rowid)?;
+    }
+
+    let tree = BTree::new(self.pager.clone(), self.table.first_page);
+    if self.table.without_rowid {
+      let descending = self.primary_key_descending();
+      let record = serialize_record(&self.table_record(&old), self.pager.text_encoding());
+      tree.delete_record(&record, |a, b| Ok(compare_records(a, b, &descending)))?;
+      self.insert_without_rowid(&row)?;
+    } else {
+      let record = serialize_record(&self.table_record(&row), self.pager.text_encoding());
+      let written = match rowid == old_rowid {
+        true => tree.update_row(rowid, &record)?,
+        false => tree.delete_row(old_rowid)? && tree.insert_row(rowid, &record)?,
+      };
+      if !written {
+        let column = alias.map_or("rowid", |n| &self.table.columns[n].name);
+        bail!("UNIQUE constraint failed: {}.{column}", self.table.name);
+      }
+    }
+
+    for index in changed {
+      self.insert_index_entry(index, &row, rowid)?;
+    }
+    Ok(())
+  }
+
+  /// Record of `row` in an index with the sort order of its values. Records end
+  /// with the rowid, or the rest of the primary key of a WITHOUT ROWID table.
+  fn index_record(
+    &self,
+    index: &IndexWriter,
+    row: &[OwnedValue],
+    rowid: i64,
+  ) -> anyhow::Result<(Vec<OwnedValue>, Vec<bool>)> {
+    let mut record = self.index_values(index, row, rowid)?;
+    let mut descending = index
+      .index
+      .columns
+      .iter()
+      .map(|c| c.descending)
+      .collect::<Vec<_>>();
+    if self.table.without_rowid {
+      for n in self.table.primary_key_columns() {
+        let name = &self.table.columns[n].name;
+        if !index
+          .index
+          .columns
+          .iter()
+          .any(|c| c.name.eq_ignore_ascii_case(name))
+        {
+          record.push(row[n].clone());
+        }
+      }
+    } else {
+      record.push(OwnedValue::Int(rowid));
+    }
+    descending.resize(record.len(), false);
+    Ok((record, descending))
+  }
+
+  /// Whether `row` belongs in the index, rows failing the condition of a partial index don't
+  fn is_indexed(&self, index: &IndexWriter, row: &[OwnedValue]) -> anyhow::Result<bool> {
+    match &index.condition {
+      Some(condition) => self.evaluator.is_true(condition, row),
+      None => Ok(true),
+    }
+  }
+
+  /// Values of the indexed columns and expressions of `row`
+  fn index_values(
+    &self,
+    index: &IndexWriter,
+    row: &[OwnedValue],
+    rowid: i64,
+  ) -> anyhow::Result<Vec<OwnedValue>> {
+    let index_name = &index.index.name;
+    index
+      .index
+      .columns
+      .iter()
+      .zip(&index.expressions)
+      .map(|(c, expression)| {
+        if let Some(expr) = expression {
+          return Ok(OwnedValue::from(self.evaluator.eval(expr, row)?));
+        }
+        let n = self
+          .table
+          .columns
+          .iter()
+          .position(|col| col.name.eq_ignore_ascii_case(&c.name));
+        match n {
+          Some(n) => Ok(row[n].clone()),
+          None
+            if matches!(
+              c.name.to_ascii_lowercase().as_str(),
+              "rowid" | "oid" | "_rowid_"
+            ) =>
+          {
+            Ok(OwnedValue::Int(rowid))
+          }
+          None => bail!("index {index_name} has unknown column {}", c.name),
+        }
+      })
+      .collect()
+  }
+}
+
+/// Converts a value to the storage class a column of that affinity keeps it in
+pub fn storage_value(value: OwnedValue, affinity: &Type) -> OwnedValue {
+  match affinity {
+    Type::Integer | Type::Numeric | Type::Bool => {
+      match OwnedValue::from(numeric_affinity(value.as_value())) {
+        // reals without a fractional part are stored as integers
+        OwnedValue::Float(f) if f.fract() == 0.0 && f.abs() < 9.2e18 => OwnedValue::Int(f as i64),
+        value => value,
+      }
+    }
+    Type::Real => match OwnedValue::from(numeric_affinity(value.as_value())) {
+      OwnedValue::Int(i) => OwnedValue::Float(i as f64),
+      value => value,
+    },
+    Type::Text => text_affinity(value.as_value()).into(),
+    Type::Blob => value,
+  }
+}
+
+/// Inserts the rows of `source` into a table and commits, the source is read in
+/// full first so that it may select from the table being written. Outputs no rows.
+#[derive(Debug)]
+pub struct Insert {
+  pub writer: TableWriter,
+  pub source: Box<Operator>,
+  /// value of every table column, over the source rows
+  pub exprs: Vec<Expr>,
+  /// rowid of the new rows when the statement names the rowid column
+  pub rowid: Option<Expr>,
+  evaluator: Evaluator,
+  done: bool,
+}
+
+impl Insert {
+  pub fn new(
+    writer: TableWriter,
+    source: Operator,
+    exprs: Vec<Expr>,
+    rowid: Option<Expr>,
+    evaluator: Evaluator,
+  ) -> Self {
+    Self {
+      writer,
+      source: Box::new(source),
+      exprs,
+      rowid,
+      evaluator,
+      done: false,
+    }
+  }
+
+  pub fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    if self.done {
+      return Ok(None);
+    }
+    self.done = true;
+
+    let mut rows = vec![];
+    while let Some(row) = self.source.next_row()? {
+      let values = self
+        .exprs
+        .iter()
+        .map(|e| self.evaluator.eval(e, row).map(OwnedValue::from))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+      let rowid = match &self.rowid {
+        Some(expr) => self.evaluator.eval(expr, row)?.into(),
+        None => OwnedValue::Null,
+      };
+      rows.push((values, rowid));
+    }
+
+    let pager = self.writer.pager();
+    pager.begin_statement()?;
+    let inserted = rows
+      .into_iter()
+      .try_for_each(|(values, rowid)| self.writer.insert(values, rowid).map(|_| ()));
+    match inserted {
+      Ok(()) => pager.commit_statement().context("commit insert")?,
+      Err(err) => {
+        pager.rollback_statement()?;
+        return Err(err);
+      }
+    }
+    Ok(None)
+  }
+}
+
+/// Deletes the rows of `source` from a table and commits. Source rows hold the
+/// rowid, unless the table is WITHOUT ROWID, followed by the value of every column.
+/// Outputs no rows.
+#[derive(Debug)]
+pub struct Delete {
+  pub writer: TableWriter,
+  pub source: Box<Operator>,
+  done: bool,
+}
+
+impl Delete {
+  pub fn new(writer: TableWriter, source: Operator) -> Self {
+    Self {
+      writer,
+      source: Box::new(source),
+      done: false,
+    }
+  }
+
+  pub fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    if self.done {
+      return Ok(None);
+    }
+    self.done = true;
+
+    // the scan reads pages the deletes rewrite, so it completes first
+    let mut rows = vec![];
+    while let Some(row) = self.source.next_row()? {
+      rows.push(match (self.writer.table.without_rowid, row) {
+        (true, row) => (row.to_vec(), 0),
+        (false, [OwnedValue::Int(rowid), row @ ..]) => (row.to_vec(), *rowid),
+        _ => bail!("deleted row of {} without rowid", self.writer.table.name),
+      });
+    }
+
+    let pager = self.writer.pager();
+    pager.begin_statement()?;
+    let deleted = rows
+      .into_iter()
+      .try_for_each(|(row, rowid)| self.writer.delete(row, rowid));
+    match deleted {
+      Ok(()) => pager.commit_statement().context("commit delete")?,
+      Err(err) => {
+        pager.rollback_statement()?;
+        return Err(err);
+      }
+    }
+    Ok(None)
+  }
+}
+
+/// Updates the rows of `source` and commits. Source rows are laid out as for
+/// `Delete`, the new value of every column is evaluated over them. Outputs no rows.
+#[derive(Debug)]
+pub struct Update {
+  pub writer: TableWriter,
+  pub source: Box<Operator>,
+  /// new value of every table column
+  pub exprs: Vec<Expr>,
+  /// new rowid when the statement sets the rowid column
+  pub rowid: Option<Expr>,
+  evaluator: Evaluator,
+  done: bool,
+}
+
+impl Update {
+  pub fn new(
+    writer: TableWriter,
+    source: Operator,
+    exprs: Vec<Expr>,
+    rowid: Option<Expr>,
+    evaluator: Evaluator,
+  ) -> Self {
+    Self {
+      writer,
+      source: Box::new(source),
+      exprs,
+      rowid,
+      evaluator,
+      done: false,
+    }
+  }
+
+  pub fn next_row(&mut self) -> anyhow::Result<Option<&[OwnedValue]>> {
+    if self.done {
+      return Ok(None);
+    }
+    self.done = true;
+
+    let without_rowid = self.writer.table.without_rowid;
+    let mut rows = vec![];
+    while let Some(row) = self.source.next_row()? {
+      let (old, old_rowid) = match (without_rowid, row) {
+        (true, row) => (row.to_vec(), 0),
+        (false, [OwnedValue::Int(rowid), old @ ..]) => (old.to_vec(), *rowid),
+        _ => bail!("updated row of {} without rowid", self.writer.table.name),
+      };
+      let new = self
+        .exprs
+        .iter()
+        .map(|e| self.evaluator.eval(e, row).map(OwnedValue::from))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+      let rowid = match &self.rowid {
+        Some(expr) => match storage_value(self.evaluator.eval(expr, row)?.into(), &Type::Integer) {
+          OwnedValue::Int(rowid) => rowid,
+          _ => bail!("datatype mismatch"),
+        },
+        None => old_rowid,
+      };
+      rows.push((old, old_rowid, new, rowid));
+    }
+
+    let pager = self.writer.pager();
+    pager.begin_statement()?;
+    let updated = rows
+      .into_iter()
+      .try_for_each(|(old, old_rowid, new, rowid)| self.writer.update(old, old_rowid, new, rowid));
+    match updated {
+      Ok(()) => pager.commit_statement().context("commit update")?,
+      Err(err) => {
+        pager.rollback_statement()?;
+        return Err(err);
+      }
+    }
+    Ok(None)
+  }
+}
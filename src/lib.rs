@@ -7,22 +7,58 @@ pub mod sql;
 
 pub use page::pager;
 
-/// read variable bytes from buffer
+/// read variable bytes from buffer. Varints are big-endian, 7 bits per byte
+/// with the high bit flagging a continuation, except the 9th byte which holds 8 bits
 /// # RETURNS (size, value)
 fn read_varint_at(buffer: &[u8], mut offset: usize) -> (u8, i64) {
   let mut size = 0;
   let mut result = 0;
 
   while size < 8 && buffer[offset] >= 0b1000_0000 {
-    result |= ((buffer[offset] as i64) & 0b0111_1111) << (7 * size);
+    result = (result << 7) | ((buffer[offset] as i64) & 0b0111_1111);
     offset += 1;
     size += 1;
   }
 
-  result |= (buffer[offset] as i64) << (7 * size);
+  if size == 8 {
+    result = (result << 8) | buffer[offset] as i64;
+  } else {
+    result = (result << 7) | buffer[offset] as i64;
+  }
   (size + 1, result)
 }
 
+/// Appends `value` as a varint, the encoding `read_varint_at` reads
+fn write_varint(buffer: &mut Vec<u8>, value: i64) {
+  let value = value as u64;
+  // the 9th byte holds 8 bits, the 8 before it 7 bits each
+  if value >> 56 != 0 {
+    let mut bytes = [0; 9];
+    bytes[8] = value as u8;
+    for (i, byte) in bytes[..8].iter_mut().enumerate() {
+      *byte = 0b1000_0000 | ((value >> (8 + 7 * (7 - i))) & 0b0111_1111) as u8;
+    }
+    buffer.extend_from_slice(&bytes);
+    return;
+  }
+
+  let mut bytes = vec![(value & 0b0111_1111) as u8];
+  let mut rest = value >> 7;
+  while rest != 0 {
+    bytes.push(0b1000_0000 | (rest & 0b0111_1111) as u8);
+    rest >>= 7;
+  }
+  buffer.extend(bytes.iter().rev());
+}
+
+/// Number of bytes `write_varint` uses for `value`
+fn varint_size(value: i64) -> usize {
+  match value as u64 {
+    v if v >> 56 != 0 => 9,
+    v => (64 - v.leading_zeros() as usize).max(1).div_ceil(7),
+  }
+}
+
 /// Read the next 2 bytes from the offset
 fn read_be_word_at(input: &[u8], offset: usize) -> u16 {
   u16::from_be_bytes(input[offset..offset + 2].try_into().unwrap())
@@ -47,6 +83,37 @@ fn test_lib_functions() {
   assert_eq!(size, 1);
   assert_eq!(value, 2);
 
+  let (size, value) = read_varint_at(&[0x81, 0x20], 0);
+  assert_eq!((size, value), (2, 160));
+
+  let (size, value) = read_varint_at(&[0xff; 9], 0);
+  assert_eq!((size, value), (9, -1));
+
+  for value in [
+    0,
+    1,
+    127,
+    128,
+    240,
+    2287,
+    16383,
+    16384,
+    1 << 55,
+    (1 << 56) - 1,
+    1 << 56,
+    -1,
+    i64::MIN,
+  ] {
+    let mut buffer = vec![];
+    write_varint(&mut buffer, value);
+    assert_eq!(buffer.len(), varint_size(value), "{value}");
+    assert_eq!(
+      read_varint_at(&buffer, 0),
+      (buffer.len() as u8, value),
+      "{value}"
+    );
+  }
+
   let buffer = [0x01, 0x00]; // 256 as big-endian u16
   let value = read_be_word_at(&buffer, 0);
   assert_eq!(value, 256);
@@ -1,10 +1,18 @@
 use std::io::{stdin, stdout, BufRead, Write};
 
 use anyhow::Context;
-use rust_sqlite::{db::Db, engine, sql};
+use rust_sqlite::{
+  db::{Db, DbOptions},
+  engine, sql,
+};
 
 fn main() -> anyhow::Result<()> {
-  let database = Db::from_file(std::env::args().nth(1).context("missing db file")?)?;
+  let path = std::env::args().nth(1).context("missing db file")?;
+  // like sqlite, a missing or empty file starts a new database
+  let database = match std::fs::metadata(&path) {
+    Ok(metadata) if metadata.len() > 0 => Db::from_file(&path)?,
+    _ => Db::create(&path, &DbOptions::default())?,
+  };
   cli(database)
 }
 
@@ -18,7 +26,11 @@ fn cli(mut db: Db) -> anyhow::Result<()> {
       ".help" => display_help(),
       ".exit" => break,
       ".tables" => display_tables(&mut db)?,
-      stmt => eval_query(&db, stmt).unwrap_or_else(|e| println!("Error: {e}")),
+      ".schema" => display_schema(&db),
+      cmd if cmd.starts_with(".indexes") => {
+        display_indexes(&db, cmd.trim_start_matches(".indexes").trim())
+      }
+      stmt => eval_query(&mut db, stmt).unwrap_or_else(|e| println!("Error: {e}")),
     }
 
     print_flushed("\nrqlite> ")?;
@@ -27,11 +39,17 @@ fn cli(mut db: Db) -> anyhow::Result<()> {
   Ok(())
 }
 
-fn eval_query(db: &Db, query: &str) -> anyhow::Result<()> {
+fn eval_query(db: &mut Db, query: &str) -> anyhow::Result<()> {
   let parsed_query = sql::parser::parse_statement(query, false)?;
-  let mut op = engine::plan::Planner::new(db).compile(&parsed_query)?;
+  let mut query = engine::plan::Planner::new(&*db).compile(&parsed_query)?;
 
-  while let Some(values) = op.next_row()? {
+  // statements that write output no rows
+  if !query.columns.is_empty() {
+    println!("{}", query.column_names().join("\t| "));
+    println!("-----------------------------------------------------------------------------------------------------------------------");
+  }
+
+  while let Some(values) = query.next_row()? {
     let formated = values
       .iter()
       .map(ToString::to_string)
@@ -41,7 +59,7 @@ fn eval_query(db: &Db, query: &str) -> anyhow::Result<()> {
     println!("{formated}");
   }
 
-  Ok(())
+  db.refresh_schema()
 }
 
 fn display_tables(db: &mut Db) -> anyhow::Result<()> {
@@ -52,10 +70,26 @@ fn display_tables(db: &mut Db) -> anyhow::Result<()> {
   Ok(())
 }
 
+fn display_schema(db: &Db) {
+  for sql in db.schema.iter().filter_map(|e| e.sql.as_ref()) {
+    println!("{sql};");
+  }
+}
+
+fn display_indexes(db: &Db, table: &str) {
+  for index in &db.indexes_metadata {
+    if table.is_empty() || index.table_name.eq_ignore_ascii_case(table) {
+      print!("{} ", &index.name)
+    }
+  }
+}
+
 fn display_help() {
   print!(
     "MAN PAGE!\n
         .tables -- display tables.
+        .schema -- display the create statements of every schema object.
+        .indexes [TABLE] -- display indexes, optionally only those of TABLE.
         .help -- display help.
         .exit -- exit REPL "
   )
@@ -0,0 +1,162 @@
+use std::{
+    collections::hash_map::RandomState,
+    fs::File,
+    hash::{BuildHasher, Hasher},
+    io::{Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+
+use crate::read_be_double_at;
+
+use super::lock::{self, LockLevel};
+
+/// First bytes of a rollback journal header
+pub const JOURNAL_MAGIC: [u8; 8] = [0xd9, 0xd5, 0x05, 0xf9, 0x20, 0xa1, 0x63, 0xd7];
+
+/// The journal header takes a whole sector, the page records start after it
+const SECTOR_SIZE: usize = 512;
+
+const RECORD_COUNT_OFFSET: usize = 8;
+const NONCE_OFFSET: usize = 12;
+const ORIGINAL_SIZE_OFFSET: usize = 16;
+const SECTOR_SIZE_OFFSET: usize = 20;
+const PAGE_SIZE_OFFSET: usize = 24;
+
+/// Rollback journal of the database at `db_path`, next to it as sqlite names it
+pub fn journal_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push("-journal");
+    PathBuf::from(path)
+}
+
+/// Writes and syncs a journal holding the original content of `pages`, from a
+/// database of `original_size` pages. Each record is the page number, the page
+/// and a checksum of sampled page bytes seeded with the header nonce.
+pub fn write_journal(
+    path: &Path,
+    page_size: usize,
+    original_size: usize,
+    pages: &[(usize, Vec<u8>)],
+) -> anyhow::Result<()> {
+    let nonce = RandomState::new().build_hasher().finish() as u32;
+
+    let mut header = vec![0; SECTOR_SIZE];
+    header[..JOURNAL_MAGIC.len()].copy_from_slice(&JOURNAL_MAGIC);
+    for (offset, value) in [
+        (RECORD_COUNT_OFFSET, pages.len()),
+        (NONCE_OFFSET, nonce as usize),
+        (ORIGINAL_SIZE_OFFSET, original_size),
+        (SECTOR_SIZE_OFFSET, SECTOR_SIZE),
+        (PAGE_SIZE_OFFSET, page_size),
+    ] {
+        header[offset..offset + 4].copy_from_slice(&(value as u32).to_be_bytes());
+    }
+
+    let mut content = header;
+    for (n, page) in pages {
+        content.extend_from_slice(&(*n as u32).to_be_bytes());
+        content.extend_from_slice(page);
+        content.extend_from_slice(&checksum(nonce, page).to_be_bytes());
+    }
+
+    let mut file = File::create(path).context("create journal")?;
+    file.write_all(&content).context("write journal")?;
+    file.sync_all().context("sync journal")
+}
+
+/// Plays back a hot journal, one left by a commit that did not complete: the
+/// saved pages are written back and the database is cut to its size before the
+/// commit. The journal is deleted once the database is synced. Returns whether
+/// there was one.
+///
+/// The caller holds the shared lock. A journal is only hot when no connection
+/// holds the reserved lock, otherwise its commit is still running. It is played
+/// back under the exclusive lock, which is dropped back to shared after.
+pub fn recover(db: &mut File, path: &Path) -> anyhow::Result<bool> {
+    if !path.exists() || lock::reserved_elsewhere(db)? {
+        return Ok(false);
+    }
+    lock::lock(db, LockLevel::Exclusive)?;
+    let recovered = play_back(db, path);
+    lock::lock(db, LockLevel::Shared)?;
+    recovered
+}
+
+fn play_back(db: &mut File, path: &Path) -> anyhow::Result<bool> {
+    let content = match std::fs::read(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err).context("read journal"),
+    };
+    // an empty or zeroed journal belongs to a finished commit
+    if content.len() < SECTOR_SIZE || !content.starts_with(&JOURNAL_MAGIC) {
+        std::fs::remove_file(path).context("delete journal")?;
+        return Ok(false);
+    }
+
+    let original_size = read_be_double_at(&content, ORIGINAL_SIZE_OFFSET) as usize;
+    let page_size = match read_be_double_at(&content, PAGE_SIZE_OFFSET) {
+        1 => 65536,
+        n if n.is_power_of_two() && n >= 512 => n as usize,
+        n => bail!("journal has an invalid page size: {n}"),
+    };
+    let sector_size = match read_be_double_at(&content, SECTOR_SIZE_OFFSET) as usize {
+        n if n.is_power_of_two() && n >= SECTOR_SIZE => n,
+        _ => SECTOR_SIZE,
+    };
+    let record_size = page_size + 8;
+
+    // sqlite starts a new segment with its own header, at the next sector, every
+    // time it syncs the journal before the commit ends
+    let mut offset = 0;
+    'segments: while content[offset..].starts_with(&JOURNAL_MAGIC) {
+        let header = &content[offset..];
+        let nonce = read_be_double_at(header, NONCE_OFFSET);
+        let records = match read_be_double_at(header, RECORD_COUNT_OFFSET) {
+            // the count is left unset when the journal is not synced
+            u32::MAX => (content.len() - offset).saturating_sub(sector_size) / record_size,
+            n => n as usize,
+        };
+        offset += sector_size;
+
+        for _ in 0..records {
+            let Some(record) = content.get(offset..offset + record_size) else {
+                break 'segments;
+            };
+            let n = read_be_double_at(record, 0) as usize;
+            let page = &record[4..4 + page_size];
+            // a torn record ends the journal
+            if n == 0 || checksum(nonce, page) != read_be_double_at(record, 4 + page_size) {
+                break 'segments;
+            }
+            db.seek(SeekFrom::Start(((n - 1) * page_size) as u64))
+                .context("seek to journaled page")?;
+            db.write_all(page).context("restore journaled page")?;
+            offset += record_size;
+        }
+        offset = offset.div_ceil(sector_size) * sector_size;
+        if offset + sector_size > content.len() {
+            break;
+        }
+    }
+
+    db.set_len((original_size * page_size) as u64)
+        .context("truncate database")?;
+    db.sync_all().context("sync database")?;
+    std::fs::remove_file(path).context("delete journal")?;
+    Ok(true)
+}
+
+/// Sum of every 200th byte of the page counting down from 200 bytes before its
+/// end, the nonce included
+fn checksum(nonce: u32, page: &[u8]) -> u32 {
+    let mut sum = nonce;
+    let mut i = page.len() as isize - 200;
+    while i > 0 {
+        sum = sum.wrapping_add(page[i as usize] as u32);
+        i -= 200;
+    }
+    sum
+}
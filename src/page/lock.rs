@@ -0,0 +1,114 @@
+use std::fs::File;
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
+
+#[cfg(unix)]
+use anyhow::bail;
+
+/// sqlite coordinates connections with POSIX advisory locks on bytes past the
+/// first gigabyte of the database file, which pages never use
+pub const PENDING_BYTE: i64 = 0x4000_0000;
+pub const RESERVED_BYTE: i64 = PENDING_BYTE + 1;
+pub const SHARED_FIRST: i64 = PENDING_BYTE + 2;
+pub const SHARED_SIZE: i64 = 510;
+
+/// Locks a connection holds on a database file, from none to exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LockLevel {
+    None,
+    /// the connection reads the file
+    Shared,
+    /// the connection writes a journal, it will commit
+    Reserved,
+    /// the connection writes pages to the file, no one else reads it
+    Exclusive,
+}
+
+/// Moves the connection's locks on `file` to `level`, as sqlite's unix VFS does.
+/// A lock above shared needs the shared lock taken first, going back to shared
+/// drops the others. Fails with "database is locked" when another connection
+/// holds a conflicting lock.
+///
+/// The locks belong to the process: closing any handle of the file drops them,
+/// and handles of a process never conflict with each other.
+#[cfg(unix)]
+pub fn lock(file: &File, level: LockLevel) -> anyhow::Result<()> {
+    match level {
+        LockLevel::None => set_lock(
+            file,
+            libc::F_UNLCK,
+            PENDING_BYTE,
+            SHARED_FIRST + SHARED_SIZE - PENDING_BYTE,
+        ),
+        LockLevel::Shared => {
+            // the pending byte keeps new readers out while a writer waits for
+            // the readers to leave
+            set_lock(file, libc::F_RDLCK, PENDING_BYTE, 1)?;
+            let shared = set_lock(file, libc::F_RDLCK, SHARED_FIRST, SHARED_SIZE);
+            set_lock(file, libc::F_UNLCK, PENDING_BYTE, 2)?;
+            shared
+        }
+        LockLevel::Reserved => set_lock(file, libc::F_WRLCK, RESERVED_BYTE, 1),
+        LockLevel::Exclusive => {
+            set_lock(file, libc::F_WRLCK, PENDING_BYTE, 1)?;
+            set_lock(file, libc::F_WRLCK, SHARED_FIRST, SHARED_SIZE)
+        }
+    }
+}
+
+/// Takes the exclusive lock on `len` bytes of `file` from `start`, fails with
+/// "database is locked" when another connection locks any of them
+#[cfg(unix)]
+pub fn lock_bytes(file: &File, start: i64, len: i64) -> anyhow::Result<()> {
+    set_lock(file, libc::F_WRLCK, start, len)
+}
+
+/// Whether another connection holds the reserved lock, it is then writing a journal
+#[cfg(unix)]
+pub fn reserved_elsewhere(file: &File) -> anyhow::Result<bool> {
+    let mut lock = flock(libc::F_WRLCK, RESERVED_BYTE, 1);
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &mut lock) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(lock.l_type != libc::F_UNLCK as libc::c_short)
+}
+
+#[cfg(unix)]
+fn set_lock(file: &File, lock_type: libc::c_int, start: i64, len: i64) -> anyhow::Result<()> {
+    let lock = flock(lock_type, start, len);
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLK, &lock) } == 0 {
+        return Ok(());
+    }
+    let err = std::io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EAGAIN | libc::EACCES) => bail!("database is locked"),
+        _ => Err(err.into()),
+    }
+}
+
+#[cfg(unix)]
+fn flock(lock_type: libc::c_int, start: i64, len: i64) -> libc::flock {
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = lock_type as libc::c_short;
+    lock.l_whence = libc::SEEK_SET as libc::c_short;
+    lock.l_start = start as libc::off_t;
+    lock.l_len = len as libc::off_t;
+    lock
+}
+
+/// Other targets have no POSIX advisory locks: a connection there takes no
+/// locks and sees none, so it must not share the file with another writer
+#[cfg(not(unix))]
+pub fn lock(_file: &File, _level: LockLevel) -> anyhow::Result<()> {
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn lock_bytes(_file: &File, _start: i64, _len: i64) -> anyhow::Result<()> {
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn reserved_elsewhere(_file: &File) -> anyhow::Result<bool> {
+    Ok(false)
+}
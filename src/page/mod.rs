@@ -1,3 +1,7 @@
+pub mod journal;
+pub mod lock;
+pub mod page_buffer;
 pub mod page_utils;
 pub mod pager;
 pub mod positioned_page;
+pub mod wal;
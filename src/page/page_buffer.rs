@@ -0,0 +1,309 @@
+use anyhow::{bail, Context};
+
+use crate::{dbheader::HEADER_SIZE, read_be_double_at, read_be_word_at, read_varint_at};
+
+use super::{
+    page_utils::{PageHeader, PageType},
+    pager::{
+        parse_page_header, PayloadLimits, PAGE_CELL_CONTENT_OFFSET, PAGE_CELL_COUNT_OFFSET,
+        PAGE_FIRST_FREEBLOCK_OFFSET, PAGE_FRAGMENTED_BYTES_COUNT_OFFSET, PAGE_INTERIOR_INDEX_ID,
+        PAGE_INTERIROR_TABLE_ID, PAGE_LEAF_HEADER_SIZE, PAGE_LEAF_INDEX_ID, PAGE_LEAF_TABLE_ID,
+    },
+};
+
+/// Cells take at least this many bytes so that their space can become a freeblock
+pub const MIN_CELL_SIZE: usize = 4;
+
+/// A b-tree page in its on-disk layout, edited before being handed back to the pager.
+/// Cells are raw bytes: the cell header, the local payload and the overflow page pointer.
+#[derive(Debug, Clone)]
+pub struct PageBuffer {
+    pub num: usize,
+    pub data: Vec<u8>,
+}
+
+impl PageBuffer {
+    pub fn new(num: usize, data: Vec<u8>) -> Self {
+        Self { num, data }
+    }
+
+    pub fn header(&self) -> anyhow::Result<PageHeader> {
+        parse_page_header(&self.data[header_offset(self.num)..])
+            .with_context(|| format!("parse header of page {}", self.num))
+    }
+
+    pub fn cell_count(&self) -> usize {
+        read_be_word_at(&self.data, header_offset(self.num) + PAGE_CELL_COUNT_OFFSET) as usize
+    }
+
+    /// Offset of cell `i` from the start of the page
+    pub fn cell_offset(&self, i: usize) -> anyhow::Result<usize> {
+        let header = self.header()?;
+        let pointer = header_offset(self.num) + header.byte_size() + 2 * i;
+        Ok(read_be_word_at(&self.data, pointer) as usize)
+    }
+
+    pub fn cell(&self, i: usize, limits: &PayloadLimits) -> anyhow::Result<&[u8]> {
+        let page_type = self.header()?.page_type;
+        let start = self.cell_offset(i)?;
+        let size = cell_size(page_type, &self.data[start..], limits);
+        self.data
+            .get(start..start + size)
+            .with_context(|| format!("cell {i} of page {} is out of bounds", self.num))
+    }
+
+    pub fn cells(&self, limits: &PayloadLimits) -> anyhow::Result<Vec<Vec<u8>>> {
+        (0..self.cell_count())
+            .map(|i| self.cell(i, limits).map(<[u8]>::to_vec))
+            .collect()
+    }
+
+    /// Bytes available for new cells and their pointers, counting the freeblocks
+    /// and fragments that defragmenting reclaims
+    pub fn free_space(&self) -> anyhow::Result<usize> {
+        let header = self.header()?;
+        let free = self.gap(&header) + header.fragmented_bytes_count as usize;
+        Ok(free + self.freeblocks(&header)?.iter().map(|b| b.1).sum::<usize>())
+    }
+
+    /// Inserts `cell` as cell `i`, returns false when the page has no room for it
+    pub fn insert_cell(
+        &mut self,
+        i: usize,
+        cell: &[u8],
+        limits: &PayloadLimits,
+    ) -> anyhow::Result<bool> {
+        let needed = cell.len() + 2;
+        let mut header = self.header()?;
+        if self.gap(&header) < needed {
+            if self.free_space()? < needed {
+                return Ok(false);
+            }
+            self.defragment(limits)?;
+            header = self.header()?;
+        }
+
+        let count = header.cell_count as usize;
+        let pointers = header_offset(self.num) + header.byte_size();
+        let start = header.cell_content_offset as usize - cell.len();
+        self.data[start..start + cell.len()].copy_from_slice(cell);
+        self.data
+            .copy_within(pointers + 2 * i..pointers + 2 * count, pointers + 2 * i + 2);
+        self.write_word(pointers + 2 * i, start);
+        self.write_word(header_offset(self.num) + PAGE_CELL_COUNT_OFFSET, count + 1);
+        self.write_word(header_offset(self.num) + PAGE_CELL_CONTENT_OFFSET, start);
+        Ok(true)
+    }
+
+    /// Removes cell `i`, its space joins the freeblock list, or the unallocated
+    /// gap when it borders it
+    pub fn remove_cell(&mut self, i: usize, limits: &PayloadLimits) -> anyhow::Result<()> {
+        let header = self.header()?;
+        let count = header.cell_count as usize;
+        if i >= count {
+            bail!("page {} has no cell {i}", self.num);
+        }
+        let start = self.cell_offset(i)?;
+        let size = self.cell(i, limits)?.len();
+
+        let offset = header_offset(self.num);
+        let pointers = offset + header.byte_size();
+        self.data
+            .copy_within(pointers + 2 * i + 2..pointers + 2 * count, pointers + 2 * i);
+        self.write_word(pointers + 2 * (count - 1), 0);
+        self.write_word(offset + PAGE_CELL_COUNT_OFFSET, count - 1);
+
+        // freeblocks are kept sorted by offset, blocks less than 4 bytes apart are
+        // merged with the fragment between them
+        let mut blocks = self.freeblocks(&header)?;
+        blocks.push((start, size));
+        blocks.sort_unstable();
+        let mut merged: Vec<(usize, usize)> = vec![];
+        let mut fragments = header.fragmented_bytes_count as usize;
+        for (start, size) in blocks {
+            match merged.last_mut() {
+                Some(last) if last.0 + last.1 + 3 >= start => {
+                    fragments = fragments.saturating_sub(start - (last.0 + last.1));
+                    last.1 = start + size - last.0;
+                }
+                _ => merged.push((start, size)),
+            }
+        }
+        self.data[offset + PAGE_FRAGMENTED_BYTES_COUNT_OFFSET] = fragments as u8;
+
+        let mut content = header.cell_content_offset as usize;
+        if merged.first().is_some_and(|b| b.0 == content) {
+            content += merged.remove(0).1;
+        }
+        self.write_word(offset + PAGE_CELL_CONTENT_OFFSET, content);
+
+        let mut next = offset + PAGE_FIRST_FREEBLOCK_OFFSET;
+        for (start, size) in merged {
+            self.write_word(next, start);
+            self.write_word(start + 2, size);
+            next = start;
+        }
+        self.write_word(next, 0);
+        Ok(())
+    }
+
+    /// Whether the page is more than two thirds empty, such pages get merged
+    /// with a sibling or take cells from it
+    pub fn is_underfull(&self, usable_size: usize) -> anyhow::Result<bool> {
+        Ok(self.free_space()? * 3 > usable_size * 2)
+    }
+
+    /// Moves every cell to the end of the page, merging freeblocks and fragments
+    /// into the gap before the cell content
+    pub fn defragment(&mut self, limits: &PayloadLimits) -> anyhow::Result<()> {
+        let header = self.header()?;
+        let cells = self.cells(limits)?;
+        self.rebuild(
+            header.page_type,
+            &cells,
+            header.rightmost_pointer,
+            limits.usable_size,
+        );
+        Ok(())
+    }
+
+    /// Rewrites the page as a `page_type` page holding `cells`, which must fit.
+    /// Page 1 keeps the database header in front of the page.
+    pub fn rebuild(
+        &mut self,
+        page_type: PageType,
+        cells: &[Vec<u8>],
+        rightmost_pointer: Option<u32>,
+        usable_size: usize,
+    ) {
+        let offset = header_offset(self.num);
+        self.data[offset..usable_size].fill(0);
+
+        self.data[offset] = match page_type {
+            PageType::TableLeaf => PAGE_LEAF_TABLE_ID,
+            PageType::TableInterior => PAGE_INTERIROR_TABLE_ID,
+            PageType::IndexLeaf => PAGE_LEAF_INDEX_ID,
+            PageType::IndexInterior => PAGE_INTERIOR_INDEX_ID,
+        };
+        let mut pointer = offset + header_size(page_type);
+        if let Some(page) = rightmost_pointer.filter(|_| is_interior(page_type)) {
+            self.data[offset + PAGE_LEAF_HEADER_SIZE..offset + PAGE_LEAF_HEADER_SIZE + 4]
+                .copy_from_slice(&page.to_be_bytes());
+        }
+
+        let mut content = usable_size;
+        for cell in cells {
+            content -= cell.len();
+            self.data[content..content + cell.len()].copy_from_slice(cell);
+            self.write_word(pointer, content);
+            pointer += 2;
+        }
+
+        self.write_word(offset + PAGE_FIRST_FREEBLOCK_OFFSET, 0);
+        self.write_word(offset + PAGE_CELL_COUNT_OFFSET, cells.len());
+        self.write_word(offset + PAGE_CELL_CONTENT_OFFSET, content);
+        self.data[offset + PAGE_FRAGMENTED_BYTES_COUNT_OFFSET] = 0;
+    }
+
+    /// Bytes the page has for cells and their pointers once rebuilt as `page_type`
+    pub fn capacity(&self, page_type: PageType, usable_size: usize) -> usize {
+        page_capacity(page_type, usable_size) - header_offset(self.num)
+    }
+
+    /// Offset and size of every freeblock
+    fn freeblocks(&self, header: &PageHeader) -> anyhow::Result<Vec<(usize, usize)>> {
+        let mut blocks = vec![];
+        let mut freeblock = header.first_freeblock as usize;
+        while freeblock != 0 {
+            if freeblock + 4 > self.data.len() || blocks.len() > self.data.len() / 4 {
+                bail!("freeblock list of page {} is corrupt", self.num);
+            }
+            blocks.push((
+                freeblock,
+                read_be_word_at(&self.data, freeblock + 2) as usize,
+            ));
+            freeblock = read_be_word_at(&self.data, freeblock) as usize;
+        }
+        Ok(blocks)
+    }
+
+    /// Unallocated bytes between the cell pointers and the cell content
+    fn gap(&self, header: &PageHeader) -> usize {
+        let pointers_end =
+            header_offset(self.num) + header.byte_size() + 2 * header.cell_count as usize;
+        (header.cell_content_offset as usize).saturating_sub(pointers_end)
+    }
+
+    /// Writes a 2-byte big-endian value, 65536 is stored as 0 as for the content offset
+    fn write_word(&mut self, offset: usize, value: usize) {
+        self.data[offset..offset + 2].copy_from_slice(&(value as u16).to_be_bytes());
+    }
+}
+
+/// Bytes a cell takes on a page with its pointer
+pub fn cell_space(cell: &[u8]) -> usize {
+    cell.len().max(MIN_CELL_SIZE) + 2
+}
+
+/// Bytes a page of `page_type` other than page 1 has for cells and their pointers
+pub fn page_capacity(page_type: PageType, usable_size: usize) -> usize {
+    usable_size - header_size(page_type)
+}
+
+/// Size of the cell at the start of `cell`, including the overflow page pointer
+pub fn cell_size(page_type: PageType, cell: &[u8], limits: &PayloadLimits) -> usize {
+    if page_type == PageType::TableInterior {
+        return 4 + read_varint_at(cell, 4).0 as usize;
+    }
+    let (local_end, overflow) = payload_layout(page_type, cell, limits);
+    (local_end + if overflow { 4 } else { 0 }).max(MIN_CELL_SIZE)
+}
+
+/// First overflow page of the payload of a cell, None when it fits on the page
+pub fn overflow_page(page_type: PageType, cell: &[u8], limits: &PayloadLimits) -> Option<u32> {
+    if page_type == PageType::TableInterior {
+        return None;
+    }
+    match payload_layout(page_type, cell, limits) {
+        (local_end, true) => Some(read_be_double_at(cell, local_end)),
+        (_, false) => None,
+    }
+}
+
+/// End of the local payload of a cell, and whether an overflow page pointer follows it
+fn payload_layout(page_type: PageType, cell: &[u8], limits: &PayloadLimits) -> (usize, bool) {
+    let child_pointer = if page_type == PageType::IndexInterior {
+        4
+    } else {
+        0
+    };
+    let (mut header, size) = read_varint_at(cell, child_pointer);
+    if page_type == PageType::TableLeaf {
+        header += read_varint_at(cell, child_pointer + header as usize).0;
+    }
+    let local = limits.local_size(page_type, size as usize);
+    (
+        child_pointer + header as usize + local,
+        local < size as usize,
+    )
+}
+
+pub fn is_interior(page_type: PageType) -> bool {
+    matches!(page_type, PageType::TableInterior | PageType::IndexInterior)
+}
+
+fn header_size(page_type: PageType) -> usize {
+    match is_interior(page_type) {
+        true => PAGE_LEAF_HEADER_SIZE + 4,
+        false => PAGE_LEAF_HEADER_SIZE,
+    }
+}
+
+/// Page 1 starts with the database header
+fn header_offset(num: usize) -> usize {
+    if num == 1 {
+        HEADER_SIZE
+    } else {
+        0
+    }
+}
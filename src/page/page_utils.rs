@@ -2,7 +2,9 @@
 pub struct TableLeafCell {
     pub size: i64,
     pub row_id: i64,
+    /// full payload, including the part spilled to overflow pages
     pub payload: Vec<u8>,
+    /// first page of the overflow chain, if the payload did not fit on the page
     pub overflow_page_num: Option<u32>,
 }
 
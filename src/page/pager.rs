@@ -1,18 +1,28 @@
 use std::{
-    collections::HashMap,
-    io::{Read, Seek, SeekFrom},
-    sync::{Arc, Mutex, RwLock},
-    usize,
+    collections::{btree_map::Entry, BTreeMap, HashMap},
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard, RwLock},
 };
 
 use anyhow::{Context, Ok};
 
 use crate::{
-    dbheader::{HEADER_SIZE, PAGE_MAX_SIZE},
+    dbheader::{
+        DbHeader, TextEncoding, DB_SIZE_OFFSET, FILE_CHANGE_COUNTER_OFFSET, FILE_FORMAT_R_OFFSET,
+        FILE_FORMAT_W_OFFSET, FREELIST_COUNT_OFFSET, FREELIST_TRUNK_OFFSET, HEADER_SIZE,
+        LEGACY_FILE_FORMAT, PAGE_MAX_SIZE, VERSION_VALID_FOR_OFFSET, WAL_FILE_FORMAT,
+    },
     read_be_double_at, read_be_word_at, read_varint_at,
 };
 
-use super::page_utils::{self, Cell, Page, PageHeader, PageType, TableLeafCell};
+use super::{
+    journal,
+    lock::{self, LockLevel},
+    page_utils::{self, Cell, Page, PageHeader, PageType, TableLeafCell},
+    wal::{wal_path, CheckpointMode, Wal, DEFAULT_AUTOCHECKPOINT},
+};
 
 pub const PAGE_FIRST_FREEBLOCK_OFFSET: usize = 1;
 pub const PAGE_CELL_COUNT_OFFSET: usize = 3;
@@ -20,28 +30,280 @@ pub const PAGE_CELL_CONTENT_OFFSET: usize = 5;
 pub const PAGE_FRAGMENTED_BYTES_COUNT_OFFSET: usize = 7;
 pub const PAGE_LEAF_HEADER_SIZE: usize = 8;
 
-const PAGE_INTERIOR_INDEX_ID: u8 = 0x02;
-const PAGE_INTERIROR_TABLE_ID: u8 = 0x05;
-const PAGE_LEAF_INDEX_ID: u8 = 0x0a;
-const PAGE_LEAF_TABLE_ID: u8 = 0x0d;
+pub const PAGE_INTERIOR_INDEX_ID: u8 = 0x02;
+pub const PAGE_INTERIROR_TABLE_ID: u8 = 0x05;
+pub const PAGE_LEAF_INDEX_ID: u8 = 0x0a;
+pub const PAGE_LEAF_TABLE_ID: u8 = 0x0d;
+
+/// Size of the next-page pointer at the start of every overflow page
+pub const OVERFLOW_PAGE_POINTER_SIZE: usize = 4;
+
+/// The page holding this file offset is used for locking and never stores data
+const PENDING_BYTE: usize = 0x4000_0000;
+
+/// Thresholds deciding how much of a cell payload is stored on the b-tree page
+/// itself, the rest spills over into a chain of overflow pages
+#[derive(Debug, Clone, Copy)]
+pub struct PayloadLimits {
+    pub usable_size: usize,
+    /// max local payload of index cells
+    pub max_local: usize,
+    /// min local payload of index cells
+    pub min_local: usize,
+    /// max local payload of table leaf cells
+    pub max_leaf: usize,
+    /// min local payload of table leaf cells
+    pub min_leaf: usize,
+}
+
+impl PayloadLimits {
+    pub fn new(header: &DbHeader) -> Self {
+        let usable_size = header.usable_size();
+        let fraction = |f: u8| (usable_size - 12) * f as usize / 255 - 23;
+
+        Self {
+            usable_size,
+            max_local: fraction(header.max_embedded_payload),
+            min_local: fraction(header.min_embedded_payload),
+            max_leaf: usable_size - 35,
+            min_leaf: fraction(header.leaf_payload_fraction),
+        }
+    }
+
+    /// Number of payload bytes stored on the b-tree page for a payload of `size` bytes
+    pub fn local_size(&self, page_type: PageType, size: usize) -> usize {
+        let (max, min) = match page_type {
+            PageType::TableLeaf => (self.max_leaf, self.min_leaf),
+            _ => (self.max_local, self.min_local),
+        };
 
-/// pager reads and caches pages from the db file
+        if size <= max {
+            return size;
+        }
+
+        let local = min + (size - min) % (self.usable_size - OVERFLOW_PAGE_POINTER_SIZE);
+        if local <= max {
+            local
+        } else {
+            min
+        }
+    }
+}
+
+/// Storage the pager reads pages from and commits them to
+pub trait Storage: Read + Write + Seek {
+    /// Makes the written pages durable, a commit only drops its journal after this
+    fn sync(&mut self) -> std::io::Result<()>;
+
+    /// Moves the connection's locks on the storage to `level`, see `lock::lock`.
+    /// Storage no other connection sees has nothing to lock.
+    fn lock(&mut self, _level: LockLevel) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl Storage for File {
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.sync_all()
+    }
+
+    fn lock(&mut self, level: LockLevel) -> anyhow::Result<()> {
+        lock::lock(self, level)
+    }
+}
+
+/// Pages written since the last commit, kept out of the file until `commit`
+#[derive(Debug, Default)]
+struct PendingChanges {
+    pages: BTreeMap<usize, Vec<u8>>,
+    /// size of the database in pages, with the pages allocated since the last commit
+    page_count: usize,
+    /// size of the database in pages as of the last commit
+    committed_page_count: usize,
+    /// a transaction was started with `begin`, statements leave their changes pending
+    in_transaction: bool,
+    /// pending pages as they were when the running statement started
+    statement: Option<Savepoint>,
+    /// savepoints taken by name in the transaction, innermost last. Each holds
+    /// the pages written until the next one was taken.
+    savepoints: Vec<(String, Savepoint)>,
+    /// the transaction was started by the outermost savepoint, releasing it commits
+    savepoint_transaction: bool,
+}
+
+/// Pending pages as they were at some point, the pages written since are
+/// restored from it on rollback
+#[derive(Debug, Default)]
+struct Savepoint {
+    /// earlier content of the pages written since, None for pages that weren't pending
+    pages: HashMap<usize, Option<Vec<u8>>>,
+    page_count: usize,
+}
+
+impl PendingChanges {
+    fn write(&mut self, n: usize, data: Vec<u8>) {
+        let previous = self.pages.insert(n, data);
+        if let Some((_, savepoint)) = self.savepoints.last_mut() {
+            savepoint.pages.entry(n).or_insert(previous.clone());
+        }
+        if let Some(savepoint) = &mut self.statement {
+            savepoint.pages.entry(n).or_insert(previous);
+        }
+    }
+
+    fn savepoint(&self) -> Savepoint {
+        Savepoint {
+            pages: HashMap::new(),
+            page_count: self.page_count,
+        }
+    }
+
+    /// Puts back the pages written since `savepoint`, returns their numbers
+    fn restore(&mut self, savepoint: Savepoint) -> Vec<usize> {
+        let mut written = vec![];
+        for (n, page) in savepoint.pages {
+            match page {
+                Some(page) => self.pages.insert(n, page),
+                None => self.pages.remove(&n),
+            };
+            written.push(n);
+        }
+        self.page_count = savepoint.page_count;
+        written
+    }
+
+    /// Innermost savepoint named `name`, names are case insensitive
+    fn find_savepoint(&self, name: &str) -> anyhow::Result<usize> {
+        self.savepoints
+            .iter()
+            .rposition(|(n, _)| n.eq_ignore_ascii_case(name))
+            .with_context(|| format!("no such savepoint: {name}"))
+    }
+
+    /// Ends the transaction, dropping its savepoints
+    fn end_transaction(&mut self) {
+        self.in_transaction = false;
+        self.savepoint_transaction = false;
+        self.savepoints.clear();
+    }
+}
+
+/// How commits reach the file, set with `PRAGMA journal_mode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalMode {
+    /// pages are written in place, their original content saved to a rollback
+    /// journal that is deleted once the commit is done
+    Delete,
+    /// pages are appended to the write-ahead log and copied to the file by checkpoints
+    Wal,
+}
+
+/// Write-ahead log of a database in WAL mode
 #[derive(Debug)]
-pub struct Pager<I: Read + Seek = std::fs::File> {
+struct WalState {
+    /// the database file the log is named after, none for storage that isn't a file
+    path: Option<PathBuf>,
+    /// Some in WAL mode
+    log: Option<Wal>,
+    /// committed frames after which a commit checkpoints the log, 0 for never
+    autocheckpoint: usize,
+}
+
+impl Default for WalState {
+    fn default() -> Self {
+        Self {
+            path: None,
+            log: None,
+            autocheckpoint: DEFAULT_AUTOCHECKPOINT,
+        }
+    }
+}
+
+/// pager reads and caches pages from the db file, and holds the pages written
+/// to until they are committed
+#[derive(Debug)]
+pub struct Pager<I: Storage = File> {
     input: Arc<Mutex<I>>,
+    /// rollback journal written by every commit, none for storage that isn't a file
+    journal: Option<PathBuf>,
+    /// in WAL mode commits go to the log, whose pages are read in place of those of the file
+    wal: Arc<Mutex<WalState>>,
     page_size: usize,
+    limits: PayloadLimits,
+    text_encoding: TextEncoding,
     pages: Arc<RwLock<HashMap<usize, Arc<Page>>>>,
+    pending: Arc<Mutex<PendingChanges>>,
 }
 
-impl<I: Read + Seek> Pager<I> {
-    pub fn new(input: I, page_size: usize) -> Self {
+impl<I: Storage> Pager<I> {
+    pub fn new(mut input: I, header: &DbHeader) -> Self {
+        // the size in the header is stale when an older version last wrote the file
+        let file_pages = input
+            .seek(SeekFrom::End(0))
+            .map_or(0, |len| len as usize / header.page_size as usize);
+        let page_count = file_pages.max(header.db_size as usize);
         Self {
             input: Arc::new(Mutex::new(input)),
-            page_size,
+            journal: None,
+            wal: Arc::default(),
+            page_size: header.page_size as usize,
+            limits: PayloadLimits::new(header),
+            text_encoding: header.text_encoding,
             pages: Arc::default(),
+            pending: Arc::new(Mutex::new(PendingChanges {
+                page_count,
+                committed_page_count: page_count,
+                ..PendingChanges::default()
+            })),
         }
     }
 
+    /// Saves the original pages of every commit to a rollback journal at `path` first
+    pub fn with_journal(mut self, path: PathBuf) -> Self {
+        self.journal = Some(path);
+        self
+    }
+
+    /// Commits to the write-ahead log of the database file at `path` in WAL
+    /// mode. The database is in WAL mode when its header says so or the log
+    /// exists, the pages committed to the log are then read in place of those
+    /// of the file.
+    pub fn with_wal(self, path: PathBuf) -> anyhow::Result<Self> {
+        let wal_mode = wal_path(&path).exists()
+            || self.read_file_page(1)?[FILE_FORMAT_W_OFFSET] == WAL_FILE_FORMAT;
+        let mut wal = self.lock_wal()?;
+        if wal_mode {
+            let log = Wal::open(&path, self.page_size)?;
+            if let Some(db_size) = log.db_size() {
+                let mut pending = self.lock_pending()?;
+                pending.page_count = db_size;
+                pending.committed_page_count = db_size;
+            }
+            wal.log = Some(log);
+        }
+        wal.path = Some(path);
+        drop(wal);
+        Ok(self)
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Encoding of the text values of every record
+    pub fn text_encoding(&self) -> TextEncoding {
+        self.text_encoding
+    }
+
+    pub fn limits(&self) -> &PayloadLimits {
+        &self.limits
+    }
+
+    /// Size of the database in pages, including uncommitted allocations
+    pub fn page_count(&self) -> anyhow::Result<usize> {
+        Ok(self.lock_pending()?.page_count)
+    }
+
     pub fn read_page(&self, n: usize) -> anyhow::Result<Arc<Page>> {
         {
             let read_pages = self
@@ -68,6 +330,500 @@ impl<I: Read + Seek> Pager<I> {
     }
 
     fn load_page(&self, n: usize) -> anyhow::Result<Arc<Page>> {
+        let buffer = self.read_raw_page(n)?;
+        let mut page = parse_page(&buffer, n, &self.limits)?;
+        self.resolve_overflow(&mut page)
+            .with_context(|| format!("read overflow pages of page {n}"))?;
+
+        Ok(Arc::new(page))
+    }
+
+    /// Content of page `n`, including uncommitted writes
+    pub fn read_raw_page(&self, n: usize) -> anyhow::Result<Vec<u8>> {
+        if let Some(page) = self.lock_pending()?.pages.get(&n) {
+            return Ok(page.clone());
+        }
+        self.read_committed_page(n)
+    }
+
+    /// Replaces the content of page `n`, the file is only written on `commit`.
+    /// The database header on page 1 is only changed through `set_header_field`.
+    pub fn write_page(&self, n: usize, mut data: Vec<u8>) -> anyhow::Result<()> {
+        anyhow::ensure!(data.len() == self.page_size, "page {n} has the wrong size");
+        if n == 1 {
+            let current = self.read_raw_page(1)?;
+            data[..HEADER_SIZE].copy_from_slice(&current[..HEADER_SIZE]);
+        }
+        self.lock_pending()?.write(n, data);
+        self.evict(&[n])
+    }
+
+    /// A 4-byte field of the database header, including uncommitted changes
+    pub fn header_field(&self, offset: usize) -> anyhow::Result<u32> {
+        Ok(read_be_double_at(&self.read_raw_page(1)?, offset))
+    }
+
+    pub fn set_header_field(&self, offset: usize, value: u32) -> anyhow::Result<()> {
+        let mut first_page = self.read_raw_page(1)?;
+        first_page[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+        self.lock_pending()?.write(1, first_page);
+        self.evict(&[1])
+    }
+
+    /// Returns an empty page, taken from the freelist when it has one, otherwise
+    /// added at the end of the database
+    pub fn allocate_page(&self) -> anyhow::Result<usize> {
+        let n = match self.pop_free_page()? {
+            Some(n) => n,
+            None => {
+                let mut pending = self.lock_pending()?;
+                pending.page_count += 1;
+                if (pending.page_count - 1) * self.page_size == PENDING_BYTE {
+                    pending.page_count += 1;
+                }
+                pending.page_count
+            }
+        };
+
+        self.lock_pending()?.write(n, vec![0; self.page_size]);
+        self.evict(&[n])?;
+        Ok(n)
+    }
+
+    /// Adds a page that is no longer used to the freelist. Trunk pages list the
+    /// free leaf pages and link to the next trunk, the header holds the first one.
+    pub fn free_page(&self, n: usize) -> anyhow::Result<()> {
+        let trunk = self.header_field(FREELIST_TRUNK_OFFSET)? as usize;
+        let count = self.header_field(FREELIST_COUNT_OFFSET)?;
+        // older versions of sqlite can't read fuller trunks
+        let max_leaves = self.limits.usable_size / 4 - 8;
+
+        if trunk != 0 {
+            let mut data = self.read_raw_page(trunk)?;
+            let leaves = read_be_double_at(&data, 4) as usize;
+            if leaves < max_leaves {
+                let entry = 8 + 4 * leaves;
+                data[entry..entry + 4].copy_from_slice(&(n as u32).to_be_bytes());
+                data[4..8].copy_from_slice(&(leaves as u32 + 1).to_be_bytes());
+                self.write_page(trunk, data)?;
+                return self.set_header_field(FREELIST_COUNT_OFFSET, count + 1);
+            }
+        }
+
+        let mut data = vec![0; self.page_size];
+        data[..4].copy_from_slice(&(trunk as u32).to_be_bytes());
+        self.write_page(n, data)?;
+        self.set_header_field(FREELIST_TRUNK_OFFSET, n as u32)?;
+        self.set_header_field(FREELIST_COUNT_OFFSET, count + 1)
+    }
+
+    /// Takes a page off the freelist, the last leaf of the first trunk or the
+    /// trunk itself once it has no leaves
+    fn pop_free_page(&self) -> anyhow::Result<Option<usize>> {
+        let trunk = self.header_field(FREELIST_TRUNK_OFFSET)? as usize;
+        if trunk == 0 {
+            return Ok(None);
+        }
+        let count = self.header_field(FREELIST_COUNT_OFFSET)?;
+        self.set_header_field(FREELIST_COUNT_OFFSET, count.saturating_sub(1))?;
+
+        let mut data = self.read_raw_page(trunk)?;
+        let leaves = read_be_double_at(&data, 4) as usize;
+        if leaves == 0 {
+            self.set_header_field(FREELIST_TRUNK_OFFSET, read_be_double_at(&data, 0))?;
+            return Ok(Some(trunk));
+        }
+
+        let n = read_be_double_at(&data, 8 + 4 * (leaves - 1)) as usize;
+        data[4..8].copy_from_slice(&(leaves as u32 - 1).to_be_bytes());
+        self.write_page(trunk, data)?;
+        Ok(Some(n))
+    }
+
+    pub fn journal_mode(&self) -> anyhow::Result<JournalMode> {
+        Ok(match self.lock_wal()?.log {
+            Some(_) => JournalMode::Wal,
+            None => JournalMode::Delete,
+        })
+    }
+
+    /// Switches to committing through the write-ahead log or the rollback journal.
+    /// The header records WAL mode, leaving it checkpoints and deletes the log first.
+    pub fn set_journal_mode(&self, mode: JournalMode) -> anyhow::Result<()> {
+        if self.journal_mode()? == mode {
+            return Ok(());
+        }
+        let direction = match mode {
+            JournalMode::Wal => "into",
+            JournalMode::Delete => "out of",
+        };
+        anyhow::ensure!(
+            !self.in_transaction()?,
+            "cannot change {direction} wal mode from within a transaction"
+        );
+        let path = self
+            .lock_wal()?
+            .path
+            .clone()
+            .context("wal mode needs a database file")?;
+
+        match mode {
+            JournalMode::Wal => {
+                self.set_file_format(WAL_FILE_FORMAT)?;
+                self.write_pending(self.lock_pending()?)?;
+                self.lock_wal()?.log = Some(Wal::open(&path, self.page_size)?);
+            }
+            JournalMode::Delete => {
+                self.checkpoint(CheckpointMode::Truncate)?;
+                self.lock_wal()?.log = None;
+                std::fs::remove_file(wal_path(&path)).or_else(|err| match err.kind() {
+                    std::io::ErrorKind::NotFound => Ok(()),
+                    _ => Err(err).context("delete wal"),
+                })?;
+                self.set_file_format(LEGACY_FILE_FORMAT)?;
+                self.write_pending(self.lock_pending()?)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the pages committed to the write-ahead log to the file. Returns the
+    /// frames in the log and how many of them are checkpointed, None outside of
+    /// WAL mode.
+    pub fn checkpoint(&self, mode: CheckpointMode) -> anyhow::Result<Option<(usize, usize)>> {
+        let mut wal = self.lock_wal()?;
+        let Some(log) = &mut wal.log else {
+            return Ok(None);
+        };
+        let mut output = self
+            .input
+            .lock()
+            .map_err(|_| anyhow::anyhow!("failed to lock pager mutex"))?;
+        log.checkpoint(&mut *output, mode)?;
+        Ok(Some((log.frame_count(), log.checkpointed())))
+    }
+
+    /// Committed frames after which a commit checkpoints the write-ahead log
+    pub fn wal_autocheckpoint(&self) -> anyhow::Result<usize> {
+        Ok(self.lock_wal()?.autocheckpoint)
+    }
+
+    /// Sets the frames after which a commit checkpoints the log, 0 turns it off
+    pub fn set_wal_autocheckpoint(&self, frames: usize) -> anyhow::Result<()> {
+        self.lock_wal()?.autocheckpoint = frames;
+        Ok(())
+    }
+
+    pub fn in_transaction(&self) -> anyhow::Result<bool> {
+        Ok(self.lock_pending()?.in_transaction)
+    }
+
+    /// Starts a transaction, the statements run until `commit` or `rollback`
+    /// leave their changes pending
+    pub fn begin(&self) -> anyhow::Result<()> {
+        let mut pending = self.lock_pending()?;
+        anyhow::ensure!(
+            !pending.in_transaction,
+            "cannot start a transaction within a transaction"
+        );
+        pending.in_transaction = true;
+        Ok(())
+    }
+
+    /// Ends the transaction, writing its changes to the file
+    pub fn commit(&self) -> anyhow::Result<()> {
+        let pending = self.lock_pending()?;
+        anyhow::ensure!(
+            pending.in_transaction,
+            "cannot commit - no transaction is active"
+        );
+        self.commit_transaction(pending)
+    }
+
+    /// Ends the transaction, dropping its changes
+    pub fn rollback(&self) -> anyhow::Result<()> {
+        let mut pending = self.lock_pending()?;
+        anyhow::ensure!(
+            pending.in_transaction,
+            "cannot rollback - no transaction is active"
+        );
+        pending.end_transaction();
+        self.discard_pending(pending)
+    }
+
+    /// Marks the current state of the transaction as `name`, starting a
+    /// transaction when there is none
+    pub fn savepoint(&self, name: &str) -> anyhow::Result<()> {
+        let mut pending = self.lock_pending()?;
+        if !pending.in_transaction {
+            pending.in_transaction = true;
+            pending.savepoint_transaction = true;
+        }
+        let savepoint = pending.savepoint();
+        pending.savepoints.push((name.to_owned(), savepoint));
+        Ok(())
+    }
+
+    /// Forgets savepoint `name` and those taken after it, their changes are kept.
+    /// Releasing the savepoint that started the transaction commits it.
+    pub fn release(&self, name: &str) -> anyhow::Result<()> {
+        let mut pending = self.lock_pending()?;
+        let i = pending.find_savepoint(name)?;
+        let released = pending.savepoints.split_off(i);
+        if i == 0 && pending.savepoint_transaction {
+            return self.commit_transaction(pending);
+        }
+
+        // the savepoint before keeps the earliest content of the pages written since
+        if let Some((_, outer)) = pending.savepoints.last_mut() {
+            for (_, savepoint) in released {
+                for (n, page) in savepoint.pages {
+                    outer.pages.entry(n).or_insert(page);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Undoes the changes made since savepoint `name`, which stays in place while
+    /// those taken after it are dropped. The transaction goes on.
+    pub fn rollback_to(&self, name: &str) -> anyhow::Result<()> {
+        let mut pending = self.lock_pending()?;
+        let i = pending.find_savepoint(name)?;
+        let mut written = vec![];
+        // the pages go back to their content when each savepoint was taken, newest first
+        let rolled_back = pending.savepoints.split_off(i);
+        let name = rolled_back[0].0.clone();
+        for (_, savepoint) in rolled_back.into_iter().rev() {
+            written.extend(pending.restore(savepoint));
+        }
+        let savepoint = pending.savepoint();
+        pending.savepoints.push((name, savepoint));
+        drop(pending);
+        self.evict(&written)
+    }
+
+    /// Starts a statement, the changes of a statement that fails are undone
+    /// without those of the statements before it
+    pub fn begin_statement(&self) -> anyhow::Result<()> {
+        let mut pending = self.lock_pending()?;
+        pending.statement = Some(pending.savepoint());
+        Ok(())
+    }
+
+    /// Keeps the changes of the statement, they are written to the file right
+    /// away outside of a transaction
+    pub fn commit_statement(&self) -> anyhow::Result<()> {
+        let mut pending = self.lock_pending()?;
+        pending.statement = None;
+        if pending.in_transaction {
+            return Ok(());
+        }
+        // a statement that can't be committed leaves nothing behind
+        let committed = self.write_pending(pending);
+        if committed.is_err() {
+            self.discard_pending(self.lock_pending()?)?;
+        }
+        committed
+    }
+
+    /// Undoes the changes of the statement
+    pub fn rollback_statement(&self) -> anyhow::Result<()> {
+        let mut pending = self.lock_pending()?;
+        if !pending.in_transaction {
+            return self.discard_pending(pending);
+        }
+        let written = match pending.statement.take() {
+            Some(savepoint) => pending.restore(savepoint),
+            None => vec![],
+        };
+        drop(pending);
+        self.evict(&written)
+    }
+
+    /// Ends the transaction and commits it. A commit that fails, such as when
+    /// another connection holds a lock, leaves the transaction open to be
+    /// committed again or rolled back.
+    fn commit_transaction(
+        &self,
+        mut pending: MutexGuard<'_, PendingChanges>,
+    ) -> anyhow::Result<()> {
+        pending.end_transaction();
+        let committed = self.write_pending(pending);
+        if committed.is_err() {
+            self.lock_pending()?.in_transaction = true;
+        }
+        committed
+    }
+
+    /// Commits the pending pages, with the database size and change counter
+    /// updated in the header. In WAL mode they are appended to the log, which is
+    /// checkpointed once it reaches the autocheckpoint size. Otherwise they are
+    /// written to the file.
+    fn write_pending(&self, mut pending: MutexGuard<'_, PendingChanges>) -> anyhow::Result<()> {
+        if pending.pages.is_empty() {
+            return Ok(());
+        }
+
+        if let Entry::Vacant(entry) = pending.pages.entry(1) {
+            entry.insert(self.read_committed_page(1)?);
+        }
+        let page_count = pending.page_count as u32;
+        let header = pending.pages.get_mut(&1).context("missing page 1")?;
+        let change_counter = read_be_double_at(header, FILE_CHANGE_COUNTER_OFFSET).wrapping_add(1);
+        for (offset, value) in [
+            (FILE_CHANGE_COUNTER_OFFSET, change_counter),
+            (DB_SIZE_OFFSET, page_count),
+            // the size in the header is only trusted when this matches the change counter
+            (VERSION_VALID_FOR_OFFSET, change_counter),
+        ] {
+            header[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+        }
+
+        let mut wal = self.lock_wal()?;
+        let autocheckpoint = wal.autocheckpoint;
+        match &mut wal.log {
+            Some(log) => {
+                log.append(&pending.pages, pending.page_count)?;
+                if autocheckpoint > 0 && log.frame_count() >= autocheckpoint {
+                    let mut output = self
+                        .input
+                        .lock()
+                        .map_err(|_| anyhow::anyhow!("failed to lock pager mutex"))?;
+                    log.checkpoint(&mut *output, CheckpointMode::Passive)?;
+                }
+            }
+            None => self.write_to_file(&pending)?,
+        }
+        drop(wal);
+
+        pending.pages.clear();
+        pending.statement = None;
+        pending.committed_page_count = pending.page_count;
+        drop(pending);
+        self.evict(&[1])
+    }
+
+    /// Moves the connection's locks on the file to `level`
+    pub fn lock_file(&self, level: LockLevel) -> anyhow::Result<()> {
+        self.input
+            .lock()
+            .map_err(|_| anyhow::anyhow!("failed to lock pager mutex"))?
+            .lock(level)
+    }
+
+    /// Writes the pending pages in place. The original content of the pages goes
+    /// to the journal first, a crash while they are written leaves it behind.
+    /// The reserved lock is held while the journal is written, so no one plays it
+    /// back, and the exclusive lock while the pages are, so no one reads them.
+    fn write_to_file(&self, pending: &PendingChanges) -> anyhow::Result<()> {
+        let written = self
+            .lock_file(LockLevel::Shared)
+            .and_then(|()| self.lock_file(LockLevel::Reserved))
+            .and_then(|()| self.write_locked(pending));
+        let unlocked = self.lock_file(LockLevel::None);
+        written.and(unlocked)
+    }
+
+    fn write_locked(&self, pending: &PendingChanges) -> anyhow::Result<()> {
+        if let Some(journal) = &self.journal {
+            // pages past the end of the file have nothing to restore
+            let originals = pending
+                .pages
+                .keys()
+                .filter(|&&n| n <= pending.committed_page_count)
+                .map(|&n| Ok((n, self.read_file_page(n)?)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            journal::write_journal(
+                journal,
+                self.page_size,
+                pending.committed_page_count,
+                &originals,
+            )?;
+        }
+        if let Err(err) = self.lock_file(LockLevel::Exclusive) {
+            // nothing was written, the journal holds the pages as they are
+            if let Some(journal) = &self.journal {
+                std::fs::remove_file(journal).context("delete journal")?;
+            }
+            return Err(err);
+        }
+
+        {
+            let mut output = self
+                .input
+                .lock()
+                .map_err(|_| anyhow::anyhow!("failed to lock pager mutex"))?;
+            for (n, page) in &pending.pages {
+                output
+                    .seek(SeekFrom::Start(((n - 1) * self.page_size) as u64))
+                    .context("seek to page start")?;
+                output.write_all(page).context("write page")?;
+            }
+            output.flush().context("flush db file")?;
+            output.sync().context("sync db file")?;
+        }
+        if let Some(journal) = &self.journal {
+            std::fs::remove_file(journal).context("delete journal")?;
+        }
+        Ok(())
+    }
+
+    /// Drops the pending pages, the file is left as of the last commit
+    fn discard_pending(&self, mut pending: MutexGuard<'_, PendingChanges>) -> anyhow::Result<()> {
+        let written = pending.pages.keys().copied().collect::<Vec<_>>();
+        pending.pages.clear();
+        pending.statement = None;
+        pending.page_count = pending.committed_page_count;
+        drop(pending);
+        self.evict(&written)
+    }
+
+    /// Drops cached pages whose content changed
+    fn evict(&self, pages: &[usize]) -> anyhow::Result<()> {
+        let mut cached = self
+            .pages
+            .write()
+            .map_err(|_| anyhow::anyhow!("failed to acquire pager write lock"))?;
+        for n in pages {
+            cached.remove(n);
+        }
+        Ok(())
+    }
+
+    fn lock_pending(&self) -> anyhow::Result<MutexGuard<'_, PendingChanges>> {
+        self.pending
+            .lock()
+            .map_err(|_| anyhow::anyhow!("failed to lock pending pages"))
+    }
+
+    fn lock_wal(&self) -> anyhow::Result<MutexGuard<'_, WalState>> {
+        self.wal
+            .lock()
+            .map_err(|_| anyhow::anyhow!("failed to lock wal"))
+    }
+
+    /// Content of page `n` as of the last commit, from the wal when it has the page
+    fn read_committed_page(&self, n: usize) -> anyhow::Result<Vec<u8>> {
+        if let Some(log) = &mut self.lock_wal()?.log {
+            if let Some(page) = log.read_page(n)? {
+                return Ok(page);
+            }
+        }
+        self.read_file_page(n)
+    }
+
+    /// Marks the database as in WAL mode or not, in the read and write versions
+    /// of the header
+    fn set_file_format(&self, version: u8) -> anyhow::Result<()> {
+        let mut first_page = self.read_raw_page(1)?;
+        first_page[FILE_FORMAT_W_OFFSET] = version;
+        first_page[FILE_FORMAT_R_OFFSET] = version;
+        self.lock_pending()?.write(1, first_page);
+        self.evict(&[1])
+    }
+
+    fn read_file_page(&self, n: usize) -> anyhow::Result<Vec<u8>> {
         let offset = n.saturating_sub(1) * self.page_size;
 
         let mut input_guard = self
@@ -81,11 +837,52 @@ impl<I: Read + Seek> Pager<I> {
 
         let mut buffer = vec![0; self.page_size];
         input_guard.read_exact(&mut buffer).context("read page")?;
-        // println!("page({n}): {buffer:?}");
-        // let page = parse_page(&buffer, n)?;
-        // println!("{page:?}");
+        Ok(buffer)
+    }
+
+    /// Appends the spilled part of every overflowing cell so that cells
+    /// always carry their full payload
+    fn resolve_overflow(&self, page: &mut Page) -> anyhow::Result<()> {
+        for cell in page.cells.iter_mut() {
+            let (size, payload, overflow_page_num) = match cell {
+                Cell::TableLeaf(c) => (c.size, &mut c.payload, c.overflow_page_num),
+                Cell::IndexLeaf(c) => (c.size, &mut c.payload, c.overflow_page_num),
+                Cell::IndexInterior(c) => (c.size, &mut c.payload, c.overflow_page_num),
+                Cell::TableInterior(_) => continue,
+            };
 
-        Ok(Arc::new(parse_page(&buffer, n)?))
+            if let Some(first_page) = overflow_page_num {
+                self.read_overflow_chain(first_page, size as usize, payload)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_overflow_chain(
+        &self,
+        first_page: u32,
+        size: usize,
+        payload: &mut Vec<u8>,
+    ) -> anyhow::Result<()> {
+        let content_size = self.limits.usable_size - OVERFLOW_PAGE_POINTER_SIZE;
+        let mut next_page = first_page;
+
+        while payload.len() < size {
+            if next_page == 0 {
+                anyhow::bail!(
+                    "overflow chain ended after {} of {size} payload bytes",
+                    payload.len()
+                );
+            }
+
+            let buffer = self.read_raw_page(next_page as usize)?;
+            let chunk = content_size.min(size - payload.len());
+            payload.extend_from_slice(
+                &buffer[OVERFLOW_PAGE_POINTER_SIZE..OVERFLOW_PAGE_POINTER_SIZE + chunk],
+            );
+            next_page = read_be_double_at(&buffer, 0);
+        }
+        Ok(())
     }
 }
 
@@ -93,13 +890,18 @@ impl Clone for Pager {
     fn clone(&self) -> Self {
         Self {
             input: self.input.clone(),
+            journal: self.journal.clone(),
+            wal: self.wal.clone(),
             page_size: self.page_size,
+            limits: self.limits,
+            text_encoding: self.text_encoding,
             pages: self.pages.clone(),
+            pending: self.pending.clone(),
         }
     }
 }
 
-fn parse_page(pg_buffer: &[u8], page_num: usize) -> anyhow::Result<Page> {
+fn parse_page(pg_buffer: &[u8], page_num: usize, limits: &PayloadLimits) -> anyhow::Result<Page> {
     let ptr_offset = if page_num == 1 { HEADER_SIZE as u16 } else { 0 };
     let pg_content_buffer = &pg_buffer[ptr_offset as usize..];
     let header = parse_page_header(pg_content_buffer)?;
@@ -116,7 +918,9 @@ fn parse_page(pg_buffer: &[u8], page_num: usize) -> anyhow::Result<Page> {
         PageType::IndexInterior => parse_index_interior_cell,
     };
 
-    let cells = parse_cells(pg_content_buffer, &cell_pointers, cells_parsing_fn)?;
+    let cells = parse_cells(pg_content_buffer, &cell_pointers, |buffer| {
+        cells_parsing_fn(buffer, limits)
+    })?;
 
     Ok(Page {
         header,
@@ -136,11 +940,11 @@ fn parse_cells(
         .collect()
 }
 
-fn parse_page_header(pg_buffer: &[u8]) -> anyhow::Result<PageHeader> {
+pub fn parse_page_header(pg_buffer: &[u8]) -> anyhow::Result<PageHeader> {
     let (page_type, has_rightmost_ptr) = match pg_buffer[0] {
         PAGE_LEAF_TABLE_ID => (PageType::TableLeaf, false),
         PAGE_INTERIROR_TABLE_ID => (PageType::TableInterior, true),
-        PAGE_INTERIOR_INDEX_ID => (PageType::IndexInterior, false),
+        PAGE_INTERIOR_INDEX_ID => (PageType::IndexInterior, true),
         PAGE_LEAF_INDEX_ID => (PageType::IndexLeaf, false),
         _ => anyhow::bail!("unknown page type: {}", pg_buffer[0]),
     };
@@ -177,24 +981,31 @@ fn parse_cell_pointers(buffer: &[u8], n: usize, ptr_offset: u16) -> Vec<u16> {
     pointers
 }
 
-fn parse_table_leaf_cell(mut pg_content_buffer: &[u8]) -> anyhow::Result<Cell> {
+fn parse_table_leaf_cell(
+    mut pg_content_buffer: &[u8],
+    limits: &PayloadLimits,
+) -> anyhow::Result<Cell> {
     let (n, size) = read_varint_at(pg_content_buffer, 0);
     pg_content_buffer = &pg_content_buffer[n as usize..];
 
     let (n, row_id) = read_varint_at(pg_content_buffer, 0);
     pg_content_buffer = &pg_content_buffer[n as usize..];
 
-    let payload = pg_content_buffer[..size as usize].to_vec();
+    let (payload, overflow_page_num) =
+        parse_local_payload(pg_content_buffer, PageType::TableLeaf, size, limits);
     Ok(TableLeafCell {
         size,
         row_id,
         payload,
-        overflow_page_num: None,
+        overflow_page_num,
     }
     .into())
 }
 
-fn parse_table_interior_cell(mut pg_content_buff: &[u8]) -> anyhow::Result<Cell> {
+fn parse_table_interior_cell(
+    mut pg_content_buff: &[u8],
+    _: &PayloadLimits,
+) -> anyhow::Result<Cell> {
     let left_child_page = read_be_double_at(pg_content_buff, 0);
     pg_content_buff = &pg_content_buff[4..];
 
@@ -207,32 +1018,57 @@ fn parse_table_interior_cell(mut pg_content_buff: &[u8]) -> anyhow::Result<Cell>
     .into())
 }
 
-fn parse_index_leaf_cell(mut pg_content_buff: &[u8]) -> anyhow::Result<Cell> {
+fn parse_index_leaf_cell(
+    mut pg_content_buff: &[u8],
+    limits: &PayloadLimits,
+) -> anyhow::Result<Cell> {
     let (n, size) = read_varint_at(pg_content_buff, 0);
     pg_content_buff = &pg_content_buff[n as usize..];
 
-    let payload = pg_content_buff[..size as usize].to_vec();
+    let (payload, overflow_page_num) =
+        parse_local_payload(pg_content_buff, PageType::IndexLeaf, size, limits);
     Ok(page_utils::IndexLeafCell {
         size,
         payload,
-        overflow_page_num: None,
+        overflow_page_num,
     }
     .into())
 }
 
-fn parse_index_interior_cell(mut pg_content_buff: &[u8]) -> anyhow::Result<Cell> {
+fn parse_index_interior_cell(
+    mut pg_content_buff: &[u8],
+    limits: &PayloadLimits,
+) -> anyhow::Result<Cell> {
     let left_child_page = read_be_double_at(pg_content_buff, 0);
     pg_content_buff = &pg_content_buff[4..];
 
     let (n, size) = read_varint_at(pg_content_buff, 0);
     pg_content_buff = &pg_content_buff[n as usize..];
 
-    let payload = pg_content_buff[..size as usize].to_vec();
+    let (payload, overflow_page_num) =
+        parse_local_payload(pg_content_buff, PageType::IndexInterior, size, limits);
     Ok(page_utils::IndexInteriorCell {
         left_child_page,
         size,
         payload,
-        overflow_page_num: None,
+        overflow_page_num,
     }
     .into())
 }
+
+/// Splits the on-page part of a payload from the pointer to its first overflow page
+fn parse_local_payload(
+    buffer: &[u8],
+    page_type: PageType,
+    size: i64,
+    limits: &PayloadLimits,
+) -> (Vec<u8>, Option<u32>) {
+    let local_size = limits.local_size(page_type, size as usize);
+    let payload = buffer[..local_size].to_vec();
+
+    if local_size < size as usize {
+        (payload, Some(read_be_double_at(buffer, local_size)))
+    } else {
+        (payload, None)
+    }
+}
@@ -6,19 +6,48 @@ use super::page_utils::{Cell, Page, PageType};
 pub struct PositionedPage {
     pub page: Arc<Page>,
     pub cell: usize,
+    /// whether the left child of the current index interior cell was already visited
+    pub left_child_visited: bool,
 }
 
 impl PositionedPage {
+    pub fn new(page: Arc<Page>) -> Self {
+        Self {
+            page,
+            cell: 0,
+            left_child_visited: false,
+        }
+    }
+
     pub fn next_cell(&mut self) -> Option<&Cell> {
         let cell = self.page.cells.get(self.cell);
         self.cell += 1;
+        self.left_child_visited = false;
         cell
     }
 
+    /// Index interior cells hold keys of their own, ordered after everything in
+    /// their left child. The left child is handed out first, the cell itself is
+    /// returned by `next_cell` once the scanner comes back up.
+    pub fn next_left_child(&mut self) -> Option<u32> {
+        if self.page.header.page_type != PageType::IndexInterior || self.left_child_visited {
+            return None;
+        }
+
+        let Some(Cell::IndexInterior(cell)) = self.page.cells.get(self.cell) else {
+            return None;
+        };
+        self.left_child_visited = true;
+        Some(cell.left_child_page)
+    }
+
     pub fn next_page(&mut self) -> Option<u32> {
-        if self.page.header.page_type == PageType::TableInterior
-            && self.cell == self.page.cells.len()
-        {
+        let is_interior = matches!(
+            self.page.header.page_type,
+            PageType::TableInterior | PageType::IndexInterior
+        );
+
+        if is_interior && self.cell == self.page.cells.len() {
             self.cell += 1;
             self.page.header.rightmost_pointer
         } else {
@@ -0,0 +1,399 @@
+use std::{
+    collections::{hash_map::RandomState, BTreeMap, HashMap},
+    fs::File,
+    hash::{BuildHasher, Hasher},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+
+use crate::read_be_double_at;
+
+use super::{lock, pager::Storage};
+
+/// Magic number of a WAL whose checksums read the content as little-endian words,
+/// the same number plus one reads them as big-endian words
+pub const WAL_MAGIC: u32 = 0x377f0682;
+pub const WAL_VERSION: u32 = 3007000;
+pub const WAL_HEADER_SIZE: usize = 32;
+pub const WAL_FRAME_HEADER_SIZE: usize = 24;
+/// Frames the log may hold before a commit checkpoints it, as in sqlite
+pub const DEFAULT_AUTOCHECKPOINT: usize = 1000;
+
+const PAGE_SIZE_OFFSET: usize = 8;
+const CHECKPOINT_SEQUENCE_OFFSET: usize = 12;
+const SALT_OFFSET: usize = 16;
+const CHECKSUM_OFFSET: usize = 24;
+const FRAME_DB_SIZE_OFFSET: usize = 4;
+const FRAME_SALT_OFFSET: usize = 8;
+const FRAME_CHECKSUM_OFFSET: usize = 16;
+
+/// Locks sqlite takes in the -shm file: the write lock of the log, then, after
+/// the checkpoint, recover and read locks, the byte every connection that maps
+/// the file holds a shared lock on
+const SHM_WRITE_LOCK: i64 = 120;
+const SHM_DMS_LOCK: i64 = 128;
+
+/// How far `PRAGMA wal_checkpoint` goes. Checkpoints only run while no other
+/// connection has the log open, so every mode copies all the frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CheckpointMode {
+    #[default]
+    Passive,
+    Full,
+    Restart,
+    /// also empties the log file
+    Truncate,
+}
+
+/// Write-ahead log of the database at `db_path`, next to it as sqlite names it
+pub fn wal_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push("-wal");
+    PathBuf::from(path)
+}
+
+/// sqlite's wal-index of the database at `db_path`, named as the log is
+pub fn shm_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push("-shm");
+    PathBuf::from(path)
+}
+
+/// The committed frames of a write-ahead log. Pages are read from their newest
+/// committed frame instead of the database file. The index sqlite shares in the
+/// -shm file is rebuilt from the log itself and not maintained, so the log is
+/// only written while no other connection has the index open.
+#[derive(Debug)]
+pub struct Wal {
+    path: PathBuf,
+    /// sqlite's wal-index, locked while the log is written
+    shm: PathBuf,
+    /// None until the first commit when there is no log file
+    file: Option<File>,
+    page_size: usize,
+    big_endian: bool,
+    checkpoint_sequence: u32,
+    salts: (u32, u32),
+    /// running checksum as of the last committed frame
+    checksum: (u32, u32),
+    /// newest committed frame of every page, by frame number from 0
+    frames: HashMap<usize, usize>,
+    /// committed frames, new ones are written after them
+    frame_count: usize,
+    /// frames already copied to the database by a checkpoint
+    checkpointed: usize,
+    /// size of the database in pages as of the last commit, None without commits
+    db_size: Option<usize>,
+}
+
+impl Wal {
+    /// Reads the log of the database at `db_path`. A missing log, or one without
+    /// a valid header, has no frames and is written from the start on the first
+    /// commit.
+    pub fn open(db_path: &Path, page_size: usize) -> anyhow::Result<Self> {
+        let mut wal = Self {
+            path: wal_path(db_path),
+            shm: shm_path(db_path),
+            file: None,
+            page_size,
+            big_endian: false,
+            checkpoint_sequence: 0,
+            salts: (nonce(), nonce()),
+            checksum: (0, 0),
+            frames: HashMap::new(),
+            frame_count: 0,
+            checkpointed: 0,
+            db_size: None,
+        };
+        // read-only files can still be queried
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&wal.path)
+            .or_else(|_| File::open(&wal.path));
+        let mut file = match file {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(wal),
+            Err(err) => return Err(err).context("open wal"),
+        };
+        let mut content = vec![];
+        file.read_to_end(&mut content).context("read wal")?;
+        wal.file = Some(file);
+        if content.len() < WAL_HEADER_SIZE {
+            return Ok(wal);
+        }
+
+        let header = &content[..WAL_HEADER_SIZE];
+        let big_endian = match read_be_double_at(header, 0) {
+            WAL_MAGIC => false,
+            magic if magic == WAL_MAGIC + 1 => true,
+            // a log that was reset before its header was written
+            _ => return Ok(wal),
+        };
+        let checksum = checksum(big_endian, (0, 0), &header[..CHECKSUM_OFFSET]);
+        if checksum != read_pair(header, CHECKSUM_OFFSET) {
+            return Ok(wal);
+        }
+        let wal_page_size = match read_be_double_at(header, PAGE_SIZE_OFFSET) {
+            1 => 65536,
+            n => n as usize,
+        };
+        if wal_page_size != page_size {
+            bail!("wal page size {wal_page_size} differs from the database page size {page_size}");
+        }
+
+        wal.big_endian = big_endian;
+        wal.checkpoint_sequence = read_be_double_at(header, CHECKPOINT_SEQUENCE_OFFSET);
+        wal.salts = read_pair(header, SALT_OFFSET);
+        wal.checksum = checksum;
+        wal.index_frames(&content);
+        Ok(wal)
+    }
+
+    /// Content of page `n` in its newest committed frame, None when no commit has it
+    pub fn read_page(&mut self, n: usize) -> anyhow::Result<Option<Vec<u8>>> {
+        let Some(&frame) = self.frames.get(&n) else {
+            return Ok(None);
+        };
+        let offset = self.frame_offset(frame) + WAL_FRAME_HEADER_SIZE;
+        let file = self.file.as_mut().context("wal file is not open")?;
+        file.seek(SeekFrom::Start(offset as u64))
+            .context("seek to wal frame")?;
+        let mut page = vec![0; self.page_size];
+        file.read_exact(&mut page).context("read wal frame")?;
+        Ok(Some(page))
+    }
+
+    /// Size of the database in pages as of the last commit in the log
+    pub fn db_size(&self) -> Option<usize> {
+        self.db_size
+    }
+
+    /// Committed frames in the log
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    /// Frames the last checkpoint copied to the database
+    pub fn checkpointed(&self) -> usize {
+        self.checkpointed
+    }
+
+    /// Appends a frame for each of `pages`, the last one committing the
+    /// database at `db_size` pages, and syncs the log. A log whose frames were
+    /// all checkpointed starts over with new salts so that its old frames no
+    /// longer validate.
+    pub fn append(
+        &mut self,
+        pages: &BTreeMap<usize, Vec<u8>>,
+        db_size: usize,
+    ) -> anyhow::Result<()> {
+        let _index = self.lock_index()?;
+        if self.file.is_none() {
+            let file = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&self.path)
+                .context("create wal")?;
+            self.file = Some(file);
+        }
+        if self.frame_count == self.checkpointed {
+            self.restart()?;
+        }
+
+        let mut content = Vec::with_capacity(pages.len() * self.frame_size());
+        let mut checksum = self.checksum;
+        for (i, (&n, page)) in pages.iter().enumerate() {
+            let commit_size = if i + 1 == pages.len() { db_size } else { 0 };
+            let mut header = [0; WAL_FRAME_HEADER_SIZE];
+            write_u32(&mut header, 0, n as u32);
+            write_u32(&mut header, FRAME_DB_SIZE_OFFSET, commit_size as u32);
+            write_u32(&mut header, FRAME_SALT_OFFSET, self.salts.0);
+            write_u32(&mut header, FRAME_SALT_OFFSET + 4, self.salts.1);
+            checksum = self::checksum(self.big_endian, checksum, &header[..FRAME_SALT_OFFSET]);
+            checksum = self::checksum(self.big_endian, checksum, page);
+            write_u32(&mut header, FRAME_CHECKSUM_OFFSET, checksum.0);
+            write_u32(&mut header, FRAME_CHECKSUM_OFFSET + 4, checksum.1);
+            content.extend_from_slice(&header);
+            content.extend_from_slice(page);
+        }
+
+        let offset = self.frame_offset(self.frame_count);
+        let file = self.file.as_mut().context("wal file is not open")?;
+        file.seek(SeekFrom::Start(offset as u64))
+            .context("seek to wal end")?;
+        file.write_all(&content).context("write wal frames")?;
+        file.sync_all().context("sync wal")?;
+
+        for (i, &n) in pages.keys().enumerate() {
+            self.frames.insert(n, self.frame_count + i);
+        }
+        self.frame_count += pages.len();
+        self.checksum = checksum;
+        self.db_size = Some(db_size);
+        Ok(())
+    }
+
+    /// Copies the newest committed version of every page in the log to `db`,
+    /// which is synced before the frames count as checkpointed. Truncate also
+    /// empties the log, the others leave it to be started over by the next commit.
+    pub fn checkpoint(
+        &mut self,
+        db: &mut impl Storage,
+        mode: CheckpointMode,
+    ) -> anyhow::Result<()> {
+        let _index = self.lock_index()?;
+        if self.checkpointed < self.frame_count {
+            let mut pages = self.frames.keys().copied().collect::<Vec<_>>();
+            pages.sort_unstable();
+            for n in pages {
+                let page = self.read_page(n)?.context("missing wal frame")?;
+                db.seek(SeekFrom::Start(((n - 1) * self.page_size) as u64))
+                    .context("seek to checkpointed page")?;
+                db.write_all(&page).context("write checkpointed page")?;
+            }
+            db.flush().context("flush db file")?;
+            db.sync().context("sync db file")?;
+            self.checkpointed = self.frame_count;
+        }
+
+        if mode == CheckpointMode::Truncate {
+            if let Some(file) = &mut self.file {
+                file.set_len(0).context("truncate wal")?;
+                file.sync_all().context("sync wal")?;
+            }
+            self.frames.clear();
+            self.frame_count = 0;
+            self.checkpointed = 0;
+        }
+        Ok(())
+    }
+
+    /// Locks the -shm file of sqlite's wal-index for writing until the returned
+    /// file is dropped. Every connection that has the index open holds a shared
+    /// lock on its DMS byte, the exclusive lock fails while one does and keeps
+    /// others from opening it meanwhile. They rebuild the index from the log
+    /// when they open it alone.
+    fn lock_index(&self) -> anyhow::Result<File> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.shm)
+            .context("open wal-index")?;
+        lock::lock_bytes(&file, SHM_DMS_LOCK, 1)
+            .and_then(|()| lock::lock_bytes(&file, SHM_WRITE_LOCK, 1))
+            .context("another connection has the wal-index open")?;
+        Ok(file)
+    }
+
+    /// Writes a new header over the log, the frames after it are from now on
+    /// written from the start
+    fn restart(&mut self) -> anyhow::Result<()> {
+        self.checkpoint_sequence = self.checkpoint_sequence.wrapping_add(1);
+        self.salts = (self.salts.0.wrapping_add(1), nonce());
+        self.big_endian = false;
+
+        let mut header = [0; WAL_HEADER_SIZE];
+        write_u32(&mut header, 0, WAL_MAGIC);
+        write_u32(&mut header, 4, WAL_VERSION);
+        write_u32(&mut header, PAGE_SIZE_OFFSET, self.page_size as u32);
+        write_u32(
+            &mut header,
+            CHECKPOINT_SEQUENCE_OFFSET,
+            self.checkpoint_sequence,
+        );
+        write_u32(&mut header, SALT_OFFSET, self.salts.0);
+        write_u32(&mut header, SALT_OFFSET + 4, self.salts.1);
+        self.checksum = checksum(self.big_endian, (0, 0), &header[..CHECKSUM_OFFSET]);
+        write_u32(&mut header, CHECKSUM_OFFSET, self.checksum.0);
+        write_u32(&mut header, CHECKSUM_OFFSET + 4, self.checksum.1);
+
+        let file = self.file.as_mut().context("wal file is not open")?;
+        file.seek(SeekFrom::Start(0)).context("seek to wal start")?;
+        file.write_all(&header).context("write wal header")?;
+
+        self.frames.clear();
+        self.frame_count = 0;
+        self.checkpointed = 0;
+        Ok(())
+    }
+
+    /// Indexes the frames up to the last valid commit frame. A frame is valid when
+    /// it has the salts of the header and the checksum continuing that of the frame
+    /// before it, the first invalid frame ends the log.
+    fn index_frames(&mut self, content: &[u8]) {
+        let mut checksum = self.checksum;
+        // frames of a transaction are only visible once its commit frame is read
+        let mut uncommitted = vec![];
+
+        for (frame, content) in content[WAL_HEADER_SIZE..]
+            .chunks_exact(self.frame_size())
+            .enumerate()
+        {
+            let (header, page) = content.split_at(WAL_FRAME_HEADER_SIZE);
+            if read_pair(header, FRAME_SALT_OFFSET) != self.salts {
+                break;
+            }
+            checksum = self::checksum(self.big_endian, checksum, &header[..FRAME_SALT_OFFSET]);
+            checksum = self::checksum(self.big_endian, checksum, page);
+            if checksum != read_pair(header, FRAME_CHECKSUM_OFFSET) {
+                break;
+            }
+
+            uncommitted.push((read_be_double_at(header, 0) as usize, frame));
+            let db_size = read_be_double_at(header, FRAME_DB_SIZE_OFFSET) as usize;
+            if db_size != 0 {
+                self.frames.extend(uncommitted.drain(..));
+                self.frame_count = frame + 1;
+                self.checksum = checksum;
+                self.db_size = Some(db_size);
+            }
+        }
+    }
+
+    fn frame_size(&self) -> usize {
+        WAL_FRAME_HEADER_SIZE + self.page_size
+    }
+
+    fn frame_offset(&self, frame: usize) -> usize {
+        WAL_HEADER_SIZE + frame * self.frame_size()
+    }
+}
+
+/// Running checksum of the log: two sums over the content read as pairs of
+/// 32-bit words, each adding the other
+pub fn checksum(big_endian: bool, (mut s0, mut s1): (u32, u32), content: &[u8]) -> (u32, u32) {
+    let word = |bytes: &[u8]| {
+        let bytes = bytes.try_into().unwrap();
+        match big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        }
+    };
+    for pair in content.chunks_exact(8) {
+        s0 = s0.wrapping_add(word(&pair[..4])).wrapping_add(s1);
+        s1 = s1.wrapping_add(word(&pair[4..])).wrapping_add(s0);
+    }
+    (s0, s1)
+}
+
+fn read_pair(buffer: &[u8], offset: usize) -> (u32, u32) {
+    (
+        read_be_double_at(buffer, offset),
+        read_be_double_at(buffer, offset + 4),
+    )
+}
+
+fn write_u32(buffer: &mut [u8], offset: usize, value: u32) {
+    buffer[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+}
+
+fn nonce() -> u32 {
+    RandomState::new().build_hasher().finish() as u32
+}
@@ -6,31 +6,156 @@ use super::tokenizer::Ops;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
-  Select(SelectStatement),
+  Select(Box<SelectStatement>),
   CreateTable(CreateTableStatement),
+  CreateIndex(CreateIndexStatement),
+  Explain(ExplainStatement),
+  Insert(InsertStatement),
+  Delete(DeleteStatement),
+  Update(UpdateStatement),
+  Begin(TransactionMode),
+  Commit,
+  Rollback,
+  /// `SAVEPOINT name`
+  Savepoint(String),
+  /// `RELEASE [SAVEPOINT] name`
+  Release(String),
+  /// `ROLLBACK [TRANSACTION] TO [SAVEPOINT] name`
+  RollbackTo(String),
+  Pragma(PragmaStatement),
+  DropTable(DropTableStatement),
+  AlterTable(AlterTableStatement),
+}
+
+/// `INSERT INTO table [(columns)] VALUES (...), ...` or `INSERT INTO table [(columns)] SELECT ...`
+#[derive(Debug, Clone, PartialEq)]
+pub struct InsertStatement {
+  pub table: String,
+  /// columns the values are for, empty for all the columns in declaration order
+  pub columns: Vec<String>,
+  pub source: InsertSource,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InsertSource {
+  Values(Vec<Vec<Expr>>),
+  Select(Box<SelectStatement>),
+}
+
+/// `DELETE FROM table [WHERE expr]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeleteStatement {
+  pub table: String,
+  pub where_clause: Option<Expr>,
+}
+
+/// `UPDATE table SET column = expr, ... [WHERE expr]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpdateStatement {
+  pub table: String,
+  /// columns and their new values, in the order written
+  pub assignments: Vec<(String, Expr)>,
+  pub where_clause: Option<Expr>,
+}
+
+/// `BEGIN [DEFERRED | IMMEDIATE | EXCLUSIVE] [TRANSACTION]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TransactionMode {
+  #[default]
+  Deferred,
+  Immediate,
+  Exclusive,
+}
+
+/// `PRAGMA [schema.]name [= value | (value)]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PragmaStatement {
+  pub name: String,
+  /// a name, string or signed number, as written
+  pub value: Option<String>,
+}
+
+/// `EXPLAIN [QUERY PLAN] statement`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExplainStatement {
+  /// the operator tree only, without the per-operator details
+  pub query_plan: bool,
+  pub statement: Box<Statement>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct SelectStatement {
   pub core: SelectCore,
+  /// cores combined with the ones before them, left to right
+  pub compound: Vec<CompoundSelect>,
+  pub order_by: Vec<OrderingTerm>,
+  pub limit: Option<Limit>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompoundSelect {
+  pub operator: CompoundOperator,
+  pub core: SelectCore,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompoundOperator {
+  Union,
+  UnionAll,
+  Intersect,
+  Except,
+}
+
+impl CompoundOperator {
+  pub fn keyword(&self) -> &'static str {
+    match self {
+      CompoundOperator::Union => "UNION",
+      CompoundOperator::UnionAll => "UNION ALL",
+      CompoundOperator::Intersect => "INTERSECT",
+      CompoundOperator::Except => "EXCEPT",
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limit {
+  /// a negative limit means no limit
+  pub limit: Expr,
+  pub offset: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderingTerm {
+  /// an expression, a result column alias, or the 1-based number of a result column
+  pub expr: Expr,
+  pub descending: bool,
+  /// NULLs sort first in ascending order and last in descending order unless told otherwise
+  pub nulls_first: bool,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct SelectCore {
+  pub distinct: bool,
   pub result_columns: Vec<ResultColumn>,
   pub from: SelectFrom,
   pub where_clause: Option<Expr>,
+  pub group_by: Vec<Expr>,
+  pub having: Option<Expr>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ResultColumn {
   Star,
+  /// `table.*`
+  TableStar(String),
   Expr(ExprResultColumn),
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ExprResultColumn {
   pub expr: Expr,
+  /// source text of the expression, which names the column when it has no alias
+  pub text: String,
   pub alias: Option<String>,
 }
 
@@ -44,13 +169,41 @@ pub struct Comparison {
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
   Column(String),
+  /// `table.column`
+  QualifiedColumn(String, String),
   Alias(i64),
+  /// rowid of the current row, either named directly or through an INTEGER PRIMARY KEY column
+  RowId,
   Null,
   Int(i64),
   Real(f64),
   Bool(bool),
   Text(String),
+  Blob(Vec<u8>),
+  /// any binary operation, not only comparisons
   Comparison(Box<Expr>, Ops, Box<Expr>),
+  Unary(UnaryOp, Box<Expr>),
+  Function(FunctionCall),
+  /// `expr [NOT] IN (list)`
+  InList {
+    expr: Box<Expr>,
+    list: Vec<Expr>,
+    negated: bool,
+  },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionCall {
+  pub name: String,
+  pub distinct: bool,
+  /// empty for `count(*)`
+  pub args: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+  Neg,
+  Not,
 }
 
 impl Expr {
@@ -80,28 +233,261 @@ impl Expr {
   }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// SQL text of an expression, resolved columns show as `#n`, their row position
+impl std::fmt::Display for Expr {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    // operands that are operations themselves are parenthesized
+    let operand = |e: &Expr| match e {
+      Expr::Comparison(..) | Expr::InList { .. } => format!("({e})"),
+      e => e.to_string(),
+    };
+    let list = |exprs: &[Expr]| {
+      exprs
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+    };
+
+    match self {
+      Expr::Column(name) => write!(f, "{name}"),
+      Expr::QualifiedColumn(table, name) => write!(f, "{table}.{name}"),
+      Expr::Alias(n) => write!(f, "#{n}"),
+      Expr::RowId => write!(f, "rowid"),
+      Expr::Null => write!(f, "NULL"),
+      Expr::Int(i) => write!(f, "{i}"),
+      Expr::Real(r) => write!(f, "{r:?}"),
+      Expr::Bool(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+      Expr::Text(s) => write!(f, "'{}'", s.replace('\'', "''")),
+      Expr::Blob(b) => {
+        write!(f, "X'")?;
+        b.iter().try_for_each(|byte| write!(f, "{byte:02X}"))?;
+        write!(f, "'")
+      }
+      Expr::Comparison(l, op, r) => write!(f, "{} {op} {}", operand(l), operand(r)),
+      Expr::Unary(UnaryOp::Neg, e) => write!(f, "-{}", operand(e)),
+      Expr::Unary(UnaryOp::Not, e) => write!(f, "NOT {}", operand(e)),
+      Expr::Function(call) if call.args.is_empty() => write!(f, "{}(*)", call.name),
+      Expr::Function(call) => {
+        let distinct = if call.distinct { "DISTINCT " } else { "" };
+        write!(f, "{}({distinct}{})", call.name, list(&call.args))
+      }
+      Expr::InList {
+        expr,
+        list: items,
+        negated,
+      } => {
+        let not = if *negated { "NOT " } else { "" };
+        write!(f, "{} {not}IN ({})", operand(expr), list(items))
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum SelectFrom {
-  Table(String),
+  Table(TableRef),
+  Join(Box<Join>),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableRef {
+  pub name: String,
+  pub alias: Option<String>,
+}
+
+/// `left [NATURAL] <operator> right [constraint]`, joins nest to the left
+#[derive(Debug, Clone, PartialEq)]
+pub struct Join {
+  pub left: SelectFrom,
+  pub operator: JoinOperator,
+  pub natural: bool,
+  pub right: TableRef,
+  pub constraint: Option<JoinConstraint>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinOperator {
+  /// `JOIN`, `INNER JOIN` and the comma
+  Inner,
+  /// `LEFT [OUTER] JOIN`
+  Left,
+  Cross,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JoinConstraint {
+  On(Expr),
+  Using(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct ColumnDef {
   pub name: String,
+  /// declared type as written, e.g. `varchar(255)`
+  pub type_name: Option<String>,
+  /// affinity of the declared type
   pub col_type: Type,
+  pub constraints: Vec<ColumnConstraint>,
+}
+
+impl ColumnDef {
+  pub fn is_primary_key(&self) -> bool {
+    self
+      .constraints
+      .iter()
+      .any(|c| matches!(c, ColumnConstraint::PrimaryKey { .. }))
+  }
+
+  pub fn is_unique(&self) -> bool {
+    self.constraints.contains(&ColumnConstraint::Unique)
+  }
+
+  /// Expression of a generated column, and whether its value is stored
+  pub fn generated(&self) -> Option<(&Expr, bool)> {
+    self.constraints.iter().find_map(|c| match c {
+      ColumnConstraint::Generated { expr, stored } => Some((expr, *stored)),
+      _ => None,
+    })
+  }
+
+  /// Whether the column is generated and not stored in the records
+  pub fn is_virtual(&self) -> bool {
+    matches!(self.generated(), Some((_, false)))
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnConstraint {
+  PrimaryKey {
+    descending: bool,
+    autoincrement: bool,
+  },
+  NotNull,
+  Unique,
+  Check(Expr),
+  /// a literal, a parenthesized expression, or a bare name such as `current_timestamp`
+  Default(Expr),
+  Collate(String),
+  References(ForeignKeyClause),
+  Generated {
+    expr: Expr,
+    stored: bool,
+  },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableConstraint {
+  PrimaryKey(Vec<IndexedColumn>),
+  Unique(Vec<IndexedColumn>),
+  Check(Expr),
+  ForeignKey {
+    columns: Vec<String>,
+    references: ForeignKeyClause,
+  },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignKeyClause {
+  pub table: String,
+  pub columns: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct CreateTableStatement {
   pub name: String,
   pub columns: Vec<ColumnDef>,
+  pub constraints: Vec<TableConstraint>,
+  pub if_not_exists: bool,
+  pub without_rowid: bool,
+  /// the statement as stored in sqlite_schema: `CREATE TABLE` followed by the
+  /// source text from the table name on
+  pub sql: String,
+}
+
+/// `DROP TABLE [IF EXISTS] name`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DropTableStatement {
+  pub name: String,
+  pub if_exists: bool,
+}
+
+/// `ALTER TABLE table action`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlterTableStatement {
+  pub table: String,
+  pub action: AlterTableAction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlterTableAction {
+  /// `RENAME TO name`
+  RenameTable(String),
+  /// `RENAME [COLUMN] old TO new`
+  RenameColumn { old: String, new: String },
+  /// `ADD [COLUMN] column-def`, `sql` is the definition as written
+  AddColumn { column: ColumnDef, sql: String },
+  /// `DROP [COLUMN] name`
+  DropColumn(String),
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateIndexStatement {
+  pub name: String,
+  pub table: String,
+  pub unique: bool,
+  pub columns: Vec<IndexedColumn>,
+  /// condition of a partial index
+  pub where_clause: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedColumn {
+  /// the column, or the source text of an indexed expression
+  pub name: String,
+  pub descending: bool,
+  /// the indexed expression, None for a column
+  pub expression: Option<Expr>,
+}
+
+/// Column type affinity
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Type {
   Integer,
   Real,
+  /// numeric affinity, kept apart for columns declared `BOOL`/`BOOLEAN`
   Bool,
   Text,
   Blob,
+  Numeric,
+}
+
+impl Type {
+  /// Affinity of a declared column type, following sqlite's rules:
+  /// the first matching substring decides, anything unmatched is numeric
+  pub fn from_declared(type_name: Option<&str>) -> Self {
+    let Some(type_name) = type_name.map(str::to_lowercase) else {
+      return Type::Blob;
+    };
+
+    if type_name.contains("int") {
+      Type::Integer
+    } else if ["char", "clob", "text"]
+      .iter()
+      .any(|t| type_name.contains(t))
+    {
+      Type::Text
+    } else if type_name.contains("blob") {
+      Type::Blob
+    } else if ["real", "floa", "doub"]
+      .iter()
+      .any(|t| type_name.contains(t))
+    {
+      Type::Real
+    } else if type_name == "bool" || type_name == "boolean" {
+      Type::Bool
+    } else {
+      Type::Numeric
+    }
+  }
 }
@@ -1,77 +1,529 @@
+use std::ops::Range;
+
 use anyhow::{bail, Context};
 
 use super::{
   ast::{
-    ColumnDef, CreateTableStatement, Expr, ExprResultColumn, ResultColumn, SelectCore, SelectFrom,
-    SelectStatement, Statement, Type,
+    AlterTableAction, AlterTableStatement, ColumnConstraint, ColumnDef, CompoundOperator,
+    CompoundSelect, CreateIndexStatement, CreateTableStatement, DeleteStatement,
+    DropTableStatement, ExplainStatement, Expr, ExprResultColumn, ForeignKeyClause, FunctionCall,
+    IndexedColumn, InsertSource, InsertStatement, Join, JoinConstraint, JoinOperator, Limit,
+    OrderingTerm, PragmaStatement, ResultColumn, SelectCore, SelectFrom, SelectStatement,
+    Statement, TableConstraint, TableRef, TransactionMode, Type, UnaryOp, UpdateStatement,
   },
   tokenizer::{self, Ops, Token},
 };
 
+/// Binding strength of operators, loosest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+  Or,
+  And,
+  Not,
+  Equality,
+  Relational,
+  Additive,
+  Multiplicative,
+  Concat,
+}
+
+impl Precedence {
+  /// The next tighter level, right operands of left-associative operators parse at it
+  fn tighter(self) -> Self {
+    match self {
+      Precedence::Or => Precedence::And,
+      Precedence::And => Precedence::Not,
+      Precedence::Not => Precedence::Equality,
+      Precedence::Equality => Precedence::Relational,
+      Precedence::Relational => Precedence::Additive,
+      Precedence::Additive => Precedence::Multiplicative,
+      Precedence::Multiplicative | Precedence::Concat => Precedence::Concat,
+    }
+  }
+}
+
 #[derive(Debug)]
 struct ParserState {
   tokens: Vec<Token>,
+  /// byte range of every token in `source`
+  spans: Vec<Range<usize>>,
+  source: String,
   pos: usize,
 }
 
 impl ParserState {
-  fn new(tokens: Vec<Token>) -> Self {
-    Self { tokens, pos: 0 }
+  fn new(source: &str) -> anyhow::Result<Self> {
+    let (tokens, spans) = tokenizer::tokenize_spanned(source)?.into_iter().unzip();
+    Ok(Self {
+      tokens,
+      spans,
+      source: source.to_owned(),
+      pos: 0,
+    })
   }
 
   fn parse_statement(&mut self) -> anyhow::Result<Statement> {
     match self.peak_next_token().context("unexpected end of input")? {
-      Token::Create => self.parse_create_table().map(Statement::CreateTable),
-      Token::Select => self.parse_select().map(Statement::Select),
+      Token::Create => match self.tokens.get(self.pos + 1) {
+        Some(Token::Index | Token::Unique) => self.parse_create_index().map(Statement::CreateIndex),
+        _ => self.parse_create_table().map(Statement::CreateTable),
+      },
+      Token::Select => self.parse_select().map(|s| Statement::Select(Box::new(s))),
+      Token::Identifier(ident) if ident.eq_ignore_ascii_case("explain") => {
+        self.parse_explain().map(Statement::Explain)
+      }
+      Token::Identifier(ident) if ident.eq_ignore_ascii_case("insert") => {
+        self.parse_insert().map(Statement::Insert)
+      }
+      Token::Identifier(ident) if ident.eq_ignore_ascii_case("delete") => {
+        self.parse_delete().map(Statement::Delete)
+      }
+      Token::Identifier(ident) if ident.eq_ignore_ascii_case("update") => {
+        self.parse_update().map(Statement::Update)
+      }
+      Token::Identifier(ident) if ident.eq_ignore_ascii_case("begin") => {
+        self.parse_begin().map(Statement::Begin)
+      }
+      Token::Identifier(ident)
+        if ident.eq_ignore_ascii_case("commit") || ident.eq_ignore_ascii_case("end") =>
+      {
+        self.advance();
+        self.skip_keyword("transaction");
+        Ok(Statement::Commit)
+      }
+      Token::Identifier(ident) if ident.eq_ignore_ascii_case("rollback") => {
+        self.advance();
+        self.skip_keyword("transaction");
+        if !self.next_keyword_is("to") {
+          return Ok(Statement::Rollback);
+        }
+        self.advance();
+        self.skip_keyword("savepoint");
+        self.expect_savepoint_name().map(Statement::RollbackTo)
+      }
+      Token::Identifier(ident) if ident.eq_ignore_ascii_case("savepoint") => {
+        self.advance();
+        self.expect_savepoint_name().map(Statement::Savepoint)
+      }
+      Token::Identifier(ident) if ident.eq_ignore_ascii_case("release") => {
+        self.advance();
+        self.skip_keyword("savepoint");
+        self.expect_savepoint_name().map(Statement::Release)
+      }
+      Token::Identifier(ident) if ident.eq_ignore_ascii_case("pragma") => {
+        self.parse_pragma().map(Statement::Pragma)
+      }
+      Token::Identifier(ident) if ident.eq_ignore_ascii_case("drop") => {
+        self.parse_drop_table().map(Statement::DropTable)
+      }
+      Token::Identifier(ident) if ident.eq_ignore_ascii_case("alter") => {
+        self.parse_alter_table().map(Statement::AlterTable)
+      }
       token => bail!("unexpected token: {token:?}"),
     }
   }
 
-  fn parse_select(&mut self) -> anyhow::Result<SelectStatement> {
+  fn parse_explain(&mut self) -> anyhow::Result<ExplainStatement> {
+    self.expect_keyword("explain")?;
+    let query_plan = self.next_keyword_is("query");
+    if query_plan {
+      self.advance();
+      self.expect_keyword("plan")?;
+    }
+    Ok(ExplainStatement {
+      query_plan,
+      statement: Box::new(self.parse_statement()?),
+    })
+  }
+
+  fn parse_insert(&mut self) -> anyhow::Result<InsertStatement> {
+    self.expect_keyword("insert")?;
+    self.expect_keyword("into")?;
+    let table = self.parse_qualified_name()?;
+    let columns = match self.next_token_is(Token::LPar) {
+      true => self.parse_name_list()?,
+      false => vec![],
+    };
+
+    let source = if self.next_token_is(Token::Select) {
+      InsertSource::Select(Box::new(self.parse_select()?))
+    } else {
+      self.expect_keyword("values")?;
+      let mut rows = vec![self.parse_values_row()?];
+      while self.next_token_is(Token::Comma) {
+        self.advance();
+        rows.push(self.parse_values_row()?);
+      }
+      InsertSource::Values(rows)
+    };
+
+    Ok(InsertStatement {
+      table,
+      columns,
+      source,
+    })
+  }
+
+  fn parse_delete(&mut self) -> anyhow::Result<DeleteStatement> {
+    self.expect_keyword("delete")?;
+    self.expect_eq(Token::From)?;
+    let table = self.parse_qualified_name()?;
+    let where_clause = if self.next_token_is(Token::Where) {
+      Some(self.parse_where_clause()?)
+    } else {
+      None
+    };
+    Ok(DeleteStatement {
+      table,
+      where_clause,
+    })
+  }
+
+  fn parse_begin(&mut self) -> anyhow::Result<TransactionMode> {
+    self.expect_keyword("begin")?;
+    let mode = match self.tokens.get(self.pos) {
+      Some(Token::Identifier(ident)) if ident.eq_ignore_ascii_case("deferred") => {
+        TransactionMode::Deferred
+      }
+      Some(Token::Identifier(ident)) if ident.eq_ignore_ascii_case("immediate") => {
+        TransactionMode::Immediate
+      }
+      Some(Token::Identifier(ident)) if ident.eq_ignore_ascii_case("exclusive") => {
+        TransactionMode::Exclusive
+      }
+      _ => {
+        self.skip_keyword("transaction");
+        return Ok(TransactionMode::Deferred);
+      }
+    };
     self.advance();
+    self.skip_keyword("transaction");
+    Ok(mode)
+  }
+
+  fn parse_pragma(&mut self) -> anyhow::Result<PragmaStatement> {
+    self.expect_keyword("pragma")?;
+    let name = self.parse_qualified_name()?;
+    let value = match self.tokens.get(self.pos) {
+      Some(Token::Op(Ops::Eq)) => {
+        self.advance();
+        Some(self.parse_pragma_value()?)
+      }
+      Some(Token::LPar) => {
+        self.advance();
+        let value = self.parse_pragma_value()?;
+        self.expect_eq(Token::RPar)?;
+        Some(value)
+      }
+      _ => None,
+    };
+    Ok(PragmaStatement { name, value })
+  }
+
+  /// A name, string or signed number
+  fn parse_pragma_value(&mut self) -> anyhow::Result<String> {
+    let sign = match self.tokens.get(self.pos) {
+      Some(Token::Minus) => "-",
+      Some(Token::Plus) => "",
+      Some(Token::Int(_) | Token::BigInt(_) | Token::Real(_)) => {
+        return self.parse_pragma_number("")
+      }
+      _ => return self.expect_name(),
+    };
+    self.advance();
+    self.parse_pragma_number(sign)
+  }
+
+  fn parse_pragma_number(&mut self, sign: &str) -> anyhow::Result<String> {
+    match self.next_token() {
+      Some(Token::Int(n)) => Ok(format!("{sign}{n}")),
+      Some(Token::BigInt(n)) => Ok(format!("{sign}{n}")),
+      Some(Token::Real(n)) => Ok(format!("{sign}{n}")),
+      Some(token) => bail!("unexpected token: {:?}", token),
+      None => bail!("unexpected end of input"),
+    }
+  }
+
+  /// Consumes `keyword` when it comes next
+  fn skip_keyword(&mut self, keyword: &str) {
+    if self.next_keyword_is(keyword) {
+      self.advance();
+    }
+  }
+
+  fn parse_update(&mut self) -> anyhow::Result<UpdateStatement> {
+    self.expect_keyword("update")?;
+    let table = self.parse_qualified_name()?;
+    self.expect_keyword("set")?;
+    let mut assignments = vec![self.parse_assignment()?];
+    while self.next_token_is(Token::Comma) {
+      self.advance();
+      assignments.push(self.parse_assignment()?);
+    }
+    let where_clause = if self.next_token_is(Token::Where) {
+      Some(self.parse_where_clause()?)
+    } else {
+      None
+    };
+    Ok(UpdateStatement {
+      table,
+      assignments,
+      where_clause,
+    })
+  }
+
+  /// `column = expr`
+  fn parse_assignment(&mut self) -> anyhow::Result<(String, Expr)> {
+    let column = self.expect_name()?;
+    self.expect_eq(Token::Op(Ops::Eq))?;
+    Ok((column, self.parse_expr()?))
+  }
+
+  /// `(expr, ...)`
+  fn parse_values_row(&mut self) -> anyhow::Result<Vec<Expr>> {
+    self.expect_eq(Token::LPar)?;
+    let mut row = vec![self.parse_expr()?];
+    while self.next_token_is(Token::Comma) {
+      self.advance();
+      row.push(self.parse_expr()?);
+    }
+    self.expect_eq(Token::RPar)?;
+    Ok(row)
+  }
+
+  fn parse_select(&mut self) -> anyhow::Result<SelectStatement> {
+    let core = self.parse_select_core()?;
+
+    let mut compound = vec![];
+    loop {
+      let operator = match self.peak_next_token() {
+        Ok(Token::Union) => {
+          self.advance();
+          if self.next_token_is(Token::All) {
+            self.advance();
+            CompoundOperator::UnionAll
+          } else {
+            CompoundOperator::Union
+          }
+        }
+        Ok(Token::Intersect) => {
+          self.advance();
+          CompoundOperator::Intersect
+        }
+        Ok(Token::Except) => {
+          self.advance();
+          CompoundOperator::Except
+        }
+        _ => break,
+      };
+      compound.push(CompoundSelect {
+        operator,
+        core: self.parse_select_core()?,
+      });
+    }
+
+    let order_by = self.parse_order_by()?;
+    let limit = self.parse_limit()?;
+
+    Ok(SelectStatement {
+      core,
+      compound,
+      order_by,
+      limit,
+    })
+  }
+
+  fn parse_select_core(&mut self) -> anyhow::Result<SelectCore> {
+    self.expect_eq(Token::Select)?;
+    let distinct = self.next_token_is(Token::Distinct);
+    if distinct || self.next_token_is(Token::All) {
+      self.advance();
+    }
     let result_columns = self.parse_result_columns()?;
     self.expect_eq(Token::From)?;
     let from = self.parse_select_from()?;
 
     let mut where_clause = None;
-    if let Token::Where = self.peak_next_token()? {
+    if self.next_token_is(Token::Where) {
       where_clause = Some(self.parse_where_clause()?);
     }
 
-    Ok(SelectStatement {
-      core: SelectCore {
-        result_columns,
-        from,
-        where_clause,
-      },
+    let mut group_by = vec![];
+    if self.next_token_is(Token::Group) {
+      self.advance();
+      self.expect_eq(Token::By)?;
+      group_by.push(self.parse_expr()?);
+      while self.next_token_is(Token::Comma) {
+        self.advance();
+        group_by.push(self.parse_expr()?);
+      }
+    }
+
+    let mut having = None;
+    if self.next_token_is(Token::Having) {
+      self.advance();
+      having = Some(self.parse_expr()?);
+    }
+
+    Ok(SelectCore {
+      distinct,
+      result_columns,
+      from,
+      where_clause,
+      group_by,
+      having,
     })
   }
 
-  fn parse_select_from(&mut self) -> anyhow::Result<SelectFrom> {
-    let table = self.expected_identifier()?;
-    Ok(SelectFrom::Table(table.to_string()))
+  /// `LIMIT n [OFFSET m]`, or `LIMIT m, n` with the offset first
+  fn parse_limit(&mut self) -> anyhow::Result<Option<Limit>> {
+    if !self.next_token_is(Token::Limit) {
+      return Ok(None);
+    }
+    self.advance();
+
+    let first = self.parse_expr()?;
+    let limit = if self.next_token_is(Token::Offset) {
+      self.advance();
+      Limit {
+        limit: first,
+        offset: Some(self.parse_expr()?),
+      }
+    } else if self.next_token_is(Token::Comma) {
+      self.advance();
+      Limit {
+        limit: self.parse_expr()?,
+        offset: Some(first),
+      }
+    } else {
+      Limit {
+        limit: first,
+        offset: None,
+      }
+    };
+    Ok(Some(limit))
   }
 
-  fn parse_where_clause(&mut self) -> anyhow::Result<Expr> {
+  fn parse_order_by(&mut self) -> anyhow::Result<Vec<OrderingTerm>> {
+    if !self.next_token_is(Token::Order) {
+      return Ok(vec![]);
+    }
     self.advance();
-    let mut expr = Expr::Comparison(
-      Box::new(self.parse_expr()?),
-      *(self.expect_operator()?),
-      Box::new(self.expect_literal()?),
-    );
-    match self.peak_next_token() {
-      Ok(Token::Op(new_op)) => {
-        expr = Expr::Comparison(
-          Box::new(expr),
-          *new_op,
-          Box::new(self.parse_where_clause()?),
-        )
+    self.expect_eq(Token::By)?;
+
+    let mut terms = vec![self.parse_ordering_term()?];
+    while self.next_token_is(Token::Comma) {
+      self.advance();
+      terms.push(self.parse_ordering_term()?);
+    }
+    Ok(terms)
+  }
+
+  fn parse_ordering_term(&mut self) -> anyhow::Result<OrderingTerm> {
+    let expr = self.parse_expr()?;
+    let descending = self.parse_sort_order();
+
+    let mut nulls_first = !descending;
+    if self.next_keyword_is("nulls") {
+      self.advance();
+      nulls_first = match self.expected_identifier()?.to_ascii_lowercase().as_str() {
+        "first" => true,
+        "last" => false,
+        other => bail!("expected FIRST or LAST after NULLS, got {other}"),
+      };
+    }
+
+    Ok(OrderingTerm {
+      expr,
+      descending,
+      nulls_first,
+    })
+  }
+
+  fn parse_select_from(&mut self) -> anyhow::Result<SelectFrom> {
+    let mut from = SelectFrom::Table(self.parse_table_ref()?);
+
+    loop {
+      let natural = self.next_token_is(Token::Natural);
+      if natural {
+        self.advance();
       }
-      Err(e) => bail!("Error parsing where: {e:?}"),
-      _ => {}
+      let operator = match self.peak_next_token() {
+        Ok(Token::Comma) if !natural => JoinOperator::Inner,
+        Ok(Token::Join | Token::Inner) => JoinOperator::Inner,
+        Ok(Token::Left) => JoinOperator::Left,
+        Ok(Token::Cross) => JoinOperator::Cross,
+        _ if natural => bail!("expected a join after NATURAL"),
+        _ => break,
+      };
+      match self.next_token() {
+        Some(Token::Comma | Token::Join) => {}
+        Some(Token::Left) => {
+          if self.next_token_is(Token::Outer) {
+            self.advance();
+          }
+          self.expect_eq(Token::Join)?;
+        }
+        _ => {
+          self.expect_eq(Token::Join)?;
+        }
+      }
+
+      let right = self.parse_table_ref()?;
+      let constraint = match self.peak_next_token() {
+        Ok(Token::On) => {
+          self.advance();
+          Some(JoinConstraint::On(self.parse_expr()?))
+        }
+        Ok(Token::Using) => {
+          self.advance();
+          self.expect_eq(Token::LPar)?;
+          let mut columns = vec![self.expect_name()?];
+          while self.next_token_is(Token::Comma) {
+            self.advance();
+            columns.push(self.expect_name()?);
+          }
+          self.expect_eq(Token::RPar)?;
+          Some(JoinConstraint::Using(columns))
+        }
+        _ => None,
+      };
+      if natural && constraint.is_some() {
+        bail!("a NATURAL join may not have an ON or USING clause");
+      }
+
+      from = SelectFrom::Join(Box::new(Join {
+        left: from,
+        operator,
+        natural,
+        right,
+        constraint,
+      }));
     }
+    Ok(from)
+  }
 
-    Ok(expr)
+  /// `name [[AS] alias]`
+  fn parse_table_ref(&mut self) -> anyhow::Result<TableRef> {
+    let name = self.expected_identifier()?.to_string();
+    let alias = match self.peak_next_token() {
+      Ok(Token::As) => {
+        self.advance();
+        Some(self.expected_identifier()?.to_string())
+      }
+      Ok(Token::Identifier(alias)) => {
+        let alias = alias.clone();
+        self.advance();
+        Some(alias)
+      }
+      _ => None,
+    };
+    Ok(TableRef { name, alias })
+  }
+
+  fn parse_where_clause(&mut self) -> anyhow::Result<Expr> {
+    self.advance();
+    self.parse_expr()
   }
 
   fn parse_result_columns(&mut self) -> anyhow::Result<Vec<ResultColumn>> {
@@ -88,49 +540,304 @@ impl ParserState {
       self.advance();
       return Ok(ResultColumn::Star);
     }
+    if let (Some(Token::Identifier(table)), Some(Token::Dot), Some(Token::Star)) = (
+      self.tokens.get(self.pos),
+      self.tokens.get(self.pos + 1),
+      self.tokens.get(self.pos + 2),
+    ) {
+      let table = table.clone();
+      self.pos += 3;
+      return Ok(ResultColumn::TableStar(table));
+    }
 
     Ok(ResultColumn::Expr(self.parse_expr_result_column()?))
   }
 
   fn parse_expr(&mut self) -> anyhow::Result<Expr> {
-    Ok(Expr::Column(self.expected_identifier()?.to_string()))
+    self.parse_expr_above(Precedence::Or)
+  }
+
+  /// Precedence climbing: parses an expression whose binary operators all bind
+  /// at least as tightly as `min`
+  fn parse_expr_above(&mut self, min: Precedence) -> anyhow::Result<Expr> {
+    let mut expr = if min <= Precedence::Not && self.next_token_is(Token::Not) {
+      self.advance();
+      Expr::Unary(
+        UnaryOp::Not,
+        Box::new(self.parse_expr_above(Precedence::Not)?),
+      )
+    } else {
+      self.parse_unary()?
+    };
+
+    loop {
+      if min <= Precedence::Equality {
+        if let Some(postfix) = self.parse_postfix(&expr)? {
+          expr = postfix;
+          continue;
+        }
+      }
+
+      let Some((op, precedence)) = self.peak_binary_op() else {
+        break;
+      };
+      if precedence < min {
+        break;
+      }
+      self.advance();
+
+      // `IS NOT` is a single operator
+      let op = if op == Ops::Is && self.next_token_is(Token::Not) {
+        self.advance();
+        Ops::IsNot
+      } else {
+        op
+      };
+      let right = self.parse_expr_above(precedence.tighter())?;
+      expr = Expr::Comparison(Box::new(expr), op, Box::new(right));
+    }
+    Ok(expr)
+  }
+
+  /// `[NOT] BETWEEN`, `[NOT] IN`, `ISNULL`, `NOTNULL` and `NOT NULL` following `expr`
+  fn parse_postfix(&mut self, expr: &Expr) -> anyhow::Result<Option<Expr>> {
+    let is_null = |op| Expr::Comparison(Box::new(expr.clone()), op, Box::new(Expr::Null));
+
+    if self.next_keyword_is("isnull") {
+      self.advance();
+      return Ok(Some(is_null(Ops::Is)));
+    }
+    if self.next_keyword_is("notnull") {
+      self.advance();
+      return Ok(Some(is_null(Ops::IsNot)));
+    }
+
+    let negated = self.next_token_is(Token::Not);
+    match self.tokens.get(self.pos + negated as usize) {
+      Some(Token::Null) if negated => {
+        self.pos += 2;
+        Ok(Some(is_null(Ops::IsNot)))
+      }
+      Some(Token::Between) => {
+        self.pos += 1 + negated as usize;
+        let between = self.parse_between(expr)?;
+        Ok(Some(if negated {
+          Expr::Unary(UnaryOp::Not, Box::new(between))
+        } else {
+          between
+        }))
+      }
+      Some(Token::In) => {
+        self.pos += 1 + negated as usize;
+        self.expect_eq(Token::LPar)?;
+        let mut list = vec![self.parse_expr()?];
+        while self.next_token_is(Token::Comma) {
+          self.advance();
+          list.push(self.parse_expr()?);
+        }
+        self.expect_eq(Token::RPar)?;
+        Ok(Some(Expr::InList {
+          expr: Box::new(expr.clone()),
+          list,
+          negated,
+        }))
+      }
+      _ => Ok(None),
+    }
+  }
+
+  /// `x BETWEEN a AND b` is `x >= a AND x <= b`
+  fn parse_between(&mut self, expr: &Expr) -> anyhow::Result<Expr> {
+    let low = self.parse_expr_above(Precedence::Equality.tighter())?;
+    self.expect_eq(Token::Op(Ops::And))?;
+    let high = self.parse_expr_above(Precedence::Equality.tighter())?;
+    Ok(Expr::Comparison(
+      Box::new(Expr::Comparison(
+        Box::new(expr.clone()),
+        Ops::Goe,
+        Box::new(low),
+      )),
+      Ops::And,
+      Box::new(Expr::Comparison(
+        Box::new(expr.clone()),
+        Ops::Loe,
+        Box::new(high),
+      )),
+    ))
+  }
+
+  fn peak_binary_op(&self) -> Option<(Ops, Precedence)> {
+    let op = match self.tokens.get(self.pos)? {
+      Token::Op(op) => *op,
+      Token::Is => Ops::Is,
+      Token::Plus => Ops::Add,
+      Token::Minus => Ops::Sub,
+      Token::Star => Ops::Mul,
+      _ => return None,
+    };
+
+    let precedence = match op {
+      Ops::Or => Precedence::Or,
+      Ops::And => Precedence::And,
+      Ops::Eq | Ops::Ne | Ops::Is | Ops::IsNot => Precedence::Equality,
+      Ops::Lt | Ops::Gt | Ops::Loe | Ops::Goe => Precedence::Relational,
+      Ops::Add | Ops::Sub => Precedence::Additive,
+      Ops::Mul | Ops::Div | Ops::Mod => Precedence::Multiplicative,
+      Ops::Concat => Precedence::Concat,
+    };
+    Some((op, precedence))
+  }
+
+  fn parse_unary(&mut self) -> anyhow::Result<Expr> {
+    match self.peak_next_token()? {
+      Token::Minus => {
+        self.advance();
+        // the literal is negated before it is range checked, so the smallest
+        // integer can be written
+        if let Some(Token::BigInt(digits)) = self.tokens.get(self.pos) {
+          if let Ok(i) = format!("-{digits}").parse() {
+            self.advance();
+            return Ok(Expr::Int(i));
+          }
+        }
+        // negative literals are folded so they stay literals
+        Ok(match self.parse_unary()? {
+          Expr::Int(i) if i != i64::MIN => Expr::Int(-i),
+          Expr::Real(r) => Expr::Real(-r),
+          expr => Expr::Unary(UnaryOp::Neg, Box::new(expr)),
+        })
+      }
+      Token::Plus => {
+        self.advance();
+        self.parse_unary()
+      }
+      _ => self.parse_primary(),
+    }
+  }
+
+  fn parse_primary(&mut self) -> anyhow::Result<Expr> {
+    match self.next_token() {
+      Some(Token::LPar) => {
+        let expr = self.parse_expr()?;
+        self.expect_eq(Token::RPar)?;
+        Ok(expr)
+      }
+      Some(Token::Identifier(name)) => {
+        let name = name.clone();
+        if self.next_token_is(Token::LPar) {
+          return self.parse_function_call(name);
+        }
+        if self.next_token_is(Token::Dot) {
+          self.advance();
+          return Ok(Expr::QualifiedColumn(name, self.expect_name()?));
+        }
+        Ok(Expr::Column(name))
+      }
+      Some(token) => token
+        .as_literal()
+        .with_context(|| format!("unexpected token: {token:?}")),
+      None => bail!("unexpected end of input"),
+    }
+  }
+
+  /// `name([DISTINCT] args)`, `name(*)` or `name()`
+  fn parse_function_call(&mut self, name: String) -> anyhow::Result<Expr> {
+    self.expect_eq(Token::LPar)?;
+    let distinct = self.next_token_is(Token::Distinct);
+    if distinct {
+      self.advance();
+    }
+
+    let mut args = vec![];
+    if self.next_token_is(Token::Star) && !distinct {
+      self.advance();
+    } else if !self.next_token_is(Token::RPar) {
+      args.push(self.parse_expr()?);
+      while self.next_token_is(Token::Comma) {
+        self.advance();
+        args.push(self.parse_expr()?);
+      }
+    }
+    self.expect_eq(Token::RPar)?;
+
+    Ok(Expr::Function(FunctionCall {
+      name,
+      distinct,
+      args,
+    }))
+  }
+
+  fn parse_parenthesized_expr(&mut self) -> anyhow::Result<Expr> {
+    self.expect_eq(Token::LPar)?;
+    let expr = self.parse_expr()?;
+    self.expect_eq(Token::RPar)?;
+    Ok(expr)
   }
 
   fn parse_expr_result_column(&mut self) -> anyhow::Result<ExprResultColumn> {
+    let start = self.pos;
     let expr = self.parse_expr()?;
+    let text = self.source_text(start, self.pos).to_string();
     let alias = if self.next_token_is(Token::As) {
       self.advance();
-      Some(self.expected_identifier()?.to_string())
+      Some(self.expect_name()?)
+    } else if let Some(Token::Identifier(alias)) = self.tokens.get(self.pos) {
+      // `AS` is optional
+      let alias = alias.clone();
+      self.advance();
+      Some(alias)
     } else {
       None
     };
-    Ok(ExprResultColumn { expr, alias })
+    Ok(ExprResultColumn { expr, text, alias })
   }
 
   fn next_token_is(&self, expected: Token) -> bool {
     self.tokens.get(self.pos) == Some(&expected)
   }
 
-  fn expected_identifier(&mut self) -> anyhow::Result<&str> {
-    self
-      .expect_matching(|t| matches!(t, Token::Identifier(_)))
-      .map(|t| t.as_identifier().unwrap())
+  /// Non-reserved keywords are tokenized as identifiers so they stay usable as names
+  fn next_keyword_is(&self, keyword: &str) -> bool {
+    matches!(self.tokens.get(self.pos), Some(Token::Identifier(ident)) if ident.eq_ignore_ascii_case(keyword))
   }
 
-  fn expect_operator(&mut self) -> anyhow::Result<&Ops> {
-    self
-      .expect_matching(|t| matches!(t, Token::Op(_)))
-      .map(|t| t.as_op().unwrap())
+  fn expect_keyword(&mut self, keyword: &str) -> anyhow::Result<&Token> {
+    self.expect_matching(
+      |t| matches!(t, Token::Identifier(ident) if ident.eq_ignore_ascii_case(keyword)),
+    )
+  }
+
+  /// An identifier, or a string literal used as one as sqlite allows in DDL
+  fn expect_name(&mut self) -> anyhow::Result<String> {
+    match self.next_token() {
+      Some(Token::Identifier(name)) => Ok(name.clone()),
+      Some(Token::String(name)) => Ok(name.clone()),
+      Some(token) => bail!("unexpected token: {:?}", token),
+      None => bail!("unexpected end of input"),
+    }
   }
-  fn expect_literal(&mut self) -> anyhow::Result<Expr> {
+
+  /// A savepoint name, which can also be one of the keywords sqlite doesn't
+  /// reserve there
+  fn expect_savepoint_name(&mut self) -> anyhow::Result<String> {
+    let keyword = match self.peak_next_token()? {
+      Token::By => "by",
+      Token::Inner => "inner",
+      Token::Left => "left",
+      Token::Outer => "outer",
+      Token::Cross => "cross",
+      Token::Natural => "natural",
+      Token::Offset => "offset",
+      _ => return self.expect_name(),
+    };
+    self.advance();
+    Ok(keyword.to_string())
+  }
+
+  fn expected_identifier(&mut self) -> anyhow::Result<&str> {
     self
-      .expect_matching(|t| {
-        matches!(
-          t,
-          Token::Null | Token::Int(_) | Token::Real(_) | Token::String(_)
-        )
-      })
-      .map(|t| t.as_literal().unwrap())
+      .expect_matching(|t| matches!(t, Token::Identifier(_)))
+      .map(|t| t.as_identifier().unwrap())
   }
 
   fn expect_eq(&mut self, expected: Token) -> anyhow::Result<&Token> {
@@ -160,45 +867,678 @@ impl ParserState {
 
   fn parse_create_table(&mut self) -> anyhow::Result<CreateTableStatement> {
     self.expect_eq(Token::Create)?;
+    if self.next_keyword_is("temp") || self.next_keyword_is("temporary") {
+      self.advance();
+    }
     self.expect_eq(Token::Table)?;
-    let name = self.expected_identifier()?.to_string();
+    let if_not_exists = self.parse_if_not_exists()?;
+    let name = self.parse_qualified_name()?;
+    let name_pos = self.pos - 1;
+
     self.expect_eq(Token::LPar)?;
     let mut columns = vec![self.parse_column_def()?];
+    let mut constraints = vec![];
     while self.next_token_is(Token::Comma) {
       self.advance();
-      columns.push(self.parse_column_def()?);
+      if self.next_is_table_constraint() {
+        constraints.push(self.parse_table_constraint()?);
+      } else if constraints.is_empty() {
+        columns.push(self.parse_column_def()?);
+      } else {
+        bail!("column definitions should come before table constraints");
+      }
     }
     self.expect_eq(Token::RPar)?;
-    Ok(CreateTableStatement { name, columns })
+
+    let mut without_rowid = false;
+    loop {
+      if self.next_keyword_is("without") {
+        self.advance();
+        self.expect_keyword("rowid")?;
+        without_rowid = true;
+      } else if self.next_keyword_is("strict") {
+        self.advance();
+      } else {
+        break;
+      }
+
+      if self.next_token_is(Token::Comma) {
+        self.advance();
+      }
+    }
+
+    Ok(CreateTableStatement {
+      name,
+      columns,
+      constraints,
+      if_not_exists,
+      without_rowid,
+      sql: format!("CREATE TABLE {}", self.source_text(name_pos, self.pos)),
+    })
+  }
+
+  fn parse_if_not_exists(&mut self) -> anyhow::Result<bool> {
+    if !self.next_keyword_is("if") {
+      return Ok(false);
+    }
+    self.advance();
+    self.expect_eq(Token::Not)?;
+    self.expect_keyword("exists")?;
+    Ok(true)
+  }
+
+  fn parse_if_exists(&mut self) -> anyhow::Result<bool> {
+    if !self.next_keyword_is("if") {
+      return Ok(false);
+    }
+    self.advance();
+    self.expect_keyword("exists")?;
+    Ok(true)
+  }
+
+  fn parse_drop_table(&mut self) -> anyhow::Result<DropTableStatement> {
+    self.expect_keyword("drop")?;
+    self.expect_eq(Token::Table)?;
+    let if_exists = self.parse_if_exists()?;
+    let name = self.parse_qualified_name()?;
+    Ok(DropTableStatement { name, if_exists })
+  }
+
+  fn parse_alter_table(&mut self) -> anyhow::Result<AlterTableStatement> {
+    self.expect_keyword("alter")?;
+    self.expect_eq(Token::Table)?;
+    let table = self.parse_qualified_name()?;
+
+    let action = if self.next_keyword_is("rename") {
+      self.advance();
+      if self.next_keyword_is("to") {
+        self.advance();
+        AlterTableAction::RenameTable(self.expect_name()?)
+      } else {
+        self.skip_keyword("column");
+        let old = self.expect_name()?;
+        self.expect_keyword("to")?;
+        let new = self.expect_name()?;
+        AlterTableAction::RenameColumn { old, new }
+      }
+    } else if self.next_keyword_is("add") {
+      self.advance();
+      self.skip_keyword("column");
+      let start = self.pos;
+      let column = self.parse_column_def()?;
+      let sql = self.source_text(start, self.pos).to_string();
+      AlterTableAction::AddColumn { column, sql }
+    } else if self.next_keyword_is("drop") {
+      self.advance();
+      self.skip_keyword("column");
+      AlterTableAction::DropColumn(self.expect_name()?)
+    } else {
+      bail!("expected RENAME, ADD or DROP after ALTER TABLE {table}");
+    };
+    Ok(AlterTableStatement { table, action })
+  }
+
+  /// `[schema.]name`, the schema is dropped
+  fn parse_qualified_name(&mut self) -> anyhow::Result<String> {
+    let mut name = self.expect_name()?;
+    if self.next_token_is(Token::Dot) {
+      self.advance();
+      name = self.expect_name()?;
+    }
+    Ok(name)
   }
+
   fn parse_column_def(&mut self) -> anyhow::Result<ColumnDef> {
+    let name = self.expect_name()?;
+    let type_name = self.parse_type_name()?;
+    let col_type = Type::from_declared(type_name.as_deref());
+
+    let mut constraints = vec![];
+    while let Some(constraint) = self.parse_column_constraint()? {
+      constraints.push(constraint);
+    }
+
     Ok(ColumnDef {
-      name: self.expected_identifier()?.to_string(),
-      col_type: self.parse_type()?,
+      name,
+      type_name,
+      col_type,
+      constraints,
     })
   }
 
-  fn parse_type(&mut self) -> anyhow::Result<Type> {
-    let type_name = self.expected_identifier()?;
-    let t = match type_name.to_lowercase().as_str() {
-      "integer" => Type::Integer,
-      "real" => Type::Real,
-      "blob" => Type::Blob,
-      "bool" => Type::Bool,
-      "text" | "string" => Type::Text,
-      _ => bail!("unsupported type: {type_name}"),
+  /// Declared type: one or more names optionally followed by a size, e.g. `unsigned big int`
+  /// or `decimal(10, 2)`
+  fn parse_type_name(&mut self) -> anyhow::Result<Option<String>> {
+    let start = self.pos;
+    while matches!(self.tokens.get(self.pos), Some(Token::Identifier(ident)) if !ident.eq_ignore_ascii_case("generated"))
+    {
+      self.advance();
+    }
+    if self.pos == start {
+      return Ok(None);
+    }
+
+    if self.next_token_is(Token::LPar) {
+      self.skip_parenthesized()?;
+    }
+    Ok(Some(self.source_text(start, self.pos).to_string()))
+  }
+
+  fn parse_column_constraint(&mut self) -> anyhow::Result<Option<ColumnConstraint>> {
+    if self.next_token_is(Token::Constraint) {
+      self.advance();
+      self.expect_name()?;
+    }
+
+    let Some(token) = self.tokens.get(self.pos) else {
+      return Ok(None);
     };
-    Ok(t)
+
+    let constraint = match token {
+      Token::Primary => {
+        self.advance();
+        self.expect_keyword("key")?;
+        let descending = self.parse_sort_order();
+        self.parse_conflict_clause()?;
+        let autoincrement = self.next_keyword_is("autoincrement");
+        if autoincrement {
+          self.advance();
+        }
+        ColumnConstraint::PrimaryKey {
+          descending,
+          autoincrement,
+        }
+      }
+      Token::Not => {
+        self.advance();
+        self.expect_eq(Token::Null)?;
+        self.parse_conflict_clause()?;
+        ColumnConstraint::NotNull
+      }
+      Token::Null => {
+        // explicitly nullable, which is the default anyway
+        self.advance();
+        self.parse_conflict_clause()?;
+        return self.parse_column_constraint();
+      }
+      Token::Unique => {
+        self.advance();
+        self.parse_conflict_clause()?;
+        ColumnConstraint::Unique
+      }
+      Token::Check => {
+        self.advance();
+        ColumnConstraint::Check(self.parse_parenthesized_expr()?)
+      }
+      Token::Default => {
+        self.advance();
+        ColumnConstraint::Default(self.parse_default_value()?)
+      }
+      Token::Collate => {
+        self.advance();
+        ColumnConstraint::Collate(self.expect_name()?)
+      }
+      Token::References => ColumnConstraint::References(self.parse_foreign_key_clause()?),
+      Token::As => {
+        self.advance();
+        self.parse_generated_column()?
+      }
+      Token::Identifier(ident) if ident.eq_ignore_ascii_case("generated") => {
+        self.advance();
+        self.expect_keyword("always")?;
+        self.expect_eq(Token::As)?;
+        self.parse_generated_column()?
+      }
+      _ => return Ok(None),
+    };
+    Ok(Some(constraint))
+  }
+
+  fn parse_generated_column(&mut self) -> anyhow::Result<ColumnConstraint> {
+    let expr = self.parse_parenthesized_expr()?;
+    let stored = self.next_keyword_is("stored");
+    if stored || self.next_keyword_is("virtual") {
+      self.advance();
+    }
+    Ok(ColumnConstraint::Generated { expr, stored })
+  }
+
+  /// A literal, a signed number, a bare name such as `current_timestamp`,
+  /// or a parenthesized expression
+  fn parse_default_value(&mut self) -> anyhow::Result<Expr> {
+    match self.peak_next_token()? {
+      Token::LPar => self.parse_parenthesized_expr(),
+      Token::Plus
+      | Token::Minus
+      | Token::Int(_)
+      | Token::BigInt(_)
+      | Token::Real(_)
+      | Token::String(_)
+      | Token::Blob(_)
+      | Token::Null
+      | Token::Identifier(_) => self.parse_unary(),
+      token => bail!("unexpected default value: {token:?}"),
+    }
+  }
+
+  fn next_is_table_constraint(&self) -> bool {
+    matches!(
+      self.tokens.get(self.pos),
+      Some(Token::Constraint | Token::Primary | Token::Unique | Token::Check | Token::Foreign)
+    )
+  }
+
+  fn parse_table_constraint(&mut self) -> anyhow::Result<TableConstraint> {
+    if self.next_token_is(Token::Constraint) {
+      self.advance();
+      self.expect_name()?;
+    }
+
+    let constraint = match self.peak_next_token()? {
+      Token::Primary => {
+        self.advance();
+        self.expect_keyword("key")?;
+        let columns = self.parse_indexed_columns()?;
+        if self.next_keyword_is("autoincrement") {
+          self.advance();
+        }
+        TableConstraint::PrimaryKey(columns)
+      }
+      Token::Unique => {
+        self.advance();
+        TableConstraint::Unique(self.parse_indexed_columns()?)
+      }
+      Token::Check => {
+        self.advance();
+        TableConstraint::Check(self.parse_parenthesized_expr()?)
+      }
+      Token::Foreign => {
+        self.advance();
+        self.expect_keyword("key")?;
+        let columns = self.parse_name_list()?;
+        let references = self.parse_foreign_key_clause()?;
+        TableConstraint::ForeignKey {
+          columns,
+          references,
+        }
+      }
+      token => bail!("unexpected token: {token:?}"),
+    };
+
+    self.parse_conflict_clause()?;
+    Ok(constraint)
+  }
+
+  fn parse_foreign_key_clause(&mut self) -> anyhow::Result<ForeignKeyClause> {
+    self.expect_eq(Token::References)?;
+    let table = self.expect_name()?;
+    let columns = if self.next_token_is(Token::LPar) {
+      self.parse_name_list()?
+    } else {
+      vec![]
+    };
+
+    // actions and deferral are not enforced, they are only skipped over
+    loop {
+      if self.next_token_is(Token::On) {
+        // ON DELETE|UPDATE SET NULL|SET DEFAULT|CASCADE|RESTRICT|NO ACTION
+        self.advance();
+        self.advance();
+        if self.next_keyword_is("set") || self.next_keyword_is("no") {
+          self.advance();
+        }
+        self.advance();
+      } else if self.next_keyword_is("match") {
+        self.advance();
+        self.advance();
+      } else if self.next_keyword_is("deferrable")
+        || (self.next_token_is(Token::Not)
+          && matches!(self.tokens.get(self.pos + 1), Some(Token::Identifier(i)) if i.eq_ignore_ascii_case("deferrable")))
+      {
+        if self.next_token_is(Token::Not) {
+          self.advance();
+        }
+        self.advance();
+        if self.next_keyword_is("initially") {
+          self.advance();
+          self.advance();
+        }
+      } else {
+        break;
+      }
+    }
+
+    Ok(ForeignKeyClause { table, columns })
+  }
+
+  /// `ON CONFLICT <resolution>`, which is not enforced
+  fn parse_conflict_clause(&mut self) -> anyhow::Result<()> {
+    if self.next_token_is(Token::On) {
+      self.advance();
+      self.expect_keyword("conflict")?;
+      self.expected_identifier()?;
+    }
+    Ok(())
+  }
+
+  fn parse_sort_order(&mut self) -> bool {
+    let descending = self.next_keyword_is("desc");
+    if descending || self.next_keyword_is("asc") {
+      self.advance();
+    }
+    descending
+  }
+
+  fn parse_name_list(&mut self) -> anyhow::Result<Vec<String>> {
+    self.expect_eq(Token::LPar)?;
+    let mut names = vec![self.expect_name()?];
+    while self.next_token_is(Token::Comma) {
+      self.advance();
+      names.push(self.expect_name()?);
+    }
+    self.expect_eq(Token::RPar)?;
+    Ok(names)
+  }
+
+  fn parse_indexed_columns(&mut self) -> anyhow::Result<Vec<IndexedColumn>> {
+    self.expect_eq(Token::LPar)?;
+    let mut columns = vec![self.parse_indexed_column()?];
+    while self.next_token_is(Token::Comma) {
+      self.advance();
+      columns.push(self.parse_indexed_column()?);
+    }
+    self.expect_eq(Token::RPar)?;
+    Ok(columns)
+  }
+
+  /// Skips a parenthesized token group, returning the source text between the parentheses
+  fn skip_parenthesized(&mut self) -> anyhow::Result<&str> {
+    self.expect_eq(Token::LPar)?;
+    let start = self.pos;
+    let mut depth = 1;
+    while depth > 0 {
+      match self.next_token() {
+        Some(Token::LPar) => depth += 1,
+        Some(Token::RPar) => depth -= 1,
+        Some(_) => {}
+        None => bail!("unexpected end of input"),
+      }
+    }
+    Ok(self.source_text(start, self.pos - 1).trim())
+  }
+
+  /// Source text covered by the tokens in `start..end`
+  fn source_text(&self, start: usize, end: usize) -> &str {
+    if start >= end {
+      return "";
+    }
+    &self.source[self.spans[start].start..self.spans[end - 1].end]
+  }
+
+  fn parse_create_index(&mut self) -> anyhow::Result<CreateIndexStatement> {
+    self.expect_eq(Token::Create)?;
+    let unique = self.next_token_is(Token::Unique);
+    if unique {
+      self.advance();
+    }
+    self.expect_eq(Token::Index)?;
+    self.parse_if_not_exists()?;
+    let name = self.parse_qualified_name()?;
+    self.expect_eq(Token::On)?;
+    let table = self.expect_name()?;
+    let columns = self.parse_indexed_columns()?;
+
+    let where_clause = if self.next_token_is(Token::Where) {
+      Some(self.parse_where_clause()?)
+    } else {
+      None
+    };
+
+    Ok(CreateIndexStatement {
+      name,
+      table,
+      unique,
+      columns,
+      where_clause,
+    })
+  }
+
+  fn parse_indexed_column(&mut self) -> anyhow::Result<IndexedColumn> {
+    let column = self.name_at(self.pos).is_some()
+      && match self.tokens.get(self.pos + 1) {
+        Some(Token::Comma | Token::RPar | Token::Collate) => true,
+        Some(Token::Identifier(keyword)) => {
+          keyword.eq_ignore_ascii_case("asc") || keyword.eq_ignore_ascii_case("desc")
+        }
+        _ => false,
+      };
+    let (name, expression) = match column {
+      true => (self.expect_name()?, None),
+      false => {
+        let start = self.pos;
+        let expr = self.parse_expr()?;
+        (self.source_text(start, self.pos).to_string(), Some(expr))
+      }
+    };
+    if self.next_token_is(Token::Collate) {
+      self.advance();
+      self.expect_name()?;
+    }
+
+    let descending = self.parse_sort_order();
+    Ok(IndexedColumn {
+      name,
+      descending,
+      expression,
+    })
   }
 
   fn advance(&mut self) {
     self.pos += 1;
   }
+
+  /// Name held by token `i`, an identifier or a string literal used as one
+  fn name_at(&self, i: usize) -> Option<&str> {
+    match self.tokens.get(i)? {
+      Token::Identifier(name) => Some(name),
+      Token::String(name) => Some(name),
+      _ => None,
+    }
+  }
+
+  /// Whether token `i` holds `name`, names match without case as in sqlite
+  fn name_is(&self, i: usize, name: &str) -> bool {
+    self
+      .name_at(i)
+      .is_some_and(|n| n.eq_ignore_ascii_case(name))
+  }
+
+  /// Byte ranges of the column definitions of a CREATE TABLE statement
+  fn column_def_spans(&mut self) -> anyhow::Result<Vec<Range<usize>>> {
+    self.expect_eq(Token::Create)?;
+    while !self.next_token_is(Token::LPar) {
+      self.next_token().context("unexpected end of input")?;
+    }
+    self.advance();
+
+    let mut spans = vec![];
+    loop {
+      let start = self.pos;
+      self.parse_column_def()?;
+      spans.push(self.spans[start].start..self.spans[self.pos - 1].end);
+      if !self.next_token_is(Token::Comma) {
+        return Ok(spans);
+      }
+      self.advance();
+      if self.next_is_table_constraint() {
+        return Ok(spans);
+      }
+    }
+  }
+
+  /// Tokens of a CREATE TABLE or CREATE INDEX statement naming the column `column`
+  /// of `table`: in its definition, constraints, indexed columns and conditions
+  /// when the statement is on `table`, and in the foreign keys referencing it
+  fn column_positions(&self, table: &str, column: &str) -> Vec<usize> {
+    let is_index = matches!(self.tokens.get(1), Some(Token::Index | Token::Unique));
+    // the created or indexed table, the statement names no column before it
+    let target = match is_index {
+      true => self
+        .tokens
+        .iter()
+        .position(|t| *t == Token::On)
+        .map_or(0, |p| p + 1),
+      false => 2,
+    };
+    let own = self.name_is(target, table);
+
+    let mut positions = vec![];
+    let mut depth = 0;
+    // depth of the column list of a REFERENCES clause, and whether it references `table`
+    let mut references: Option<(usize, bool)> = None;
+    for (i, token) in self.tokens.iter().enumerate() {
+      let prev = i.checked_sub(1).map(|p| &self.tokens[p]);
+      match token {
+        Token::LPar => {
+          depth += 1;
+          if i >= 2 && self.tokens[i - 2] == Token::References {
+            references = Some((depth, self.name_is(i - 1, table)));
+          }
+        }
+        Token::RPar => {
+          if references.is_some_and(|(d, _)| d == depth) {
+            references = None;
+          }
+          depth -= 1;
+        }
+        Token::Identifier(name) if name.eq_ignore_ascii_case(column) && i > target => {
+          let is_column = match references {
+            Some((_, referenced)) => referenced,
+            None if !own => false,
+            // the table's column list: names that start an item define a
+            // column, those after them are types and constraint names
+            None if !is_index && depth == 1 => matches!(prev, Some(Token::LPar | Token::Comma)),
+            // function calls and collations aren't columns
+            None => prev != Some(&Token::Collate) && self.tokens.get(i + 1) != Some(&Token::LPar),
+          };
+          if is_column {
+            positions.push(i);
+          }
+        }
+        _ => {}
+      }
+    }
+    positions
+  }
+
+  /// Tokens naming a table: where it is created, indexed, referenced by a
+  /// foreign key, triggered on, read or written
+  fn table_positions(&self) -> Vec<usize> {
+    (1..self.tokens.len())
+      .filter(|&i| self.name_at(i).is_some())
+      .filter(|&i| match &self.tokens[i - 1] {
+        Token::Table | Token::On | Token::References | Token::From | Token::Join => true,
+        // `UPDATE OF columns` of a trigger names no table
+        Token::Identifier(keyword) => {
+          keyword.eq_ignore_ascii_case("into")
+            || keyword.eq_ignore_ascii_case("update") && !self.name_is(i, "of")
+        }
+        _ => false,
+      })
+      .collect()
+  }
+
+  /// Tokens of a CREATE VIEW or CREATE TRIGGER statement naming the column
+  /// `column` of `table`. Names qualified by the table, one of its aliases or,
+  /// in a trigger on it, `old` and `new` are columns of it; bare names only in
+  /// the statements of the body that use no other table.
+  fn body_column_positions(&self, table: &str, column: &str) -> Vec<usize> {
+    let tables = self.table_positions();
+    let mut qualifiers = vec![table];
+    for &p in tables.iter().filter(|&&p| self.name_is(p, table)) {
+      let alias = match self.tokens.get(p + 1) {
+        Some(Token::As) => p + 2,
+        _ => p + 1,
+      };
+      if let Some(Token::Identifier(alias)) = self.tokens.get(alias) {
+        qualifiers.push(alias);
+      }
+    }
+    let trigger = self.tokens.get(1..3).is_some_and(|t| {
+      t.iter()
+        .any(|t| matches!(t, Token::Identifier(k) if k.eq_ignore_ascii_case("trigger")))
+    });
+    let trigger_on = self.tokens.iter().position(|t| *t == Token::On);
+    if trigger && trigger_on.is_some_and(|p| self.name_is(p + 1, table)) {
+      qualifiers.extend(["old", "new"]);
+    }
+
+    // the header of a trigger and each statement of its body, or the whole view
+    let mut positions = vec![];
+    let mut start = 0;
+    for (end, token) in self
+      .tokens
+      .iter()
+      .enumerate()
+      .chain([(self.tokens.len(), &Token::SemiColon)])
+    {
+      let boundary = match token {
+        Token::SemiColon => true,
+        Token::Identifier(keyword) => trigger && keyword.eq_ignore_ascii_case("begin"),
+        _ => false,
+      };
+      if !boundary {
+        continue;
+      }
+      let only_table = tables
+        .iter()
+        .filter(|&&p| (start..end).contains(&p))
+        .all(|&p| self.name_is(p, table));
+      for i in start..end {
+        if !matches!(&self.tokens[i], Token::Identifier(name) if name.eq_ignore_ascii_case(column))
+          || tables.contains(&i)
+        {
+          continue;
+        }
+        let prev = i.checked_sub(1).map(|p| &self.tokens[p]);
+        // function calls, qualifiers, collations and aliases aren't columns
+        if matches!(self.tokens.get(i + 1), Some(Token::LPar | Token::Dot))
+          || matches!(prev, Some(Token::Collate | Token::As))
+        {
+          continue;
+        }
+        let is_column = match prev {
+          Some(Token::Dot) => self
+            .name_at(i - 2)
+            .is_some_and(|q| qualifiers.iter().any(|n| n.eq_ignore_ascii_case(q))),
+          _ => only_table,
+        };
+        if is_column {
+          positions.push(i);
+        }
+      }
+      start = end + 1;
+    }
+    positions
+  }
+
+  /// The source with every token at `positions` replaced by `replacement` of its text
+  fn replace_tokens(&self, positions: &[usize], replacement: impl Fn(&str) -> String) -> String {
+    let mut sql = String::with_capacity(self.source.len());
+    let mut end = 0;
+    for &i in positions {
+      let span = self.spans[i].clone();
+      sql.push_str(&self.source[end..span.start]);
+      sql.push_str(&replacement(&self.source[span.clone()]));
+      end = span.end;
+    }
+    sql.push_str(&self.source[end..]);
+    sql
+  }
 }
 
 pub fn parse_statement(input: &str, trailing_semicolon: bool) -> anyhow::Result<Statement> {
-  let tokens = tokenizer::tokenize(input)?;
-  let mut state = ParserState::new(tokens);
+  let mut state = ParserState::new(input)?;
   let statements = state.parse_statement()?;
   if trailing_semicolon {
     state.expect_eq(Token::SemiColon)?;
@@ -209,6 +1549,89 @@ pub fn parse_statement(input: &str, trailing_semicolon: bool) -> anyhow::Result<
 pub fn parse_create_statement(input: &str) -> anyhow::Result<CreateTableStatement> {
   match parse_statement(input, false)? {
     Statement::CreateTable(c) => Ok(c),
-    Statement::Select(_) => bail!("expected a create statement"),
+    _ => bail!("expected a create statement"),
+  }
+}
+
+/// Create statement `sql` of a schema object with the table `old` renamed to
+/// `new` where it is created, indexed, referenced by a foreign key or read and
+/// written by the body of a view or trigger. Like sqlite, the new name is quoted.
+pub fn rename_table(sql: &str, old: &str, new: &str) -> anyhow::Result<String> {
+  let state = ParserState::new(sql)?;
+  let positions = state
+    .table_positions()
+    .into_iter()
+    .filter(|&i| state.name_is(i, old))
+    .collect::<Vec<_>>();
+  Ok(state.replace_tokens(&positions, |_| quote_identifier(new)))
+}
+
+/// Create statement `sql` of a schema object with the column `old` of `table`
+/// renamed to `new`. The new name is quoted where the old one was.
+pub fn rename_column(sql: &str, table: &str, old: &str, new: &str) -> anyhow::Result<String> {
+  let state = ParserState::new(sql)?;
+  let positions = match state.tokens.get(1) {
+    Some(Token::Table | Token::Index | Token::Unique) => state.column_positions(table, old),
+    _ => state.body_column_positions(table, old),
+  };
+  let plain = new.chars().next().is_some_and(|c| !c.is_ascii_digit())
+    && new.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    && matches!(
+      tokenizer::tokenize(new).as_deref(),
+      Ok([Token::Identifier(_)])
+    );
+  Ok(state.replace_tokens(&positions, |text| {
+    match plain && !text.starts_with(['"', '`', '[']) {
+      true => new.to_string(),
+      false => quote_identifier(new),
+    }
+  }))
+}
+
+/// First reference of the body of the view or trigger created by `sql` to the
+/// column `column` of `table`, with its qualifier
+pub fn column_reference(sql: &str, table: &str, column: &str) -> anyhow::Result<Option<String>> {
+  let state = ParserState::new(sql)?;
+  let Some(&i) = state.body_column_positions(table, column).first() else {
+    return Ok(None);
+  };
+  let start = match i >= 2 && state.tokens[i - 1] == Token::Dot {
+    true => state.spans[i - 2].start,
+    false => state.spans[i].start,
+  };
+  Ok(Some(sql[start..state.spans[i].end].to_string()))
+}
+
+/// `CREATE TABLE` statement `sql` with `column_def` added after its last column
+pub fn add_column(sql: &str, column_def: &str) -> anyhow::Result<String> {
+  let mut state = ParserState::new(sql)?;
+  let spans = state.column_def_spans()?;
+  let end = spans.last().context("table has no columns")?.end;
+  Ok(format!("{}, {column_def}{}", &sql[..end], &sql[end..]))
+}
+
+/// `CREATE TABLE` statement `sql` without the definition of its column `n`
+pub fn drop_column(sql: &str, n: usize) -> anyhow::Result<String> {
+  let mut state = ParserState::new(sql)?;
+  let spans = state.column_def_spans()?;
+  if n >= spans.len() || spans.len() == 1 {
+    bail!("cannot drop column {n} of {} columns", spans.len());
+  }
+  // the separating comma goes with the column
+  let removed = match n {
+    0 => spans[0].start..spans[1].start,
+    n => spans[n - 1].end..spans[n].end,
+  };
+  Ok(format!("{}{}", &sql[..removed.start], &sql[removed.end..]))
+}
+
+fn quote_identifier(name: &str) -> String {
+  format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+pub fn parse_create_index_statement(input: &str) -> anyhow::Result<CreateIndexStatement> {
+  match parse_statement(input, false)? {
+    Statement::CreateIndex(c) => Ok(c),
+    _ => bail!("expected a create index statement"),
   }
 }
@@ -1,6 +1,6 @@
-use anyhow::bail;
+use std::{iter::Peekable, ops::Range, str::CharIndices};
 
-use crate::cursor::value::Value;
+use anyhow::{bail, Context};
 
 use super::ast::Expr;
 
@@ -17,13 +17,52 @@ pub enum Token {
   Comma,
   SemiColon,
   Where,
+  Order,
+  By,
+  Group,
+  Having,
+  Distinct,
+  All,
+  Union,
+  Intersect,
+  Except,
+  Join,
+  Inner,
+  Left,
+  Outer,
+  Cross,
+  Natural,
+  Using,
+  Limit,
+  Offset,
+  Is,
+  In,
+  Between,
+  Primary,
+  Index,
+  Unique,
+  On,
+  Not,
+  Default,
+  Check,
+  References,
+  Constraint,
+  Collate,
+  Foreign,
+  Dot,
+  Plus,
+  Minus,
   Op(Ops),
   Identifier(String),
 
   Int(i64),
+  /// digits of an integer literal too large for an i64, read as a real unless
+  /// negating it makes it fit
+  BigInt(String),
   Real(f64),
   Bool(bool),
   String(String),
+  Blob(Vec<u8>),
   Null,
 }
 
@@ -37,21 +76,47 @@ pub enum Ops {
   Goe,
   And,
   Or,
+  Is,
+  IsNot,
+  Add,
+  Sub,
+  Mul,
+  Div,
+  Mod,
+  /// string concatenation, `||`
+  Concat,
 }
 
 impl Ops {
-  #[inline(always)]
-  pub fn compare(&self, l: Value, r: Value) -> bool {
-    match self {
-      Ops::Eq => l == r,
-      Ops::Ne => l != r,
-      Ops::Lt => l < r,
-      Ops::Gt => l > r,
-      Ops::Loe => l <= r,
-      Ops::Goe => l >= r,
-      Ops::And => l.into() && r.into(),
-      Ops::Or => l.into() || r.into(),
-    }
+  /// Operators yielding a truth value rather than a number or a string
+  pub fn is_comparison(&self) -> bool {
+    !matches!(
+      self,
+      Ops::Add | Ops::Sub | Ops::Mul | Ops::Div | Ops::Mod | Ops::Concat
+    )
+  }
+}
+
+impl std::fmt::Display for Ops {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(match self {
+      Ops::Eq => "=",
+      Ops::Ne => "!=",
+      Ops::Lt => "<",
+      Ops::Gt => ">",
+      Ops::Loe => "<=",
+      Ops::Goe => ">=",
+      Ops::And => "AND",
+      Ops::Or => "OR",
+      Ops::Is => "IS",
+      Ops::IsNot => "IS NOT",
+      Ops::Add => "+",
+      Ops::Sub => "-",
+      Ops::Mul => "*",
+      Ops::Div => "/",
+      Ops::Mod => "%",
+      Ops::Concat => "||",
+    })
   }
 }
 
@@ -73,85 +138,210 @@ impl Token {
   pub fn as_literal(&self) -> Option<Expr> {
     match self {
       Token::Int(i) => Some(Expr::Int(*i)),
+      // integers too large for an i64 are read as reals, like sqlite does
+      Token::BigInt(digits) => digits.parse().ok().map(Expr::Real),
       Token::Real(i) => Some(Expr::Real(*i)),
       Token::Bool(v) => Some(Expr::Bool(*v)),
       Token::Null => Some(Expr::Null),
       Token::String(v) => Some(Expr::Text(v.clone())),
+      Token::Blob(v) => Some(Expr::Blob(v.clone())),
       _ => None,
     }
   }
 }
 
 pub fn tokenize(input: &str) -> anyhow::Result<Vec<Token>> {
+  Ok(
+    tokenize_spanned(input)?
+      .into_iter()
+      .map(|(token, _)| token)
+      .collect(),
+  )
+}
+
+/// Tokenizes `input`, pairing every token with the byte range it was read from
+pub fn tokenize_spanned(input: &str) -> anyhow::Result<Vec<(Token, Range<usize>)>> {
   let mut tokens = vec![];
-  let mut chars = input.chars().peekable();
-
-  while let Some(c) = chars.next() {
-    match c {
-      '*' => tokens.push(Token::Star),
-      ',' => tokens.push(Token::Comma),
-      ';' => tokens.push(Token::SemiColon),
-      '(' => tokens.push(Token::LPar),
-      ')' => tokens.push(Token::RPar),
+  let mut chars = input.char_indices().peekable();
+
+  while let Some((start, c)) = chars.next() {
+    let token = match c {
+      '*' => Token::Star,
+      ',' => Token::Comma,
+      ';' => Token::SemiColon,
+      '(' => Token::LPar,
+      ')' => Token::RPar,
+      '+' => Token::Plus,
+      '.' if !chars.peek().is_some_and(|(_, cc)| cc.is_ascii_digit()) => Token::Dot,
+      '-' if chars.next_if(|&(_, cc)| cc == '-').is_some() => {
+        // line comment
+        while chars.next_if(|&(_, cc)| cc != '\n').is_some() {}
+        continue;
+      }
+      '-' => Token::Minus,
+      '/' if chars.next_if(|&(_, cc)| cc == '*').is_some() => {
+        // block comment
+        let mut prev = ' ';
+        loop {
+          match chars.next() {
+            Some((_, '/')) if prev == '*' => break,
+            Some((_, cc)) => prev = cc,
+            None => bail!("Unterminated comment"),
+          }
+        }
+        continue;
+      }
+      '/' => Token::Op(Ops::Div),
+      '%' => Token::Op(Ops::Mod),
+      '|' if chars.next_if(|&(_, cc)| cc == '|').is_some() => Token::Op(Ops::Concat),
       '=' | '<' | '>' | '!' => {
         let mut op = c.to_string();
-        if let Some(cc) = chars.next_if(|&cc| cc == '=') {
+        if let Some((_, cc)) = chars.next_if(|&(_, cc)| cc == '=' || (c == '<' && cc == '>')) {
           op.push(cc);
         }
         match op.as_str() {
-          "=" => tokens.push(Token::Op(Ops::Eq)),
-          "!=" => tokens.push(Token::Op(Ops::Ne)),
-          "<" => tokens.push(Token::Op(Ops::Lt)),
-          ">" => tokens.push(Token::Op(Ops::Gt)),
-          ">=" => tokens.push(Token::Op(Ops::Goe)),
-          "<=" => tokens.push(Token::Op(Ops::Loe)),
+          "=" | "==" => Token::Op(Ops::Eq),
+          "!=" | "<>" => Token::Op(Ops::Ne),
+          "<" => Token::Op(Ops::Lt),
+          ">" => Token::Op(Ops::Gt),
+          ">=" => Token::Op(Ops::Goe),
+          "<=" => Token::Op(Ops::Loe),
           _ => anyhow::bail!("unexpected character: {c}"),
         }
       }
       c if c.is_whitespace() => continue,
-      c if c.is_numeric() => {
+      c if c.is_ascii_digit() || c == '.' => {
         let mut num = c.to_string();
-        while let Some(cc) = chars.next_if(|&cc| cc.is_numeric() || cc == '.') {
-          num.extend(cc.to_lowercase());
+        while let Some((_, cc)) = chars.next_if(|&(_, cc)| cc.is_ascii_alphanumeric() || cc == '.')
+        {
+          num.push(cc);
+          // exponent sign
+          if matches!(cc, 'e' | 'E') && !num.starts_with("0x") {
+            if let Some((_, sign)) = chars.next_if(|&(_, s)| s == '+' || s == '-') {
+              num.push(sign);
+            }
+          }
         }
-        tokens.push(if num.contains('.') {
-          Token::Real(num.parse()?)
-        } else {
-          Token::Int(num.parse()?)
-        });
+        parse_number(&num)?
       }
-      '\'' | '"' => {
-        let mut value = String::new();
-        while let Some(cc) = chars.next_if(|&cc| cc.is_alphanumeric() || cc == '_' || cc == ' ') {
-          value.extend(cc.to_lowercase());
-        }
-        if chars.next_if(|&cc| cc == '\'' || cc == '"').is_some() {
-          tokens.push(Token::String(value));
-        } else {
-          bail!("Unterminated string '{value}")
-        }
+      '\'' => Token::String(read_quoted(&mut chars, '\'')?),
+      '"' | '`' => Token::Identifier(read_quoted(&mut chars, c)?),
+      '[' => Token::Identifier(read_quoted(&mut chars, ']')?),
+      'x' | 'X' if chars.next_if(|&(_, cc)| cc == '\'').is_some() => {
+        let hex = read_quoted(&mut chars, '\'')?;
+        Token::Blob(parse_hex_blob(&hex)?)
       }
-      c if c.is_alphabetic() => {
-        let mut ident = c.to_string().to_lowercase();
-        while let Some(cc) = chars.next_if(|&cc| cc.is_alphanumeric() || cc == '_') {
-          ident.extend(cc.to_lowercase());
+      c if c.is_alphabetic() || c == '_' => {
+        // names keep their spelling, keywords are matched without case
+        let mut ident = c.to_string();
+        while let Some((_, cc)) =
+          chars.next_if(|&(_, cc)| cc.is_alphanumeric() || cc == '_' || cc == '$')
+        {
+          ident.push(cc);
         }
 
-        match ident.as_str() {
-          "create" => tokens.push(Token::Create),
-          "table" => tokens.push(Token::Table),
-          "select" => tokens.push(Token::Select),
-          "where" => tokens.push(Token::Where),
-          "as" => tokens.push(Token::As),
-          "from" => tokens.push(Token::From),
-          "and" => tokens.push(Token::Op(Ops::And)),
-          "or" => tokens.push(Token::Op(Ops::Or)),
-          "null" => tokens.push(Token::Null),
-          _ => tokens.push(Token::Identifier(ident)),
+        match ident.to_ascii_lowercase().as_str() {
+          "create" => Token::Create,
+          "table" => Token::Table,
+          "select" => Token::Select,
+          "where" => Token::Where,
+          "order" => Token::Order,
+          "by" => Token::By,
+          "group" => Token::Group,
+          "having" => Token::Having,
+          "distinct" => Token::Distinct,
+          "all" => Token::All,
+          "union" => Token::Union,
+          "intersect" => Token::Intersect,
+          "except" => Token::Except,
+          "join" => Token::Join,
+          "inner" => Token::Inner,
+          "left" => Token::Left,
+          "outer" => Token::Outer,
+          "cross" => Token::Cross,
+          "natural" => Token::Natural,
+          "using" => Token::Using,
+          "limit" => Token::Limit,
+          "offset" => Token::Offset,
+          "as" => Token::As,
+          "is" => Token::Is,
+          "in" => Token::In,
+          "between" => Token::Between,
+          "primary" => Token::Primary,
+          "index" => Token::Index,
+          "unique" => Token::Unique,
+          "on" => Token::On,
+          "not" => Token::Not,
+          "default" => Token::Default,
+          "check" => Token::Check,
+          "references" => Token::References,
+          "constraint" => Token::Constraint,
+          "collate" => Token::Collate,
+          "foreign" => Token::Foreign,
+          "from" => Token::From,
+          "and" => Token::Op(Ops::And),
+          "or" => Token::Op(Ops::Or),
+          "null" => Token::Null,
+          _ => Token::Identifier(ident),
         }
       }
       _ => anyhow::bail!("unexpected character: {}", c),
-    }
+    };
+
+    let end = chars.peek().map_or(input.len(), |&(i, _)| i);
+    tokens.push((token, start..end));
   }
   Ok(tokens)
 }
+
+/// Reads up to the closing `quote`, a doubled quote stands for the quote itself
+fn read_quoted(chars: &mut Peekable<CharIndices>, quote: char) -> anyhow::Result<String> {
+  let mut value = String::new();
+  loop {
+    match chars.next() {
+      Some((_, c)) if c == quote => {
+        if chars.next_if(|&(_, cc)| cc == quote).is_none() {
+          return Ok(value);
+        }
+        value.push(quote);
+      }
+      Some((_, c)) => value.push(c),
+      None => bail!("Unterminated string '{value}"),
+    }
+  }
+}
+
+fn parse_number(num: &str) -> anyhow::Result<Token> {
+  if let Some(hex) = num.strip_prefix("0x").or(num.strip_prefix("0X")) {
+    return Ok(Token::Int(
+      u64::from_str_radix(hex, 16).with_context(|| format!("invalid number: {num}"))? as i64,
+    ));
+  }
+
+  if num.contains(['.', 'e', 'E']) {
+    return Ok(Token::Real(
+      num
+        .parse()
+        .with_context(|| format!("invalid number: {num}"))?,
+    ));
+  }
+
+  match num.parse::<i64>() {
+    Ok(i) => Ok(Token::Int(i)),
+    Err(_) => Ok(Token::BigInt(num.to_string())),
+  }
+}
+
+fn parse_hex_blob(hex: &str) -> anyhow::Result<Vec<u8>> {
+  if !hex.len().is_multiple_of(2) {
+    bail!("blob literal should have an even number of hex digits: x'{hex}'");
+  }
+
+  (0..hex.len())
+    .step_by(2)
+    .map(|i| {
+      u8::from_str_radix(&hex[i..i + 2], 16)
+        .with_context(|| format!("invalid blob literal: x'{hex}'"))
+    })
+    .collect()
+}
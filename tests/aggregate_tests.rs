@@ -0,0 +1,74 @@
+#[cfg(test)]
+mod aggregate {
+  use rust_sqlite::{
+    cursor::value::OwnedValue,
+    db::Db,
+    engine::{operator::Operator, plan::Planner},
+    sql::parser::parse_statement,
+  };
+
+  const OVERFLOW_DB: &str = "tests/fixtures/overflow.db";
+  const GROUPS_QUERY: &str = "SELECT id % 7, count(*), sum(id) FROM docs GROUP BY 1";
+
+  /// Runs the query, returning its rows sorted by the first column and the
+  /// number of groups that went through the sort fallback
+  fn run(budget: Option<usize>) -> (Vec<Vec<OwnedValue>>, usize) {
+    let db = Db::from_file(OVERFLOW_DB).unwrap();
+    let parsed = parse_statement(GROUPS_QUERY, false).unwrap();
+    let planner = match budget {
+      Some(bytes) => Planner::new(&db).with_sort_memory(bytes),
+      None => Planner::new(&db),
+    };
+    let mut op = planner.compile(&parsed).unwrap();
+
+    let mut rows = vec![];
+    while let Some(row) = op.next_row().unwrap() {
+      rows.push(row.to_vec());
+    }
+    rows.sort_by_key(|r| r[0].to_string());
+
+    let Operator::Project(project) = op.operator else {
+      panic!("expected a projection");
+    };
+    let Operator::Aggregate(aggregate) = *project.source else {
+      panic!("expected an aggregate");
+    };
+    (rows, aggregate.sorted_groups())
+  }
+
+  fn expected() -> Vec<Vec<OwnedValue>> {
+    [
+      (0, 5, 105),
+      (1, 6, 111),
+      (2, 6, 117),
+      (3, 6, 123),
+      (4, 6, 129),
+      (5, 6, 135),
+      (6, 5, 100),
+    ]
+    .map(|(k, c, s)| vec![OwnedValue::Int(k), OwnedValue::Int(c), OwnedValue::Int(s)])
+    .to_vec()
+  }
+
+  #[test]
+  fn hash_aggregate_in_memory() {
+    let (rows, sorted_groups) = run(None);
+    assert_eq!(rows, expected());
+    assert_eq!(sorted_groups, 0);
+  }
+
+  #[test]
+  fn sort_fallback_over_budget() {
+    // only the first groups fit in the hash table
+    let (rows, sorted_groups) = run(Some(256));
+    assert_eq!(rows, expected());
+    assert!(sorted_groups > 0 && sorted_groups < 7, "{sorted_groups}");
+  }
+
+  #[test]
+  fn sort_fallback_spilling_runs() {
+    let (rows, sorted_groups) = run(Some(1));
+    assert_eq!(rows, expected());
+    assert_eq!(sorted_groups, 6);
+  }
+}
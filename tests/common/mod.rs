@@ -0,0 +1,61 @@
+//! Helpers shared by the integration tests, each test file uses some of them
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+use rust_sqlite::cursor::value::OwnedValue;
+use rust_sqlite::db::{Db, DbOptions};
+use rust_sqlite::engine::plan::Planner;
+use rust_sqlite::sql::parser::parse_statement;
+
+/// Path of a scratch database file named after the test file and `name`,
+/// removed if a previous run left it
+pub fn scratch_path(name: &str) -> PathBuf {
+  let file = module_path!().split("::").next().unwrap();
+  let path = std::env::temp_dir().join(format!("{file}_{}_{name}.db", std::process::id()));
+  let _ = std::fs::remove_file(&path);
+  path
+}
+
+/// Copies a fixture to a scratch file the test can write to
+pub fn scratch_copy(fixture: &str, name: &str) -> PathBuf {
+  let path = scratch_path(name);
+  std::fs::copy(fixture, &path).unwrap();
+  path
+}
+
+/// A scratch database with the `users` table the query tests read
+pub fn scratch_users(name: &str) -> PathBuf {
+  let path = scratch_path(name);
+  let db = Db::create(&path, &DbOptions::default()).unwrap();
+  for query in [
+    "CREATE TABLE users (id INTEGER, name TEXT)",
+    "INSERT INTO users VALUES (1, 'kratos'), (2, 'ama'), (3, 'prince'), (4, 'kofi'), (10, 'k')",
+  ] {
+    execute(&db, query).unwrap();
+  }
+  path
+}
+
+/// Runs a statement to completion and collects its rows
+pub fn execute(db: &Db, query: &str) -> anyhow::Result<Vec<Vec<OwnedValue>>> {
+  let parsed = parse_statement(query, false)?;
+  let mut query = Planner::new(db).compile(&parsed)?;
+  let mut rows = vec![];
+  while let Some(values) = query.next_row()? {
+    rows.push(values.to_vec());
+  }
+  Ok(rows)
+}
+
+/// The single integer a query such as `SELECT count(*) ...` returns
+pub fn count(db: &Db, query: &str) -> i64 {
+  match execute(db, query).unwrap().as_slice() {
+    [row] => row[0].as_value().as_int().unwrap(),
+    rows => panic!("expected one row, got {rows:?}"),
+  }
+}
+
+pub fn text(s: &str) -> OwnedValue {
+  OwnedValue::String(s.to_string().into())
+}
@@ -1,8 +1,11 @@
+mod common;
+
 #[cfg(test)]
 mod compiler {
   use rust_sqlite::{
+    cursor::value::OwnedValue,
     db::Db,
-    engine::{operator::Operator, plan::Planner},
+    engine::{operator::Operator, plan::Planner, query::QueryColumn},
     sql::{
       ast::{Expr, Statement, Type},
       parser::{parse_create_statement, parse_statement},
@@ -10,14 +13,16 @@ mod compiler {
     },
   };
 
+  use crate::common::scratch_users;
+
   #[test]
   fn simple_select() {
-    let db = &Db::from_file("queries_test.db").unwrap();
+    let db = &Db::from_file(scratch_users("simple_select")).unwrap();
     let query = "SELECT * FROM users;";
     let parsed = &parse_statement(query, false).unwrap();
     let op = Planner::new(db).compile(parsed);
     assert!(op.is_ok());
-    match op.unwrap() {
+    match op.unwrap().operator {
       Operator::SeqScan(s) => {
         assert_eq!(s.fields.len(), 2);
       }
@@ -28,11 +33,11 @@ mod compiler {
   #[test]
   fn select_with_columns() {
     let query = "SELECT id, name FROM users;";
-    let db = &Db::from_file("queries_test.db").unwrap();
+    let db = &Db::from_file(scratch_users("select_with_columns")).unwrap();
     let parsed = &parse_statement(query, false).unwrap();
     let op = Planner::new(db).compile(parsed);
     assert!(op.is_ok());
-    match op.unwrap() {
+    match op.unwrap().operator {
       Operator::SeqScan(s) => {
         assert_eq!(s.fields.len(), 2);
       }
@@ -42,11 +47,11 @@ mod compiler {
   #[test]
   fn select_with_where_clause() {
     let query = "SELECT * FROM users WHERE id = 10;";
-    let db = &Db::from_file("queries_test.db").unwrap();
+    let db = &Db::from_file(scratch_users("select_with_where_clause")).unwrap();
     let parsed = &parse_statement(query, false).unwrap();
     let op = Planner::new(db).compile(parsed);
     assert!(op.is_ok());
-    match op.unwrap() {
+    match op.unwrap().operator {
       Operator::SeqScanWithPredicate(s) => {
         assert_comparison(s.predicate, Expr::Alias(0), Ops::Eq, Expr::Int(10));
       }
@@ -57,11 +62,11 @@ mod compiler {
   #[test]
   fn select_with_where_compound_clause() {
     let query = "SELECT * FROM users WHERE id = 10 or name = 'k';";
-    let db = &Db::from_file("queries_test.db").unwrap();
+    let db = &Db::from_file(scratch_users("select_with_where_compound_clause")).unwrap();
     let parsed = &parse_statement(query, false).unwrap();
     let op = Planner::new(db).compile(parsed);
     assert!(op.is_ok());
-    match op.unwrap() {
+    match op.unwrap().operator {
       Operator::SeqScanWithPredicate(s) => match s.predicate {
         Expr::Comparison(l, ops, r) => {
           assert_comparison(*l, Expr::Alias(0), Ops::Eq, Expr::Int(10));
@@ -120,11 +125,218 @@ mod compiler {
   }
 
   #[test]
-  fn create_with_unsupported_type() {
+  fn create_with_unknown_type() {
     let query = "CREATE TABLE test (data INVALID_TYPE)";
     let result = parse_statement(query, false);
-    assert!(result.is_err());
-    assert!(result.unwrap_err().to_string().contains("unsupported type"));
+    assert!(result.is_ok());
+  }
+
+  #[test]
+  fn select_by_rowid_seeks() {
+    let db = &Db::from_file("tests/fixtures/overflow.db").unwrap();
+    let parsed =
+      &parse_statement("SELECT id FROM docs WHERE rowid BETWEEN 5 AND 9", false).unwrap();
+    match Planner::new(db).compile(parsed).unwrap().operator {
+      Operator::SeqScan(s) => assert_eq!(s.scanner.rowid_bounds(), (Some(5), Some(9))),
+      _ => panic!("Expected Sequential Scan operation"),
+    }
+  }
+
+  #[test]
+  fn select_by_rowid_with_predicate() {
+    let db = &Db::from_file("tests/fixtures/overflow.db").unwrap();
+    let query = "SELECT id FROM docs WHERE rowid > 30 and id < 34";
+    let parsed = &parse_statement(query, false).unwrap();
+    let mut op = Planner::new(db).compile(parsed).unwrap();
+
+    let mut ids = vec![];
+    while let Some(row) = op.next_row().unwrap() {
+      ids.push(row[0].clone());
+    }
+    assert_eq!(
+      ids,
+      vec![
+        OwnedValue::Int(31),
+        OwnedValue::Int(32),
+        OwnedValue::Int(33)
+      ]
+    );
+  }
+
+  #[test]
+  fn limit_stops_pulling_rows() {
+    let db = &Db::from_file("tests/fixtures/overflow.db").unwrap();
+    let parsed = &parse_statement("SELECT id FROM docs LIMIT 3 OFFSET 2", false).unwrap();
+    let mut op = Planner::new(db).compile(parsed).unwrap();
+
+    let mut ids = vec![];
+    while let Some(row) = op.next_row().unwrap() {
+      ids.push(row[0].clone());
+    }
+    assert_eq!(
+      ids,
+      vec![OwnedValue::Int(3), OwnedValue::Int(4), OwnedValue::Int(5)]
+    );
+
+    let Operator::Limit(mut limit) = op.operator else {
+      panic!("Expected Limit operation");
+    };
+    assert_eq!((limit.limit, limit.offset), (Some(3), 2));
+
+    // the scan is left right after the last row handed out
+    let Operator::SeqScan(scan) = limit.source.as_mut() else {
+      panic!("Expected Sequential Scan operation");
+    };
+    let next = scan.scanner.next_record().unwrap().unwrap();
+    assert_eq!(next.row_id, Some(6));
+  }
+
+  #[test]
+  fn equi_joins_use_a_hash_join() {
+    let db = &Db::from_file("tests/fixtures/company.db").unwrap();
+    let compile = |query: &str| {
+      let parsed = &parse_statement(query, false).unwrap();
+      let Operator::Project(project) = Planner::new(db).compile(parsed).unwrap().operator else {
+        panic!("Expected Project operation");
+      };
+      *project.source
+    };
+
+    let op = compile(
+      "SELECT e.name FROM employees e JOIN departments d ON e.dept_id = d.id WHERE d.id > 1",
+    );
+    let Operator::HashJoin(join) = op else {
+      panic!("Expected Hash Join operation");
+    };
+    assert_eq!(join.left_keys.len(), 1);
+    assert!(join.predicate.is_none());
+    // the filter on the right table is checked by its scan
+    let Operator::SeqScan(scan) = *join.right else {
+      panic!("Expected Sequential Scan operation");
+    };
+    assert_eq!(scan.scanner.rowid_bounds(), (Some(2), None));
+
+    let op = compile("SELECT e.name FROM employees e LEFT JOIN departments d ON e.dept_id > d.id");
+    let Operator::NestedLoopJoin(join) = op else {
+      panic!("Expected Nested Loop Join operation");
+    };
+    assert!(join.outer && join.predicate.is_some());
+  }
+
+  #[test]
+  fn leading_index_columns_use_an_index_scan() {
+    let db = &Db::from_file("tests/fixtures/inventory.db").unwrap();
+    let compile = |query: &str| {
+      let parsed = &parse_statement(query, false).unwrap();
+      Planner::new(db).compile(parsed).unwrap().operator
+    };
+
+    // the index holds every column read, the table isn't touched
+    let Operator::IndexScan(scan) =
+      compile("SELECT id, price FROM items WHERE category = 7 AND price > 50")
+    else {
+      panic!("Expected Index Scan operation");
+    };
+    assert_eq!(scan.index, "items_category");
+    assert!(scan.table_scanner.is_none() && scan.predicate.is_none());
+    assert_eq!(scan.ranges.len(), 1);
+    assert_eq!(
+      scan.ranges[0].start,
+      [OwnedValue::Int(7), OwnedValue::Int(50)]
+    );
+    assert!(!scan.ranges[0].start_inclusive);
+    assert_eq!(scan.ranges[0].end, [OwnedValue::Int(7)]);
+
+    let Operator::IndexScan(scan) =
+      compile("SELECT code FROM items WHERE category IN (2, 1) AND code > 'c01'")
+    else {
+      panic!("Expected Index Scan operation");
+    };
+    assert_eq!(scan.index, "items_category");
+    assert!(scan.table_scanner.is_some() && scan.predicate.is_some());
+    assert_eq!(scan.ranges.len(), 2);
+    assert_eq!(scan.ranges[0].start, [OwnedValue::Int(1)]);
+
+    // a partial index lacks rows, a rowid range is preferred over an index
+    for query in [
+      "SELECT id FROM items WHERE price < 10",
+      "SELECT id FROM items WHERE id = 5 AND category = 5",
+    ] {
+      assert!(
+        matches!(compile(query), Operator::SeqScanWithPredicate(_)),
+        "{query}"
+      );
+    }
+  }
+
+  #[test]
+  fn limit_should_be_an_integer() {
+    let db = &Db::from_file(scratch_users("limit_should_be_an_integer")).unwrap();
+    for query in [
+      "SELECT * FROM users LIMIT 1.5",
+      "SELECT * FROM users LIMIT 'ten'",
+      "SELECT * FROM users LIMIT id",
+    ] {
+      let parsed = &parse_statement(query, false).unwrap();
+      assert!(Planner::new(db).compile(parsed).is_err(), "{query}");
+    }
+  }
+
+  #[test]
+  fn result_columns_describe_their_origin() {
+    let db = &Db::from_file("tests/fixtures/company.db").unwrap();
+    let columns = |query: &str| {
+      let parsed = &parse_statement(query, false).unwrap();
+      Planner::new(db).compile(parsed).unwrap().columns
+    };
+    let origin = |name: &str, declared_type: &str, table: &str, column: &str| QueryColumn {
+      name: name.into(),
+      declared_type: Some(declared_type.into()),
+      table: Some(table.into()),
+      column: Some(column.into()),
+    };
+
+    assert_eq!(
+      columns(
+        "SELECT e.name AS who, salary * 2, d.*, e.rowid \
+         FROM employees e JOIN departments d ON e.dept_id = d.id ORDER BY 2"
+      ),
+      vec![
+        origin("who", "TEXT", "employees", "name"),
+        QueryColumn::computed("salary * 2"),
+        origin("id", "INTEGER", "departments", "id"),
+        origin("name", "TEXT", "departments", "name"),
+        // the rowid reads the column aliasing it
+        origin("id", "INTEGER", "employees", "id"),
+      ]
+    );
+    // columns are named as the table spells them, a rowid without an alias
+    // as the query does and with no declared type
+    assert_eq!(
+      columns("SELECT VALUE, Settings.Key, ROWID FROM settings"),
+      vec![
+        origin("value", "TEXT", "settings", "value"),
+        origin("key", "TEXT", "settings", "key"),
+        QueryColumn {
+          declared_type: None,
+          ..origin("ROWID", "", "settings", "rowid")
+        },
+      ]
+    );
+    assert_eq!(
+      columns("SELECT COUNT(*), max(salary)+1 FROM employees"),
+      ["COUNT(*)", "max(salary)+1"].map(QueryColumn::computed)
+    );
+
+    // compound selects are described by their first select
+    assert_eq!(
+      columns("SELECT name FROM departments UNION SELECT count(*) FROM employees"),
+      vec![origin("name", "TEXT", "departments", "name")]
+    );
+    assert_eq!(
+      columns("EXPLAIN QUERY PLAN SELECT * FROM employees"),
+      ["id", "parent", "detail"].map(QueryColumn::computed)
+    );
   }
 
   fn assert_comparison(e: Expr, lc: Expr, o: Ops, rc: Expr) {
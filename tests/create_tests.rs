@@ -0,0 +1,223 @@
+mod common;
+
+#[cfg(test)]
+mod create {
+  use rust_sqlite::cursor::value::OwnedValue;
+  use rust_sqlite::db::{Db, DbOptions};
+  use rust_sqlite::dbheader::TextEncoding;
+
+  use crate::common::{execute, scratch_path, text};
+
+  fn texts(db: &Db, query: &str) -> Vec<String> {
+    execute(db, query)
+      .unwrap()
+      .into_iter()
+      .map(|row| row[0].as_value().as_str().unwrap().to_string())
+      .collect()
+  }
+
+  #[test]
+  fn creates_an_empty_database() {
+    let path = scratch_path("empty");
+    let options = DbOptions {
+      page_size: 1024,
+      reserved_bytes: 16,
+      ..DbOptions::default()
+    };
+    let db = Db::create(&path, &options).unwrap();
+
+    assert!(db.schema.is_empty());
+    assert_eq!(db.header.page_size, 1024);
+    assert_eq!(db.header.reserved_bytes, 16);
+    assert_eq!(db.header.db_size, 1);
+    assert_eq!(db.header.text_encoding, TextEncoding::Utf8);
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 1024);
+    drop(db);
+
+    assert_eq!(
+      Db::create(&path, &options).err().unwrap().to_string(),
+      format!("database already exists: {}", path.display())
+    );
+    let reopened = Db::from_file(&path).unwrap();
+    assert_eq!(reopened.header.usable_size(), 1008);
+  }
+
+  #[test]
+  fn rejects_invalid_options() {
+    let path = scratch_path("invalid");
+    let options = |page_size, reserved_bytes| DbOptions {
+      page_size,
+      reserved_bytes,
+      ..DbOptions::default()
+    };
+
+    assert!(Db::create(&path, &options(1000, 0)).is_err());
+    assert!(Db::create(&path, &options(256, 0)).is_err());
+    assert!(Db::create(&path, &options(512, 40)).is_err());
+    assert!(!path.exists());
+  }
+
+  #[test]
+  fn create_table_writes_the_schema() {
+    let path = scratch_path("create_table");
+    let mut db = Db::create(&path, &DbOptions::default()).unwrap();
+
+    execute(
+      &db,
+      "CREATE TABLE main.items (id INTEGER PRIMARY KEY, sku TEXT UNIQUE, qty INT)",
+    )
+    .unwrap();
+    execute(
+      &db,
+      "CREATE TABLE pairs (a, b, PRIMARY KEY (a, b)) WITHOUT ROWID",
+    )
+    .unwrap();
+    db.reload_schema().unwrap();
+
+    let names = db
+      .schema
+      .iter()
+      .map(|e| e.name.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(names, vec!["items", "sqlite_autoindex_items_1", "pairs"]);
+    assert_eq!(
+      db.table("items").unwrap().columns[1].name,
+      "sku".to_string()
+    );
+    assert_eq!(
+      db.schema[0].sql.as_deref(),
+      Some("CREATE TABLE items (id INTEGER PRIMARY KEY, sku TEXT UNIQUE, qty INT)")
+    );
+    assert_eq!(
+      execute(&db, "CREATE TABLE items (x)")
+        .unwrap_err()
+        .to_string(),
+      "table items already exists"
+    );
+    assert!(execute(&db, "CREATE TABLE loose (a) WITHOUT ROWID").is_err());
+    assert!(execute(&db, "CREATE TABLE twice (a, a)").is_err());
+
+    execute(
+      &db,
+      "INSERT INTO items (sku, qty) VALUES ('b', 2), ('a', 1)",
+    )
+    .unwrap();
+    execute(&db, "INSERT INTO pairs VALUES (2, 1), (1, 2)").unwrap();
+    assert!(execute(&db, "INSERT INTO items (sku, qty) VALUES ('a', 3)").is_err());
+    drop(db);
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      texts(&db, "SELECT sku FROM items ORDER BY sku"),
+      vec!["a", "b"]
+    );
+    assert_eq!(
+      execute(&db, "SELECT a FROM pairs").unwrap(),
+      vec![vec![OwnedValue::Int(1)], vec![OwnedValue::Int(2)]]
+    );
+  }
+
+  #[test]
+  fn names_keep_their_spelling() {
+    let path = scratch_path("names");
+    let db = Db::create(&path, &DbOptions::default()).unwrap();
+    execute(&db, "CREATE TABLE Foo (Id INTEGER PRIMARY KEY, Name TEXT)").unwrap();
+    execute(&db, "CREATE TABLE \"Baz Q\" ([Code] UNIQUE)").unwrap();
+    assert_eq!(
+      execute(&db, "CREATE TABLE FOO (x)")
+        .unwrap_err()
+        .to_string(),
+      "table FOO already exists"
+    );
+
+    // names are matched without case
+    execute(&db, "INSERT INTO foo (name) VALUES ('a')").unwrap();
+    execute(&db, "INSERT INTO [baz q] (CODE) VALUES (1)").unwrap();
+    assert_eq!(
+      execute(&db, "SELECT NAME, rowid FROM FOO").unwrap(),
+      vec![vec![text("a"), OwnedValue::Int(1)]]
+    );
+    drop(db);
+
+    let db = Db::from_file(&path).unwrap();
+    let entries = db
+      .schema
+      .iter()
+      .map(|e| (e.name.as_str(), e.table_name.as_str(), e.sql.as_deref()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      entries,
+      vec![
+        (
+          "Foo",
+          "Foo",
+          Some("CREATE TABLE Foo (Id INTEGER PRIMARY KEY, Name TEXT)")
+        ),
+        (
+          "Baz Q",
+          "Baz Q",
+          Some("CREATE TABLE \"Baz Q\" ([Code] UNIQUE)")
+        ),
+        ("sqlite_autoindex_Baz Q_1", "Baz Q", None),
+      ]
+    );
+    assert_eq!(db.table("Baz Q").unwrap().columns[0].name, "Code");
+  }
+
+  #[test]
+  fn text_is_stored_in_the_database_encoding() {
+    for (name, encoding) in [
+      ("utf16le", TextEncoding::Utf16le),
+      ("utf16be", TextEncoding::Utf16be),
+    ] {
+      let path = scratch_path(name);
+      let options = DbOptions {
+        text_encoding: encoding,
+        ..DbOptions::default()
+      };
+      let db = Db::create(&path, &options).unwrap();
+      execute(&db, "CREATE TABLE words (word TEXT UNIQUE)").unwrap();
+      execute(&db, "INSERT INTO words VALUES ('crème'), ('brûlée')").unwrap();
+      drop(db);
+
+      let db = Db::from_file(&path).unwrap();
+      assert_eq!(db.header.text_encoding, encoding);
+      assert_eq!(
+        texts(&db, "SELECT word FROM words WHERE word = 'crème'"),
+        vec!["crème"]
+      );
+      assert_eq!(db.schema[0].name, "words");
+
+      let file = std::fs::read(&path).unwrap();
+      let utf16 = match encoding {
+        TextEncoding::Utf16le => "brûlée"
+          .encode_utf16()
+          .flat_map(u16::to_le_bytes)
+          .collect::<Vec<_>>(),
+        _ => "brûlée".encode_utf16().flat_map(u16::to_be_bytes).collect(),
+      };
+      assert!(file.windows(utf16.len()).any(|w| w == utf16));
+    }
+  }
+
+  #[test]
+  fn invalid_utf8_text_is_read_with_replacement_characters() {
+    let path = scratch_path("invalid_utf8");
+    let db = Db::create(&path, &DbOptions::default()).unwrap();
+    execute(&db, "CREATE TABLE words (word TEXT)").unwrap();
+    execute(&db, "INSERT INTO words VALUES ('~~A')").unwrap();
+    drop(db);
+
+    // sqlite stores such text as written, e.g. from CAST(x'ff41' AS TEXT)
+    let mut file = std::fs::read(&path).unwrap();
+    let at = file.windows(3).position(|w| w == b"~~A").unwrap();
+    file[at..at + 2].copy_from_slice(&[0xff, 0xfe]);
+    std::fs::write(&path, file).unwrap();
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      texts(&db, "SELECT word FROM words"),
+      vec!["\u{fffd}\u{fffd}A"]
+    );
+  }
+}
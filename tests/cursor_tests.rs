@@ -1,10 +1,11 @@
 #[cfg(test)]
 mod cursor {
   use rust_sqlite::cursor::{
-    cursor::Cursor,
+    cursor::{Cursor, Field},
     record::{RecordField, RecordFieldType, RecordHeader},
     value::{OwnedValue, Value},
   };
+  use rust_sqlite::dbheader::TextEncoding;
 
   #[test]
   fn field_null() {
@@ -18,6 +19,8 @@ mod cursor {
     let cursor = Cursor {
       header,
       payload: vec![], // No payload needed for null
+      row_id: None,
+      encoding: TextEncoding::Utf8,
     };
 
     let field = cursor.field(0);
@@ -36,10 +39,12 @@ mod cursor {
     let cursor = Cursor {
       header,
       payload: vec![0xFF], // -1 as i8
+      row_id: None,
+      encoding: TextEncoding::Utf8,
     };
 
     let field = cursor.field(0);
-    assert_eq!(field, Some(Value::Int(255)));
+    assert_eq!(field, Some(Value::Int(-1)));
   }
 
   #[test]
@@ -54,6 +59,8 @@ mod cursor {
     let cursor = Cursor {
       header,
       payload: vec![0xFF, 0xFE], // -2 as i16 in big endian
+      row_id: None,
+      encoding: TextEncoding::Utf8,
     };
 
     let field = cursor.field(0);
@@ -72,6 +79,8 @@ mod cursor {
     let cursor = Cursor {
       header,
       payload: b"hello".to_vec(),
+      row_id: None,
+      encoding: TextEncoding::Utf8,
     };
 
     let field = cursor.field(0);
@@ -93,6 +102,8 @@ mod cursor {
     let cursor = Cursor {
       header,
       payload: vec![0x01, 0x02, 0x03],
+      row_id: None,
+      encoding: TextEncoding::Utf8,
     };
 
     let field = cursor.field(0);
@@ -114,6 +125,8 @@ mod cursor {
     let cursor = Cursor {
       header,
       payload: vec![],
+      row_id: None,
+      encoding: TextEncoding::Utf8,
     };
 
     let field = cursor.field(5); // Index out of bounds
@@ -132,6 +145,8 @@ mod cursor {
     let cursor = Cursor {
       header,
       payload: vec![0x2A], // 42 as i8
+      row_id: None,
+      encoding: TextEncoding::Utf8,
     };
 
     let owned_field = cursor.owned_field(0);
@@ -141,4 +156,24 @@ mod cursor {
       panic!("Expected Some(OwnedValue::Int(42))");
     }
   }
+
+  #[test]
+  fn get_row_id() {
+    let header = RecordHeader {
+      fields: vec![RecordField {
+        offset: 0,
+        field_type: RecordFieldType::Null,
+      }],
+    };
+
+    let cursor = Cursor {
+      header,
+      payload: vec![],
+      row_id: Some(7),
+      encoding: TextEncoding::Utf8,
+    };
+
+    assert_eq!(cursor.get(Field::RowId), Some(Value::Int(7)));
+    assert_eq!(cursor.get(Field::Record(0)), Some(Value::Null));
+  }
 }
@@ -0,0 +1,302 @@
+mod common;
+
+#[cfg(test)]
+mod ddl {
+  use rust_sqlite::cursor::value::OwnedValue;
+  use rust_sqlite::db::{Db, DbOptions};
+  use rust_sqlite::dbheader::{FREELIST_COUNT_OFFSET, SCHEMA_COOKIE_OFFSET};
+
+  use crate::common::{count, execute, scratch_copy, scratch_path, text};
+
+  /// A new database with the tables `items` and `orders`
+  fn scratch_db(name: &str) -> Db {
+    let mut db = Db::create(scratch_path(name), &DbOptions::default()).unwrap();
+    for query in [
+      "CREATE TABLE items (id INTEGER PRIMARY KEY, sku TEXT UNIQUE, qty INT CHECK (qty > 0), note)",
+      "CREATE TABLE orders (item REFERENCES items(id), amount)",
+      "INSERT INTO items (sku, qty, note) VALUES ('a', 1, 'first'), ('b', 2, 'second')",
+      "INSERT INTO orders VALUES (1, 10), (2, 20)",
+    ] {
+      execute(&db, query).unwrap();
+    }
+    db.refresh_schema().unwrap();
+    db
+  }
+
+  fn sql_of<'a>(db: &'a Db, name: &str) -> &'a str {
+    let entry = db.schema.iter().find(|e| e.name == name).unwrap();
+    entry.sql.as_deref().unwrap()
+  }
+
+  fn error(db: &Db, query: &str) -> String {
+    execute(db, query).unwrap_err().to_string()
+  }
+
+  #[test]
+  fn schema_changes_bump_the_cookie() {
+    let mut db = scratch_db("cookie");
+    let cookie = db.header.schema_cookie;
+    assert_eq!(
+      db.pager().header_field(SCHEMA_COOKIE_OFFSET).unwrap(),
+      cookie
+    );
+
+    execute(&db, "CREATE TABLE IF NOT EXISTS items (x)").unwrap();
+    db.refresh_schema().unwrap();
+    assert_eq!(db.header.schema_cookie, cookie);
+
+    execute(&db, "BEGIN").unwrap();
+    execute(&db, "CREATE TABLE IF NOT EXISTS extra (x)").unwrap();
+    db.refresh_schema().unwrap();
+    assert_eq!(db.header.schema_cookie, cookie + 1);
+    assert!(db.table("extra").is_some());
+
+    // the rollback restores the cookie, so the schema is read again
+    execute(&db, "ROLLBACK").unwrap();
+    db.refresh_schema().unwrap();
+    assert_eq!(db.header.schema_cookie, cookie);
+    assert!(db.table("extra").is_none());
+
+    assert_eq!(
+      error(&db, "CREATE TABLE sqlite_stat1 (x)"),
+      "object name reserved for internal use: sqlite_stat1"
+    );
+  }
+
+  #[test]
+  fn drop_table_frees_its_pages() {
+    let mut db = scratch_db("drop");
+    let long = "x".repeat(10_000);
+    execute(
+      &db,
+      &format!("INSERT INTO items (sku, qty, note) VALUES ('c', 3, '{long}')"),
+    )
+    .unwrap();
+    let pages = db.pager().page_count().unwrap();
+
+    execute(&db, "DROP TABLE items").unwrap();
+    db.refresh_schema().unwrap();
+    assert!(db.table("items").is_none());
+    assert!(db.index("sqlite_autoindex_items_1").is_none());
+    let names = db
+      .schema
+      .iter()
+      .map(|e| e.name.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(names, vec!["orders"]);
+
+    // the table, its index and the two overflow pages of the long note
+    let free = db.pager().header_field(FREELIST_COUNT_OFFSET).unwrap() as usize;
+    assert_eq!(free, 4);
+    assert_eq!(db.pager().page_count().unwrap(), pages);
+
+    assert_eq!(error(&db, "DROP TABLE items"), "no such table: items");
+    execute(&db, "DROP TABLE IF EXISTS items").unwrap();
+
+    // freed pages are reused
+    execute(&db, "CREATE TABLE again (x)").unwrap();
+    let free_after = db.pager().header_field(FREELIST_COUNT_OFFSET).unwrap() as usize;
+    assert_eq!(free_after, free - 1);
+  }
+
+  #[test]
+  fn rename_table_and_column() {
+    let mut db = scratch_db("rename");
+    execute(&db, "ALTER TABLE items RENAME TO Stock").unwrap();
+    db.refresh_schema().unwrap();
+
+    // the new name is kept as written
+    assert!(db.table("items").is_none());
+    assert_eq!(db.table("stock").unwrap().name, "Stock");
+    assert_eq!(
+      sql_of(&db, "Stock"),
+      "CREATE TABLE \"Stock\" (id INTEGER PRIMARY KEY, sku TEXT UNIQUE, qty INT CHECK (qty > 0), note)"
+    );
+    assert_eq!(
+      sql_of(&db, "orders"),
+      "CREATE TABLE orders (item REFERENCES \"Stock\"(id), amount)"
+    );
+    assert!(db.index("sqlite_autoindex_Stock_1").is_some());
+
+    execute(&db, "ALTER TABLE stock RENAME COLUMN qty TO Quantity").unwrap();
+    execute(&db, "ALTER TABLE stock RENAME id TO code").unwrap();
+    db.refresh_schema().unwrap();
+    assert_eq!(
+      sql_of(&db, "Stock"),
+      "CREATE TABLE \"Stock\" (code INTEGER PRIMARY KEY, sku TEXT UNIQUE, Quantity INT CHECK (Quantity > 0), note)"
+    );
+    assert_eq!(
+      sql_of(&db, "orders"),
+      "CREATE TABLE orders (item REFERENCES \"Stock\"(code), amount)"
+    );
+    assert_eq!(
+      execute(&db, "SELECT code, quantity FROM stock WHERE quantity > 1").unwrap(),
+      vec![vec![OwnedValue::Int(2), OwnedValue::Int(2)]]
+    );
+
+    assert_eq!(
+      error(&db, "ALTER TABLE stock RENAME TO orders"),
+      "there is already another table or index with this name: orders"
+    );
+    assert_eq!(
+      error(&db, "ALTER TABLE stock RENAME nope TO other"),
+      "no such column: nope"
+    );
+    assert_eq!(
+      error(&db, "ALTER TABLE stock RENAME sku TO note"),
+      "duplicate column name: note"
+    );
+  }
+
+  #[test]
+  fn add_column() {
+    let mut db = scratch_db("add");
+    execute(&db, "ALTER TABLE items ADD COLUMN color TEXT").unwrap();
+    // the records lack the column, which reads as NULL
+    assert_eq!(
+      execute(&db, "SELECT sku, color FROM items").unwrap(),
+      vec![
+        vec![text("a"), OwnedValue::Null],
+        vec![text("b"), OwnedValue::Null]
+      ]
+    );
+    execute(&db, "UPDATE items SET note = 'old' WHERE color IS NULL").unwrap();
+    assert_eq!(
+      count(&db, "SELECT count(*) FROM items WHERE note = 'old'"),
+      2
+    );
+    execute(&db, "ALTER TABLE items ADD size INT DEFAULT '4' NOT NULL").unwrap();
+    db.refresh_schema().unwrap();
+
+    assert_eq!(
+      sql_of(&db, "items"),
+      "CREATE TABLE items (id INTEGER PRIMARY KEY, sku TEXT UNIQUE, qty INT CHECK (qty > 0), note, color TEXT, size INT DEFAULT '4' NOT NULL)"
+    );
+    assert_eq!(
+      execute(&db, "SELECT color, size FROM items").unwrap(),
+      vec![vec![OwnedValue::Null, OwnedValue::Int(4)]; 2]
+    );
+    execute(&db, "INSERT INTO items (sku, qty) VALUES ('c', 3)").unwrap();
+    assert_eq!(
+      execute(&db, "SELECT size FROM items WHERE sku = 'c'").unwrap(),
+      vec![vec![OwnedValue::Int(4)]]
+    );
+
+    assert_eq!(
+      error(&db, "ALTER TABLE items ADD COLUMN code UNIQUE"),
+      "Cannot add a UNIQUE column"
+    );
+    assert_eq!(
+      error(&db, "ALTER TABLE items ADD COLUMN code NOT NULL"),
+      "Cannot add a NOT NULL column with default value NULL"
+    );
+    assert_eq!(
+      error(
+        &db,
+        "ALTER TABLE items ADD COLUMN at DEFAULT current_timestamp"
+      ),
+      "Cannot add a column with non-constant default"
+    );
+    assert_eq!(
+      error(&db, "ALTER TABLE items ADD COLUMN note"),
+      "duplicate column name: note"
+    );
+  }
+
+  #[test]
+  fn drop_column() {
+    let mut db = scratch_db("drop_column");
+    execute(&db, "ALTER TABLE items DROP COLUMN note").unwrap();
+    // statements are planned against the altered table
+    execute(&db, "INSERT INTO items (sku, qty) VALUES ('c', 3)").unwrap();
+    execute(&db, "ALTER TABLE orders DROP amount").unwrap();
+    db.refresh_schema().unwrap();
+
+    assert_eq!(
+      sql_of(&db, "items"),
+      "CREATE TABLE items (id INTEGER PRIMARY KEY, sku TEXT UNIQUE, qty INT CHECK (qty > 0))"
+    );
+    assert_eq!(
+      sql_of(&db, "orders"),
+      "CREATE TABLE orders (item REFERENCES items(id))"
+    );
+    assert_eq!(
+      execute(&db, "SELECT * FROM items WHERE sku >= 'b'").unwrap(),
+      vec![
+        vec![OwnedValue::Int(2), text("b"), OwnedValue::Int(2)],
+        vec![OwnedValue::Int(3), text("c"), OwnedValue::Int(3)]
+      ]
+    );
+
+    assert_eq!(
+      error(&db, "ALTER TABLE items DROP COLUMN id"),
+      "cannot drop PRIMARY KEY column: \"id\""
+    );
+    assert_eq!(
+      error(&db, "ALTER TABLE items DROP COLUMN sku"),
+      "cannot drop UNIQUE column: \"sku\""
+    );
+    assert_eq!(
+      error(&db, "ALTER TABLE orders DROP COLUMN item"),
+      "cannot drop column \"item\": no other columns exist"
+    );
+  }
+
+  #[test]
+  fn indexes_views_and_triggers_follow_the_table() {
+    let path = scratch_copy("tests/fixtures/company.db", "objects");
+    let mut db = Db::from_file(&path).unwrap();
+    execute(&db, "ALTER TABLE employees RENAME TO staff").unwrap();
+    execute(&db, "ALTER TABLE departments RENAME TO teams").unwrap();
+    execute(&db, "ALTER TABLE staff RENAME COLUMN dept_id TO dept").unwrap();
+    db.refresh_schema().unwrap();
+
+    assert_eq!(
+      sql_of(&db, "employees_dept"),
+      "CREATE INDEX employees_dept ON \"staff\" (dept, salary DESC)"
+    );
+    assert_eq!(
+      sql_of(&db, "engineers"),
+      "CREATE VIEW engineers AS SELECT name FROM \"staff\" WHERE dept = 1"
+    );
+    assert_eq!(
+      sql_of(&db, "departments_cleanup"),
+      "CREATE TRIGGER departments_cleanup AFTER DELETE ON \"teams\"\nBEGIN\n  UPDATE \"staff\" SET dept = NULL WHERE dept = old.id;\nEND"
+    );
+    assert_eq!(db.index("departments_name").unwrap().table_name, "teams");
+
+    assert_eq!(
+      error(&db, "ALTER TABLE staff DROP COLUMN salary"),
+      "error in index employees_dept after drop column: no such column: salary"
+    );
+    assert_eq!(
+      error(&db, "ALTER TABLE staff DROP COLUMN name"),
+      "error in view engineers after drop column: no such column: name"
+    );
+    assert_eq!(
+      error(&db, "CREATE TABLE employees_dept (x)"),
+      "there is already an index named employees_dept"
+    );
+
+    execute(&db, "DROP TABLE teams").unwrap();
+    db.refresh_schema().unwrap();
+    let names = db
+      .schema
+      .iter()
+      .map(|e| e.name.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(
+      names,
+      vec![
+        "staff",
+        "settings",
+        "sqlite_autoindex_settings_1",
+        "employees_dept",
+        "engineers"
+      ]
+    );
+    assert_eq!(db.pager().header_field(FREELIST_COUNT_OFFSET).unwrap(), 2);
+    drop(db);
+    std::fs::remove_file(path).unwrap();
+  }
+}
@@ -0,0 +1,146 @@
+mod common;
+
+#[cfg(test)]
+mod delete {
+  use std::path::PathBuf;
+
+  use rust_sqlite::cursor::value::OwnedValue;
+  use rust_sqlite::db::Db;
+  use rust_sqlite::dbheader::FREELIST_COUNT_OFFSET;
+
+  use crate::common::{count, execute, scratch_copy, text};
+
+  fn freelist_count(path: &PathBuf) -> u32 {
+    let file = std::fs::read(path).unwrap();
+    u32::from_be_bytes(
+      file[FREELIST_COUNT_OFFSET..FREELIST_COUNT_OFFSET + 4]
+        .try_into()
+        .unwrap(),
+    )
+  }
+
+  #[test]
+  fn delete_rows_and_index_entries() {
+    let path = scratch_copy("tests/fixtures/company.db", "where");
+    let db = Db::from_file(&path).unwrap();
+    execute(&db, "DELETE FROM employees WHERE salary > 5000").unwrap();
+    execute(&db, "DELETE FROM departments WHERE name = 'sales'").unwrap();
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(count(&db, "SELECT count(*) FROM employees"), 5);
+    assert_eq!(
+      count(&db, "SELECT count(*) FROM employees WHERE salary > 5000"),
+      0
+    );
+    assert_eq!(
+      execute(&db, "SELECT id, name FROM departments").unwrap(),
+      vec![
+        vec![OwnedValue::Int(1), text("engineering")],
+        vec![OwnedValue::Int(3), text("support")],
+      ]
+    );
+    // the unique index no longer holds the deleted name
+    execute(&db, "INSERT INTO departments (name) VALUES ('sales')").unwrap();
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn emptied_pages_go_on_the_freelist() {
+    // 512 byte pages, the table and its indexes span many levels
+    let path = scratch_copy("tests/fixtures/inventory.db", "freelist");
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(freelist_count(&path), 0);
+    execute(&db, "DELETE FROM items WHERE id % 3 <> 0 OR id > 1500").unwrap();
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(count(&db, "SELECT count(*) FROM items"), 500);
+    assert_eq!(
+      count(&db, "SELECT count(*) FROM items WHERE category = 7"),
+      25
+    );
+    assert_eq!(
+      execute(&db, "SELECT id FROM items WHERE code = 'c00097'").unwrap(),
+      vec![vec![OwnedValue::Int(300)]]
+    );
+    assert_eq!(
+      count(&db, "SELECT count(*) FROM items WHERE code = 'c00090'"),
+      0
+    );
+    let free = freelist_count(&path);
+    assert!(free > 100, "{free} free pages");
+
+    // new pages come off the freelist before the file grows
+    let size = std::fs::metadata(&path).unwrap().len();
+    execute(
+      &db,
+      "INSERT INTO items (category, code, price) SELECT category, code, price FROM items",
+    )
+    .unwrap();
+    assert!(freelist_count(&path) < free);
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), size);
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn delete_all_rows() {
+    let path = scratch_copy("tests/fixtures/inventory.db", "all");
+    let db = Db::from_file(&path).unwrap();
+    execute(&db, "DELETE FROM items").unwrap();
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(count(&db, "SELECT count(*) FROM items"), 0);
+    assert_eq!(
+      count(&db, "SELECT count(*) FROM items WHERE category = 1"),
+      0
+    );
+    execute(
+      &db,
+      "INSERT INTO items (category, code, price) VALUES (1, 'a', 2.5)",
+    )
+    .unwrap();
+    assert_eq!(
+      execute(&db, "SELECT id, code FROM items WHERE category = 1").unwrap(),
+      vec![vec![OwnedValue::Int(1), text("a")]]
+    );
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn delete_overflowing_row() {
+    let path = scratch_copy("tests/fixtures/company.db", "overflow");
+    let db = Db::from_file(&path).unwrap();
+    let long = "x".repeat(20_000);
+    execute(
+      &db,
+      &format!("INSERT INTO settings VALUES ('motd', '{long}')"),
+    )
+    .unwrap();
+    execute(&db, "DELETE FROM settings WHERE key = 'motd'").unwrap();
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      count(&db, "SELECT count(*) FROM settings WHERE key = 'motd'"),
+      0
+    );
+    assert!(freelist_count(&path) >= 4);
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn delete_without_rowid() {
+    let path = scratch_copy("tests/fixtures/schemas.db", "without_rowid");
+    let db = Db::from_file(&path).unwrap();
+    execute(&db, "DELETE FROM kv WHERE bucket = 'a'").unwrap();
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      execute(&db, "SELECT key, bucket FROM kv").unwrap(),
+      vec![vec![text("k2"), text("b")]]
+    );
+    assert_eq!(
+      execute(&db, "DELETE FROM missing").unwrap_err().to_string(),
+      "no such table: missing"
+    );
+    std::fs::remove_file(path).unwrap();
+  }
+}
@@ -0,0 +1,158 @@
+mod common;
+
+#[cfg(test)]
+mod eval {
+  use rust_sqlite::{
+    cursor::value::{OwnedValue, Value},
+    engine::eval::Evaluator,
+    sql::{
+      ast::{Expr, ResultColumn, Statement, Type},
+      parser::parse_statement,
+    },
+  };
+
+  use crate::common::text;
+
+  /// Evaluates a result column expression over a row of `a, b, c` values,
+  /// where `a` has integer, `b` text and `c` no affinity
+  fn eval_with(expr: &str, row: &[OwnedValue]) -> OwnedValue {
+    let query = format!("SELECT {expr} FROM t");
+    let Ok(Statement::Select(select)) = parse_statement(&query, false) else {
+      panic!("Expected SELECT statement");
+    };
+    let ResultColumn::Expr(column) = &select.core.result_columns[0] else {
+      panic!("Expected an expression");
+    };
+
+    let expr = resolve(&column.expr);
+    let evaluator = Evaluator::new(vec![Type::Integer, Type::Text, Type::Blob]);
+    evaluator.eval(&expr, row).unwrap().into()
+  }
+
+  fn resolve(expr: &Expr) -> Expr {
+    match expr {
+      Expr::Column(name) => Expr::Alias(match name.as_str() {
+        "a" => 0,
+        "b" => 1,
+        _ => 2,
+      }),
+      Expr::Comparison(l, op, r) => {
+        Expr::Comparison(Box::new(resolve(l)), *op, Box::new(resolve(r)))
+      }
+      Expr::Unary(op, e) => Expr::Unary(*op, Box::new(resolve(e))),
+      e => e.clone(),
+    }
+  }
+
+  fn eval(expr: &str) -> OwnedValue {
+    eval_with(expr, &[OwnedValue::Int(7), text("7"), OwnedValue::Null])
+  }
+
+  #[test]
+  fn integer_arithmetic() {
+    assert_eq!(eval("1 + 2 * 3"), OwnedValue::Int(7));
+    assert_eq!(eval("7 / 2"), OwnedValue::Int(3));
+    assert_eq!(eval("-7 % 3"), OwnedValue::Int(-1));
+    assert_eq!(eval("-(a - 10)"), OwnedValue::Int(3));
+  }
+
+  #[test]
+  fn smallest_integer_literal() {
+    assert_eq!(eval("-9223372036854775808"), OwnedValue::Int(i64::MIN));
+    assert_eq!(eval("- 9223372036854775808"), OwnedValue::Int(i64::MIN));
+    assert_eq!(
+      eval("9223372036854775808"),
+      OwnedValue::Float(9223372036854775808.0)
+    );
+    assert_eq!(
+      eval("-9223372036854775809"),
+      OwnedValue::Float(-9223372036854775809.0)
+    );
+  }
+
+  #[test]
+  fn real_arithmetic() {
+    assert_eq!(eval("7 / 2.0"), OwnedValue::Float(3.5));
+    assert_eq!(eval("5.5 % 2"), OwnedValue::Float(1.0));
+    assert_eq!(
+      eval("9223372036854775807 + 1"),
+      OwnedValue::Float(9223372036854775808.0)
+    );
+  }
+
+  #[test]
+  fn division_by_zero_is_null() {
+    assert_eq!(eval("1 / 0"), OwnedValue::Null);
+    assert_eq!(eval("1.5 / 0"), OwnedValue::Null);
+    assert_eq!(eval("1 % 0"), OwnedValue::Null);
+  }
+
+  #[test]
+  fn text_in_arithmetic() {
+    assert_eq!(eval("'12abc' + 1"), OwnedValue::Int(13));
+    assert_eq!(eval("'1.5e1' * 2"), OwnedValue::Float(30.0));
+    assert_eq!(eval("'abc' + 1"), OwnedValue::Int(1));
+    assert_eq!(eval("b * 2"), OwnedValue::Int(14));
+  }
+
+  #[test]
+  fn null_propagation() {
+    assert_eq!(eval("c + 1"), OwnedValue::Null);
+    assert_eq!(eval("-c"), OwnedValue::Null);
+    assert_eq!(eval("c = 1"), OwnedValue::Null);
+    assert_eq!(eval("NOT c"), OwnedValue::Null);
+    assert_eq!(eval("c || 'x'"), OwnedValue::Null);
+  }
+
+  #[test]
+  fn three_valued_logic() {
+    assert_eq!(eval("NULL AND 0"), OwnedValue::Int(0));
+    assert_eq!(eval("NULL AND 1"), OwnedValue::Null);
+    assert_eq!(eval("NULL OR 1"), OwnedValue::Int(1));
+    assert_eq!(eval("NULL OR 0"), OwnedValue::Null);
+    assert_eq!(eval("NOT 0"), OwnedValue::Int(1));
+  }
+
+  #[test]
+  fn is_compares_nulls() {
+    assert_eq!(eval("c IS NULL"), OwnedValue::Int(1));
+    assert_eq!(eval("NULL IS NOT NULL"), OwnedValue::Int(0));
+    assert_eq!(eval("a IS 7"), OwnedValue::Int(1));
+    assert_eq!(eval("a IS NOT c"), OwnedValue::Int(1));
+  }
+
+  #[test]
+  fn comparison_affinity() {
+    // an integer column compared with text applies numeric affinity to the text
+    assert_eq!(eval("a = '7'"), OwnedValue::Int(1));
+    assert_eq!(eval("a < '10'"), OwnedValue::Int(1));
+    // a text column compared with a number applies text affinity to the number
+    assert_eq!(eval("b = 7"), OwnedValue::Int(1));
+    // literals have no affinity, numbers sort before text
+    assert_eq!(eval("7 = '7'"), OwnedValue::Int(0));
+    assert_eq!(eval("100 < 'a'"), OwnedValue::Int(1));
+    assert_eq!(eval("1 = 1.0"), OwnedValue::Int(1));
+  }
+
+  #[test]
+  fn concatenation() {
+    assert_eq!(eval("'a' || 1 || 2.5"), text("a12.5"));
+    assert_eq!(eval("b || 1.0"), text("71.0"));
+  }
+
+  #[test]
+  fn unresolved_column_is_an_error() {
+    let evaluator = Evaluator::default();
+    let row: &[OwnedValue] = &[];
+    assert!(evaluator
+      .eval(&Expr::Column("missing".to_string()), row)
+      .is_err());
+    assert!(evaluator.eval(&Expr::Alias(3), row).is_err());
+  }
+
+  #[test]
+  fn literal_values() {
+    assert_eq!(Value::try_from(&Expr::Int(3)).unwrap(), Value::Int(3));
+    assert!(Value::try_from(&Expr::Column("a".to_string())).is_err());
+  }
+}
@@ -0,0 +1,21 @@
+-- Regenerate with: sqlite3 tests/fixtures/company.db < tests/fixtures/company.sql
+CREATE TABLE departments (id INTEGER PRIMARY KEY, name TEXT);
+CREATE TABLE employees (id INTEGER PRIMARY KEY, name TEXT, dept_id INTEGER, salary INTEGER);
+INSERT INTO departments VALUES (1, 'engineering'), (2, 'sales'), (3, 'support');
+INSERT INTO employees VALUES
+  (1, 'ama', 1, 5200),
+  (2, 'kofi', 1, 4800),
+  (3, 'esi', 2, 3900),
+  (4, 'yaw', 2, 4100),
+  (5, 'akua', 3, 3100),
+  (7, 'kwame', 1, 6100),
+  (9, 'abena', NULL, 2800);
+CREATE TABLE settings (key TEXT PRIMARY KEY, value TEXT);
+INSERT INTO settings VALUES ('theme', 'dark'), ('lang', 'en');
+CREATE INDEX employees_dept ON employees (dept_id, salary DESC);
+CREATE UNIQUE INDEX departments_name ON departments (name);
+CREATE VIEW engineers AS SELECT name FROM employees WHERE dept_id = 1;
+CREATE TRIGGER departments_cleanup AFTER DELETE ON departments
+BEGIN
+  UPDATE employees SET dept_id = NULL WHERE dept_id = old.id;
+END;
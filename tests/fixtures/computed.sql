@@ -0,0 +1,7 @@
+-- Regenerate with: sqlite3 tests/fixtures/computed.db < tests/fixtures/computed.sql
+CREATE TABLE words (id INTEGER PRIMARY KEY, word TEXT, n INT);
+CREATE INDEX words_suffixed ON words (word || 'z' DESC);
+CREATE UNIQUE INDEX words_double ON words (n * 2);
+INSERT INTO words (word, n) VALUES ('b', 1), ('a', 2), ('c', 3);
+CREATE TABLE readings (a INT, tenfold INT GENERATED ALWAYS AS (a * 10) VIRTUAL, label TEXT, next INT GENERATED ALWAYS AS (a + 1) STORED);
+INSERT INTO readings (a, label) VALUES (1, 'one'), (2, 'two');
@@ -0,0 +1,9 @@
+-- Regenerate with: sqlite3 tests/fixtures/inventory.db < tests/fixtures/inventory.sql
+PRAGMA page_size = 512;
+CREATE TABLE items (id INTEGER PRIMARY KEY, category INTEGER, code TEXT, price REAL);
+CREATE INDEX items_category ON items (category, price);
+CREATE INDEX items_code ON items (code DESC);
+CREATE INDEX items_cheap ON items (price) WHERE price < 10;
+WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 2000)
+INSERT INTO items
+SELECT i, CASE WHEN i % 97 = 0 THEN NULL ELSE i % 20 END, printf('c%05d', i * 7 % 2003), (i * 37 % 1000) / 10.0 FROM n;
@@ -0,0 +1,7 @@
+-- Regenerate with: sqlite3 tests/fixtures/overflow.db < tests/fixtures/overflow.sql
+PRAGMA page_size = 1024;
+CREATE TABLE docs (id INTEGER, title TEXT, body TEXT);
+CREATE INDEX docs_body ON docs (body);
+WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 40)
+INSERT INTO docs
+SELECT i, 'doc' || i, substr(replace(hex(zeroblob(i * 150)), '00', 'ab'), 1, i * 150) || i FROM n;
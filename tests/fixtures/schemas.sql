@@ -0,0 +1,36 @@
+-- Regenerate with: sqlite3 tests/fixtures/schemas.db < tests/fixtures/schemas.sql
+CREATE TABLE IF NOT EXISTS "Customers" (
+  "CustomerId" INTEGER PRIMARY KEY AUTOINCREMENT,
+  [First Name] VARCHAR(40) NOT NULL,
+  `email` NVARCHAR(60) UNIQUE COLLATE NOCASE,
+  balance DECIMAL(10, 2) DEFAULT 0.0 CHECK (balance >= 0),
+  created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+  notes
+);
+CREATE TABLE orders (
+  id INTEGER,
+  customer_id INT NOT NULL REFERENCES "Customers" ("CustomerId") ON DELETE CASCADE,
+  total DOUBLE PRECISION DEFAULT -1,
+  status TEXT DEFAULT 'new' NOT NULL ON CONFLICT REPLACE,
+  code BLOB DEFAULT x'00ff',
+  total_with_tax REAL GENERATED ALWAYS AS (total * 1.2) VIRTUAL,
+  -- table constraints
+  CONSTRAINT orders_pk PRIMARY KEY (id),
+  UNIQUE (customer_id, code),
+  FOREIGN KEY (customer_id) REFERENCES "Customers" ("CustomerId") DEFERRABLE INITIALLY DEFERRED,
+  CHECK (total >= -1)
+);
+CREATE TABLE kv (
+  bucket TEXT,
+  key TEXT,
+  value,
+  version UNSIGNED BIG INT,
+  PRIMARY KEY (key, bucket)
+) WITHOUT ROWID;
+/* a block comment */
+CREATE TEMP TABLE IF NOT EXISTS scratch (x);
+INSERT INTO Customers ("First Name", email, balance, notes) VALUES
+  ('Ama', 'ama@example.com', 10.5, NULL),
+  ('Kofi', 'kofi@example.com', 0, 'vip');
+INSERT INTO orders (id, customer_id, total, code) VALUES (10, 1, 20.0, x'01'), (11, 2, 5.5, x'02');
+INSERT INTO kv VALUES ('b', 'k2', 'two', 2), ('a', 'k1', 'one', 1), ('a', 'k2', 'also two', 3);
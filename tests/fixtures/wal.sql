@@ -0,0 +1,16 @@
+-- Regenerate with: rm -f tests/fixtures/wal.db*; sqlite3 tests/fixtures/wal.db < tests/fixtures/wal.sql; rm tests/fixtures/wal.db-shm
+-- The last line kills the shell before it can checkpoint, leaving the later commits in wal.db-wal only.
+PRAGMA page_size = 1024;
+PRAGMA journal_mode = WAL;
+CREATE TABLE notes (id INTEGER PRIMARY KEY, body TEXT);
+INSERT INTO notes VALUES (1, 'checkpointed'), (2, 'old'), (3, 'removed');
+PRAGMA wal_checkpoint(TRUNCATE);
+PRAGMA wal_autocheckpoint = 0;
+UPDATE notes SET body = 'new' WHERE id = 2;
+DELETE FROM notes WHERE id = 3;
+WITH RECURSIVE n(i) AS (SELECT 4 UNION ALL SELECT i + 1 FROM n WHERE i < 60)
+INSERT INTO notes SELECT i, printf('note %d %s', i, hex(zeroblob(20))) FROM n;
+CREATE TABLE tags (note_id INTEGER, tag TEXT);
+CREATE INDEX tags_tag ON tags (tag);
+INSERT INTO tags VALUES (1, 'a'), (2, 'b'), (4, 'a');
+.shell kill -9 $PPID
@@ -10,7 +10,7 @@ mod header {
   const LEAF_PAYLOAD_FRACTION_OFFSET: usize = 23;
   const FILE_CHANGE_COUNTER_OFFSET: usize = 24;
   const DB_SIZE_OFFSET: usize = 28;
-  const SCHEMA_COOKIE_OFFSET: usize = 32;
+  const SCHEMA_COOKIE_OFFSET: usize = 40;
   const SQ_VERSION_OFFSET: usize = 96;
 
   #[test]
@@ -32,13 +32,14 @@ mod header {
       .copy_from_slice(&[0x00, 0x00, 0x00, 0x01]); // change counter
     buffer[DB_SIZE_OFFSET..DB_SIZE_OFFSET + 4].copy_from_slice(&[0x00, 0x00, 0x00, 0x10]); // db size
     buffer[SCHEMA_COOKIE_OFFSET..SCHEMA_COOKIE_OFFSET + 4]
-      .copy_from_slice(&[0x00, 0x00, 0x00, 0x00]); // schema cookie
+      .copy_from_slice(&[0x00, 0x00, 0x00, 0x07]); // schema cookie
     buffer[SQ_VERSION_OFFSET..SQ_VERSION_OFFSET + 4].copy_from_slice(&[0x00, 0x00, 0x00, 0x04]); // SQL version
 
     let header = parse_header(&buffer).unwrap();
     assert_eq!(header.page_size, 4096);
     assert_eq!(header.file_format_w, 4);
     assert_eq!(header.file_format_r, 4);
+    assert_eq!(header.schema_cookie, 7);
   }
 
   #[test]
@@ -0,0 +1,280 @@
+mod common;
+
+#[cfg(test)]
+mod insert {
+  use rust_sqlite::cursor::cursor::Field;
+  use rust_sqlite::cursor::value::OwnedValue;
+  use rust_sqlite::db::Db;
+
+  use crate::common::{execute, scratch_copy, text};
+
+  #[test]
+  fn insert_values_with_generated_rowid() {
+    let path = scratch_copy("tests/fixtures/company.db", "values");
+    let db = Db::from_file(&path).unwrap();
+
+    let rows = execute(
+      &db,
+      "INSERT INTO departments (name) VALUES ('marketing'), ('legal')",
+    );
+    assert_eq!(rows.unwrap(), Vec::<Vec<OwnedValue>>::new());
+    execute(
+      &db,
+      "INSERT INTO employees VALUES (20, 'kojo', 4, 1000 + 500)",
+    )
+    .unwrap();
+
+    // the rows are on disk once the statement is done
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      execute(&db, "SELECT id, name FROM departments WHERE id > 3").unwrap(),
+      vec![
+        vec![OwnedValue::Int(4), text("marketing")],
+        vec![OwnedValue::Int(5), text("legal")],
+      ]
+    );
+    assert_eq!(
+      execute(&db, "SELECT name, salary FROM employees WHERE dept_id = 4").unwrap(),
+      vec![vec![text("kojo"), OwnedValue::Int(1500)]]
+    );
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn constraint_failures_leave_the_table_unchanged() {
+    let path = scratch_copy("tests/fixtures/company.db", "unique");
+    let db = Db::from_file(&path).unwrap();
+
+    let err = execute(
+      &db,
+      "INSERT INTO departments (name) VALUES ('hr'), ('sales')",
+    )
+    .unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "UNIQUE constraint failed: departments.name"
+    );
+    let err = execute(&db, "INSERT INTO departments VALUES (2, 'ops')").unwrap_err();
+    assert_eq!(err.to_string(), "UNIQUE constraint failed: departments.id");
+    let err = execute(&db, "INSERT INTO departments VALUES ('x', 'ops')").unwrap_err();
+    assert_eq!(err.to_string(), "datatype mismatch");
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      execute(&db, "SELECT count(*) FROM departments").unwrap(),
+      vec![vec![OwnedValue::Int(3)]]
+    );
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn insert_select_splits_pages() {
+    // 512 byte pages, the table and its three indexes split on every level
+    let path = scratch_copy("tests/fixtures/inventory.db", "split");
+    let db = Db::from_file(&path).unwrap();
+    execute(
+      &db,
+      "INSERT INTO items (category, code, price) SELECT category, code, price + 100 FROM items",
+    )
+    .unwrap();
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      execute(&db, "SELECT count(*), max(id) FROM items").unwrap(),
+      vec![vec![OwnedValue::Int(4000), OwnedValue::Int(4000)]]
+    );
+    // read through each index
+    assert_eq!(
+      execute(&db, "SELECT count(*) FROM items WHERE category = 7").unwrap(),
+      vec![vec![OwnedValue::Int(198)]]
+    );
+    assert_eq!(
+      execute(&db, "SELECT id FROM items WHERE code = 'c00007'").unwrap(),
+      vec![vec![OwnedValue::Int(1)], vec![OwnedValue::Int(2001)]]
+    );
+    // the copies cost 100 more, so none enters the partial index
+    assert_eq!(
+      execute(&db, "SELECT count(*) FROM items WHERE price < 10").unwrap(),
+      vec![vec![OwnedValue::Int(200)]]
+    );
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn insert_overflowing_payload() {
+    let path = scratch_copy("tests/fixtures/company.db", "overflow");
+    let db = Db::from_file(&path).unwrap();
+    let long = "x".repeat(20_000);
+    execute(
+      &db,
+      &format!("INSERT INTO settings VALUES ('motd', '{long}')"),
+    )
+    .unwrap();
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      execute(&db, "SELECT value FROM settings WHERE key = 'motd'").unwrap(),
+      vec![vec![text(&long)]]
+    );
+    let err = execute(&db, "INSERT INTO settings VALUES ('lang', 'fr')").unwrap_err();
+    assert_eq!(err.to_string(), "UNIQUE constraint failed: settings.key");
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn defaults_affinity_and_checks() {
+    let path = scratch_copy("tests/fixtures/schemas.db", "constraints");
+    let db = Db::from_file(&path).unwrap();
+
+    execute(&db, "INSERT INTO orders (id, customer_id) VALUES (12, '2')").unwrap();
+    assert_eq!(
+      execute(
+        &db,
+        "SELECT customer_id, total, status FROM orders WHERE id = 12"
+      )
+      .unwrap(),
+      vec![vec![
+        OwnedValue::Int(2),
+        OwnedValue::Float(-1.0),
+        text("new")
+      ]]
+    );
+
+    let err = execute(&db, "INSERT INTO orders (id, total) VALUES (13, 1)").unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "NOT NULL constraint failed: orders.customer_id"
+    );
+    let err = execute(
+      &db,
+      "INSERT INTO orders (customer_id, total) VALUES (1, -5)",
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "CHECK constraint failed: total >= -1");
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn insert_without_rowid() {
+    let path = scratch_copy("tests/fixtures/schemas.db", "without_rowid");
+    let db = Db::from_file(&path).unwrap();
+
+    execute(
+      &db,
+      "INSERT INTO kv VALUES ('a', 'k0', 'zero', 0), ('c', 'k2', 'c two', 4)",
+    )
+    .unwrap();
+    let err = execute(&db, "INSERT INTO kv (key, bucket) VALUES ('k1', 'a')").unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "UNIQUE constraint failed: kv.key, kv.bucket"
+    );
+    let err = execute(&db, "INSERT INTO kv (key) VALUES ('k9')").unwrap_err();
+    assert_eq!(err.to_string(), "NOT NULL constraint failed: kv.bucket");
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      execute(&db, "SELECT key, bucket FROM kv").unwrap(),
+      vec![
+        vec![text("k0"), text("a")],
+        vec![text("k1"), text("a")],
+        vec![text("k2"), text("a")],
+        vec![text("k2"), text("b")],
+        vec![text("k2"), text("c")],
+      ]
+    );
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn column_count_errors() {
+    let db = Db::from_file("tests/fixtures/company.db").unwrap();
+    let error = |query| execute(&db, query).unwrap_err().to_string();
+
+    assert_eq!(
+      error("INSERT INTO departments VALUES (5)"),
+      "table departments has 2 columns but 1 values were supplied"
+    );
+    assert_eq!(
+      error("INSERT INTO departments (name) VALUES (1, 'x')"),
+      "2 values for 1 columns"
+    );
+    assert_eq!(
+      error("INSERT INTO departments (title) VALUES ('x')"),
+      "table departments has no column named title"
+    );
+    assert_eq!(
+      error("INSERT INTO teams VALUES (1)"),
+      "no such table: teams"
+    );
+  }
+
+  #[test]
+  fn insert_into_expression_indexes() {
+    let path = scratch_copy("tests/fixtures/computed.db", "expressions");
+    let db = Db::from_file(&path).unwrap();
+    execute(&db, "INSERT INTO words (word, n) VALUES ('d', 4)").unwrap();
+    assert_eq!(
+      execute(&db, "INSERT INTO words (word, n) VALUES ('e', 1)")
+        .unwrap_err()
+        .to_string(),
+      "UNIQUE constraint failed: index 'words_double'"
+    );
+
+    // entries hold the value of the expression, then the rowid
+    let root = db.index("words_suffixed").unwrap().root_page;
+    let mut scanner = db.scanner(root);
+    let mut keys = vec![];
+    while let Some(record) = scanner.next_record().unwrap() {
+      keys.push(record.owned_get(Field::Record(0)).unwrap());
+    }
+    assert_eq!(keys, vec![text("dz"), text("cz"), text("bz"), text("az")]);
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn insert_around_generated_columns() {
+    let path = scratch_copy("tests/fixtures/computed.db", "generated");
+    let db = Db::from_file(&path).unwrap();
+    execute(&db, "INSERT INTO readings VALUES (3, 'three')").unwrap();
+    execute(&db, "UPDATE readings SET a = 5 WHERE label = 'two'").unwrap();
+    assert_eq!(
+      execute(&db, "SELECT * FROM readings WHERE tenfold > 10").unwrap(),
+      vec![
+        vec![
+          OwnedValue::Int(5),
+          OwnedValue::Int(50),
+          text("two"),
+          OwnedValue::Int(6)
+        ],
+        vec![
+          OwnedValue::Int(3),
+          OwnedValue::Int(30),
+          text("three"),
+          OwnedValue::Int(4)
+        ],
+      ]
+    );
+
+    // the virtual column takes no space in the record
+    let root = db.table("readings").unwrap().first_page;
+    let mut scanner = db.scanner(root);
+    let record = scanner.next_record().unwrap().unwrap();
+    assert_eq!(record.owned_get(Field::Record(1)).unwrap(), text("one"));
+
+    let error = |query| execute(&db, query).unwrap_err().to_string();
+    assert_eq!(
+      error("INSERT INTO readings VALUES (4, 40, 'four', 5)"),
+      "table readings has 2 columns but 4 values were supplied"
+    );
+    assert_eq!(
+      error("INSERT INTO readings (a, next) VALUES (4, 5)"),
+      "cannot INSERT into generated column \"next\""
+    );
+    assert_eq!(
+      error("UPDATE readings SET tenfold = 1"),
+      "cannot UPDATE generated column \"tenfold\""
+    );
+    std::fs::remove_file(path).unwrap();
+  }
+}
@@ -1,9 +1,14 @@
+mod common;
+
 #[cfg(test)]
 mod integration {
+  use rust_sqlite::cursor::value::OwnedValue;
   use rust_sqlite::db::Db;
   use rust_sqlite::engine::plan::Planner;
   use rust_sqlite::sql::parser::parse_statement;
 
+  use crate::common::{execute, scratch_users, text};
+
   const USER_QUERY: &str = "SELECT * FROM users;";
   const USER_WHERE_QUERY: &str = "SELECT * FROM users where id = 3;";
 
@@ -21,44 +26,842 @@ mod integration {
 
   #[test]
   fn read_queries_database() {
-    // Test reading the queries_test.db file if it exists
-    let result = Db::from_file("queries_test.db");
-
-    if std::path::Path::new("queries_test.db").exists() {
-      assert!(result.is_ok());
-      let _db = result.unwrap();
-
-      // Test that we can access table metadata
-      // The exact tables will depend on what's in the test database
-      // println!("Found {} tables", db.tables_metadata.len());
-      // for table in &db.tables_metadata {
-      //   println!("Table: {}, columns: {}", table.name, table.columns.len());
-      // }
-    } else {
-      // If the file doesn't exist, ensure we get an appropriate error
-      assert!(result.is_err());
-    }
+    let db = Db::from_file(scratch_users("read")).unwrap();
+    assert_eq!(db.tables_metadata.len(), 1);
+    assert_eq!(db.tables_metadata[0].columns.len(), 2);
   }
 
   #[test]
   fn execute_simple_query() {
-    execute_query(USER_QUERY);
+    execute_query("simple", USER_QUERY);
   }
 
   #[test]
   fn execute_simple_where_query() {
-    execute_query(USER_WHERE_QUERY);
+    execute_query("where", USER_WHERE_QUERY);
   }
 
   #[test]
   fn execute_where_compound_query() {
     const USER_WHERE_COMPOUND_QUERY: &str = "SELECT * FROM users where id = 3 and name = 'prince';";
-    execute_query(USER_WHERE_COMPOUND_QUERY);
+    execute_query("compound", USER_WHERE_COMPOUND_QUERY);
+  }
+
+  const COMPANY_DB: &str = "tests/fixtures/company.db";
+
+  #[test]
+  fn integer_primary_key_reads_rowid() {
+    let rows = collect_rows(COMPANY_DB, "SELECT id, name FROM departments");
+    assert_eq!(
+      rows,
+      vec![
+        vec![OwnedValue::Int(1), text("engineering")],
+        vec![OwnedValue::Int(2), text("sales")],
+        vec![OwnedValue::Int(3), text("support")],
+      ]
+    );
+  }
+
+  #[test]
+  fn select_rowid_aliases() {
+    let rows = collect_rows(COMPANY_DB, "SELECT rowid, oid, _rowid_, * FROM departments");
+    assert_eq!(rows[1][..4], vec![OwnedValue::Int(2); 4]);
+    assert_eq!(rows[1][4..], [text("sales")]);
+  }
+
+  #[test]
+  fn filter_on_integer_primary_key() {
+    let rows = collect_rows(COMPANY_DB, "SELECT name FROM employees WHERE id = 7");
+    assert_eq!(rows, vec![vec![text("kwame")]]);
+
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT id FROM employees WHERE rowid > 7 or name = 'ama'",
+    );
+    assert_eq!(
+      rows,
+      vec![vec![OwnedValue::Int(1)], vec![OwnedValue::Int(9)]]
+    );
+  }
+
+  #[test]
+  fn filter_with_parenthesized_conditions() {
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT name FROM employees WHERE (dept_id = 2 OR dept_id = 3) AND salary > 3500",
+    );
+    assert_eq!(rows, vec![vec![text("esi")], vec![text("yaw")]]);
+  }
+
+  #[test]
+  fn computed_result_columns() {
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT name, salary * 12 AS yearly, id || ':' || name FROM employees WHERE id <= 2",
+    );
+    assert_eq!(
+      rows,
+      vec![
+        vec![text("ama"), OwnedValue::Int(62400), text("1:ama")],
+        vec![text("kofi"), OwnedValue::Int(57600), text("2:kofi")],
+      ]
+    );
+  }
+
+  #[test]
+  fn filter_with_arbitrary_predicates() {
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT name FROM employees WHERE NOT dept_id = 1 AND salary / 100 > 35",
+    );
+    assert_eq!(rows, vec![vec![text("esi")], vec![text("yaw")]]);
+
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT name FROM employees WHERE dept_id IS NULL",
+    );
+    assert_eq!(rows, vec![vec![text("abena")]]);
+
+    // comparisons with NULL are never true
+    let rows = collect_rows(COMPANY_DB, "SELECT name FROM employees WHERE dept_id != 1");
+    assert_eq!(rows.len(), 3);
+  }
+
+  #[test]
+  fn numeric_affinity_in_filters() {
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT name FROM employees WHERE salary = '6100'",
+    );
+    assert_eq!(rows, vec![vec![text("kwame")]]);
+  }
+
+  #[test]
+  fn order_by_expressions() {
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT name FROM employees ORDER BY dept_id DESC, salary",
+    );
+    let names = rows.iter().map(|r| r[0].to_string()).collect::<Vec<_>>();
+    assert_eq!(
+      names,
+      vec!["akua", "esi", "yaw", "kofi", "ama", "kwame", "abena"]
+    );
+
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT name FROM employees WHERE salary > 4000 ORDER BY salary % 1000 DESC, name",
+    );
+    let names = rows.iter().map(|r| r[0].to_string()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["kofi", "ama", "kwame", "yaw"]);
+  }
+
+  #[test]
+  fn order_by_nulls_placement() {
+    let first = |query: &str| collect_rows(COMPANY_DB, query)[0][0].to_string();
+    assert_eq!(
+      first("SELECT name FROM employees ORDER BY dept_id"),
+      "abena"
+    );
+    assert_eq!(
+      first("SELECT name FROM employees ORDER BY dept_id NULLS LAST, id"),
+      "ama"
+    );
+    assert_eq!(
+      first("SELECT name FROM employees ORDER BY dept_id DESC NULLS FIRST"),
+      "abena"
+    );
+  }
+
+  #[test]
+  fn order_by_alias_and_column_number() {
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT name, -salary AS neg FROM employees WHERE dept_id = 1 ORDER BY neg",
+    );
+    let names = rows.iter().map(|r| r[0].to_string()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["kwame", "ama", "kofi"]);
+
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT salary, name FROM employees WHERE dept_id = 1 ORDER BY 2 DESC",
+    );
+    let names = rows.iter().map(|r| r[1].to_string()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["kwame", "kofi", "ama"]);
+
+    let db = &Db::from_file(COMPANY_DB).unwrap();
+    let parsed = &parse_statement("SELECT name FROM employees ORDER BY 2", false).unwrap();
+    assert!(Planner::new(db).compile(parsed).is_err());
+  }
+
+  #[test]
+  fn limit_and_offset() {
+    let names = |query: &str| {
+      collect_rows(COMPANY_DB, query)
+        .iter()
+        .map(|r| r[0].to_string())
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+      names("SELECT name FROM employees ORDER BY salary DESC LIMIT 2"),
+      vec!["kwame", "ama"]
+    );
+    assert_eq!(
+      names("SELECT name FROM employees ORDER BY salary DESC LIMIT 2 OFFSET 1"),
+      vec!["ama", "kofi"]
+    );
+    assert_eq!(
+      names("SELECT name FROM employees ORDER BY salary DESC LIMIT 1, 2"),
+      vec!["ama", "kofi"]
+    );
+    assert_eq!(
+      names("SELECT name FROM employees LIMIT -1 OFFSET 5"),
+      vec!["kwame", "abena"]
+    );
+    assert_eq!(
+      names("SELECT name FROM employees LIMIT 0"),
+      Vec::<String>::new()
+    );
+    assert_eq!(
+      names("SELECT name FROM employees LIMIT 1 + 1 OFFSET 10"),
+      Vec::<String>::new()
+    );
+  }
+
+  #[test]
+  fn aggregate_without_group_by() {
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT count(*), count(dept_id), sum(salary), avg(salary), min(name), max(salary), \
+       total(dept_id), group_concat(name) FROM employees",
+    );
+    assert_eq!(
+      rows,
+      vec![vec![
+        OwnedValue::Int(7),
+        OwnedValue::Int(6),
+        OwnedValue::Int(30000),
+        OwnedValue::Float(30000.0 / 7.0),
+        text("abena"),
+        OwnedValue::Int(6100),
+        OwnedValue::Float(10.0),
+        text("ama,kofi,esi,yaw,akua,kwame,abena"),
+      ]]
+    );
+
+    // an empty input still makes a row
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT count(*), sum(id), total(id), max(id) FROM employees WHERE id > 100",
+    );
+    assert_eq!(
+      rows,
+      vec![vec![
+        OwnedValue::Int(0),
+        OwnedValue::Null,
+        OwnedValue::Float(0.0),
+        OwnedValue::Null
+      ]]
+    );
+
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT count(DISTINCT dept_id), sum(DISTINCT dept_id), name, max(salary) FROM employees",
+    );
+    assert_eq!(
+      rows,
+      vec![vec![
+        OwnedValue::Int(3),
+        OwnedValue::Int(6),
+        text("kwame"),
+        OwnedValue::Int(6100)
+      ]]
+    );
+  }
+
+  #[test]
+  fn group_by_and_having() {
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT dept_id, count(*), sum(salary), group_concat(name, '/') FROM employees \
+       GROUP BY dept_id ORDER BY dept_id",
+    );
+    assert_eq!(
+      rows,
+      vec![
+        vec![
+          OwnedValue::Null,
+          OwnedValue::Int(1),
+          OwnedValue::Int(2800),
+          text("abena")
+        ],
+        vec![
+          OwnedValue::Int(1),
+          OwnedValue::Int(3),
+          OwnedValue::Int(16100),
+          text("ama/kofi/kwame")
+        ],
+        vec![
+          OwnedValue::Int(2),
+          OwnedValue::Int(2),
+          OwnedValue::Int(8000),
+          text("esi/yaw")
+        ],
+        vec![
+          OwnedValue::Int(3),
+          OwnedValue::Int(1),
+          OwnedValue::Int(3100),
+          text("akua")
+        ],
+      ]
+    );
+
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT dept_id, avg(salary) FROM employees GROUP BY 1 HAVING count(*) > 1 ORDER BY 2 DESC",
+    );
+    assert_eq!(
+      rows,
+      vec![
+        vec![OwnedValue::Int(1), OwnedValue::Float(16100.0 / 3.0)],
+        vec![OwnedValue::Int(2), OwnedValue::Float(4000.0)],
+      ]
+    );
+
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT salary / 1000 AS band, max(salary) - min(salary) FROM employees \
+       GROUP BY band HAVING band >= 4 ORDER BY band",
+    );
+    assert_eq!(
+      rows,
+      vec![
+        vec![OwnedValue::Int(4), OwnedValue::Int(700)],
+        vec![OwnedValue::Int(5), OwnedValue::Int(0)],
+        vec![OwnedValue::Int(6), OwnedValue::Int(0)],
+      ]
+    );
+  }
+
+  #[test]
+  fn aggregate_misuse() {
+    let db = &Db::from_file(COMPANY_DB).unwrap();
+    for query in [
+      "SELECT name FROM employees WHERE count(*) > 1",
+      "SELECT count(*) FROM employees GROUP BY count(*)",
+      "SELECT sum(max(salary)) FROM employees",
+      "SELECT sum(salary, id) FROM employees",
+    ] {
+      let parsed = &parse_statement(query, false).unwrap();
+      let compiled = Planner::new(db).compile(parsed);
+      let failed = compiled
+        .and_then(|mut op| op.next_row().map(|_| ()))
+        .is_err();
+      assert!(failed, "{query}");
+    }
+  }
+
+  #[test]
+  fn select_distinct() {
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT DISTINCT dept_id FROM employees ORDER BY 1",
+    );
+    let expected = [
+      OwnedValue::Null,
+      OwnedValue::Int(1),
+      OwnedValue::Int(2),
+      OwnedValue::Int(3),
+    ];
+    assert_eq!(rows, expected.map(|v| vec![v]));
+
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT DISTINCT dept_id, salary > 4000 FROM employees WHERE dept_id IS NOT NULL",
+    );
+    assert_eq!(rows.len(), 4);
+  }
+
+  #[test]
+  fn compound_selects() {
+    let ids = |query: &str| {
+      collect_rows(COMPANY_DB, query)
+        .into_iter()
+        .map(|mut r| r.remove(0))
+        .collect::<Vec<_>>()
+    };
+    let ints = |values: &[i64]| {
+      values
+        .iter()
+        .map(|&i| OwnedValue::Int(i))
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+      ids("SELECT id FROM departments UNION SELECT dept_id FROM employees ORDER BY 1"),
+      [vec![OwnedValue::Null], ints(&[1, 2, 3])].concat()
+    );
+    assert_eq!(
+      ids(
+        "SELECT id FROM departments UNION ALL SELECT dept_id FROM employees WHERE salary > 5000 \
+         ORDER BY id DESC"
+      ),
+      ints(&[3, 2, 1, 1, 1])
+    );
+    assert_eq!(
+      ids(
+        "SELECT dept_id FROM employees INTERSECT SELECT id FROM departments WHERE name <> 'sales' \
+         ORDER BY 1"
+      ),
+      ints(&[1, 3])
+    );
+    assert_eq!(
+      ids("SELECT id FROM departments EXCEPT SELECT dept_id FROM employees WHERE salary > 4000"),
+      ints(&[3])
+    );
+    // compounds chain left to right and share one LIMIT
+    assert_eq!(
+      ids(
+        "SELECT id FROM departments UNION ALL SELECT id FROM departments \
+         EXCEPT SELECT 2 FROM departments ORDER BY 1 DESC LIMIT 1"
+      ),
+      ints(&[3])
+    );
+
+    let names = collect_rows(
+      COMPANY_DB,
+      "SELECT name FROM departments UNION SELECT name FROM employees ORDER BY name LIMIT 3",
+    );
+    assert_eq!(
+      names,
+      [text("abena"), text("akua"), text("ama")].map(|v| vec![v])
+    );
+
+    let db = &Db::from_file(COMPANY_DB).unwrap();
+    for query in [
+      "SELECT id, name FROM departments UNION SELECT id FROM employees",
+      "SELECT id FROM departments UNION SELECT id FROM employees ORDER BY salary",
+    ] {
+      let parsed = &parse_statement(query, false).unwrap();
+      assert!(Planner::new(db).compile(parsed).is_err(), "{query}");
+    }
+  }
+
+  fn pairs(query: &str) -> Vec<(String, String)> {
+    collect_rows(COMPANY_DB, query)
+      .iter()
+      .map(|r| (r[0].to_string(), r[1].to_string()))
+      .collect()
+  }
+
+  fn pair(a: &str, b: &str) -> (String, String) {
+    (a.to_string(), b.to_string())
+  }
+
+  #[test]
+  fn inner_joins() {
+    let expected = vec![
+      pair("ama", "engineering"),
+      pair("kofi", "engineering"),
+      pair("esi", "sales"),
+      pair("yaw", "sales"),
+      pair("akua", "support"),
+      pair("kwame", "engineering"),
+    ];
+    assert_eq!(
+      pairs(
+        "SELECT e.name, d.name FROM employees e JOIN departments d ON e.dept_id = d.id \
+         ORDER BY e.id"
+      ),
+      expected
+    );
+    assert_eq!(
+      pairs(
+        "SELECT e.name, d.name FROM employees AS e, departments AS d WHERE d.id = e.dept_id \
+         ORDER BY e.id"
+      ),
+      expected
+    );
+
+    assert_eq!(
+      pairs(
+        "SELECT employees.name, departments.name FROM employees CROSS JOIN departments \
+         WHERE employees.dept_id = departments.id AND departments.id > 1 ORDER BY 1"
+      ),
+      vec![
+        pair("akua", "support"),
+        pair("esi", "sales"),
+        pair("yaw", "sales")
+      ]
+    );
+    // a condition over both tables that isn't an equality
+    assert_eq!(
+      pairs(
+        "SELECT e.name, d.name FROM employees e, departments d \
+         WHERE e.dept_id = d.id AND e.salary < d.id * 2000 ORDER BY 1"
+      ),
+      vec![pair("akua", "support"), pair("esi", "sales")]
+    );
+    assert_eq!(
+      pairs(
+        "SELECT e.name, m.name FROM employees e JOIN employees m ON e.salary < m.salary - 2000 \
+         ORDER BY 1, 2"
+      ),
+      vec![
+        pair("abena", "ama"),
+        pair("abena", "kwame"),
+        pair("akua", "ama"),
+        pair("akua", "kwame"),
+        pair("esi", "kwame"),
+      ]
+    );
+
+    let rows = collect_rows(COMPANY_DB, "SELECT count(*) FROM employees, departments");
+    assert_eq!(rows, vec![vec![OwnedValue::Int(21)]]);
+  }
+
+  #[test]
+  fn left_joins() {
+    assert_eq!(
+      pairs(
+        "SELECT e.name, d.name FROM employees e LEFT JOIN departments d \
+         ON d.id = e.dept_id AND d.name <> 'sales' ORDER BY e.id"
+      ),
+      vec![
+        pair("ama", "engineering"),
+        pair("kofi", "engineering"),
+        pair("esi", "null"),
+        pair("yaw", "null"),
+        pair("akua", "support"),
+        pair("kwame", "engineering"),
+        pair("abena", "null"),
+      ]
+    );
+
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT d.name, count(e.id), sum(e.salary) FROM departments d \
+       LEFT OUTER JOIN employees e ON e.dept_id = d.id AND e.salary > 4500 \
+       GROUP BY d.name ORDER BY 1",
+    );
+    assert_eq!(
+      rows,
+      vec![
+        vec![
+          text("engineering"),
+          OwnedValue::Int(3),
+          OwnedValue::Int(16100)
+        ],
+        vec![text("sales"), OwnedValue::Int(0), OwnedValue::Null],
+        vec![text("support"), OwnedValue::Int(0), OwnedValue::Null],
+      ]
+    );
+
+    // the where clause filters the padded rows
+    assert_eq!(
+      pairs(
+        "SELECT d.name, e.name FROM departments d LEFT JOIN employees e \
+         ON e.dept_id = d.id AND e.salary > 4500 WHERE e.id IS NULL ORDER BY 1"
+      ),
+      vec![pair("sales", "null"), pair("support", "null")]
+    );
+  }
+
+  #[test]
+  fn using_and_natural_joins() {
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT * FROM employees JOIN departments USING (id) ORDER BY id",
+    );
+    assert_eq!(
+      rows[0],
+      vec![
+        OwnedValue::Int(1),
+        text("ama"),
+        OwnedValue::Int(1),
+        OwnedValue::Int(5200),
+        text("engineering")
+      ]
+    );
+    assert_eq!(rows.len(), 3);
+
+    assert!(collect_rows(
+      COMPANY_DB,
+      "SELECT id, departments.name FROM employees NATURAL JOIN departments",
+    )
+    .is_empty());
+
+    let rows = collect_rows(
+      COMPANY_DB,
+      "SELECT d.*, e.id FROM departments d LEFT JOIN employees e USING (id) \
+       WHERE e.id IS NULL OR d.id = 1",
+    );
+    assert_eq!(
+      rows,
+      vec![vec![
+        OwnedValue::Int(1),
+        text("engineering"),
+        OwnedValue::Int(1)
+      ]]
+    );
+  }
+
+  #[test]
+  fn join_name_resolution_errors() {
+    let db = &Db::from_file(COMPANY_DB).unwrap();
+    for query in [
+      "SELECT name FROM employees JOIN departments ON dept_id = departments.id",
+      "SELECT x.name FROM employees e",
+      "SELECT e.name FROM employees e JOIN employees e ON 1",
+      "SELECT name FROM employees JOIN departments USING (salary)",
+    ] {
+      let parsed = &parse_statement(query, false).unwrap();
+      assert!(Planner::new(db).compile(parsed).is_err(), "{query}");
+    }
+  }
+
+  #[test]
+  fn in_lists() {
+    let names = |query: &str| {
+      collect_rows(COMPANY_DB, query)
+        .iter()
+        .map(|r| r[0].to_string())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+      names("SELECT name FROM employees WHERE id IN (2, 9, 4) ORDER BY id"),
+      ["kofi", "yaw", "abena"]
+    );
+    assert_eq!(
+      names("SELECT name FROM employees WHERE salary NOT IN (5200, 4800, 3900, 4100) ORDER BY id"),
+      ["akua", "kwame", "abena"]
+    );
+    // a NULL in the list makes a non-match NULL, which filters the row out
+    assert_eq!(
+      names("SELECT name FROM employees WHERE id NOT IN (1, NULL)"),
+      Vec::<String>::new()
+    );
+    assert_eq!(
+      collect_rows(
+        COMPANY_DB,
+        "SELECT 2 IN (1, 2), 3 IN (1, NULL), NULL IN (1) FROM departments WHERE id = 1"
+      ),
+      vec![vec![OwnedValue::Int(1), OwnedValue::Null, OwnedValue::Null]]
+    );
+  }
+
+  const INVENTORY_DB: &str = "tests/fixtures/inventory.db";
+
+  #[test]
+  fn index_lookups() {
+    let count_and_sum = |condition: &str| {
+      let query = format!("SELECT count(*), sum(id) FROM items WHERE {condition}");
+      let rows = collect_rows(INVENTORY_DB, &query);
+      (rows[0][0].to_string(), rows[0][1].to_string())
+    };
+    for (condition, count, sum) in [
+      ("category = 7", "99", "98633"),
+      ("category = 7 AND price > 50", "50", "50350"),
+      (
+        "category = 7 AND price >= 50.1 AND price < 80",
+        "30",
+        "31210",
+      ),
+      ("category IN (3, 19, '5', NULL, 3)", "297", "296693"),
+      ("category < 2", "198", "196899"),
+      ("category >= 18", "198", "199663"),
+      ("category = 25", "0", "null"),
+      ("code = 'c00700'", "1", "100"),
+      ("code > 'c01990'", "11", "10291"),
+      ("code <= 'c00010'", "10", "10309"),
+      (
+        "code BETWEEN 'c01000' AND 'c01010' AND category = 4",
+        "1",
+        "144",
+      ),
+      ("price < 10", "200", "206700"),
+      ("category = 7 AND price > 50 AND id % 2 = 1", "50", "50350"),
+    ] {
+      assert_eq!(
+        count_and_sum(condition),
+        (count.to_string(), sum.to_string()),
+        "{condition}"
+      );
+    }
+  }
+
+  #[test]
+  fn index_scans_follow_index_order() {
+    let rows = collect_rows(
+      INVENTORY_DB,
+      "SELECT id, code FROM items WHERE code BETWEEN 'c01000' AND 'c01005'",
+    );
+    let expected = [
+      (1002, "c01005"),
+      (1288, "c01004"),
+      (1574, "c01003"),
+      (1860, "c01002"),
+      (143, "c01001"),
+      (429, "c01000"),
+    ]
+    .map(|(id, code)| vec![OwnedValue::Int(id), text(code)]);
+    assert_eq!(rows, expected);
+
+    let rows = collect_rows(
+      INVENTORY_DB,
+      "SELECT id FROM items WHERE category = 7 AND price > 95",
+    );
+    let ids = rows.iter().map(|r| r[0].to_string()).collect::<Vec<_>>();
+    assert_eq!(ids, ["107", "1107", "567", "1567", "27", "1027"]);
+  }
+
+  #[test]
+  fn real_columns_read_whole_numbers_as_reals() {
+    // sqlite stores the price 10.0 of item 300 as the integer 10
+    let rows = collect_rows(
+      INVENTORY_DB,
+      "SELECT price, price / 4 FROM items WHERE id = 300",
+    );
+    assert_eq!(
+      rows,
+      vec![vec![OwnedValue::Float(10.0), OwnedValue::Float(2.5)]]
+    );
+    assert_eq!(rows[0][0].to_string(), "10.0");
+
+    // through a covering index, and in aggregates
+    let rows = collect_rows(
+      INVENTORY_DB,
+      "SELECT price FROM items WHERE category = 0 AND price = 10",
+    );
+    assert_eq!(rows, vec![vec![OwnedValue::Float(10.0)]; 2]);
+    let rows = collect_rows(
+      INVENTORY_DB,
+      "SELECT max(price), sum(price) FROM items WHERE id IN (300, 1000)",
+    );
+    assert_eq!(
+      rows,
+      vec![vec![OwnedValue::Float(10.0), OwnedValue::Float(10.0)]]
+    );
+  }
+
+  #[test]
+  fn explain_query_plan() {
+    let rows = collect_rows(
+      COMPANY_DB,
+      "EXPLAIN QUERY PLAN SELECT e.name, d.name FROM employees e JOIN departments d \
+       ON e.dept_id = d.id WHERE e.dept_id = 1 AND salary > 4000 ORDER BY e.name LIMIT 2",
+    );
+    let expected = [
+      (1, 0, "SORT FOR ORDER BY"),
+      (2, 1, "HASH JOIN"),
+      (
+        3,
+        2,
+        "SEARCH e USING INDEX employees_dept (dept_id=? AND salary>?)",
+      ),
+      (4, 2, "SCAN d"),
+    ]
+    .map(|(id, parent, detail)| vec![OwnedValue::Int(id), OwnedValue::Int(parent), text(detail)]);
+    assert_eq!(rows, expected);
+
+    let details = |query: &str| {
+      collect_rows(COMPANY_DB, query)
+        .iter()
+        .map(|r| r[2].to_string())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+      details(
+        "EXPLAIN QUERY PLAN SELECT name FROM employees WHERE dept_id IN (1, 2) \
+         UNION SELECT name FROM departments WHERE id > 1"
+      ),
+      [
+        "COMPOUND UNION",
+        "SEARCH employees USING INDEX employees_dept (dept_id=?)",
+        "SEARCH departments USING INTEGER PRIMARY KEY (rowid>?)"
+      ]
+    );
+    assert_eq!(
+      details("EXPLAIN QUERY PLAN SELECT name FROM departments WHERE id BETWEEN 1 AND 2"),
+      ["SEARCH departments USING INTEGER PRIMARY KEY (rowid>=? AND rowid<=?)"]
+    );
+    assert_eq!(
+      details(
+        "EXPLAIN QUERY PLAN SELECT name FROM employees \
+         WHERE dept_id = 1 AND salary >= 4000 AND salary < 5000"
+      ),
+      ["SEARCH employees USING INDEX employees_dept (dept_id=? AND salary>=? AND salary<?)"]
+    );
+    assert_eq!(
+      details("EXPLAIN QUERY PLAN SELECT id FROM departments WHERE name = 'sales'"),
+      ["SEARCH departments USING COVERING INDEX departments_name (name=?)"]
+    );
+  }
+
+  #[test]
+  fn explain_operators() {
+    let rows = collect_rows(
+      COMPANY_DB,
+      "EXPLAIN SELECT dept_id, count(*) FROM employees LEFT JOIN departments d \
+       ON salary > d.id GROUP BY dept_id HAVING count(*) > 1",
+    );
+    let operators = rows
+      .iter()
+      .map(|r| (r[0].to_string(), r[1].to_string(), r[2].to_string()))
+      .collect::<Vec<_>>();
+    let expected = [
+      ("1", "0", "Project"),
+      ("2", "1", "Filter"),
+      ("3", "2", "Aggregate"),
+      ("4", "3", "NestedLoopJoin"),
+      ("5", "4", "SeqScan"),
+      ("6", "4", "SeqScan"),
+    ]
+    .map(|(id, parent, operator)| (id.to_string(), parent.to_string(), operator.to_string()));
+    assert_eq!(operators, expected);
+    assert_eq!(rows[1][4], text("where #1 > 1"));
+    assert_eq!(rows[3][4], text("on #1 > #2"));
+    assert_eq!(rows[4][4], text("table employees; 2 columns"));
+    // 7 employees, every one of them kept by the left join
+    assert_eq!(rows[4][3], OwnedValue::Int(7));
+    assert_eq!(rows[3][3], OwnedValue::Int(7));
+
+    let estimate = |query: &str| {
+      let rows = collect_rows(INVENTORY_DB, query);
+      let OwnedValue::Int(estimate) = rows[0][3] else {
+        panic!("estimated rows should be an integer");
+      };
+      (estimate, rows[0][4].to_string())
+    };
+    // estimated from the fan-out of the b-tree pages, there are 2000 items
+    let (rows, detail) = estimate("EXPLAIN SELECT * FROM items");
+    assert!((1800..2200).contains(&rows), "{rows}");
+    assert_eq!(detail, "table items; 4 columns");
+
+    let (rows, detail) = estimate("EXPLAIN SELECT * FROM items WHERE id = 5");
+    assert_eq!(
+      (rows, detail.as_str()),
+      (1, "table items; 4 columns; rowid=?")
+    );
+
+    let (rows, detail) =
+      estimate("EXPLAIN SELECT id FROM items WHERE category = 7 AND price > 50 AND id > code");
+    assert!(rows < 100, "{rows}");
+    assert_eq!(
+      detail,
+      "table items; index items_category (category=? AND price>?); 1 range; rows by rowid; \
+       where rowid > #2"
+    );
+  }
+
+  fn collect_rows(db: &str, query: &str) -> Vec<Vec<OwnedValue>> {
+    execute(&Db::from_file(db).unwrap(), query).unwrap()
   }
 
-  fn execute_query(query: &str) {
+  fn execute_query(name: &str, query: &str) {
     println!("{query}");
-    let db = &Db::from_file("queries_test.db").unwrap();
+    let db = &Db::from_file(scratch_users(name)).unwrap();
     // Test parsing a statement
     let parsed = &parse_statement(query, false).unwrap();
     println!("{parsed:?}");
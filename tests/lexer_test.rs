@@ -36,10 +36,10 @@ mod lexer {
         Token::Identifier("users".to_string()),
         Token::LPar,
         Token::Identifier("id".to_string()),
-        Token::Identifier("integer".to_string()),
+        Token::Identifier("INTEGER".to_string()),
         Token::Comma,
         Token::Identifier("name".to_string()),
-        Token::Identifier("text".to_string()),
+        Token::Identifier("TEXT".to_string()),
         Token::RPar
       ]
     );
@@ -159,4 +159,66 @@ mod lexer {
     // by ensuring the comparison methods exist and compile correctly
     // Actual comprehensive tests would require setting up Value instances
   }
+
+  #[test]
+  fn comments_and_quoted_names() {
+    let input = "SELECT \"First Name\", [id], `x` -- trailing\n/* block */ FROM t";
+    let tokens = tokenize(input).unwrap();
+
+    assert_eq!(
+      tokens,
+      vec![
+        Token::Select,
+        Token::Identifier("First Name".to_string()),
+        Token::Comma,
+        Token::Identifier("id".to_string()),
+        Token::Comma,
+        Token::Identifier("x".to_string()),
+        Token::From,
+        Token::Identifier("t".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn escaped_strings_and_blobs() {
+    let tokens = tokenize("'it''s' x'00fF'").unwrap();
+    assert_eq!(
+      tokens,
+      vec![
+        Token::String("it's".to_string()),
+        Token::Blob(vec![0x00, 0xff])
+      ]
+    );
+  }
+
+  #[test]
+  fn arithmetic_operators() {
+    let tokens = tokenize("a + b - c * d / e % f || g == h <> i IS j").unwrap();
+    let ident = |s: &str| Token::Identifier(s.to_string());
+    assert_eq!(
+      tokens,
+      vec![
+        ident("a"),
+        Token::Plus,
+        ident("b"),
+        Token::Minus,
+        ident("c"),
+        Token::Star,
+        ident("d"),
+        Token::Op(Ops::Div),
+        ident("e"),
+        Token::Op(Ops::Mod),
+        ident("f"),
+        Token::Op(Ops::Concat),
+        ident("g"),
+        Token::Op(Ops::Eq),
+        ident("h"),
+        Token::Op(Ops::Ne),
+        ident("i"),
+        Token::Is,
+        ident("j"),
+      ]
+    );
+  }
 }
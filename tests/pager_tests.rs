@@ -0,0 +1,72 @@
+#[cfg(test)]
+mod pager {
+  use rust_sqlite::{
+    db::Db,
+    page::page_utils::Cell,
+    pager::{Pager, PayloadLimits},
+  };
+
+  const OVERFLOW_DB: &str = "tests/fixtures/overflow.db";
+
+  fn expected_body(id: i64) -> String {
+    "ab".repeat(id as usize * 75) + &id.to_string()
+  }
+
+  #[test]
+  fn payload_limits_from_header() {
+    let db = Db::from_file(OVERFLOW_DB).unwrap();
+    let limits = PayloadLimits::new(&db.header);
+
+    assert_eq!(limits.usable_size, 1024);
+    assert_eq!(limits.max_leaf, 989);
+    assert_eq!(limits.max_local, 230);
+    assert_eq!(limits.min_local, 103);
+    assert_eq!(limits.min_leaf, 103);
+  }
+
+  #[test]
+  fn read_overflowing_table_rows() {
+    let db = Db::from_file(OVERFLOW_DB).unwrap();
+    let table = db
+      .tables_metadata
+      .iter()
+      .find(|t| t.name == "docs")
+      .unwrap();
+
+    let mut scanner = db.scanner(table.first_page);
+    let mut rows = 0;
+    while let Some(record) = scanner.next_record().unwrap() {
+      let id = record.field(0).unwrap().as_int().unwrap();
+      let body = record.field(2).unwrap();
+      assert_eq!(body.as_str(), Some(expected_body(id).as_str()));
+      rows += 1;
+    }
+    assert_eq!(rows, 40);
+  }
+
+  #[test]
+  fn overflowing_cells_keep_first_overflow_page() {
+    let file = std::fs::File::open(OVERFLOW_DB).unwrap();
+    let header = Db::from_file(OVERFLOW_DB).unwrap().header;
+    let pager = Pager::new(file, &header);
+
+    let mut overflowing = 0;
+    for n in 2..=header.db_size as usize {
+      let Ok(page) = pager.read_page(n) else {
+        continue;
+      };
+      for cell in &page.cells {
+        match cell {
+          Cell::TableLeaf(c) => {
+            assert_eq!(c.payload.len(), c.size as usize);
+            overflowing += c.overflow_page_num.is_some() as usize;
+          }
+          Cell::IndexLeaf(c) => assert_eq!(c.payload.len(), c.size as usize),
+          Cell::IndexInterior(c) => assert_eq!(c.payload.len(), c.size as usize),
+          Cell::TableInterior(_) => {}
+        }
+      }
+    }
+    assert!(overflowing > 0);
+  }
+}
@@ -1,8 +1,14 @@
 #[cfg(test)]
 mod parser {
   use rust_sqlite::sql::{
-    ast::{ColumnDef, Expr, ExprResultColumn, ResultColumn, SelectFrom, Statement, Type},
-    parser::{parse_create_statement, parse_statement},
+    ast::{
+      AlterTableAction, AlterTableStatement, ColumnConstraint, ColumnDef, CompoundOperator,
+      DeleteStatement, DropTableStatement, Expr, ExprResultColumn, ForeignKeyClause, FunctionCall,
+      IndexedColumn, InsertSource, InsertStatement, Join, JoinConstraint, JoinOperator, Limit,
+      PragmaStatement, ResultColumn, SelectFrom, Statement, TableConstraint, TableRef,
+      TransactionMode, Type, UnaryOp, UpdateStatement,
+    },
+    parser::{self, parse_create_statement, parse_statement},
     tokenizer::Ops,
   };
 
@@ -20,7 +26,10 @@ mod parser {
     assert_eq!(ResultColumn::Star, select_stmt.core.result_columns[0]);
     assert_eq!(
       select_stmt.core.from,
-      SelectFrom::Table("users".to_string())
+      SelectFrom::Table(TableRef {
+        name: "users".to_string(),
+        alias: None
+      })
     );
   }
 
@@ -40,10 +49,12 @@ mod parser {
       vec![
         ResultColumn::Expr(ExprResultColumn {
           expr: Expr::Column("id".to_string()),
+          text: "id".to_string(),
           alias: None
         }),
         ResultColumn::Expr(ExprResultColumn {
           expr: Expr::Column("name".to_string()),
+          text: "name".to_string(),
           alias: None
         }),
       ]
@@ -65,23 +76,33 @@ mod parser {
       vec![
         ColumnDef {
           name: "id".to_string(),
+          type_name: Some("INTEGER".to_string()),
           col_type: Type::Integer,
+          constraints: vec![],
         },
         ColumnDef {
           name: "name".to_string(),
+          type_name: Some("TEXT".to_string()),
           col_type: Type::Text,
+          constraints: vec![],
         },
         ColumnDef {
           name: "is_admin".to_string(),
+          type_name: Some("BOOL".to_string()),
           col_type: Type::Bool,
+          constraints: vec![],
         },
         ColumnDef {
           name: "amount".to_string(),
+          type_name: Some("REAL".to_string()),
           col_type: Type::Real,
+          constraints: vec![],
         },
         ColumnDef {
           name: "raw".to_string(),
+          type_name: Some("BLOB".to_string()),
           col_type: Type::Blob,
+          constraints: vec![],
         }
       ]
     );
@@ -134,6 +155,83 @@ mod parser {
     }
   }
 
+  #[test]
+  fn select_with_between() {
+    let query = "SELECT * FROM users WHERE rowid BETWEEN 2 AND 5";
+    let Ok(Statement::Select(select_stmt)) = parse_statement(query, false) else {
+      panic!("Expected SELECT statement");
+    };
+
+    let rowid = || Box::new(Expr::Column("rowid".to_string()));
+    assert_eq!(
+      select_stmt.core.where_clause,
+      Some(Expr::Comparison(
+        Box::new(Expr::Comparison(rowid(), Ops::Goe, Box::new(Expr::Int(2)))),
+        Ops::And,
+        Box::new(Expr::Comparison(rowid(), Ops::Loe, Box::new(Expr::Int(5)))),
+      ))
+    );
+  }
+
+  #[test]
+  fn create_table_with_primary_key() {
+    let query = "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT)";
+    let create_stmt = parse_create_statement(query).unwrap();
+
+    assert_eq!(
+      create_stmt.columns[0].constraints,
+      vec![ColumnConstraint::PrimaryKey {
+        descending: false,
+        autoincrement: false
+      }]
+    );
+    assert!(create_stmt.columns[0].is_primary_key());
+    assert!(!create_stmt.columns[1].is_primary_key());
+  }
+
+  #[test]
+  fn create_index() {
+    let query = "CREATE UNIQUE INDEX users_name ON users (name COLLATE nocase DESC, id)";
+    let Ok(Statement::CreateIndex(create_stmt)) = parse_statement(query, false) else {
+      panic!("Expected CREATE INDEX statement");
+    };
+
+    assert_eq!(create_stmt.name, "users_name");
+    assert_eq!(create_stmt.table, "users");
+    assert!(create_stmt.unique);
+    assert_eq!(
+      create_stmt.columns,
+      vec![
+        IndexedColumn {
+          name: "name".to_string(),
+          descending: true,
+          expression: None,
+        },
+        IndexedColumn {
+          name: "id".to_string(),
+          descending: false,
+          expression: None,
+        },
+      ]
+    );
+    assert_eq!(create_stmt.where_clause, None);
+
+    let query = "CREATE INDEX users_email ON users (lower(email) DESC, id + 1)";
+    let Ok(Statement::CreateIndex(create_stmt)) = parse_statement(query, false) else {
+      panic!("Expected CREATE INDEX statement");
+    };
+    let names = create_stmt
+      .columns
+      .iter()
+      .map(|c| (c.name.as_str(), c.descending))
+      .collect::<Vec<_>>();
+    assert_eq!(names, vec![("lower(email)", true), ("id + 1", false)]);
+    assert!(matches!(
+      create_stmt.columns[0].expression,
+      Some(Expr::Function(_))
+    ));
+  }
+
   #[test]
   fn create_statement_function() {
     let query = "CREATE TABLE users (id INTEGER)";
@@ -154,10 +252,754 @@ mod parser {
   }
 
   #[test]
-  fn create_with_unsupported_type() {
+  fn create_with_unknown_type() {
     let query = "CREATE TABLE test (data INVALID_TYPE)";
-    let result = parse_statement(query, false);
-    assert!(result.is_err());
-    assert!(result.unwrap_err().to_string().contains("unsupported type"));
+    let create_stmt = parse_create_statement(query).unwrap();
+    assert_eq!(create_stmt.columns[0].col_type, Type::Numeric);
+  }
+
+  #[test]
+  fn create_table_type_affinity() {
+    let query = "CREATE TABLE t (a VARCHAR(255), b UNSIGNED BIG INT, c DOUBLE PRECISION, d, e DECIMAL(10, 5), f CLOB)";
+    let create_stmt = parse_create_statement(query).unwrap();
+
+    let columns = create_stmt
+      .columns
+      .iter()
+      .map(|c| (c.type_name.as_deref(), c.col_type.clone()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      columns,
+      vec![
+        (Some("VARCHAR(255)"), Type::Text),
+        (Some("UNSIGNED BIG INT"), Type::Integer),
+        (Some("DOUBLE PRECISION"), Type::Real),
+        (None, Type::Blob),
+        (Some("DECIMAL(10, 5)"), Type::Numeric),
+        (Some("CLOB"), Type::Text),
+      ]
+    );
+  }
+
+  #[test]
+  fn create_table_with_constraints() {
+    let query = "CREATE TABLE IF NOT EXISTS main.\"Order Items\" (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      sku TEXT NOT NULL UNIQUE COLLATE NOCASE, -- stock keeping unit
+      qty INT DEFAULT 1 CHECK (qty > 0),
+      order_id INTEGER REFERENCES orders(id) ON DELETE CASCADE,
+      /* computed */ total REAL GENERATED ALWAYS AS (qty * 2) STORED,
+      CONSTRAINT sku_qty UNIQUE (sku, qty DESC),
+      FOREIGN KEY (order_id) REFERENCES orders (id) DEFERRABLE INITIALLY DEFERRED
+    )";
+    let create_stmt = parse_create_statement(query).unwrap();
+
+    assert!(create_stmt.if_not_exists);
+    assert_eq!(create_stmt.name, "Order Items");
+    // stored without IF NOT EXISTS and the schema name, as sqlite does
+    assert!(create_stmt
+      .sql
+      .starts_with("CREATE TABLE \"Order Items\" (\n      id INTEGER"));
+    assert_eq!(
+      create_stmt.columns[0].constraints,
+      vec![ColumnConstraint::PrimaryKey {
+        descending: false,
+        autoincrement: true
+      }]
+    );
+    assert_eq!(
+      create_stmt.columns[1].constraints,
+      vec![
+        ColumnConstraint::NotNull,
+        ColumnConstraint::Unique,
+        ColumnConstraint::Collate("NOCASE".to_string()),
+      ]
+    );
+    assert_eq!(
+      create_stmt.columns[2].constraints,
+      vec![
+        ColumnConstraint::Default(Expr::Int(1)),
+        ColumnConstraint::Check(Expr::Comparison(
+          Box::new(Expr::Column("qty".to_string())),
+          Ops::Gt,
+          Box::new(Expr::Int(0)),
+        )),
+      ]
+    );
+    assert_eq!(
+      create_stmt.columns[3].constraints,
+      vec![ColumnConstraint::References(ForeignKeyClause {
+        table: "orders".to_string(),
+        columns: vec!["id".to_string()],
+      })]
+    );
+    assert_eq!(
+      create_stmt.columns[4].constraints,
+      vec![ColumnConstraint::Generated {
+        expr: Expr::Comparison(
+          Box::new(Expr::Column("qty".to_string())),
+          Ops::Mul,
+          Box::new(Expr::Int(2)),
+        ),
+        stored: true
+      }]
+    );
+    assert_eq!(create_stmt.constraints.len(), 2);
+    assert!(matches!(
+      &create_stmt.constraints[0],
+      TableConstraint::Unique(columns) if columns.len() == 2 && columns[1].descending
+    ));
+  }
+
+  #[test]
+  fn create_without_rowid_table() {
+    let query = "CREATE TABLE kv (k TEXT, v BLOB, PRIMARY KEY (k)) WITHOUT ROWID";
+    let create_stmt = parse_create_statement(query).unwrap();
+
+    assert!(create_stmt.without_rowid);
+    assert_eq!(
+      create_stmt.constraints,
+      vec![TableConstraint::PrimaryKey(vec![IndexedColumn {
+        name: "k".to_string(),
+        descending: false,
+        expression: None,
+      }])]
+    );
+  }
+
+  fn parse_where(clause: &str) -> Expr {
+    let query = format!("SELECT * FROM t WHERE {clause}");
+    let Ok(Statement::Select(select_stmt)) = parse_statement(&query, false) else {
+      panic!("Expected SELECT statement");
+    };
+    select_stmt.core.where_clause.unwrap()
+  }
+
+  fn column(name: &str) -> Box<Expr> {
+    Box::new(Expr::Column(name.to_string()))
+  }
+
+  fn binary(l: Expr, op: Ops, r: Expr) -> Expr {
+    Expr::Comparison(Box::new(l), op, Box::new(r))
+  }
+
+  #[test]
+  fn arithmetic_precedence() {
+    assert_eq!(
+      parse_where("a + b * 2 - c % 3 = 10"),
+      binary(
+        binary(
+          binary(
+            *column("a"),
+            Ops::Add,
+            binary(*column("b"), Ops::Mul, Expr::Int(2)),
+          ),
+          Ops::Sub,
+          binary(*column("c"), Ops::Mod, Expr::Int(3)),
+        ),
+        Ops::Eq,
+        Expr::Int(10),
+      )
+    );
+  }
+
+  #[test]
+  fn parentheses_override_precedence() {
+    assert_eq!(
+      parse_where("(a + b) * 2 > 1"),
+      binary(
+        binary(
+          binary(*column("a"), Ops::Add, *column("b")),
+          Ops::Mul,
+          Expr::Int(2),
+        ),
+        Ops::Gt,
+        Expr::Int(1),
+      )
+    );
+  }
+
+  #[test]
+  fn and_binds_tighter_than_or() {
+    assert_eq!(
+      parse_where("a = 1 OR b = 2 AND c = 3"),
+      binary(
+        binary(*column("a"), Ops::Eq, Expr::Int(1)),
+        Ops::Or,
+        binary(
+          binary(*column("b"), Ops::Eq, Expr::Int(2)),
+          Ops::And,
+          binary(*column("c"), Ops::Eq, Expr::Int(3)),
+        ),
+      )
+    );
+  }
+
+  #[test]
+  fn not_and_unary_minus() {
+    assert_eq!(
+      parse_where("NOT a < -b AND c"),
+      binary(
+        Expr::Unary(
+          UnaryOp::Not,
+          Box::new(binary(
+            *column("a"),
+            Ops::Lt,
+            Expr::Unary(UnaryOp::Neg, column("b")),
+          )),
+        ),
+        Ops::And,
+        *column("c"),
+      )
+    );
+    assert_eq!(
+      parse_where("-2 < a"),
+      binary(Expr::Int(-2), Ops::Lt, *column("a"))
+    );
+  }
+
+  #[test]
+  fn is_null_and_is_not() {
+    assert_eq!(
+      parse_where("a IS NULL OR b IS NOT c"),
+      binary(
+        binary(*column("a"), Ops::Is, Expr::Null),
+        Ops::Or,
+        binary(*column("b"), Ops::IsNot, *column("c")),
+      )
+    );
+    assert_eq!(
+      parse_where("a NOT NULL"),
+      binary(*column("a"), Ops::IsNot, Expr::Null)
+    );
+  }
+
+  #[test]
+  fn concat_binds_tightest() {
+    assert_eq!(
+      parse_where("a || 'x' = b || c"),
+      binary(
+        binary(*column("a"), Ops::Concat, Expr::Text("x".to_string())),
+        Ops::Eq,
+        binary(*column("b"), Ops::Concat, *column("c")),
+      )
+    );
+  }
+
+  #[test]
+  fn not_between() {
+    assert_eq!(
+      parse_where("a NOT BETWEEN 1 + 1 AND 5"),
+      Expr::Unary(
+        UnaryOp::Not,
+        Box::new(binary(
+          binary(
+            *column("a"),
+            Ops::Goe,
+            binary(Expr::Int(1), Ops::Add, Expr::Int(1)),
+          ),
+          Ops::And,
+          binary(*column("a"), Ops::Loe, Expr::Int(5)),
+        )),
+      )
+    );
+  }
+
+  #[test]
+  fn in_lists() {
+    assert_eq!(
+      parse_where("a IN (1, 'b') AND c NOT IN (d + 1)"),
+      binary(
+        Expr::InList {
+          expr: column("a"),
+          list: vec![Expr::Int(1), Expr::Text("b".into())],
+          negated: false,
+        },
+        Ops::And,
+        Expr::InList {
+          expr: column("c"),
+          list: vec![binary(*column("d"), Ops::Add, Expr::Int(1))],
+          negated: true,
+        },
+      )
+    );
+    assert!(parse_statement("SELECT * FROM t WHERE a IN ()", false).is_err());
+    assert!(parse_statement("SELECT * FROM t WHERE a IN (1, 2", false).is_err());
+  }
+
+  #[test]
+  fn explain() {
+    let Ok(Statement::Explain(explain)) =
+      parse_statement("EXPLAIN QUERY PLAN SELECT * FROM t", false)
+    else {
+      panic!("Expected EXPLAIN statement");
+    };
+    assert!(explain.query_plan);
+    assert!(matches!(*explain.statement, Statement::Select(_)));
+
+    let Ok(Statement::Explain(explain)) = parse_statement("explain select a from t", false) else {
+      panic!("Expected EXPLAIN statement");
+    };
+    assert!(!explain.query_plan);
+    assert!(parse_statement("EXPLAIN QUERY SELECT * FROM t", false).is_err());
+  }
+
+  #[test]
+  fn expression_result_columns() {
+    let query = "SELECT price * qty AS total, -price discount, (a) FROM t";
+    let Ok(Statement::Select(select_stmt)) = parse_statement(query, false) else {
+      panic!("Expected SELECT statement");
+    };
+
+    assert_eq!(
+      select_stmt.core.result_columns,
+      vec![
+        ResultColumn::Expr(ExprResultColumn {
+          expr: binary(*column("price"), Ops::Mul, *column("qty")),
+          text: "price * qty".to_string(),
+          alias: Some("total".to_string()),
+        }),
+        ResultColumn::Expr(ExprResultColumn {
+          expr: Expr::Unary(UnaryOp::Neg, column("price")),
+          text: "-price".to_string(),
+          alias: Some("discount".to_string()),
+        }),
+        ResultColumn::Expr(ExprResultColumn {
+          expr: *column("a"),
+          text: "(a)".to_string(),
+          alias: None,
+        }),
+      ]
+    );
+  }
+
+  #[test]
+  fn unbalanced_parentheses() {
+    assert!(parse_statement("SELECT * FROM t WHERE (a = 1", false).is_err());
+    assert!(parse_statement("SELECT * FROM t WHERE a = ", false).is_err());
+  }
+
+  #[test]
+  fn function_calls() {
+    let call = |name: &str, distinct, args| {
+      Expr::Function(FunctionCall {
+        name: name.to_string(),
+        distinct,
+        args,
+      })
+    };
+
+    assert_eq!(
+      parse_where("count(*) > 1"),
+      binary(call("count", false, vec![]), Ops::Gt, Expr::Int(1))
+    );
+    assert_eq!(
+      parse_where("count() = 0"),
+      binary(call("count", false, vec![]), Ops::Eq, Expr::Int(0))
+    );
+    assert_eq!(
+      parse_where("group_concat(DISTINCT a, '-') IS NULL"),
+      binary(
+        call(
+          "group_concat",
+          true,
+          vec![*column("a"), Expr::Text("-".into())]
+        ),
+        Ops::Is,
+        Expr::Null
+      )
+    );
+    assert!(parse_statement("SELECT count(DISTINCT *) FROM t", false).is_err());
+    assert!(parse_statement("SELECT sum(a FROM t", false).is_err());
+  }
+
+  #[test]
+  fn select_with_group_by_and_having() {
+    let Ok(Statement::Select(select_stmt)) = parse_statement(
+      "SELECT a, count(*) FROM t WHERE b > 0 GROUP BY a, c HAVING count(*) > 1 ORDER BY 2",
+      false,
+    ) else {
+      panic!("Expected SELECT statement");
+    };
+
+    assert_eq!(select_stmt.core.group_by, vec![*column("a"), *column("c")]);
+    assert!(matches!(
+      select_stmt.core.having,
+      Some(Expr::Comparison(_, Ops::Gt, _))
+    ));
+    assert!(select_stmt.core.where_clause.is_some());
+    assert_eq!(select_stmt.order_by.len(), 1);
+  }
+
+  #[test]
+  fn compound_select() {
+    let Ok(Statement::Select(select_stmt)) = parse_statement(
+      "SELECT DISTINCT a FROM t UNION ALL SELECT ALL b FROM u INTERSECT SELECT c FROM v \
+       EXCEPT SELECT d FROM w UNION SELECT e FROM x ORDER BY 1 LIMIT 2",
+      false,
+    ) else {
+      panic!("Expected SELECT statement");
+    };
+
+    assert!(select_stmt.core.distinct);
+    let operators = select_stmt
+      .compound
+      .iter()
+      .map(|c| c.operator)
+      .collect::<Vec<_>>();
+    assert_eq!(
+      operators,
+      vec![
+        CompoundOperator::UnionAll,
+        CompoundOperator::Intersect,
+        CompoundOperator::Except,
+        CompoundOperator::Union
+      ]
+    );
+    assert!(select_stmt.compound.iter().all(|c| !c.core.distinct));
+    assert_eq!(
+      select_stmt.compound[3].core.from,
+      SelectFrom::Table(TableRef {
+        name: "x".to_string(),
+        alias: None
+      })
+    );
+    assert_eq!(select_stmt.order_by.len(), 1);
+    assert!(select_stmt.limit.is_some());
+
+    assert!(parse_statement("SELECT a FROM t UNION", false).is_err());
+  }
+
+  #[test]
+  fn select_with_joins() {
+    let Ok(Statement::Select(select_stmt)) = parse_statement(
+      "SELECT e.*, d.name FROM employees e LEFT OUTER JOIN departments AS d ON e.dept_id = d.id \
+       NATURAL JOIN teams, projects CROSS JOIN sites USING (site_id)",
+      false,
+    ) else {
+      panic!("Expected SELECT statement");
+    };
+
+    assert_eq!(
+      select_stmt.core.result_columns[0],
+      ResultColumn::TableStar("e".to_string())
+    );
+    let ResultColumn::Expr(column) = &select_stmt.core.result_columns[1] else {
+      panic!("Expected an expression");
+    };
+    assert_eq!(
+      column.expr,
+      Expr::QualifiedColumn("d".to_string(), "name".to_string())
+    );
+
+    let table = |name: &str, alias: Option<&str>| TableRef {
+      name: name.to_string(),
+      alias: alias.map(str::to_string),
+    };
+    let SelectFrom::Join(sites) = select_stmt.core.from else {
+      panic!("Expected a join");
+    };
+    let Join {
+      left: SelectFrom::Join(projects),
+      operator: JoinOperator::Cross,
+      natural: false,
+      right,
+      constraint: Some(JoinConstraint::Using(using)),
+    } = *sites
+    else {
+      panic!("Expected a cross join");
+    };
+    assert_eq!(
+      (right, using),
+      (table("sites", None), vec!["site_id".to_string()])
+    );
+
+    let Join {
+      left: SelectFrom::Join(teams),
+      operator: JoinOperator::Inner,
+      natural: false,
+      constraint: None,
+      ..
+    } = *projects
+    else {
+      panic!("Expected a comma join");
+    };
+    let Join {
+      left: SelectFrom::Join(departments),
+      natural: true,
+      constraint: None,
+      ..
+    } = *teams
+    else {
+      panic!("Expected a natural join");
+    };
+    assert_eq!(departments.operator, JoinOperator::Left);
+    assert_eq!(
+      departments.left,
+      SelectFrom::Table(table("employees", Some("e")))
+    );
+    assert_eq!(departments.right, table("departments", Some("d")));
+    assert!(matches!(
+      departments.constraint,
+      Some(JoinConstraint::On(_))
+    ));
+
+    assert!(parse_statement("SELECT * FROM a NATURAL JOIN b ON a.x = b.x", false).is_err());
+    assert!(parse_statement("SELECT * FROM a LEFT b", false).is_err());
+  }
+
+  #[test]
+  fn select_with_limit_and_offset() {
+    let limit = |query: &str| {
+      let Ok(Statement::Select(select_stmt)) = parse_statement(query, false) else {
+        panic!("Expected SELECT statement");
+      };
+      select_stmt.limit.unwrap()
+    };
+
+    assert_eq!(
+      limit("SELECT * FROM t LIMIT 10"),
+      Limit {
+        limit: Expr::Int(10),
+        offset: None
+      }
+    );
+    assert_eq!(
+      limit("SELECT * FROM t ORDER BY a LIMIT 10 OFFSET 5"),
+      Limit {
+        limit: Expr::Int(10),
+        offset: Some(Expr::Int(5))
+      }
+    );
+    // the comma form puts the offset first
+    assert_eq!(
+      limit("SELECT * FROM t LIMIT 5, 10"),
+      Limit {
+        limit: Expr::Int(10),
+        offset: Some(Expr::Int(5))
+      }
+    );
+  }
+
+  #[test]
+  fn insert_statements() {
+    assert_eq!(
+      parse_statement("INSERT INTO t (a, b) VALUES (1, 'x'), (2, NULL)", false).unwrap(),
+      Statement::Insert(InsertStatement {
+        table: "t".to_string(),
+        columns: vec!["a".to_string(), "b".to_string()],
+        source: InsertSource::Values(vec![
+          vec![Expr::Int(1), Expr::Text("x".to_string())],
+          vec![Expr::Int(2), Expr::Null],
+        ]),
+      })
+    );
+
+    let Ok(Statement::Insert(insert)) = parse_statement("INSERT INTO t SELECT * FROM u;", true)
+    else {
+      panic!("Expected INSERT statement");
+    };
+    assert!(insert.columns.is_empty());
+    assert!(matches!(insert.source, InsertSource::Select(_)));
+  }
+
+  #[test]
+  fn delete_statements() {
+    assert_eq!(
+      parse_statement("DELETE FROM t WHERE a = 1", false).unwrap(),
+      Statement::Delete(DeleteStatement {
+        table: "t".to_string(),
+        where_clause: Some(Expr::Comparison(
+          Box::new(Expr::Column("a".to_string())),
+          Ops::Eq,
+          Box::new(Expr::Int(1)),
+        )),
+      })
+    );
+    assert_eq!(
+      parse_statement("DELETE FROM t;", true).unwrap(),
+      Statement::Delete(DeleteStatement {
+        table: "t".to_string(),
+        where_clause: None,
+      })
+    );
+  }
+
+  #[test]
+  fn update_statements() {
+    assert_eq!(
+      parse_statement("UPDATE t SET a = 1, b = b + 1 WHERE c", false).unwrap(),
+      Statement::Update(UpdateStatement {
+        table: "t".to_string(),
+        assignments: vec![
+          ("a".to_string(), Expr::Int(1)),
+          (
+            "b".to_string(),
+            Expr::Comparison(
+              Box::new(Expr::Column("b".to_string())),
+              Ops::Add,
+              Box::new(Expr::Int(1)),
+            )
+          ),
+        ],
+        where_clause: Some(Expr::Column("c".to_string())),
+      })
+    );
+    assert!(parse_statement("UPDATE t SET a", false).is_err());
+  }
+
+  #[test]
+  fn transaction_statements() {
+    let parse = |query| parse_statement(query, false).unwrap();
+    assert_eq!(parse("BEGIN"), Statement::Begin(TransactionMode::Deferred));
+    assert_eq!(
+      parse("BEGIN IMMEDIATE TRANSACTION"),
+      Statement::Begin(TransactionMode::Immediate)
+    );
+    assert_eq!(
+      parse("begin exclusive"),
+      Statement::Begin(TransactionMode::Exclusive)
+    );
+    assert_eq!(parse("COMMIT TRANSACTION"), Statement::Commit);
+    assert_eq!(parse("END"), Statement::Commit);
+    assert_eq!(parse("ROLLBACK"), Statement::Rollback);
+    assert_eq!(parse("SAVEPOINT step"), Statement::Savepoint("step".into()));
+    assert_eq!(
+      parse("RELEASE SAVEPOINT step"),
+      Statement::Release("step".into())
+    );
+    assert_eq!(parse("release step"), Statement::Release("step".into()));
+    assert_eq!(
+      parse("ROLLBACK TRANSACTION TO SAVEPOINT step"),
+      Statement::RollbackTo("step".into())
+    );
+    assert_eq!(
+      parse("ROLLBACK TO step"),
+      Statement::RollbackTo("step".into())
+    );
+    // join keywords aren't reserved in savepoint names
+    assert_eq!(
+      parse("SAVEPOINT outer"),
+      Statement::Savepoint("outer".into())
+    );
+    assert_eq!(parse("RELEASE Inner"), Statement::Release("inner".into()));
+    assert_eq!(
+      parse("ROLLBACK TO SAVEPOINT left"),
+      Statement::RollbackTo("left".into())
+    );
+    assert!(parse_statement("SAVEPOINT select", false).is_err());
+  }
+
+  #[test]
+  fn pragma_statements() {
+    let parse = |query| parse_statement(query, false).unwrap();
+    let pragma = |name: &str, value: Option<&str>| {
+      Statement::Pragma(PragmaStatement {
+        name: name.to_string(),
+        value: value.map(str::to_string),
+      })
+    };
+    assert_eq!(parse("PRAGMA journal_mode"), pragma("journal_mode", None));
+    assert_eq!(
+      parse("PRAGMA main.wal_checkpoint(TRUNCATE)"),
+      pragma("wal_checkpoint", Some("TRUNCATE"))
+    );
+    assert_eq!(
+      parse("pragma wal_autocheckpoint = -1"),
+      pragma("wal_autocheckpoint", Some("-1"))
+    );
+    assert_eq!(
+      parse("PRAGMA journal_mode = 'wal'"),
+      pragma("journal_mode", Some("wal"))
+    );
+  }
+
+  #[test]
+  fn drop_and_alter_table_statements() {
+    let parse = |query| parse_statement(query, false).unwrap();
+    assert_eq!(
+      parse("DROP TABLE IF EXISTS main.items"),
+      Statement::DropTable(DropTableStatement {
+        name: "items".to_string(),
+        if_exists: true,
+      })
+    );
+    let alter = |action| {
+      Statement::AlterTable(AlterTableStatement {
+        table: "items".to_string(),
+        action,
+      })
+    };
+    assert_eq!(
+      parse("ALTER TABLE items RENAME TO stock"),
+      alter(AlterTableAction::RenameTable("stock".to_string()))
+    );
+    assert_eq!(
+      parse("alter table items rename qty to quantity"),
+      alter(AlterTableAction::RenameColumn {
+        old: "qty".to_string(),
+        new: "quantity".to_string(),
+      })
+    );
+    assert_eq!(
+      parse("ALTER TABLE items DROP COLUMN qty"),
+      alter(AlterTableAction::DropColumn("qty".to_string()))
+    );
+    let Statement::AlterTable(AlterTableStatement {
+      action: AlterTableAction::AddColumn { column, sql },
+      ..
+    }) = parse("ALTER TABLE items ADD size INT DEFAULT 4")
+    else {
+      panic!("Expected ADD COLUMN");
+    };
+    assert_eq!(column.name, "size");
+    assert_eq!(sql, "size INT DEFAULT 4");
+    assert!(parse_statement("ALTER TABLE items RENAME", false).is_err());
+  }
+
+  #[test]
+  fn schema_rewrites() {
+    let view = "CREATE VIEW v AS SELECT e.name, d.name AS title, dept FROM employees e JOIN departments AS d ON e.dept = d.id";
+    assert_eq!(
+      parser::rename_table(view, "departments", "teams").unwrap(),
+      "CREATE VIEW v AS SELECT e.name, d.name AS title, dept FROM employees e JOIN \"teams\" AS d ON e.dept = d.id"
+    );
+    // only qualified names are resolved where the view joins several tables
+    assert_eq!(
+      parser::rename_column(view, "departments", "name", "label").unwrap(),
+      "CREATE VIEW v AS SELECT e.name, d.label AS title, dept FROM employees e JOIN departments AS d ON e.dept = d.id"
+    );
+    let trigger = "CREATE TRIGGER t AFTER UPDATE OF qty ON items WHEN new.qty > 0 BEGIN INSERT INTO log (qty) VALUES (new.qty); END";
+    assert_eq!(
+      parser::rename_column(trigger, "items", "qty", "amount").unwrap(),
+      "CREATE TRIGGER t AFTER UPDATE OF amount ON items WHEN new.amount > 0 BEGIN INSERT INTO log (qty) VALUES (new.amount); END"
+    );
+    assert_eq!(
+      parser::column_reference(trigger, "items", "qty")
+        .unwrap()
+        .as_deref(),
+      Some("qty")
+    );
+    assert_eq!(
+      parser::column_reference(trigger, "log", "qty")
+        .unwrap()
+        .as_deref(),
+      Some("qty")
+    );
+
+    let table =
+      "CREATE TABLE \"items\" (id, \"qty\" INT CHECK (qty > 0), parent REFERENCES items(id))";
+    assert_eq!(
+      parser::rename_column(table, "items", "qty", "amount").unwrap(),
+      "CREATE TABLE \"items\" (id, \"amount\" INT CHECK (amount > 0), parent REFERENCES items(id))"
+    );
+    assert_eq!(
+      parser::add_column(table, "note TEXT").unwrap(),
+      "CREATE TABLE \"items\" (id, \"qty\" INT CHECK (qty > 0), parent REFERENCES items(id), note TEXT)"
+    );
+    assert_eq!(
+      parser::drop_column(table, 1).unwrap(),
+      "CREATE TABLE \"items\" (id, parent REFERENCES items(id))"
+    );
   }
 }
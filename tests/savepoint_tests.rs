@@ -0,0 +1,118 @@
+mod common;
+
+#[cfg(test)]
+mod savepoint {
+  use rust_sqlite::cursor::value::OwnedValue;
+  use rust_sqlite::db::Db;
+
+  use crate::common::{count, execute, scratch_copy};
+
+  fn departments(db: &Db) -> Vec<String> {
+    execute(db, "SELECT name FROM departments WHERE id > 3")
+      .unwrap()
+      .into_iter()
+      .map(|row| row[0].as_value().as_str().unwrap().to_string())
+      .collect()
+  }
+
+  #[test]
+  fn rollback_to_keeps_the_transaction() {
+    let path = scratch_copy("tests/fixtures/company.db", "rollback_to");
+    let db = Db::from_file(&path).unwrap();
+
+    execute(&db, "BEGIN").unwrap();
+    execute(&db, "INSERT INTO departments (name) VALUES ('kept')").unwrap();
+    execute(&db, "SAVEPOINT step1").unwrap();
+    execute(&db, "INSERT INTO departments (name) VALUES ('undone')").unwrap();
+    execute(&db, "SAVEPOINT step2").unwrap();
+    execute(&db, "DELETE FROM employees").unwrap();
+    execute(&db, "ROLLBACK TO step1").unwrap();
+    assert_eq!(departments(&db), vec!["kept"]);
+    assert_eq!(count(&db, "SELECT count(*) FROM employees"), 7);
+
+    // the savepoint stays, step2 is gone
+    execute(&db, "UPDATE departments SET name = 'renamed' WHERE id = 1").unwrap();
+    execute(&db, "ROLLBACK TRANSACTION TO SAVEPOINT step1").unwrap();
+    assert_eq!(
+      execute(&db, "RELEASE step2").unwrap_err().to_string(),
+      "no such savepoint: step2"
+    );
+    execute(&db, "RELEASE step1").unwrap();
+    execute(&db, "COMMIT").unwrap();
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(departments(&db), vec!["kept"]);
+    assert_eq!(
+      execute(&db, "SELECT name FROM departments WHERE id = 1").unwrap(),
+      vec![vec![OwnedValue::String("engineering".to_string().into())]]
+    );
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn outermost_savepoint_is_a_transaction() {
+    let path = scratch_copy("tests/fixtures/company.db", "transaction");
+    let original = std::fs::read(&path).unwrap();
+    let db = Db::from_file(&path).unwrap();
+
+    execute(&db, "SAVEPOINT migration").unwrap();
+    assert_eq!(
+      execute(&db, "BEGIN").unwrap_err().to_string(),
+      "cannot start a transaction within a transaction"
+    );
+    execute(&db, "INSERT INTO departments (name) VALUES ('a')").unwrap();
+    execute(&db, "SAVEPOINT nested").unwrap();
+    execute(&db, "INSERT INTO departments (name) VALUES ('b')").unwrap();
+    execute(&db, "RELEASE SAVEPOINT nested").unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+
+    // the changes of a released savepoint belong to the one before it
+    execute(&db, "SAVEPOINT outer").unwrap();
+    execute(&db, "INSERT INTO departments (name) VALUES ('c')").unwrap();
+    execute(&db, "RELEASE outer").unwrap();
+    execute(&db, "ROLLBACK TO migration").unwrap();
+    assert_eq!(departments(&db), Vec::<String>::new());
+
+    execute(&db, "INSERT INTO departments (name) VALUES ('d')").unwrap();
+    execute(&db, "RELEASE migration").unwrap();
+    assert_ne!(std::fs::read(&path).unwrap(), original);
+    assert_eq!(
+      execute(&db, "COMMIT").unwrap_err().to_string(),
+      "cannot commit - no transaction is active"
+    );
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(departments(&db), vec!["d"]);
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn transaction_end_drops_savepoints() {
+    let path = scratch_copy("tests/fixtures/company.db", "end");
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      execute(&db, "ROLLBACK TO missing").unwrap_err().to_string(),
+      "no such savepoint: missing"
+    );
+
+    execute(&db, "SAVEPOINT a").unwrap();
+    execute(&db, "INSERT INTO departments (name) VALUES ('a')").unwrap();
+    execute(&db, "SAVEPOINT b").unwrap();
+    execute(&db, "COMMIT").unwrap();
+    assert_eq!(
+      execute(&db, "RELEASE a").unwrap_err().to_string(),
+      "no such savepoint: a"
+    );
+
+    execute(&db, "BEGIN").unwrap();
+    execute(&db, "SAVEPOINT a").unwrap();
+    execute(&db, "INSERT INTO departments (name) VALUES ('b')").unwrap();
+    // releasing the outermost savepoint of a transaction started by BEGIN doesn't commit
+    execute(&db, "RELEASE a").unwrap();
+    execute(&db, "ROLLBACK").unwrap();
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(departments(&db), vec!["a"]);
+    std::fs::remove_file(path).unwrap();
+  }
+}
@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod scanner {
+  use rust_sqlite::db::Db;
+
+  const OVERFLOW_DB: &str = "tests/fixtures/overflow.db";
+
+  fn index_root(db: &Db, name: &str) -> usize {
+    let mut schema = db.scanner(1);
+    while let Some(record) = schema.next_record().unwrap() {
+      if record.field(0).unwrap().as_str() == Some("index")
+        && record.field(1).unwrap().as_str() == Some(name)
+      {
+        return record.field(3).unwrap().as_int().unwrap() as usize;
+      }
+    }
+    panic!("missing index {name}");
+  }
+
+  #[test]
+  fn scan_index_in_key_order() {
+    let db = Db::from_file(OVERFLOW_DB).unwrap();
+    let mut scanner = db.scanner(index_root(&db, "docs_body"));
+
+    let mut keys = vec![];
+    while let Some(record) = scanner.next_record().unwrap() {
+      assert_eq!(record.header.fields.len(), 2);
+      let body = record.field(0).unwrap().as_str().unwrap().to_owned();
+      let rowid = record.field(1).unwrap().as_int().unwrap();
+      assert!(body.ends_with(&rowid.to_string()));
+      keys.push(body);
+    }
+
+    assert_eq!(keys.len(), 40);
+    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+  }
+
+  #[test]
+  fn scan_index_visits_interior_cells() {
+    let db = Db::from_file(OVERFLOW_DB).unwrap();
+    let mut scanner = db.scanner(index_root(&db, "docs_body"));
+
+    let mut rowids = vec![];
+    while let Some(record) = scanner.next_record().unwrap() {
+      rowids.push(record.field(1).unwrap().as_int().unwrap());
+    }
+
+    rowids.sort();
+    assert_eq!(rowids, (1..=40).collect::<Vec<_>>());
+  }
+
+  fn docs_root(db: &Db) -> usize {
+    db.tables_metadata
+      .iter()
+      .find(|t| t.name == "docs")
+      .unwrap()
+      .first_page
+  }
+
+  #[test]
+  fn seek_every_rowid() {
+    let db = Db::from_file(OVERFLOW_DB).unwrap();
+
+    for rowid in 1..=40 {
+      let mut scanner = db.scanner(docs_root(&db));
+      scanner.seek(rowid).unwrap();
+      scanner.stop_after(rowid);
+
+      let record = scanner.next_record().unwrap().unwrap();
+      assert_eq!(record.field(0).unwrap().as_int(), Some(rowid));
+      assert!(scanner.next_record().unwrap().is_none());
+    }
+  }
+
+  #[test]
+  fn seek_every_index_key() {
+    let db = Db::from_file(OVERFLOW_DB).unwrap();
+    let root = index_root(&db, "docs_body");
+
+    let mut keys = vec![];
+    let mut scanner = db.scanner(root);
+    while let Some(record) = scanner.next_record().unwrap() {
+      keys.push(record.field(0).unwrap().as_str().unwrap().to_owned());
+    }
+
+    // every key is found from the root, and the scan goes on in key order
+    for (n, key) in keys.iter().enumerate() {
+      let mut scanner = db.scanner(root);
+      scanner
+        .seek_index(|record| Ok(record.field(0).unwrap().as_str().unwrap() < key.as_str()))
+        .unwrap();
+      let mut rest = vec![];
+      while let Some(record) = scanner.next_record().unwrap() {
+        rest.push(record.field(0).unwrap().as_str().unwrap().to_owned());
+      }
+      assert_eq!(rest, keys[n..]);
+    }
+
+    let mut scanner = db.scanner(root);
+    scanner.seek_index(|_| Ok(true)).unwrap();
+    assert!(scanner.next_record().unwrap().is_none());
+  }
+
+  #[test]
+  fn seek_past_last_rowid() {
+    let db = Db::from_file(OVERFLOW_DB).unwrap();
+    let mut scanner = db.scanner(docs_root(&db));
+    scanner.seek(41).unwrap();
+    assert!(scanner.next_record().unwrap().is_none());
+  }
+
+  #[test]
+  fn seek_iterates_forward() {
+    let db = Db::from_file(OVERFLOW_DB).unwrap();
+    let mut scanner = db.scanner(docs_root(&db));
+    scanner.seek(0).unwrap();
+    scanner.stop_after(25);
+
+    let mut ids = vec![];
+    while let Some(record) = scanner.next_record().unwrap() {
+      ids.push(record.field(0).unwrap().as_int().unwrap());
+    }
+    assert_eq!(ids, (1..=25).collect::<Vec<_>>());
+  }
+}
@@ -0,0 +1,199 @@
+#[cfg(test)]
+mod schema {
+  use rust_sqlite::{
+    cursor::value::OwnedValue,
+    db::{Db, SchemaKind},
+    engine::plan::Planner,
+    sql::{ast::Type, parser::parse_statement},
+  };
+
+  const COMPANY_DB: &str = "tests/fixtures/company.db";
+  const COMPUTED_DB: &str = "tests/fixtures/computed.db";
+
+  #[test]
+  fn collect_every_schema_object() {
+    let db = Db::from_file(COMPANY_DB).unwrap();
+    let kinds = db.schema.iter().map(|e| e.kind).collect::<Vec<_>>();
+
+    assert_eq!(
+      kinds,
+      vec![
+        SchemaKind::Table,
+        SchemaKind::Table,
+        SchemaKind::Table,
+        SchemaKind::Index,
+        SchemaKind::Index,
+        SchemaKind::Index,
+        SchemaKind::View,
+        SchemaKind::Trigger,
+      ]
+    );
+    assert_eq!(db.tables_metadata.len(), 3);
+    assert_eq!(db.indexes_metadata.len(), 3);
+  }
+
+  #[test]
+  fn index_metadata() {
+    let db = Db::from_file(COMPANY_DB).unwrap();
+    let index = db.index("employees_dept").unwrap();
+
+    assert_eq!(index.table_name, "employees");
+    assert!(!index.unique);
+    let columns = index
+      .columns
+      .iter()
+      .map(|c| (c.name.as_str(), c.descending))
+      .collect::<Vec<_>>();
+    assert_eq!(columns, vec![("dept_id", false), ("salary", true)]);
+
+    let entry = db
+      .schema
+      .iter()
+      .find(|e| e.name == "employees_dept")
+      .unwrap();
+    assert_eq!(index.root_page, entry.root_page);
+    assert!(index.root_page > 1);
+
+    let names = db
+      .table_indexes("departments")
+      .map(|i| i.name.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(names, vec!["departments_name"]);
+    assert!(db.index("departments_name").unwrap().unique);
+  }
+
+  #[test]
+  fn automatic_index_metadata() {
+    let db = Db::from_file(COMPANY_DB).unwrap();
+    let index = db.index("sqlite_autoindex_settings_1").unwrap();
+
+    assert_eq!(index.table_name, "settings");
+    assert!(index.unique);
+    assert_eq!(index.columns.len(), 1);
+    assert_eq!(index.columns[0].name, "key");
+  }
+
+  #[test]
+  fn views_and_triggers() {
+    let db = Db::from_file(COMPANY_DB).unwrap();
+
+    let views = db.views().collect::<Vec<_>>();
+    assert_eq!(views.len(), 1);
+    assert_eq!(views[0].name, "engineers");
+    assert!(views[0]
+      .sql
+      .as_ref()
+      .unwrap()
+      .starts_with("CREATE VIEW engineers AS SELECT"));
+
+    let triggers = db.triggers().collect::<Vec<_>>();
+    assert_eq!(triggers.len(), 1);
+    assert_eq!(triggers[0].name, "departments_cleanup");
+    assert_eq!(triggers[0].table_name, "departments");
+    assert_eq!(triggers[0].root_page, 0);
+  }
+
+  const SCHEMAS_DB: &str = "tests/fixtures/schemas.db";
+
+  #[test]
+  fn open_real_world_schemas() {
+    let db = Db::from_file(SCHEMAS_DB).unwrap();
+    let names = db
+      .tables_metadata
+      .iter()
+      .map(|t| t.name.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(names, vec!["Customers", "sqlite_sequence", "orders", "kv"]);
+
+    let customers = db.table("customers").unwrap();
+    let columns = customers
+      .columns
+      .iter()
+      .map(|c| (c.name.as_str(), c.type_name.as_deref(), c.col_type.clone()))
+      .collect::<Vec<_>>();
+    assert_eq!(
+      columns,
+      vec![
+        ("CustomerId", Some("INTEGER"), Type::Integer),
+        ("First Name", Some("VARCHAR(40)"), Type::Text),
+        ("email", Some("NVARCHAR(60)"), Type::Text),
+        ("balance", Some("DECIMAL(10, 2)"), Type::Numeric),
+        ("created_at", Some("DATETIME"), Type::Numeric),
+        ("notes", None, Type::Blob),
+      ]
+    );
+    assert_eq!(customers.rowid_alias(), Some(0));
+  }
+
+  #[test]
+  fn expression_indexes() {
+    let db = Db::from_file(COMPUTED_DB).unwrap();
+    let index = db.index("words_suffixed").unwrap();
+    assert_eq!(index.columns[0].name, "word || 'z'");
+    assert!(index.columns[0].descending);
+    assert!(index.columns[0].expression.is_some());
+    assert!(db.index("words_double").unwrap().unique);
+
+    // the indexes aren't used to scan, the table still is
+    let parsed = parse_statement("SELECT id FROM words WHERE n * 2 = 4", false).unwrap();
+    let mut query = Planner::new(&db).compile(&parsed).unwrap();
+    let row = query.next_row().unwrap().unwrap().to_vec();
+    assert_eq!(row, vec![OwnedValue::Int(2)]);
+  }
+
+  #[test]
+  fn automatic_indexes_of_unique_constraints() {
+    let db = Db::from_file(SCHEMAS_DB).unwrap();
+
+    let email = db.index("sqlite_autoindex_Customers_1").unwrap();
+    assert_eq!(email.table_name, "Customers");
+    assert_eq!(email.columns[0].name, "email");
+
+    let orders = db.index("sqlite_autoindex_orders_1").unwrap();
+    let columns = orders
+      .columns
+      .iter()
+      .map(|c| c.name.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(columns, vec!["customer_id", "code"]);
+    assert_eq!(db.table("orders").unwrap().rowid_alias(), Some(0));
+  }
+
+  #[test]
+  fn query_without_rowid_table() {
+    let db = Db::from_file(SCHEMAS_DB).unwrap();
+    let kv = db.table("kv").unwrap();
+    assert!(kv.without_rowid);
+    assert_eq!(kv.rowid_alias(), None);
+    assert_eq!(kv.field("rowid"), None);
+
+    let parsed = parse_statement("SELECT bucket, key, value, version FROM kv", false).unwrap();
+    let mut op = Planner::new(&db).compile(&parsed).unwrap();
+    let mut rows = vec![];
+    while let Some(row) = op.next_row().unwrap() {
+      rows.push(
+        row
+          .iter()
+          .map(ToString::to_string)
+          .collect::<Vec<_>>()
+          .join(","),
+      );
+    }
+    assert_eq!(rows, vec!["a,k1,one,1", "a,k2,also two,3", "b,k2,two,2"]);
+  }
+
+  #[test]
+  fn query_quoted_columns() {
+    let db = Db::from_file(SCHEMAS_DB).unwrap();
+    let parsed = parse_statement(
+      "SELECT customerid, \"First Name\" FROM customers WHERE [First Name] = 'Kofi'",
+      false,
+    )
+    .unwrap();
+    let mut op = Planner::new(&db).compile(&parsed).unwrap();
+
+    let row = op.next_row().unwrap().unwrap();
+    assert_eq!(row[0].to_string(), "2");
+    assert_eq!(row[1].to_string(), "Kofi");
+  }
+}
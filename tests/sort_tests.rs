@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod sort {
+  use rust_sqlite::{
+    cursor::value::OwnedValue,
+    db::Db,
+    engine::{
+      eval::Evaluator,
+      operator::Operator,
+      plan::Planner,
+      sort::{Sort, SortKey, MERGE_FAN_IN},
+    },
+    sql::{ast::Expr, parser::parse_statement},
+  };
+
+  const OVERFLOW_DB: &str = "tests/fixtures/overflow.db";
+
+  fn scan(db: &Db, query: &str) -> Operator {
+    let parsed = parse_statement(query, false).unwrap();
+    Planner::new(db).compile(&parsed).unwrap().operator
+  }
+
+  fn key(n: i64, descending: bool) -> SortKey {
+    SortKey {
+      expr: Expr::Alias(n),
+      descending,
+      nulls_first: !descending,
+    }
+  }
+
+  fn collect_ids(sort: &mut Sort) -> Vec<i64> {
+    let mut ids = vec![];
+    while let Some(row) = sort.next_row().unwrap() {
+      let OwnedValue::Int(id) = row[0] else {
+        panic!("Expected an integer id");
+      };
+      ids.push(id);
+    }
+    ids
+  }
+
+  #[test]
+  fn sort_in_memory() {
+    let db = Db::from_file(OVERFLOW_DB).unwrap();
+    let source = scan(&db, "SELECT id, body FROM docs");
+    let mut sort = Sort::new(source, vec![key(0, true)], Evaluator::default(), 1 << 20);
+
+    assert_eq!(collect_ids(&mut sort), (1..=40).rev().collect::<Vec<_>>());
+    assert_eq!(sort.spilled_runs(), 0);
+  }
+
+  #[test]
+  fn sort_spills_runs_over_budget() {
+    let db = Db::from_file(OVERFLOW_DB).unwrap();
+    let source = scan(&db, "SELECT id, body FROM docs");
+    // bodies are up to 6kB, a 16kB budget spills every few rows
+    let mut sort = Sort::new(source, vec![key(0, true)], Evaluator::default(), 16 * 1024);
+
+    assert_eq!(collect_ids(&mut sort), (1..=40).rev().collect::<Vec<_>>());
+    assert!(sort.spilled_runs() > 5);
+  }
+
+  #[test]
+  fn spilled_sort_is_stable() {
+    let db = Db::from_file(OVERFLOW_DB).unwrap();
+    let source = scan(&db, "SELECT id, id % 3, body FROM docs");
+    let mut sort = Sort::new(source, vec![key(1, false)], Evaluator::default(), 8 * 1024);
+
+    let ids = collect_ids(&mut sort);
+    let expected = (0..3)
+      .flat_map(|m| (1..=40).filter(move |id| id % 3 == m))
+      .collect::<Vec<_>>();
+    assert_eq!(ids, expected);
+    assert!(sort.spilled_runs() > 1);
+  }
+
+  #[test]
+  fn merges_many_runs_in_passes() {
+    let db = Db::from_file(OVERFLOW_DB).unwrap();
+    let source = scan(&db, "SELECT id, id % 3 FROM docs");
+    // every row spills its own run, more than a single merge reads
+    let mut sort = Sort::new(source, vec![key(1, true)], Evaluator::default(), 0);
+
+    let ids = collect_ids(&mut sort);
+    let expected = (0..3)
+      .rev()
+      .flat_map(|m| (1..=40).filter(move |id| id % 3 == m))
+      .collect::<Vec<_>>();
+    assert_eq!(ids, expected);
+    assert_eq!(sort.spilled_runs(), 40);
+    assert!(sort.spilled_runs() > MERGE_FAN_IN);
+  }
+
+  #[test]
+  fn planner_sort_memory_budget() {
+    let db = Db::from_file(OVERFLOW_DB).unwrap();
+    let parsed = parse_statement("SELECT id FROM docs ORDER BY body DESC", false).unwrap();
+    let mut op = Planner::new(&db)
+      .with_sort_memory(4 * 1024)
+      .compile(&parsed)
+      .unwrap();
+
+    let mut ids = vec![];
+    while let Some(row) = op.next_row().unwrap() {
+      ids.push(row[0].clone());
+    }
+    // bodies repeat "ab" i * 75 times, longer ones sort after their prefixes
+    let expected = (1..=40).rev().map(OwnedValue::Int).collect::<Vec<_>>();
+    assert_eq!(ids, expected);
+  }
+}
@@ -0,0 +1,208 @@
+mod common;
+
+#[cfg(test)]
+mod transaction {
+  #[cfg(target_os = "linux")]
+  use std::fs::File;
+  #[cfg(target_os = "linux")]
+  use std::os::fd::AsRawFd;
+  #[cfg(target_os = "linux")]
+  use std::path::Path;
+
+  use rust_sqlite::cursor::value::OwnedValue;
+  use rust_sqlite::db::Db;
+  use rust_sqlite::page::journal::{journal_path, write_journal};
+  #[cfg(target_os = "linux")]
+  use rust_sqlite::page::lock::{RESERVED_BYTE, SHARED_FIRST, SHARED_SIZE};
+
+  use crate::common::{count, execute, scratch_copy};
+
+  #[test]
+  fn rollback_drops_the_transaction() {
+    let path = scratch_copy("tests/fixtures/company.db", "rollback");
+    let original = std::fs::read(&path).unwrap();
+    let db = Db::from_file(&path).unwrap();
+
+    execute(&db, "BEGIN").unwrap();
+    execute(&db, "INSERT INTO departments (name) VALUES ('legal')").unwrap();
+    execute(&db, "DELETE FROM employees WHERE dept_id = 1").unwrap();
+    // the transaction sees its own changes, the file doesn't
+    assert_eq!(count(&db, "SELECT count(*) FROM departments"), 4);
+    assert_eq!(count(&db, "SELECT count(*) FROM employees"), 4);
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+    execute(&db, "ROLLBACK").unwrap();
+
+    assert_eq!(count(&db, "SELECT count(*) FROM departments"), 3);
+    assert_eq!(count(&db, "SELECT count(*) FROM employees"), 7);
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn commit_writes_every_statement() {
+    let path = scratch_copy("tests/fixtures/inventory.db", "commit");
+    let db = Db::from_file(&path).unwrap();
+
+    execute(&db, "BEGIN IMMEDIATE").unwrap();
+    execute(&db, "DELETE FROM items WHERE category = 3").unwrap();
+    execute(&db, "UPDATE items SET price = 0 WHERE category = 4").unwrap();
+    // a failing statement only undoes its own changes
+    let err = execute(
+      &db,
+      "INSERT INTO items (id, code) VALUES (5000, 'a'), (1, 'b')",
+    );
+    assert_eq!(
+      err.unwrap_err().to_string(),
+      "UNIQUE constraint failed: items.id"
+    );
+    execute(&db, "COMMIT TRANSACTION").unwrap();
+    assert!(!journal_path(&path).exists());
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(count(&db, "SELECT count(*) FROM items"), 1901);
+    assert_eq!(
+      execute(&db, "SELECT count(*) FROM items WHERE price = 0").unwrap(),
+      vec![vec![OwnedValue::Int(101)]]
+    );
+    assert_eq!(
+      execute(&db, "SELECT count(*) FROM items WHERE id = 5000").unwrap(),
+      vec![vec![OwnedValue::Int(0)]]
+    );
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn transaction_state_errors() {
+    let path = scratch_copy("tests/fixtures/company.db", "errors");
+    let db = Db::from_file(&path).unwrap();
+    let error = |query| execute(&db, query).unwrap_err().to_string();
+
+    assert_eq!(error("COMMIT"), "cannot commit - no transaction is active");
+    assert_eq!(
+      error("ROLLBACK"),
+      "cannot rollback - no transaction is active"
+    );
+    execute(&db, "BEGIN DEFERRED TRANSACTION").unwrap();
+    assert_eq!(
+      error("BEGIN EXCLUSIVE"),
+      "cannot start a transaction within a transaction"
+    );
+    execute(&db, "END").unwrap();
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn hot_journal_is_played_back() {
+    let path = scratch_copy("tests/fixtures/company.db", "hot");
+    let original = std::fs::read(&path).unwrap();
+    let page_size = Db::from_file(&path).unwrap().header.page_size as usize;
+
+    // a commit that died after overwriting pages 1 and 2 and growing the file
+    let pages = [1, 2]
+      .map(|n| (n, original[(n - 1) * page_size..n * page_size].to_vec()))
+      .to_vec();
+    write_journal(
+      &journal_path(&path),
+      page_size,
+      original.len() / page_size,
+      &pages,
+    )
+    .unwrap();
+    let mut torn = original.clone();
+    torn[..2 * page_size].fill(0x55);
+    torn.extend(vec![0x77; page_size]);
+    std::fs::write(&path, torn).unwrap();
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(count(&db, "SELECT count(*) FROM employees"), 7);
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+    assert!(!journal_path(&path).exists());
+    std::fs::remove_file(path).unwrap();
+  }
+
+  /// Takes a lock on `len` bytes from `start` as another connection would: open
+  /// file description locks conflict with the locks of this process too
+  #[cfg(target_os = "linux")]
+  fn hold_lock(path: &Path, lock_type: i32, start: i64, len: i64) -> File {
+    let file = File::options().read(true).write(true).open(path).unwrap();
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = lock_type as libc::c_short;
+    lock.l_whence = libc::SEEK_SET as libc::c_short;
+    lock.l_start = start;
+    lock.l_len = len;
+    let result = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_OFD_SETLK, &lock) };
+    assert_eq!(result, 0, "{}", std::io::Error::last_os_error());
+    file
+  }
+
+  #[test]
+  #[cfg(target_os = "linux")]
+  fn journal_of_a_running_commit_is_not_hot() {
+    let path = scratch_copy("tests/fixtures/company.db", "reserved");
+    let page_size = Db::from_file(&path).unwrap().header.page_size as usize;
+    let pages = std::fs::metadata(&path).unwrap().len() as usize / page_size;
+    write_journal(
+      &journal_path(&path),
+      page_size,
+      pages,
+      &[(2, vec![0x55; page_size])],
+    )
+    .unwrap();
+
+    // the connection writing the journal holds the reserved lock
+    let writer = hold_lock(&path, libc::F_WRLCK, RESERVED_BYTE, 1);
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(count(&db, "SELECT count(*) FROM employees"), 7);
+    assert!(journal_path(&path).exists());
+    drop(db);
+
+    // once it is gone, the journal is played back
+    drop(writer);
+    Db::from_file(&path).unwrap();
+    assert!(!journal_path(&path).exists());
+    let content = std::fs::read(&path).unwrap();
+    assert_eq!(content[page_size..2 * page_size], vec![0x55; page_size]);
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  #[cfg(target_os = "linux")]
+  fn commits_wait_for_the_locks_of_other_connections() {
+    let path = scratch_copy("tests/fixtures/company.db", "locked");
+    let original = std::fs::read(&path).unwrap();
+    let db = Db::from_file(&path).unwrap();
+    let insert = "INSERT INTO departments (name) VALUES ('legal')";
+
+    // another connection is about to commit
+    let writer = hold_lock(&path, libc::F_WRLCK, RESERVED_BYTE, 1);
+    assert_eq!(
+      execute(&db, insert).unwrap_err().root_cause().to_string(),
+      "database is locked"
+    );
+    assert!(!journal_path(&path).exists());
+    drop(writer);
+
+    // another connection reads the file, the pages can't be written
+    let reader = hold_lock(&path, libc::F_RDLCK, SHARED_FIRST, SHARED_SIZE);
+    assert_eq!(
+      execute(&db, insert).unwrap_err().root_cause().to_string(),
+      "database is locked"
+    );
+    assert!(!journal_path(&path).exists());
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+    drop(reader);
+
+    // a COMMIT that fails leaves the transaction open
+    execute(&db, "BEGIN").unwrap();
+    execute(&db, insert).unwrap();
+    let writer = hold_lock(&path, libc::F_WRLCK, RESERVED_BYTE, 1);
+    assert_eq!(
+      execute(&db, "COMMIT").unwrap_err().root_cause().to_string(),
+      "database is locked"
+    );
+    drop(writer);
+    execute(&db, "COMMIT").unwrap();
+    assert_eq!(count(&db, "SELECT count(*) FROM departments"), 4);
+    std::fs::remove_file(path).unwrap();
+  }
+}
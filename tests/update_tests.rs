@@ -0,0 +1,130 @@
+mod common;
+
+#[cfg(test)]
+mod update {
+  use rust_sqlite::cursor::value::OwnedValue;
+  use rust_sqlite::db::Db;
+
+  use crate::common::{count, execute, scratch_copy, text};
+
+  #[test]
+  fn update_columns_and_indexes() {
+    let path = scratch_copy("tests/fixtures/company.db", "indexes");
+    let db = Db::from_file(&path).unwrap();
+    execute(
+      &db,
+      "UPDATE employees SET salary = salary + 100, dept_id = 2 WHERE dept_id = 1",
+    )
+    .unwrap();
+    execute(&db, "UPDATE departments SET name = 'ops' WHERE id = 3").unwrap();
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      count(&db, "SELECT count(*) FROM employees WHERE dept_id = 1"),
+      0
+    );
+    assert_eq!(
+      execute(
+        &db,
+        "SELECT name, salary FROM employees WHERE dept_id = 2 AND salary > 5000"
+      )
+      .unwrap(),
+      vec![
+        vec![text("kwame"), OwnedValue::Int(6200)],
+        vec![text("ama"), OwnedValue::Int(5300)],
+      ]
+    );
+    assert_eq!(
+      execute(&db, "SELECT id FROM departments WHERE name = 'ops'").unwrap(),
+      vec![vec![OwnedValue::Int(3)]]
+    );
+    // the old name left the unique index
+    execute(&db, "INSERT INTO departments (name) VALUES ('support')").unwrap();
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn changing_the_rowid_moves_the_row() {
+    let path = scratch_copy("tests/fixtures/inventory.db", "rowid");
+    let db = Db::from_file(&path).unwrap();
+    execute(&db, "UPDATE items SET id = id + 5000 WHERE id % 7 = 0").unwrap();
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      count(&db, "SELECT count(*) FROM items WHERE id > 5000"),
+      285
+    );
+    assert_eq!(count(&db, "SELECT count(*) FROM items WHERE id = 7"), 0);
+    // index entries point to the new rowid
+    assert_eq!(
+      execute(&db, "SELECT id FROM items WHERE code = 'c00097'").unwrap(),
+      vec![vec![OwnedValue::Int(300)]]
+    );
+    assert_eq!(
+      execute(&db, "SELECT id, category FROM items WHERE code = 'c00049'").unwrap(),
+      execute(&db, "SELECT id, category FROM items WHERE id = 5007").unwrap(),
+    );
+
+    let error = |query| execute(&db, query).unwrap_err().to_string();
+    assert_eq!(
+      error("UPDATE items SET id = 1 WHERE id = 2"),
+      "UNIQUE constraint failed: items.id"
+    );
+    assert_eq!(error("UPDATE items SET rowid = NULL"), "datatype mismatch");
+    assert_eq!(error("UPDATE items SET size = 1"), "no such column: size");
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn growing_records_move_and_split_pages() {
+    let path = scratch_copy("tests/fixtures/inventory.db", "grow");
+    let db = Db::from_file(&path).unwrap();
+    let padding = "z".repeat(300);
+    execute(
+      &db,
+      &format!("UPDATE items SET code = code || '{padding}' WHERE id % 3 = 0"),
+    )
+    .unwrap();
+    execute(&db, "UPDATE items SET code = 'short' WHERE id % 6 = 0").unwrap();
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(count(&db, "SELECT count(*) FROM items"), 2000);
+    assert_eq!(
+      count(&db, "SELECT count(*) FROM items WHERE code = 'short'"),
+      333
+    );
+    assert_eq!(
+      execute(&db, "SELECT code FROM items WHERE id = 3").unwrap(),
+      vec![vec![text(&format!("c00021{padding}"))]]
+    );
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn update_without_rowid() {
+    let path = scratch_copy("tests/fixtures/schemas.db", "without_rowid");
+    let db = Db::from_file(&path).unwrap();
+    execute(&db, "UPDATE kv SET bucket = 'c' WHERE key = 'k1'").unwrap();
+    execute(
+      &db,
+      "UPDATE kv SET value = 'three', version = version + 1 WHERE bucket = 'b'",
+    )
+    .unwrap();
+    let err = execute(&db, "UPDATE kv SET bucket = 'a' WHERE bucket = 'b'").unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "UNIQUE constraint failed: kv.key, kv.bucket"
+    );
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      execute(&db, "SELECT key, bucket, value, version FROM kv").unwrap(),
+      vec![
+        vec![text("k1"), text("c"), text("one"), OwnedValue::Int(1)],
+        vec![text("k2"), text("a"), text("also two"), OwnedValue::Int(3)],
+        vec![text("k2"), text("b"), text("three"), OwnedValue::Int(3)],
+      ]
+    );
+    std::fs::remove_file(path).unwrap();
+  }
+}
@@ -0,0 +1,340 @@
+mod common;
+
+#[cfg(test)]
+mod wal {
+  #[cfg(target_os = "linux")]
+  use std::fs::File;
+  #[cfg(target_os = "linux")]
+  use std::os::fd::AsRawFd;
+  use std::path::PathBuf;
+
+  use rust_sqlite::cursor::value::OwnedValue;
+  use rust_sqlite::db::Db;
+  use rust_sqlite::page::wal::{
+    checksum, shm_path, wal_path, WAL_FRAME_HEADER_SIZE, WAL_HEADER_SIZE,
+  };
+
+  use crate::common::{execute, text};
+
+  /// 1024 byte pages, every commit after the first rows is only in the wal
+  const FRAME_SIZE: usize = WAL_FRAME_HEADER_SIZE + 1024;
+
+  /// Copies the fixture and its wal, as `wal` when given, to scratch files
+  fn scratch_copy(name: &str, wal: Option<Vec<u8>>) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("wal_{}_{name}.db", std::process::id()));
+    std::fs::copy("tests/fixtures/wal.db", &path).unwrap();
+    if let Some(wal) = wal {
+      std::fs::write(wal_path(&path), wal).unwrap();
+    }
+    path
+  }
+
+  fn fixture_wal() -> Vec<u8> {
+    std::fs::read("tests/fixtures/wal.db-wal").unwrap()
+  }
+
+  fn remove(path: PathBuf) {
+    std::fs::remove_file(wal_path(&path)).ok();
+    std::fs::remove_file(shm_path(&path)).ok();
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn reads_committed_frames() {
+    let path = scratch_copy("committed", Some(fixture_wal()));
+    let db = Db::from_file(&path).unwrap();
+
+    assert_eq!(
+      execute(&db, "SELECT count(*), max(id) FROM notes").unwrap(),
+      vec![vec![OwnedValue::Int(59), OwnedValue::Int(60)]]
+    );
+    assert_eq!(
+      execute(&db, "SELECT body FROM notes WHERE id < 4").unwrap(),
+      vec![vec![text("checkpointed")], vec![text("new")]]
+    );
+    // the table and index created after the checkpoint
+    assert!(db.index("tags_tag").is_some());
+    assert_eq!(
+      execute(&db, "SELECT note_id FROM tags WHERE tag = 'a'").unwrap(),
+      vec![vec![OwnedValue::Int(1)], vec![OwnedValue::Int(4)]]
+    );
+    remove(path);
+  }
+
+  #[test]
+  fn database_file_alone_is_the_last_checkpoint() {
+    let path = scratch_copy("checkpoint", None);
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      execute(&db, "SELECT id, body FROM notes").unwrap(),
+      vec![
+        vec![OwnedValue::Int(1), text("checkpointed")],
+        vec![OwnedValue::Int(2), text("old")],
+        vec![OwnedValue::Int(3), text("removed")],
+      ]
+    );
+    assert!(db.table("tags").is_none());
+
+    // a log without a valid header is ignored as well
+    let mut wal = fixture_wal();
+    wal[0] ^= 1 << 4;
+    std::fs::write(wal_path(&path), wal).unwrap();
+    let db = Db::from_file(&path).unwrap();
+    assert!(db.table("tags").is_none());
+    remove(path);
+  }
+
+  #[test]
+  fn invalid_frames_end_the_log() {
+    let wal = fixture_wal();
+    let frames = (wal.len() - WAL_HEADER_SIZE) / FRAME_SIZE;
+    let last_frame = WAL_HEADER_SIZE + (frames - 1) * FRAME_SIZE;
+
+    // the last commit inserts into tags: without it the table is empty
+    let mut bad_checksum = wal.clone();
+    bad_checksum[last_frame + WAL_FRAME_HEADER_SIZE + 100] ^= 0xff;
+    let mut bad_salt = wal.clone();
+    bad_salt[last_frame + 8] ^= 0xff;
+    let truncated = wal[..wal.len() - 1].to_vec();
+    for (name, wal) in [
+      ("checksum", bad_checksum),
+      ("salt", bad_salt),
+      ("truncated", truncated),
+    ] {
+      let path = scratch_copy(name, Some(wal));
+      let db = Db::from_file(&path).unwrap();
+      assert_eq!(
+        execute(&db, "SELECT count(*) FROM tags").unwrap(),
+        vec![vec![OwnedValue::Int(0)]],
+        "{name}"
+      );
+      assert_eq!(
+        execute(&db, "SELECT count(*) FROM notes").unwrap(),
+        vec![vec![OwnedValue::Int(59)]],
+        "{name}"
+      );
+      remove(path);
+    }
+  }
+
+  #[test]
+  fn uncommitted_frames_are_skipped() {
+    // a valid frame that isn't a commit: its page stays as of the last commit
+    let mut wal = fixture_wal();
+    let big_endian = wal[3] & 1 == 1;
+    let last_checksum = wal.len() - FRAME_SIZE + 16;
+    let previous = (
+      u32::from_be_bytes(wal[last_checksum..last_checksum + 4].try_into().unwrap()),
+      u32::from_be_bytes(
+        wal[last_checksum + 4..last_checksum + 8]
+          .try_into()
+          .unwrap(),
+      ),
+    );
+
+    let mut frame = vec![0; FRAME_SIZE];
+    frame[..4].copy_from_slice(&1u32.to_be_bytes());
+    frame[8..16].copy_from_slice(&wal[16..24]);
+    let sum = checksum(big_endian, previous, &frame[..8]);
+    let sum = checksum(big_endian, sum, &frame[WAL_FRAME_HEADER_SIZE..]);
+    frame[16..20].copy_from_slice(&sum.0.to_be_bytes());
+    frame[20..24].copy_from_slice(&sum.1.to_be_bytes());
+    wal.extend_from_slice(&frame);
+
+    let path = scratch_copy("uncommitted", Some(wal));
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      execute(&db, "SELECT count(*) FROM tags").unwrap(),
+      vec![vec![OwnedValue::Int(3)]]
+    );
+    remove(path);
+  }
+
+  fn notes(db: &Db) -> i64 {
+    let rows = execute(db, "SELECT count(*) FROM notes").unwrap();
+    rows[0][0].as_value().as_int().unwrap()
+  }
+
+  fn checkpoint(db: &Db, mode: &str) -> Vec<OwnedValue> {
+    execute(db, &format!("PRAGMA wal_checkpoint({mode})")).unwrap()[0].clone()
+  }
+
+  #[test]
+  fn commits_append_to_the_log() {
+    let path = scratch_copy("append", Some(fixture_wal()));
+    let original = std::fs::read(&path).unwrap();
+    let db = Db::from_file(&path).unwrap();
+    execute(&db, "PRAGMA wal_autocheckpoint = 0").unwrap();
+
+    execute(&db, "INSERT INTO notes (body) VALUES ('appended')").unwrap();
+    execute(&db, "BEGIN").unwrap();
+    execute(&db, "DELETE FROM notes WHERE id < 10").unwrap();
+    execute(&db, "INSERT INTO tags VALUES (61, 'c')").unwrap();
+    execute(&db, "COMMIT").unwrap();
+    execute(&db, "BEGIN").unwrap();
+    execute(&db, "DELETE FROM tags").unwrap();
+    execute(&db, "ROLLBACK").unwrap();
+
+    // the database file is only written by checkpoints
+    assert_eq!(std::fs::read(&path).unwrap(), original);
+    assert!(std::fs::metadata(wal_path(&path)).unwrap().len() > fixture_wal().len() as u64);
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(notes(&db), 52);
+    assert_eq!(
+      execute(&db, "SELECT id FROM notes WHERE body = 'appended'").unwrap(),
+      vec![vec![OwnedValue::Int(61)]]
+    );
+    assert_eq!(
+      execute(&db, "SELECT note_id FROM tags WHERE tag = 'c'").unwrap(),
+      vec![vec![OwnedValue::Int(61)]]
+    );
+    remove(path);
+  }
+
+  #[test]
+  #[cfg(target_os = "linux")]
+  fn writes_wait_for_other_connections_to_close_the_index() {
+    let path = scratch_copy("shm", Some(fixture_wal()));
+    let db = Db::from_file(&path).unwrap();
+    execute(&db, "PRAGMA wal_autocheckpoint = 0").unwrap();
+
+    // a sqlite connection holds a shared lock on the DMS byte of the index
+    // while it has it mapped, as an open file description lock conflicts with
+    // the locks of this process too
+    let shm = File::options()
+      .read(true)
+      .write(true)
+      .create(true)
+      .truncate(false)
+      .open(shm_path(&path))
+      .unwrap();
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_RDLCK as libc::c_short;
+    lock.l_whence = libc::SEEK_SET as libc::c_short;
+    lock.l_start = 128;
+    lock.l_len = 1;
+    let result = unsafe { libc::fcntl(shm.as_raw_fd(), libc::F_OFD_SETLK, &lock) };
+    assert_eq!(result, 0, "{}", std::io::Error::last_os_error());
+
+    let insert = "INSERT INTO notes (body) VALUES ('appended')";
+    assert_eq!(
+      execute(&db, insert).unwrap_err().root_cause().to_string(),
+      "database is locked"
+    );
+    assert!(execute(&db, "PRAGMA wal_checkpoint(TRUNCATE)").is_err());
+    assert_eq!(std::fs::read(wal_path(&path)).unwrap(), fixture_wal());
+
+    drop(shm);
+    execute(&db, insert).unwrap();
+    assert_eq!(notes(&db), 60);
+    remove(path);
+  }
+
+  #[test]
+  fn checkpoints_copy_the_log_to_the_database() {
+    let path = scratch_copy("checkpoint_modes", Some(fixture_wal()));
+    let db = Db::from_file(&path).unwrap();
+    let frames = ((fixture_wal().len() - WAL_HEADER_SIZE) / FRAME_SIZE) as i64;
+
+    assert_eq!(
+      checkpoint(&db, "PASSIVE"),
+      vec![
+        OwnedValue::Int(0),
+        OwnedValue::Int(frames),
+        OwnedValue::Int(frames)
+      ]
+    );
+    // the next commit starts the log over
+    execute(&db, "UPDATE notes SET body = 'newer' WHERE id = 2").unwrap();
+    assert_eq!(
+      checkpoint(&db, "FULL"),
+      vec![OwnedValue::Int(0), OwnedValue::Int(2), OwnedValue::Int(2)]
+    );
+    assert_eq!(
+      checkpoint(&db, "TRUNCATE"),
+      vec![OwnedValue::Int(0), OwnedValue::Int(0), OwnedValue::Int(0)]
+    );
+    assert_eq!(std::fs::metadata(wal_path(&path)).unwrap().len(), 0);
+
+    // the database file alone is up to date
+    std::fs::remove_file(wal_path(&path)).unwrap();
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(notes(&db), 59);
+    assert_eq!(
+      execute(&db, "SELECT body FROM notes WHERE id = 2").unwrap(),
+      vec![vec![text("newer")]]
+    );
+    assert_eq!(
+      execute(&db, "PRAGMA journal_mode").unwrap(),
+      vec![vec![text("wal")]]
+    );
+    remove(path);
+  }
+
+  #[test]
+  fn commits_checkpoint_a_full_log() {
+    let path = scratch_copy("autocheckpoint", Some(fixture_wal()));
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(
+      execute(&db, "PRAGMA wal_autocheckpoint").unwrap(),
+      vec![vec![OwnedValue::Int(1000)]]
+    );
+    assert_eq!(
+      execute(&db, "PRAGMA wal_autocheckpoint = 20").unwrap(),
+      vec![vec![OwnedValue::Int(20)]]
+    );
+
+    // the first commit takes the log past 20 frames
+    execute(&db, "INSERT INTO notes (body) VALUES ('a')").unwrap();
+    let [_, log, checkpointed] = &checkpoint(&db, "PASSIVE")[..] else {
+      panic!("checkpoint outputs three columns");
+    };
+    assert_eq!(log, checkpointed);
+
+    std::fs::remove_file(wal_path(&path)).unwrap();
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(notes(&db), 60);
+    remove(path);
+  }
+
+  #[test]
+  fn journal_mode_switches() {
+    let path = std::env::temp_dir().join(format!("wal_{}_journal_mode.db", std::process::id()));
+    std::fs::copy("tests/fixtures/company.db", &path).unwrap();
+    let db = Db::from_file(&path).unwrap();
+    let mode = |db: &Db, query: &str| execute(db, query).unwrap()[0][0].clone();
+
+    assert_eq!(mode(&db, "PRAGMA journal_mode"), text("delete"));
+    assert_eq!(
+      checkpoint(&db, "PASSIVE"),
+      vec![OwnedValue::Int(0), OwnedValue::Int(-1), OwnedValue::Int(-1)]
+    );
+    assert_eq!(mode(&db, "PRAGMA journal_mode = WAL"), text("wal"));
+    execute(&db, "INSERT INTO departments (name) VALUES ('legal')").unwrap();
+    assert!(wal_path(&path).exists());
+
+    // the header keeps the database in WAL mode
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(db.header.file_format_w, 2);
+    assert_eq!(mode(&db, "PRAGMA journal_mode"), text("wal"));
+    execute(&db, "BEGIN").unwrap();
+    let err = execute(&db, "PRAGMA journal_mode = delete").unwrap_err();
+    assert_eq!(
+      err.to_string(),
+      "cannot change out of wal mode from within a transaction"
+    );
+    execute(&db, "COMMIT").unwrap();
+    assert_eq!(mode(&db, "PRAGMA journal_mode = delete"), text("delete"));
+    assert!(!wal_path(&path).exists());
+
+    let db = Db::from_file(&path).unwrap();
+    assert_eq!(db.header.file_format_w, 1);
+    assert_eq!(
+      execute(&db, "SELECT count(*) FROM departments").unwrap(),
+      vec![vec![OwnedValue::Int(4)]]
+    );
+    let err = execute(&db, "PRAGMA journal_mode = memory").unwrap_err();
+    assert_eq!(err.to_string(), "unsupported journal mode: memory");
+    remove(path);
+  }
+}